@@ -0,0 +1,160 @@
+# Messages of wayshot in German, one for every variant of Message in src/i18n.rs,
+# named after it in kebab case.
+
+missing-shm = Dem Compositor fehlt die wl_shm-Schnittstelle
+missing-screencopy = Dem Compositor fehlt die zwlr_screencopy_manager_v1-Schnittstelle
+output-without-name = Ein Ausgang hat keinen Namen gemeldet
+region-outside-outputs = Der Aufnahmebereich überschneidet sich mit keinem Ausgang!
+missing-frame-format = { $output } hat kein Bildformat angegeben
+frame-copy-failed = Kopieren des Bildes fehlgeschlagen
+unsupported-buffer-format = Nicht unterstütztes Pufferformat: { $format }
+feature-request = Unterstützung für dieses Format kann über die Mailingliste von wayshot angefragt werden: https://sr.ht/~shinyzenith/wayshot.
+composite-failed = Das Bild von { $output } konnte nicht auf die Leinwand kopiert werden, es passt nicht
+exported-frames =
+    { $count ->
+        [one] Ein Bild
+       *[other] { $count } Bilder
+    } nach { $directory } exportiert
+geometry-not-received = Keine Geometrie empfangen.
+invalid-geometry = Ungültige Geometrie
+selection-cancelled = Auswahl abgebrochen
+no-focused-window = Kein Fenster hat den Fokus
+unsupported-compositor-ipc = Das aktive Fenster kann nur unter sway und Hyprland ermittelt werden
+invalid-jpeg-subsampling = Ungültiges Jpeg-Subsampling angegeben.
+invalid-png-filter = Ungültiger Png-Filter angegeben.
+invalid-extension = Ungültige Erweiterung angegeben.
+invalid-output-formats = Ungültige Formate pro Ausgang angegeben, erwartet zum Beispiel: DP-1=png,HDMI-A-1=jpg:80
+invalid-trim-tolerance = Ungültige Toleranz zum Zuschneiden angegeben, erwartet wird eine Zahl zwischen 0 und 255
+invalid-cursor-mode = Ungültiger Cursor-Modus angegeben.
+invalid-scale = Ungültige Skalierung angegeben, erwartet wird eine positive Zahl oder max
+invalid-annotations = Ungültige Anmerkungen angegeben: { $error }
+invalid-thumbnail-size = Ungültige Vorschaugröße angegeben, erwartet wird z. B. 320x180
+cursor-unavailable = Der Cursor kann nicht gezeichnet werden: { $error }
+valid-arguments = Gültige Argumente:
+invalid-filename-format = Ungültiges Dateinamenformat, erwartet wird ein strftime-Format wie { $example }
+stream-fallback = Die Aufnahmen müssen skaliert werden oder der Encoder kann nicht zeilenweise schreiben, es wird normal kodiert
+copy-with-damage-unsupported = Der Compositor kann nicht auf Änderungen warten, stattdessen wird das zuletzt dargestellte Bild kopiert
+compositor-unavailable = Verbindung zu einem Wayland-Compositor fehlgeschlagen
+compositor-disconnected = Die Verbindung zum Compositor ist abgebrochen: { $error }
+compositor-protocol-error = Der Compositor hat einen Protokollfehler gemeldet: { $error }
+frame-already-copied = Ein Bild wurde zweimal kopiert, das ist ein Fehler in wayshot. Bitte melden Sie ihn
+invalid-frame-buffer = Der Compositor hat den Aufnahmepuffer abgelehnt, weil Größe, Zeilenlänge oder Format nicht seiner Anforderung entsprechen. Meist hat ein Ausgang während der Aufnahme Modus oder Skalierung geändert, versuchen Sie es erneut
+invalid-shm-format = Der Compositor unterstützt das Pixelformat des Aufnahmepuffers nicht. Bitte melden Sie einen Fehler mit der Ausgabe von wayshot --debug
+invalid-shm-stride = Der Compositor hat Größe oder Zeilenlänge des Aufnahmepuffers abgelehnt. Meist hat ein Ausgang während der Aufnahme den Modus geändert, versuchen Sie es erneut
+invalid-shm-fd = Der Compositor konnte den gemeinsamen Speicher des Aufnahmepuffers nicht einbinden. Prüfen Sie, ob memfd_create oder /dev/shm nutzbar ist und freien Platz hat
+invalid-request = wayshot hat eine Anfrage gesendet, die der Compositor nicht kennt, meist wegen unterschiedlicher Protokollversionen. Bitte melden Sie einen Fehler mit Ihrem Compositor und dessen Version
+compositor-out-of-memory = Dem Compositor ist der Speicher ausgegangen
+compositor-internal-error = Im Compositor ist ein interner Fehler aufgetreten. Vermutlich ist das ein Fehler des Compositors, prüfen Sie seine Logs
+feature-unavailable = Dieser Build von wayshot wurde ohne Unterstützung für { $feature } kompiliert
+no-app-windows = Keine sichtbaren Fenster von { $app_id }
+invalid-window-index = Ungültige Fensternummer, erwartet 1 bis { $count }
+invalid-stack-count = Ungültige Anzahl zu stapelnder Bilder, erwartet 1 bis { $max }
+invalid-stack-mode = Ungültiger Stapelmodus angegeben
+stack-outputs-changed = Die Ausgaben haben sich während der Aufnahme der zu stapelnden Bilder geändert
+logical-geometry-estimated = Der Compositor hat die logische Geometrie von { $output } nicht über xdg_output gemeldet, sie wird aus ihrem wl_output-Modus und ihrer Skalierung geschätzt
+logical-geometry-missing = Der Compositor hat die Geometrie von { $output } nicht gemeldet, sie kann nicht aufgenommen werden
+flash-failed = Der aufgenommene Bereich konnte nicht aufblitzen: { $error }
+shutter-sound-failed = Der Auslöserton konnte nicht abgespielt werden: { $error }
+invalid-frame-size = Der Compositor hat ein Bild von { $output } beschrieben, dessen Zeilenlänge für seine Breite zu kurz ist oder das für einen gemeinsamen Speicherpuffer zu groß ist: { $width }x{ $height }, Zeilenlänge { $stride }
+invalid-palette-size = Ungültige Palettengröße, erwartet 2 bis { $max }
+auto-format-stream = --extension auto wählt den Encoder anhand des fertigen Bildes und kann nicht mit --stream kombiniert werden
+invalid-max-duration = Ungültige --max-duration, erwartet eine Anzahl Millisekunden über 0
+deadline-exceeded = Die Aufnahme hat länger als --max-duration gedauert, die Zeit lief ab während { $stage }
+screencopy-sandboxed = Compositoren verbergen zwlr_screencopy_manager_v1 vor Anwendungen in einer { $sandbox }-Sandbox, starten Sie wayshot außerhalb der Sandbox
+invalid-quirk = Unbekannter Workaround: { $quirk }
+detected-compositor = Compositor: { $compositor }
+blank-frame = Das aufgenommene Bild von { $output } hat nur eine Farbe, { $color }, der Ausgang ist eventuell aus oder der Compositor hat die Aufnahme verweigert
+invalid-port = Ungültiger --port, erwartet eine Zahl von 1 bis 65535
+invalid-fps = Ungültige --fps, erwartet eine Anzahl Bilder pro Sekunde von 1 bis { $max }
+serve-bind-failed = Lauschen auf { $address } fehlgeschlagen: { $error }
+serving = Die Aufnahme wird auf { $url } bereitgestellt
+serve-capture-failed = Aufnahme eines bereitzustellenden Bildes fehlgeschlagen: { $error }
+invalid-dzi-format = Kacheln von --dzi können nur png- oder jpg-Bilder sein
+reference-unreadable = Das Vergleichsbild { $path } konnte nicht gelesen werden: { $error }
+invalid-diff-tolerance = Ungültige --tolerance, erwartet wird eine Zahl zwischen 0 und 255
+diff-size-mismatch = Das Vergleichsbild und die Aufnahme haben unterschiedliche Größen, { $reference } und { $capture }
+changed-regions =
+    { $count ->
+        [one] Ein geänderter Bereich
+       *[other] { $count } geänderte Bereiche
+    }
+invalid-zoom = Ungültiger --zoom, erwartet ein Faktor über 0 und höchstens { $max }
+invalid-zoom-filter = Ungültiger Zoom-Filter angegeben.
+test-pattern-failed = Anzeigen und Aufnehmen des Testbilds fehlgeschlagen: { $error }
+test-pattern-deltas = Größte Abweichung je Kanal auf { $output }: Rot { $red }, Grün { $green }, Blau { $blue }
+test-pattern-mismatch = Die Aufnahme stimmt auf { $output } nicht mit dem Testbild überein
+invalid-force-format = Ungültiges --force-format angegeben.
+forced-format-mismatch = Das Bild von { $output } hat weniger als 4 Bytes pro Pixel ({ $format }) und kann nicht im erzwungenen Format gelesen werden
+invalid-battery-fps = Ungültige --battery-fps, erwartet eine Anzahl Bilder pro Sekunde von 0 bis { $max }
+serve-on-battery = Akkubetrieb, { $fps } Bilder pro Sekunde
+serve-on-ac = Netzbetrieb, { $fps } Bilder pro Sekunde
+invalid-collision = Ungültige Kollisionsstrategie angegeben.
+file-exists = Die Datei { $path } existiert bereits
+ci-shot-spawn-failed = Fehler beim Starten von { $program }: { $error }
+ci-shot-exited = { $command } wurde vor der Aufnahme beendet ({ $status })
+ci-shot-timeout = Warten auf Compositor und Anwendung abgebrochen: { $command }
+invalid-ci-timeout = Ungültiges --timeout, erwartet eine Anzahl Millisekunden über 0
+ci-shot-failed = Vorbereiten des Compositors ohne Bildschirm fehlgeschlagen: { $error }
+invalid-tiff-compression = Ungültige Tiff-Kompression angegeben.
+invalid-output-timeout = Ungültiges --output-timeout, erwartet eine Anzahl Millisekunden über 0
+frame-timed-out = Das Bild von { $output } wurde nicht innerhalb von --output-timeout kopiert und wird ausgelassen
+no-frame-copied = Das Bild keiner Ausgabe wurde innerhalb von --output-timeout kopiert
+invalid-gamma = Ungültiges --gamma, erwartet eine Zahl über 0 und höchstens { $max }
+invalid-brightness = Ungültige --brightness, erwartet eine Zahl von -1 bis 1
+invalid-contrast = Ungültiger --contrast, erwartet eine Zahl von 0 bis { $max }
+invalid-crop = Ungültiger --crop { $crop }, erwartet NAME=GEOMETRIE
+duplicate-crop-name = Mehrere --crop heißen { $name }
+encoders = Encoder: { $encoders }
+not-enough-space = Nicht genug freier Speicher für das Bild in { $directory }, { $needed } Bytes werden benötigt und { $available } sind verfügbar, --auto-degrade weicht auf kleinere Dateien aus
+encoding-degraded = Nicht genug freier Speicher für das Bild, es wird als { $encoder } geschrieben
+toplevels-unavailable = Der Compositor listet seine Fenster nicht auf, fullscreen-Regeln greifen nie
+watch-triggered = Aufnahme nach { $reason }
+watch-capture-failed = Aufnahme für eine Regel fehlgeschlagen: { $error }
+invalid-redact-style = Ungültiger Schwärzungsstil angegeben.
+invalid-simulation = Ungültige Farbsehschwäche angegeben.
+invalid-analysis = Ungültige Analyse angegeben.
+output-cache-failed = Ausgaben konnten nicht zwischengespeichert werden: { $error }
+invalid-also-save = Ungültiger zusätzlicher Encoder angegeben, erwartet KODIERER=PFAD, zum Beispiel: jpg:80=/tmp/chat.jpg
+invalid-checksum = Ungültige Prüfsumme angegeben.
+capture-budget-exceeded = Die Aufnahme würde { $needed } Bytes Speicher belegen, mehr als die erlaubten { $max }
+capture-command-failed = Der Befehl { $name } ist fehlgeschlagen: { $error }
+invalid-hook-timeout = Ungültiges --hook-timeout, erwartet eine Anzahl Millisekunden über 0
+invalid-hook-failure = Ungültige Fehlerbehandlung für Befehle angegeben.
+dnd-unavailable = Kein Benachrichtigungsdienst mit Nicht-stören-Modus läuft, Benachrichtigungen können aufgenommen werden
+dnd-failed = Nicht-stören-Modus konnte nicht aktiviert werden: { $error }
+dnd-restore-failed = Nicht-stören-Modus konnte nicht deaktiviert werden: { $error }
+bug-report-failed = Fehlerbericht konnte nicht geschrieben werden: { $error }
+invalid-tone-map = Ungültiger Tone-Mapping-Operator angegeben.
+invalid-hdr-outputs = Ungültige HDR-Ausgänge angegeben, erwartet auto oder Ausgangsnamen, zum Beispiel: DP-1,DP-2
+invalid-max-size = Ungültiges --max-size, erwartet eine Anzahl Bytes mit optionaler Einheit, zum Beispiel: 2MB, 500KiB oder 1048576
+quality-lowered = Jpg-Qualität auf { $quality } verringert, um --max-size einzuhalten
+image-too-large = Das Bild überschreitet --max-size selbst bei niedrigster Qualität, { $size } Bytes bei höchstens { $max }
+compositor-connection-failed = Verbindung zu einem Wayland-Compositor fehlgeschlagen: { $error }
+geometry-unreadable = Die Geometrie konnte nicht gelesen werden: { $error }
+cursor-unavailable-with-layout = Der Cursor kann mit --layout nicht gezeichnet werden
+frame-budget-exceeded = Das Bild von { $output } würde { $needed } Bytes Speicher belegen, mehr als die { $left } vom erlaubten Speicher übrigen
+region-skipped = Der Bereich { $region } überschneidet sich mit keinem Ausgang und wird übersprungen
+command-spawn-failed = { $program } konnte nicht gestartet werden: { $error }
+command-failed = { $program } wurde mit { $status } beendet
+redactions-unreadable = Die Schwärzungen { $path } konnten nicht gelesen werden: { $error }
+redactions-invalid = Ungültige Schwärzungen { $path }: { $error }
+redaction-line-invalid = Zeile { $line }: „app_id REGEX“ oder „title REGEX“ erwartet
+redaction-regex-invalid = Zeile { $line }: { $error }
+rules-unreadable = Die Regeln { $path } konnten nicht gelesen werden: { $error }
+rules-invalid = Ungültige Regeln { $path }: { $error }
+config-unreadable = Die Konfiguration { $path } konnte nicht gelesen werden: { $error }
+config-invalid = Ungültige Konfiguration { $path }: { $error }
+config-unknown-option = Die Konfiguration setzt { $option }, das keine Option mit Wert ist
+config-value-invalid = Die Konfiguration setzt { $option } auf etwas anderes als eine Zeichenkette oder Zahl
+layer-shell-unsupported = Der Compositor unterstützt wlr-layer-shell nicht
+flash-output-unnamed = Ausgänge, die keinen Namen melden, können nicht aufblitzen
+hooks-unloadable = Die Hooks aus { $path } konnten nicht geladen werden: { $error }
+filename-hook-not-string = Der filename-Hook hat { $type } statt einer Zeichenkette zurückgegeben
+filename-hook-invalid = Der filename-Hook hat einen ungültigen Namen zurückgegeben: { $name }
+post-process-hook-not-image = Der post_process-Hook hat { $type } statt eines Bildes zurückgegeben
+hook-failed = Der Hook { $hook } ist fehlgeschlagen: { $error }
+capture-worker-panicked = Die Aufnahme eines Ausgangs ist unerwartet fehlgeschlagen
+invalid-bench-size = Ungültige --size, erwartet wird eine positive Anzahl MiB
+invalid-bench-runs = Ungültige --runs, erwartet wird eine positive Zahl
+bench-failed = Der Benchmark ist fehlgeschlagen: { $error }
+bench-write-timing = { $method }: { $bytes } Bytes in { $milliseconds } ms, { $throughput } MiB/s
@@ -0,0 +1,160 @@
+# Messages of wayshot in English, one for every variant of Message in src/i18n.rs,
+# named after it in kebab case.
+
+missing-shm = Compositor is missing wl_shm interface
+missing-screencopy = Compositor is missing zwlr_screencopy_manager_v1 interface
+output-without-name = An output did not report its name
+region-outside-outputs = Provided capture region doesn't intersect with any outputs!
+missing-frame-format = { $output } did not specify a frame format
+frame-copy-failed = Frame copy failed
+unsupported-buffer-format = Unsupported buffer format: { $format }
+feature-request = You can send a feature request for the above format to the mailing list for wayshot over at https://sr.ht/~shinyzenith/wayshot.
+composite-failed = Failed to copy the image of { $output } onto the canvas, it does not fit
+exported-frames =
+    Exported { $count ->
+        [one] one frame
+       *[other] { $count } frames
+    } to { $directory }
+geometry-not-received = Failed to recieve geometry.
+invalid-geometry = Invalid geometry specification
+selection-cancelled = Selection cancelled
+no-focused-window = No window is focused
+unsupported-compositor-ipc = Active window lookup is only supported on sway and Hyprland
+invalid-jpeg-subsampling = Invalid jpeg subsampling provided.
+invalid-png-filter = Invalid png filter provided.
+invalid-extension = Invalid extension provided.
+invalid-output-formats = Invalid output format overrides provided, expected eg: DP-1=png,HDMI-A-1=jpg:80
+invalid-trim-tolerance = Invalid trim tolerance provided, expected a number between 0 and 255
+invalid-cursor-mode = Invalid cursor mode provided.
+invalid-scale = Invalid scale provided, expected a positive number or max
+invalid-annotations = Invalid annotations provided: { $error }
+invalid-thumbnail-size = Invalid thumbnail size provided, expected eg: 320x180
+cursor-unavailable = Can not draw the cursor: { $error }
+valid-arguments = Valid arguments:
+invalid-filename-format = Invalid filename format, expected a strftime format such as { $example }
+stream-fallback = Captures need scaling or the encoder can not write rows incrementally, falling back to regular encoding
+copy-with-damage-unsupported = The compositor can not wait for damage, copying the last presented frame instead
+compositor-unavailable = Could not connect to a Wayland compositor
+compositor-disconnected = Lost the connection to the compositor: { $error }
+compositor-protocol-error = The compositor reported a protocol error: { $error }
+frame-already-copied = A frame was copied twice, this is a bug in wayshot. Please report it
+invalid-frame-buffer = The compositor rejected the capture buffer because it does not match the size, stride or format it asked for. This usually means an output changed mode or scale during the capture, try again
+invalid-shm-format = The compositor does not support the pixel format of the capture buffer. Please report a bug including the output of wayshot --debug
+invalid-shm-stride = The compositor rejected the size or stride of the capture buffer. This usually means an output changed mode during the capture, try again
+invalid-shm-fd = The compositor could not map the shared memory of the capture buffer. Check that memfd_create or /dev/shm is usable and has free space
+invalid-request = wayshot sent a request the compositor does not know about, usually a protocol version mismatch. Please report a bug including your compositor and its version
+compositor-out-of-memory = The compositor ran out of memory
+compositor-internal-error = The compositor hit an internal error. This is most likely a compositor bug, check its logs
+feature-unavailable = This build of wayshot was compiled without support for { $feature }
+no-app-windows = No visible windows of { $app_id }
+invalid-window-index = Invalid window number, expected 1 to { $count }
+invalid-stack-count = Invalid number of frames to stack, expected 1 to { $max }
+invalid-stack-mode = Invalid stack mode provided
+stack-outputs-changed = The outputs changed while capturing the frames to stack
+logical-geometry-estimated = The compositor did not report the logical geometry of { $output } over xdg_output, estimating it from its wl_output mode and scale
+logical-geometry-missing = The compositor did not report the geometry of { $output }, it can not be captured
+flash-failed = Failed to flash the captured area: { $error }
+shutter-sound-failed = Failed to play the shutter sound: { $error }
+invalid-frame-size = The compositor described a frame of { $output } whose stride is too short for its width or which is too large for a shared memory buffer: { $width }x{ $height }, stride { $stride }
+invalid-palette-size = Invalid palette size, expected 2 to { $max }
+auto-format-stream = --extension auto picks the encoder from the rendered image and can not be combined with --stream
+invalid-max-duration = Invalid --max-duration, expected a number of milliseconds above 0
+deadline-exceeded = The capture took longer than --max-duration, it ran out of time during { $stage }
+screencopy-sandboxed = Compositors hide zwlr_screencopy_manager_v1 from applications sandboxed by { $sandbox }, run wayshot outside of the sandbox
+invalid-quirk = Unknown quirk: { $quirk }
+detected-compositor = Compositor: { $compositor }
+blank-frame = The captured frame of { $output } has a single color, { $color }, the output may be off or the compositor may have refused the capture
+invalid-port = Invalid --port, expected a number from 1 to 65535
+invalid-fps = Invalid --fps, expected a number of frames per second from 1 to { $max }
+serve-bind-failed = Failed to listen on { $address }: { $error }
+serving = Serving the capture on { $url }
+serve-capture-failed = Failed to capture a frame to serve: { $error }
+invalid-dzi-format = --dzi tiles can only be png or jpg images
+reference-unreadable = Failed to read the image to compare against, { $path }: { $error }
+invalid-diff-tolerance = Invalid --tolerance, expected a number between 0 and 255
+diff-size-mismatch = The image to compare against and the capture have different sizes, { $reference } and { $capture }
+changed-regions =
+    { $count ->
+        [one] One changed region
+       *[other] { $count } changed regions
+    }
+invalid-zoom = Invalid --zoom, expected a factor above 0 and at most { $max }
+invalid-zoom-filter = Invalid zoom filter provided.
+test-pattern-failed = Failed to show and capture the test pattern: { $error }
+test-pattern-deltas = Largest difference per channel on { $output }: red { $red }, green { $green }, blue { $blue }
+test-pattern-mismatch = The capture does not match the test pattern on { $output }
+invalid-force-format = Invalid --force-format provided.
+forced-format-mismatch = The frame of { $output } has fewer than 4 bytes per pixel ({ $format }), it cannot be read as the forced format
+invalid-battery-fps = Invalid --battery-fps, expected a number of frames per second from 0 to { $max }
+serve-on-battery = Running on battery, serving { $fps } frames per second
+serve-on-ac = Plugged in, serving { $fps } frames per second
+invalid-collision = Invalid collision policy provided.
+file-exists = The file { $path } already exists
+ci-shot-spawn-failed = Failed to start { $program }: { $error }
+ci-shot-exited = { $command } exited before the screenshot was taken ({ $status })
+ci-shot-timeout = Gave up waiting for the compositor and application to show up: { $command }
+invalid-ci-timeout = Invalid --timeout, expected a number of milliseconds above 0
+ci-shot-failed = Failed to prepare the headless compositor: { $error }
+invalid-tiff-compression = Invalid tiff compression provided.
+invalid-output-timeout = Invalid --output-timeout, expected a number of milliseconds above 0
+frame-timed-out = The frame of { $output } was not copied within --output-timeout, leaving it out
+no-frame-copied = The frame of no output was copied within --output-timeout
+invalid-gamma = Invalid --gamma, expected a number above 0 and at most { $max }
+invalid-brightness = Invalid --brightness, expected a number from -1 to 1
+invalid-contrast = Invalid --contrast, expected a number from 0 to { $max }
+invalid-crop = Invalid --crop { $crop }, expected NAME=GEOMETRY
+duplicate-crop-name = Several --crop are named { $name }
+encoders = Encoders: { $encoders }
+not-enough-space = Not enough free space for the image in { $directory }, { $needed } bytes are needed and { $available } available, --auto-degrade falls back to smaller files
+encoding-degraded = Not enough free space for the image, writing it as { $encoder }
+toplevels-unavailable = The compositor does not list its windows, fullscreen rules never match
+watch-triggered = Capturing after { $reason }
+watch-capture-failed = Failed to capture the screen for a rule: { $error }
+invalid-redact-style = Invalid redaction style provided.
+invalid-simulation = Invalid color vision deficiency provided.
+invalid-analysis = Invalid analysis provided.
+output-cache-failed = Failed to cache the outputs: { $error }
+invalid-also-save = Invalid extra encoder provided, expected ENCODER=PATH, eg: jpg:80=/tmp/chat.jpg
+invalid-checksum = Invalid checksum provided.
+capture-budget-exceeded = The capture would take { $needed } bytes of memory, more than the { $max } allowed
+capture-command-failed = The { $name } command failed: { $error }
+invalid-hook-timeout = Invalid --hook-timeout, expected a number of milliseconds above 0
+invalid-hook-failure = Invalid hook failure policy provided.
+dnd-unavailable = No notification daemon supporting do-not-disturb is running, notifications may be captured
+dnd-failed = Failed to turn on do-not-disturb: { $error }
+dnd-restore-failed = Failed to turn off do-not-disturb: { $error }
+bug-report-failed = Failed to write the bug report: { $error }
+invalid-tone-map = Invalid tone mapping operator provided.
+invalid-hdr-outputs = Invalid HDR outputs provided, expected auto or output names, eg: DP-1,DP-2
+invalid-max-size = Invalid --max-size, expected a number of bytes with an optional unit, eg: 2MB, 500KiB or 1048576
+quality-lowered = Lowered the jpg quality to { $quality } to fit --max-size
+image-too-large = The image does not fit --max-size even at the lowest quality, { $size } bytes for at most { $max }
+compositor-connection-failed = Could not connect to a Wayland compositor: { $error }
+geometry-unreadable = Failed to read the geometry: { $error }
+cursor-unavailable-with-layout = Can not draw the cursor with --layout
+frame-budget-exceeded = The frame of { $output } would take { $needed } bytes of memory, more than the { $left } left of what is allowed
+region-skipped = The region { $region } does not intersect with any output, skipping it
+command-spawn-failed = Failed to spawn { $program }: { $error }
+command-failed = { $program } exited with { $status }
+redactions-unreadable = Failed to read the redactions { $path }: { $error }
+redactions-invalid = Invalid redactions { $path }: { $error }
+redaction-line-invalid = line { $line }: expected "app_id REGEX" or "title REGEX"
+redaction-regex-invalid = line { $line }: { $error }
+rules-unreadable = Failed to read the rules { $path }: { $error }
+rules-invalid = Invalid rules { $path }: { $error }
+config-unreadable = Failed to read the config { $path }: { $error }
+config-invalid = Invalid config { $path }: { $error }
+config-unknown-option = The config sets { $option }, which is not an option taking a value
+config-value-invalid = The config sets { $option } to something other than a string or a number
+layer-shell-unsupported = The compositor does not support wlr-layer-shell
+flash-output-unnamed = Outputs which report no name can not be flashed
+hooks-unloadable = Failed to load hooks from { $path }: { $error }
+filename-hook-not-string = The filename hook returned { $type } instead of a string
+filename-hook-invalid = The filename hook returned an invalid name { $name }
+post-process-hook-not-image = The post_process hook returned { $type } instead of an image
+hook-failed = The { $hook } hook failed: { $error }
+capture-worker-panicked = The capture of an output failed unexpectedly
+invalid-bench-size = Invalid --size, expected a positive number of MiB
+invalid-bench-runs = Invalid --runs, expected a positive number
+bench-failed = The benchmark failed: { $error }
+bench-write-timing = { $method }: { $bytes } bytes in { $milliseconds } ms, { $throughput } MiB/s
@@ -0,0 +1,160 @@
+# Messages of wayshot in French, one for every variant of Message in src/i18n.rs,
+# named after it in kebab case.
+
+missing-shm = Le compositeur ne fournit pas l'interface wl_shm
+missing-screencopy = Le compositeur ne fournit pas l'interface zwlr_screencopy_manager_v1
+output-without-name = Une sortie n'a pas indiqué son nom
+region-outside-outputs = La région de capture ne croise aucune sortie !
+missing-frame-format = { $output } n'a pas indiqué de format d'image
+frame-copy-failed = La copie de l'image a échoué
+unsupported-buffer-format = Format de tampon non pris en charge : { $format }
+feature-request = Vous pouvez demander la prise en charge de ce format sur la liste de diffusion de wayshot : https://sr.ht/~shinyzenith/wayshot.
+composite-failed = Impossible de copier l'image de { $output } sur le canevas, elle dépasse
+exported-frames =
+    { $count ->
+        [one] { $count } image exportée
+       *[other] { $count } images exportées
+    } vers { $directory }
+geometry-not-received = Aucune géométrie reçue.
+invalid-geometry = Géométrie invalide
+selection-cancelled = Sélection annulée
+no-focused-window = Aucune fenêtre n'a le focus
+unsupported-compositor-ipc = La fenêtre active n'est disponible que sous sway et Hyprland
+invalid-jpeg-subsampling = Sous-échantillonnage jpeg invalide.
+invalid-png-filter = Filtre png invalide.
+invalid-extension = Extension invalide.
+invalid-output-formats = Formats par sortie invalides, attendu par exemple : DP-1=png,HDMI-A-1=jpg:80
+invalid-trim-tolerance = Tolérance de rognage invalide, un nombre entre 0 et 255 est attendu
+invalid-cursor-mode = Mode de curseur invalide.
+invalid-scale = Échelle invalide, un nombre positif ou max est attendu
+invalid-annotations = Annotations invalides : { $error }
+invalid-thumbnail-size = Taille de vignette invalide, par exemple 320x180 est attendu
+cursor-unavailable = Impossible de dessiner le curseur : { $error }
+valid-arguments = Arguments valides :
+invalid-filename-format = Format de nom de fichier invalide, un format strftime tel que { $example } est attendu
+stream-fallback = Les captures doivent être redimensionnées ou l'encodeur ne peut pas écrire ligne par ligne, retour à l'encodage normal
+copy-with-damage-unsupported = Le compositeur ne peut pas attendre de dommages, copie de la dernière image présentée
+compositor-unavailable = Impossible de se connecter à un compositeur Wayland
+compositor-disconnected = Connexion au compositeur perdue : { $error }
+compositor-protocol-error = Le compositeur a signalé une erreur de protocole : { $error }
+frame-already-copied = Une image a été copiée deux fois, c'est un bug de wayshot. Merci de le signaler
+invalid-frame-buffer = Le compositeur a refusé le tampon de capture car sa taille, son pas ou son format ne correspondent pas à sa demande. Cela signifie généralement qu'une sortie a changé de mode ou d'échelle pendant la capture, réessayez
+invalid-shm-format = Le compositeur ne prend pas en charge le format de pixels du tampon de capture. Merci de signaler un bug avec la sortie de wayshot --debug
+invalid-shm-stride = Le compositeur a refusé la taille ou le pas du tampon de capture. Cela signifie généralement qu'une sortie a changé de mode pendant la capture, réessayez
+invalid-shm-fd = Le compositeur n'a pas pu projeter la mémoire partagée du tampon de capture. Vérifiez que memfd_create ou /dev/shm est utilisable et dispose d'espace libre
+invalid-request = wayshot a envoyé une requête inconnue du compositeur, généralement une différence de version du protocole. Merci de signaler un bug en indiquant votre compositeur et sa version
+compositor-out-of-memory = Le compositeur n'a plus de mémoire
+compositor-internal-error = Le compositeur a rencontré une erreur interne. Il s'agit très probablement d'un bug du compositeur, consultez ses journaux
+feature-unavailable = Cette version de wayshot a été compilée sans prise en charge de { $feature }
+no-app-windows = Aucune fenêtre visible de { $app_id }
+invalid-window-index = Numéro de fenêtre invalide, attendu de 1 à { $count }
+invalid-stack-count = Nombre d'images à empiler invalide, attendu de 1 à { $max }
+invalid-stack-mode = Mode d'empilement invalide
+stack-outputs-changed = Les sorties ont changé pendant la capture des images à empiler
+logical-geometry-estimated = Le compositeur n'a pas indiqué la géométrie logique de { $output } via xdg_output, elle est estimée à partir de son mode et de son échelle wl_output
+logical-geometry-missing = Le compositeur n'a pas indiqué la géométrie de { $output }, elle ne peut pas être capturée
+flash-failed = Impossible de faire clignoter la zone capturée : { $error }
+shutter-sound-failed = Impossible de jouer le son d'obturateur : { $error }
+invalid-frame-size = Le compositeur a décrit une image de { $output } dont le pas est trop court pour sa largeur ou qui est trop grande pour un tampon de mémoire partagée : { $width }x{ $height }, pas { $stride }
+invalid-palette-size = Taille de palette invalide, attendu de 2 à { $max }
+auto-format-stream = --extension auto choisit l'encodeur d'après l'image rendue et ne peut pas être combiné avec --stream
+invalid-max-duration = --max-duration invalide, attendu un nombre de millisecondes supérieur à 0
+deadline-exceeded = La capture a dépassé --max-duration, le temps a manqué pendant l'étape { $stage }
+screencopy-sandboxed = Les compositeurs cachent zwlr_screencopy_manager_v1 aux applications isolées par { $sandbox }, lancez wayshot hors du bac à sable
+invalid-quirk = Contournement inconnu : { $quirk }
+detected-compositor = Compositeur : { $compositor }
+blank-frame = L'image capturée de { $output } n'a qu'une seule couleur, { $color }, la sortie est peut-être éteinte ou le compositeur a pu refuser la capture
+invalid-port = --port invalide, attendu un nombre de 1 à 65535
+invalid-fps = --fps invalide, attendu un nombre d'images par seconde de 1 à { $max }
+serve-bind-failed = Impossible d'écouter sur { $address } : { $error }
+serving = Capture servie sur { $url }
+serve-capture-failed = Impossible de capturer une image à servir : { $error }
+invalid-dzi-format = Les tuiles de --dzi ne peuvent être que des images png ou jpg
+reference-unreadable = Impossible de lire l'image de comparaison { $path } : { $error }
+invalid-diff-tolerance = --tolerance invalide, un nombre entre 0 et 255 est attendu
+diff-size-mismatch = L'image de comparaison et la capture n'ont pas la même taille, { $reference } et { $capture }
+changed-regions =
+    { $count ->
+        [one] { $count } zone modifiée
+       *[other] { $count } zones modifiées
+    }
+invalid-zoom = --zoom invalide, attendu un facteur supérieur à 0 et d'au plus { $max }
+invalid-zoom-filter = Filtre de zoom invalide.
+test-pattern-failed = Impossible d'afficher et de capturer la mire : { $error }
+test-pattern-deltas = Plus grand écart par canal sur { $output } : rouge { $red }, vert { $green }, bleu { $blue }
+test-pattern-mismatch = La capture ne correspond pas à la mire sur { $output }
+invalid-force-format = --force-format invalide.
+forced-format-mismatch = L'image de { $output } a moins de 4 octets par pixel ({ $format }), elle ne peut pas être lue dans le format imposé
+invalid-battery-fps = --battery-fps invalide, attendu un nombre d'images par seconde de 0 à { $max }
+serve-on-battery = Sur batterie, { $fps } images par seconde
+serve-on-ac = Sur secteur, { $fps } images par seconde
+invalid-collision = Politique de collision invalide.
+file-exists = Le fichier { $path } existe déjà
+ci-shot-spawn-failed = Impossible de lancer { $program } : { $error }
+ci-shot-exited = { $command } s'est arrêté avant la capture d'écran ({ $status })
+ci-shot-timeout = Abandon de l'attente du compositeur et de l'application : { $command }
+invalid-ci-timeout = --timeout invalide, attendu un nombre de millisecondes supérieur à 0
+ci-shot-failed = Impossible de préparer le compositeur sans écran : { $error }
+invalid-tiff-compression = Compression tiff invalide.
+invalid-output-timeout = --output-timeout invalide, attendu un nombre de millisecondes supérieur à 0
+frame-timed-out = L'image de { $output } n'a pas été copiée avant --output-timeout, elle est laissée de côté
+no-frame-copied = Aucune image de sortie n'a été copiée avant --output-timeout
+invalid-gamma = --gamma invalide, attendu un nombre supérieur à 0 et d'au plus { $max }
+invalid-brightness = --brightness invalide, attendu un nombre de -1 à 1
+invalid-contrast = --contrast invalide, attendu un nombre de 0 à { $max }
+invalid-crop = --crop { $crop } invalide, attendu NOM=GÉOMÉTRIE
+duplicate-crop-name = Plusieurs --crop s'appellent { $name }
+encoders = Encodeurs : { $encoders }
+not-enough-space = Pas assez d'espace libre pour l'image dans { $directory }, { $needed } octets sont nécessaires et { $available } disponibles, --auto-degrade se rabat sur des fichiers plus petits
+encoding-degraded = Pas assez d'espace libre pour l'image, elle est écrite en { $encoder }
+toplevels-unavailable = Le compositeur ne liste pas ses fenêtres, les règles fullscreen ne s'appliquent jamais
+watch-triggered = Capture après { $reason }
+watch-capture-failed = Impossible de capturer l'écran pour une règle : { $error }
+invalid-redact-style = Style de masquage invalide.
+invalid-simulation = Déficience de la vision des couleurs invalide.
+invalid-analysis = Analyse invalide.
+output-cache-failed = Impossible de mettre les sorties en cache : { $error }
+invalid-also-save = Encodeur supplémentaire invalide, ENCODEUR=CHEMIN attendu, par exemple : jpg:80=/tmp/chat.jpg
+invalid-checksum = Somme de contrôle invalide.
+capture-budget-exceeded = La capture prendrait { $needed } octets de mémoire, plus que les { $max } permis
+capture-command-failed = Échec de la commande { $name } : { $error }
+invalid-hook-timeout = --hook-timeout invalide, attendu un nombre de millisecondes supérieur à 0
+invalid-hook-failure = Politique d'échec des commandes invalide.
+dnd-unavailable = Aucun démon de notifications gérant le mode ne pas déranger n'est lancé, les notifications peuvent être capturées
+dnd-failed = Échec de l'activation du mode ne pas déranger : { $error }
+dnd-restore-failed = Échec de la désactivation du mode ne pas déranger : { $error }
+bug-report-failed = Échec de l'écriture du rapport de bogue : { $error }
+invalid-tone-map = Opérateur de mappage tonal invalide.
+invalid-hdr-outputs = Sorties HDR invalides, auto ou des noms de sorties attendus, par exemple : DP-1,DP-2
+invalid-max-size = --max-size invalide, attendu un nombre d'octets avec une unité facultative, par exemple : 2MB, 500KiB ou 1048576
+quality-lowered = Qualité jpg réduite à { $quality } pour respecter --max-size
+image-too-large = L'image dépasse --max-size même à la qualité la plus basse, { $size } octets pour au plus { $max }
+compositor-connection-failed = Impossible de se connecter à un compositeur Wayland : { $error }
+geometry-unreadable = Impossible de lire la géométrie : { $error }
+cursor-unavailable-with-layout = Impossible de dessiner le curseur avec --layout
+frame-budget-exceeded = L'image de { $output } prendrait { $needed } octets de mémoire, plus que les { $left } restants sur ce qui est permis
+region-skipped = La région { $region } ne croise aucune sortie, elle est ignorée
+command-spawn-failed = Impossible de lancer { $program } : { $error }
+command-failed = { $program } s'est terminé avec { $status }
+redactions-unreadable = Impossible de lire les masquages { $path } : { $error }
+redactions-invalid = Masquages { $path } invalides : { $error }
+redaction-line-invalid = ligne { $line } : « app_id REGEX » ou « title REGEX » attendu
+redaction-regex-invalid = ligne { $line } : { $error }
+rules-unreadable = Impossible de lire les règles { $path } : { $error }
+rules-invalid = Règles { $path } invalides : { $error }
+config-unreadable = Impossible de lire la configuration { $path } : { $error }
+config-invalid = Configuration { $path } invalide : { $error }
+config-unknown-option = La configuration définit { $option }, qui n'est pas une option prenant une valeur
+config-value-invalid = La configuration donne à { $option } autre chose qu'une chaîne ou un nombre
+layer-shell-unsupported = Le compositeur ne prend pas en charge wlr-layer-shell
+flash-output-unnamed = Les sorties qui n'indiquent pas leur nom ne peuvent pas clignoter
+hooks-unloadable = Impossible de charger les hooks depuis { $path } : { $error }
+filename-hook-not-string = Le hook filename a renvoyé { $type } au lieu d'une chaîne
+filename-hook-invalid = Le hook filename a renvoyé un nom invalide { $name }
+post-process-hook-not-image = Le hook post_process a renvoyé { $type } au lieu d'une image
+hook-failed = Le hook { $hook } a échoué : { $error }
+capture-worker-panicked = La capture d'une sortie a échoué de façon inattendue
+invalid-bench-size = --size invalide, un nombre positif de Mio est attendu
+invalid-bench-runs = --runs invalide, un nombre positif est attendu
+bench-failed = Le banc d'essai a échoué : { $error }
+bench-write-timing = { $method } : { $bytes } octets en { $milliseconds } ms, { $throughput } Mio/s
@@ -0,0 +1,239 @@
+use std::{error::Error, io::Write};
+
+use flate2::{write::ZlibEncoder, Compression};
+use image::RgbaImage;
+
+use crate::backend::{EncodingOptions, PngFilter};
+
+const PNG_SIGNATURE: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
+
+/// Bytes per pixel of Rgba8 images.
+const BYTES_PER_PIXEL: usize = 4;
+
+/// Largest IDAT chunk we emit, the image data is split over several chunks above that.
+const MAX_IDAT_LENGTH: usize = 1 << 20;
+
+/// Gamma of sRGB as stored in the gAMA chunk (1/2.2 scaled by 100000).
+const SRGB_GAMMA: u32 = 45455;
+
+/// White point and red, green, blue primaries of sRGB as stored in the cHRM chunk.
+const SRGB_CHROMATICITIES: [u32; 8] = [31270, 32900, 64000, 33000, 30000, 60000, 15000, 6000];
+
+/// Starting column, starting row, column step and row step of the seven Adam7 passes.
+const ADAM7_PASSES: [(u32, u32, u32, u32); 7] = [
+    (0, 0, 8, 8),
+    (4, 0, 8, 8),
+    (0, 4, 4, 8),
+    (2, 0, 4, 4),
+    (0, 2, 2, 4),
+    (1, 0, 2, 2),
+    (0, 1, 1, 2),
+];
+
+/// Write `image` as an Adam7 interlaced png. The png crate can only write non-interlaced
+/// images, so the chunks are assembled here instead.
+pub fn write_interlaced_png(
+    mut writer: impl Write,
+    image: &RgbaImage,
+    options: &EncodingOptions,
+) -> Result<(), Box<dyn Error>> {
+    writer.write_all(&PNG_SIGNATURE)?;
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&image.width().to_be_bytes());
+    header.extend_from_slice(&image.height().to_be_bytes());
+    // Bit depth 8, color type 6 (Rgba), deflate compression, adaptive filtering, Adam7.
+    header.extend_from_slice(&[8, 6, 0, 0, 1]);
+    write_chunk(&mut writer, b"IHDR", &header)?;
+
+    if options.png_srgb {
+        write_chunk(&mut writer, b"sRGB", &[0])?;
+        write_chunk(&mut writer, b"gAMA", &SRGB_GAMMA.to_be_bytes())?;
+        let chromaticities: Vec<u8> = SRGB_CHROMATICITIES
+            .iter()
+            .flat_map(|value| value.to_be_bytes())
+            .collect();
+        write_chunk(&mut writer, b"cHRM", &chromaticities)?;
+    }
+
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::fast());
+    for (x_start, y_start, x_step, y_step) in ADAM7_PASSES {
+        let pass_width = pass_length(image.width(), x_start, x_step);
+        let pass_height = pass_length(image.height(), y_start, y_step);
+        if pass_width == 0 || pass_height == 0 {
+            continue;
+        }
+
+        let row_length = pass_width as usize * BYTES_PER_PIXEL;
+        let mut previous = vec![0u8; row_length];
+        let mut current = Vec::with_capacity(row_length);
+        let mut filtered = Vec::with_capacity(row_length + 1);
+        for pass_y in 0..pass_height {
+            let y = y_start + pass_y * y_step;
+            current.clear();
+            for pass_x in 0..pass_width {
+                let x = x_start + pass_x * x_step;
+                current.extend_from_slice(&image.get_pixel(x, y).0);
+            }
+
+            filter_row(options.png_filter, &current, &previous, &mut filtered);
+            encoder.write_all(&filtered)?;
+            std::mem::swap(&mut previous, &mut current);
+        }
+    }
+
+    let data = encoder.finish()?;
+    for chunk in data.chunks(MAX_IDAT_LENGTH) {
+        write_chunk(&mut writer, b"IDAT", chunk)?;
+    }
+    write_chunk(&mut writer, b"IEND", &[])?;
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// Number of pixels of a `length` long line which belong to a pass.
+fn pass_length(length: u32, start: u32, step: u32) -> u32 {
+    if length > start {
+        (length - start).div_ceil(step)
+    } else {
+        0
+    }
+}
+
+fn write_chunk(writer: &mut impl Write, kind: &[u8; 4], data: &[u8]) -> std::io::Result<()> {
+    let mut crc = crc32fast::Hasher::new();
+    crc.update(kind);
+    crc.update(data);
+
+    writer.write_all(&(data.len() as u32).to_be_bytes())?;
+    writer.write_all(kind)?;
+    writer.write_all(data)?;
+    writer.write_all(&crc.finalize().to_be_bytes())
+}
+
+/// Filter a scanline into `out`, prefixed by the filter type byte. The adaptive strategy picks
+/// the filter with the smallest sum of absolute differences, like libpng does.
+fn filter_row(filter: PngFilter, row: &[u8], previous: &[u8], out: &mut Vec<u8>) {
+    let filter_type = match filter {
+        PngFilter::NoFilter => 0,
+        PngFilter::Sub => 1,
+        PngFilter::Up => 2,
+        PngFilter::Avg => 3,
+        PngFilter::Paeth => 4,
+        PngFilter::Adaptive => (0..=4)
+            .min_by_key(|&filter_type| {
+                apply_filter(filter_type, row, previous, out);
+                out[1..]
+                    .iter()
+                    .map(|&byte| (byte as i8).unsigned_abs() as u64)
+                    .sum::<u64>()
+            })
+            .unwrap(),
+    };
+    apply_filter(filter_type, row, previous, out);
+}
+
+fn apply_filter(filter_type: u8, row: &[u8], previous: &[u8], out: &mut Vec<u8>) {
+    out.clear();
+    out.push(filter_type);
+    for i in 0..row.len() {
+        let left = if i >= BYTES_PER_PIXEL {
+            row[i - BYTES_PER_PIXEL]
+        } else {
+            0
+        };
+        let up = previous[i];
+        let up_left = if i >= BYTES_PER_PIXEL {
+            previous[i - BYTES_PER_PIXEL]
+        } else {
+            0
+        };
+        let predictor = match filter_type {
+            0 => 0,
+            1 => left,
+            2 => up,
+            3 => ((left as u16 + up as u16) / 2) as u8,
+            _ => paeth(left, up, up_left),
+        };
+        out.push(row[i].wrapping_sub(predictor));
+    }
+}
+
+fn paeth(left: u8, up: u8, up_left: u8) -> u8 {
+    let estimate = left as i16 + up as i16 - up_left as i16;
+    let distance_left = (estimate - left as i16).abs();
+    let distance_up = (estimate - up as i16).abs();
+    let distance_up_left = (estimate - up_left as i16).abs();
+    if distance_left <= distance_up && distance_left <= distance_up_left {
+        left
+    } else if distance_up <= distance_up_left {
+        up
+    } else {
+        up_left
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A pattern which gives neighbouring pixels, and pixels of different passes, different
+    /// values on every channel.
+    fn pattern(width: u32, height: u32) -> RgbaImage {
+        RgbaImage::from_fn(width, height, |x, y| {
+            image::Rgba([
+                (x * 37 + y * 11) as u8,
+                (x * 5) as u8 ^ (y * 91) as u8,
+                (x * y * 7 + 3) as u8,
+                255 - (x + y * width) as u8,
+            ])
+        })
+    }
+
+    #[test]
+    fn interlaced_images_decode_to_the_same_pixels() {
+        let filters = [
+            PngFilter::NoFilter,
+            PngFilter::Sub,
+            PngFilter::Up,
+            PngFilter::Avg,
+            PngFilter::Paeth,
+            PngFilter::Adaptive,
+        ];
+        for (width, height) in [(1, 1), (3, 5), (9, 9)] {
+            let image = pattern(width, height);
+            for png_filter in filters {
+                let options = EncodingOptions {
+                    png_filter,
+                    ..Default::default()
+                };
+                let mut encoded = Vec::new();
+                write_interlaced_png(&mut encoded, &image, &options).unwrap();
+
+                let mut reader = png::Decoder::new(encoded.as_slice()).read_info().unwrap();
+                let info = reader.info();
+                assert!(info.interlaced, "{}x{}", width, height);
+                assert_eq!((info.width, info.height), (width, height));
+                assert_eq!(info.color_type, png::ColorType::Rgba);
+                assert_eq!(info.bit_depth, png::BitDepth::Eight);
+
+                let mut decoded = vec![0; reader.output_buffer_size()];
+                reader.next_frame(&mut decoded).unwrap();
+                for (x, y, pixel) in image.enumerate_pixels() {
+                    let offset = (y * width + x) as usize * BYTES_PER_PIXEL;
+                    assert_eq!(
+                        decoded[offset..offset + BYTES_PER_PIXEL],
+                        pixel.0,
+                        "pixel {},{} of {}x{} with {:?}",
+                        x,
+                        y,
+                        width,
+                        height,
+                        png_filter
+                    );
+                }
+            }
+        }
+    }
+}
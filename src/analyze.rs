@@ -0,0 +1,337 @@
+use std::{collections::HashSet, fmt::Write};
+
+use image::RgbaImage;
+
+use crate::{
+    convert::create_converter, EncodingFormat, EncodingOptions, RawCapture, MAX_PALETTE_COLORS,
+};
+
+/// Share of horizontally neighbouring pixels with the same color above which an image is
+/// considered a screenshot of an interface rather than photographic content, eg: a video or
+/// a game. Text and flat widgets repeat colors almost everywhere, camera and rendered content
+/// hardly ever does.
+const FLAT_THRESHOLD: f64 = 0.5;
+
+/// Quality of jpeg images picked for photographic content, high enough for artifacts to stay
+/// unnoticeable on screen.
+const AUTO_JPEG_QUALITY: u8 = 90;
+
+/// Rows sampled by [`analyze`] on large images, evenly spread over their height.
+const SAMPLED_ROWS: u32 = 256;
+
+/// Colors in the palette of --analyze palette.
+pub const DOMINANT_COLORS: usize = 8;
+
+/// Pixels [`dominant_colors`] clusters at most, larger images are sampled evenly.
+const CLUSTER_SAMPLES: usize = 65536;
+
+/// Most rounds of k-means run by [`dominant_colors`], screenshots settle well before.
+const CLUSTER_ROUNDS: usize = 16;
+
+/// What [`choose_encoding`] looks at in an image.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ContentStats {
+    /// Number of distinct colors, or None if there are more than [`MAX_PALETTE_COLORS`].
+    pub colors: Option<usize>,
+    /// Whether every pixel is fully opaque.
+    pub opaque: bool,
+    /// Share of sampled pixels with the same color as their left neighbour, from 0 to 1.
+    pub flat: f64,
+}
+
+/// Measure the content of `image`. Colors and opacity are checked on every pixel, stopping
+/// early once there are too many colors to count, flatness on a sample of rows.
+pub fn analyze(image: &RgbaImage) -> ContentStats {
+    let mut colors = HashSet::new();
+    let mut opaque = true;
+    for pixel in image.pixels() {
+        opaque &= pixel.0[3] == u8::MAX;
+        if colors.len() <= MAX_PALETTE_COLORS as usize {
+            colors.insert(pixel.0);
+        } else if !opaque {
+            break;
+        }
+    }
+
+    let step = (image.height() / SAMPLED_ROWS).max(1);
+    let (mut same, mut total) = (0u64, 0u64);
+    for y in (0..image.height()).step_by(step as usize) {
+        for x in 1..image.width() {
+            same += (image.get_pixel(x, y) == image.get_pixel(x - 1, y)) as u64;
+            total += 1;
+        }
+    }
+
+    ContentStats {
+        colors: Some(colors.len()).filter(|&count| count <= MAX_PALETTE_COLORS as usize),
+        opaque,
+        flat: if total == 0 {
+            1.0
+        } else {
+            same as f64 / total as f64
+        },
+    }
+}
+
+/// Pick the encoder writing `image` best: a lossless indexed png when it has few colors, a jpeg
+/// for opaque photographic content and a regular png otherwise. Options which are not picked
+/// are taken from `base_options`.
+pub fn choose_encoding(
+    image: &RgbaImage,
+    base_options: EncodingOptions,
+) -> (EncodingFormat, EncodingOptions) {
+    let stats = analyze(image);
+    tracing::debug!("Content of the image: {:?}", stats);
+    let mut options = base_options;
+    if let Some(colors) = stats.colors {
+        options.png_palette = Some((colors as u16).max(2));
+        (EncodingFormat::Png, options)
+    } else if stats.opaque && stats.flat < FLAT_THRESHOLD && EncodingFormat::Jpg.is_available() {
+        options.jpeg_quality = AUTO_JPEG_QUALITY;
+        (EncodingFormat::Jpg, options)
+    } else {
+        (EncodingFormat::Png, options)
+    }
+}
+
+/// Color of every pixel of `capture` as Rgba8, or None if it has more than one. Blank frames,
+/// eg: all black or transparent, usually mean the output is off or the compositor refused the
+/// capture. Padding at the end of rows is ignored.
+pub fn uniform_color(capture: &RawCapture) -> Option<[u8; 4]> {
+    let format = capture.frame_format;
+    let row_bytes = format.width as usize * 4;
+    let mut rows = capture
+        .frame_mmap
+        .chunks(format.stride as usize)
+        .take(format.height as usize)
+        .map(|row| &row[..row_bytes.min(row.len())]);
+    let first = rows.next()?;
+    let pixel = first.get(..4)?;
+    let uniform = first.chunks_exact(4).all(|other| other == pixel)
+        && rows.all(|row| row.chunks_exact(4).all(|other| other == pixel));
+    if !uniform {
+        return None;
+    }
+    let mut color = [pixel[0], pixel[1], pixel[2], pixel[3]];
+    create_converter(format.format)?.convert_inplace(&mut color);
+    Some(color)
+}
+
+/// Measurements of the saved images --analyze prints.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Analysis {
+    /// The average color and the [`DOMINANT_COLORS`] dominant colors, eg: for theming tools.
+    Palette,
+    /// Histograms, mean luminance and clipping, see [`ChannelStats`], eg: for calibration.
+    Stats,
+}
+
+/// Fields of the JSON object --analyze prints for `image`, without the braces around them, eg:
+/// `"average": "#1d1f21", "palette": [{"color": "#1d1f21", "share": 0.8125}, ...]`.
+pub fn analysis_fields(image: &RgbaImage, analyses: &[Analysis]) -> String {
+    let mut fields = Vec::new();
+    for analysis in analyses {
+        match analysis {
+            Analysis::Palette => {
+                fields.push(format!(
+                    "\"average\": \"{}\"",
+                    hex_color(average_color(image))
+                ));
+                let mut palette = String::from("\"palette\": [");
+                for (index, (color, share)) in dominant_colors(image, DOMINANT_COLORS)
+                    .into_iter()
+                    .enumerate()
+                {
+                    let separator = if index == 0 { "" } else { ", " };
+                    let _ = write!(
+                        palette,
+                        "{}{{\"color\": \"{}\", \"share\": {:.4}}}",
+                        separator,
+                        hex_color(color),
+                        share
+                    );
+                }
+                palette.push(']');
+                fields.push(palette);
+            }
+            Analysis::Stats => {
+                let stats = channel_stats(image);
+                let mut histograms = String::from("\"histogram\": {");
+                let mut clipping = String::from("\"clipping\": {");
+                for (index, name) in ["r", "g", "b"].iter().enumerate() {
+                    let separator = if index == 0 { "" } else { ", " };
+                    let counts: Vec<String> =
+                        stats.histograms[index].iter().map(u64::to_string).collect();
+                    let _ = write!(
+                        histograms,
+                        "{}\"{}\": [{}]",
+                        separator,
+                        name,
+                        counts.join(", ")
+                    );
+                    let (black, white) = stats.clipping(index);
+                    let _ = write!(
+                        clipping,
+                        "{}\"{}\": {{\"black\": {:.4}, \"white\": {:.4}}}",
+                        separator, name, black, white
+                    );
+                }
+                histograms.push('}');
+                clipping.push('}');
+                fields.push(histograms);
+                fields.push(format!("\"mean_luminance\": {:.4}", stats.mean_luminance));
+                fields.push(clipping);
+            }
+        }
+    }
+    fields.join(", ")
+}
+
+/// Statistics of the color channels of an image, measured by [`channel_stats`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChannelStats {
+    /// Number of pixels with each value of the red, green and blue channels.
+    pub histograms: [[u64; 256]; 3],
+    /// Mean Rec. 709 luma of the pixels, from 0 to 255, computed on the sRGB encoded values.
+    pub mean_luminance: f64,
+}
+
+impl ChannelStats {
+    /// Percentages of pixels whose channel `channel`, 0 to 2 for red to blue, is clipped to
+    /// black and to white, ie: 0 and 255.
+    pub fn clipping(&self, channel: usize) -> (f64, f64) {
+        let histogram = &self.histograms[channel];
+        let pixels = histogram.iter().sum::<u64>().max(1) as f64;
+        (
+            histogram[0] as f64 / pixels * 100.0,
+            histogram[255] as f64 / pixels * 100.0,
+        )
+    }
+}
+
+/// Histograms and mean luminance of every pixel of `image`. Alpha is ignored.
+pub fn channel_stats(image: &RgbaImage) -> ChannelStats {
+    let mut histograms = [[0u64; 256]; 3];
+    let mut luminance = 0.0;
+    for pixel in image.pixels() {
+        let [r, g, b, _] = pixel.0;
+        histograms[0][r as usize] += 1;
+        histograms[1][g as usize] += 1;
+        histograms[2][b as usize] += 1;
+        luminance += 0.2126 * r as f64 + 0.7152 * g as f64 + 0.0722 * b as f64;
+    }
+    let pixels = (image.width() as u64 * image.height() as u64).max(1);
+    ChannelStats {
+        histograms,
+        mean_luminance: luminance / pixels as f64,
+    }
+}
+
+/// `color` as a CSS hex color, eg: #1d1f21.
+pub fn hex_color([r, g, b]: [u8; 3]) -> String {
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+
+/// Mean of the color channels of every pixel of `image`, black for empty images. Alpha is
+/// ignored.
+pub fn average_color(image: &RgbaImage) -> [u8; 3] {
+    let mut sums = [0u64; 3];
+    for pixel in image.pixels() {
+        for (sum, &channel) in sums.iter_mut().zip(&pixel.0[..3]) {
+            *sum += channel as u64;
+        }
+    }
+    let pixels = (image.width() as u64 * image.height() as u64).max(1);
+    sums.map(|sum| ((sum + pixels / 2) / pixels) as u8)
+}
+
+/// Up to `count` colors most of `image` is made of, along with the share of the sampled pixels
+/// closest to each, most common first. Found with k-means seeded by farthest point sampling,
+/// so the same image always gets the same palette. Images with fewer colors get fewer.
+pub fn dominant_colors(image: &RgbaImage, count: usize) -> Vec<([u8; 3], f64)> {
+    let pixels = image.width() as usize * image.height() as usize;
+    let step = pixels.div_ceil(CLUSTER_SAMPLES).max(1);
+    let samples: Vec<[f64; 3]> = image
+        .pixels()
+        .step_by(step)
+        .map(|pixel| [0, 1, 2].map(|channel| pixel.0[channel] as f64))
+        .collect();
+    if samples.is_empty() || count == 0 {
+        return Vec::new();
+    }
+    let distance = |a: &[f64; 3], b: &[f64; 3]| {
+        (0..3)
+            .map(|channel| (a[channel] - b[channel]).powi(2))
+            .sum::<f64>()
+    };
+    let nearest = |centers: &[[f64; 3]], sample: &[f64; 3]| {
+        (0..centers.len())
+            .min_by(|&a, &b| {
+                distance(&centers[a], sample).total_cmp(&distance(&centers[b], sample))
+            })
+            .unwrap()
+    };
+
+    let mut centers = vec![samples[0]];
+    while centers.len() < count {
+        let (farthest, gap) = samples
+            .iter()
+            .map(|sample| {
+                let gap = centers
+                    .iter()
+                    .map(|center| distance(center, sample))
+                    .fold(f64::INFINITY, f64::min);
+                (sample, gap)
+            })
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .unwrap();
+        if gap == 0.0 {
+            break;
+        }
+        centers.push(*farthest);
+    }
+
+    let mut assignments = vec![usize::MAX; samples.len()];
+    for _ in 0..CLUSTER_ROUNDS {
+        let mut changed = false;
+        for (sample, assignment) in samples.iter().zip(&mut assignments) {
+            let cluster = nearest(&centers, sample);
+            changed |= cluster != *assignment;
+            *assignment = cluster;
+        }
+        if !changed {
+            break;
+        }
+        let mut sums = vec![([0.0; 3], 0usize); centers.len()];
+        for (sample, &cluster) in samples.iter().zip(&assignments) {
+            let (sum, members) = &mut sums[cluster];
+            for (sum, channel) in sum.iter_mut().zip(sample) {
+                *sum += channel;
+            }
+            *members += 1;
+        }
+        for (center, (sum, members)) in centers.iter_mut().zip(sums) {
+            if members > 0 {
+                *center = sum.map(|sum| sum / members as f64);
+            }
+        }
+    }
+
+    let mut members = vec![0usize; centers.len()];
+    for &cluster in &assignments {
+        members[cluster] += 1;
+    }
+    let mut palette: Vec<([u8; 3], f64)> = centers
+        .iter()
+        .zip(members)
+        .filter(|(_, members)| *members > 0)
+        .map(|(center, members)| {
+            (
+                center.map(|channel| channel.round() as u8),
+                members as f64 / samples.len() as f64,
+            )
+        })
+        .collect();
+    palette.sort_by(|a, b| b.1.total_cmp(&a.1));
+    palette
+}
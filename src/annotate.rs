@@ -0,0 +1,271 @@
+use std::{error::Error, f64::consts::FRAC_PI_6};
+
+use font8x8::{UnicodeFonts, BASIC_FONTS, LATIN_FONTS};
+use image::{Pixel, Rgba, RgbaImage};
+
+/// Side length in pixels of a glyph of the built in bitmap font, before scaling.
+const GLYPH_SIZE: u32 = 8;
+
+/// A shape to draw onto a screenshot, in image pixel coordinates.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Shape {
+    Rect {
+        x: i32,
+        y: i32,
+        width: i32,
+        height: i32,
+    },
+    Arrow {
+        x1: i32,
+        y1: i32,
+        x2: i32,
+        y2: i32,
+    },
+    Text {
+        x: i32,
+        y: i32,
+        text: String,
+    },
+}
+
+/// A shape along with the color and size it is drawn with. The size is the line thickness of
+/// rectangles and arrows and the scale of the 8x8 bitmap font for text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Annotation {
+    pub shape: Shape,
+    pub color: Rgba<u8>,
+    pub size: u32,
+}
+
+/// Parse annotations of the form `kind:coordinates[:color[:size]]`, separated by semicolons.
+/// eg: `rect:10,10,200,100:red:3; arrow:50,50,200,200:yellow; text:20,250:'Step 1'`.
+/// Text is quoted with single or double quotes when it contains separators.
+pub fn parse_annotations(spec: &str) -> Result<Vec<Annotation>, Box<dyn Error>> {
+    split_unquoted(spec, ';')
+        .iter()
+        .map(|command| command.trim())
+        .filter(|command| !command.is_empty())
+        .map(parse_annotation)
+        .collect()
+}
+
+fn parse_annotation(command: &str) -> Result<Annotation, Box<dyn Error>> {
+    let fields = split_unquoted(command, ':');
+    let kind = fields[0].trim();
+    let arguments = fields
+        .get(1)
+        .ok_or("Annotation is missing its coordinates")?;
+
+    // Color and size follow the coordinates, or the string for text annotations.
+    let (shape, color_field, default_size) = match kind {
+        "rect" => {
+            let [x, y, width, height] = parse_numbers(arguments)?;
+            let shape = Shape::Rect {
+                x,
+                y,
+                width,
+                height,
+            };
+            (shape, 2, 3)
+        }
+        "arrow" => {
+            let [x1, y1, x2, y2] = parse_numbers(arguments)?;
+            (Shape::Arrow { x1, y1, x2, y2 }, 2, 3)
+        }
+        "text" => {
+            let [x, y] = parse_numbers(arguments)?;
+            let text = fields.get(2).ok_or("Text annotation is missing its text")?;
+            let text = unquote(text.trim()).to_string();
+            (Shape::Text { x, y, text }, 3, 2)
+        }
+        _ => return Err(format!("Unknown annotation {:?}", kind).into()),
+    };
+
+    let color = match fields.get(color_field).map(|color| color.trim()) {
+        Some(color) if !color.is_empty() => parse_color(color)?,
+        _ => Rgba([255, 0, 0, 255]),
+    };
+    let size = match fields.get(color_field + 1) {
+        Some(size) => size.trim().parse()?,
+        None => default_size,
+    };
+    Ok(Annotation { shape, color, size })
+}
+
+/// Split `value` at `separator`, ignoring separators inside single or double quotes.
+fn split_unquoted(value: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut quote = None;
+    let mut start = 0;
+    for (i, c) in value.char_indices() {
+        match (quote, c) {
+            (None, '\'' | '"') => quote = Some(c),
+            (Some(open), _) if c == open => quote = None,
+            (None, _) if c == separator => {
+                parts.push(&value[start..i]);
+                start = i + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    parts.push(&value[start..]);
+    parts
+}
+
+fn unquote(value: &str) -> &str {
+    for quote in ['\'', '"'] {
+        if let Some(inner) = value
+            .strip_prefix(quote)
+            .and_then(|value| value.strip_suffix(quote))
+        {
+            return inner;
+        }
+    }
+    value
+}
+
+fn parse_numbers<const N: usize>(value: &str) -> Result<[i32; N], Box<dyn Error>> {
+    let numbers = value
+        .split(',')
+        .map(|number| number.trim().parse::<i32>())
+        .collect::<Result<Vec<_>, _>>()?;
+    numbers
+        .try_into()
+        .map_err(|_| format!("Expected {} comma separated numbers in {:?}", N, value).into())
+}
+
+/// Parse a color name or a `#rrggbb` / `#rrggbbaa` hex color.
+pub fn parse_color(value: &str) -> Result<Rgba<u8>, Box<dyn Error>> {
+    let color = match value.to_lowercase().as_str() {
+        "red" => [255, 0, 0, 255],
+        "green" => [0, 200, 0, 255],
+        "blue" => [0, 80, 255, 255],
+        "yellow" => [255, 220, 0, 255],
+        "orange" => [255, 140, 0, 255],
+        "magenta" | "purple" => [200, 0, 200, 255],
+        "cyan" => [0, 200, 220, 255],
+        "black" => [0, 0, 0, 255],
+        "white" => [255, 255, 255, 255],
+        hex => {
+            let digits = hex
+                .strip_prefix('#')
+                .filter(|digits| digits.len() == 6 || digits.len() == 8)
+                .ok_or_else(|| format!("Unknown color {:?}", value))?;
+            let channel = |i: usize| u8::from_str_radix(&digits[i..i + 2], 16);
+            let alpha = if digits.len() == 8 { channel(6)? } else { 255 };
+            [channel(0)?, channel(2)?, channel(4)?, alpha]
+        }
+    };
+    Ok(Rgba(color))
+}
+
+/// Draw `annotations` onto `image` in order.
+pub fn draw_annotations(image: &mut RgbaImage, annotations: &[Annotation]) {
+    for annotation in annotations {
+        let color = annotation.color;
+        let size = annotation.size.max(1) as i32;
+        match &annotation.shape {
+            Shape::Rect {
+                x,
+                y,
+                width,
+                height,
+            } => {
+                // The bands do not overlap, so translucent borders are blended once.
+                let band = size.min(width / 2).min(height / 2).max(1);
+                let inner_height = height - 2 * band;
+                fill_rect(image, *x, *y, *width, band, color);
+                fill_rect(image, *x, y + height - band, *width, band, color);
+                fill_rect(image, *x, y + band, band, inner_height, color);
+                fill_rect(image, x + width - band, y + band, band, inner_height, color);
+            }
+            Shape::Arrow { x1, y1, x2, y2 } => {
+                draw_line(image, *x1, *y1, *x2, *y2, size, color);
+                // Head lines at 30 degrees from the shaft.
+                let angle = ((y1 - y2) as f64).atan2((x1 - x2) as f64);
+                let head_length = (size * 5).max(12) as f64;
+                for offset in [-FRAC_PI_6, FRAC_PI_6] {
+                    let head_x = *x2 + (head_length * (angle + offset).cos()).round() as i32;
+                    let head_y = *y2 + (head_length * (angle + offset).sin()).round() as i32;
+                    draw_line(image, *x2, *y2, head_x, head_y, size, color);
+                }
+            }
+            Shape::Text { x, y, text } => draw_text(image, *x, *y, text, size, color),
+        }
+    }
+}
+
+fn blend_pixel(image: &mut RgbaImage, x: i32, y: i32, color: Rgba<u8>) {
+    if x >= 0 && y >= 0 && (x as u32) < image.width() && (y as u32) < image.height() {
+        image.get_pixel_mut(x as u32, y as u32).blend(&color);
+    }
+}
+
+fn fill_rect(image: &mut RgbaImage, x: i32, y: i32, width: i32, height: i32, color: Rgba<u8>) {
+    for py in y..y + height {
+        for px in x..x + width {
+            blend_pixel(image, px, py, color);
+        }
+    }
+}
+
+/// Bresenham line, stamping a `thickness` wide square at every step. Translucent colors get
+/// blended more than once where stamps overlap, which is fine for annotations.
+fn draw_line(
+    image: &mut RgbaImage,
+    x1: i32,
+    y1: i32,
+    x2: i32,
+    y2: i32,
+    thickness: i32,
+    color: Rgba<u8>,
+) {
+    let (dx, dy) = ((x2 - x1).abs(), -(y2 - y1).abs());
+    let (step_x, step_y) = ((x2 - x1).signum(), (y2 - y1).signum());
+    let (mut x, mut y, mut error) = (x1, y1, dx + dy);
+    let half = thickness / 2;
+    loop {
+        fill_rect(image, x - half, y - half, thickness, thickness, color);
+        if x == x2 && y == y2 {
+            break;
+        }
+        let doubled = 2 * error;
+        if doubled >= dy {
+            error += dy;
+            x += step_x;
+        }
+        if doubled <= dx {
+            error += dx;
+            y += step_y;
+        }
+    }
+}
+
+fn draw_text(image: &mut RgbaImage, x: i32, y: i32, text: &str, scale: i32, color: Rgba<u8>) {
+    let advance = GLYPH_SIZE as i32 * scale;
+    for (line_index, line) in text.lines().enumerate() {
+        let line_y = y + line_index as i32 * advance;
+        for (index, c) in line.chars().enumerate() {
+            let glyph = BASIC_FONTS
+                .get(c)
+                .or_else(|| LATIN_FONTS.get(c))
+                .or_else(|| BASIC_FONTS.get('?'))
+                .unwrap_or_default();
+            let glyph_x = x + index as i32 * advance;
+            for (row, bits) in glyph.iter().enumerate() {
+                for column in 0..GLYPH_SIZE as i32 {
+                    if bits & (1 << column) != 0 {
+                        fill_rect(
+                            image,
+                            glyph_x + column * scale,
+                            line_y + row as i32 * scale,
+                            scale,
+                            scale,
+                            color,
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,101 @@
+use std::error::Error;
+
+use image::{Rgba, RgbaImage};
+
+use crate::{
+    annotate::{draw_annotations, Annotation, Shape},
+    postprocess::{self, RedactStyle},
+    Placement, PostProcessor, Region,
+};
+
+/// Callback registered with [`Annotator::register`], drawing onto an image before it is
+/// encoded. The placement tells where the image and the outputs it shows lie, see
+/// [`Placement::to_pixels`] to draw over one of them.
+pub type DrawCallback<'a> =
+    Box<dyn Fn(&mut RgbaImage, &Placement) -> Result<(), Box<dyn Error>> + 'a>;
+
+/// Runs the draw callbacks of embedders on every image of a capture, eg: for a bar to brand its
+/// screenshots or a tool to mark what it found. Added to a pipeline with
+/// [`crate::PipelineBuilder::post_process`] like any other post processor, so callbacks see
+/// the image as the steps added before it left it. [`draw_rect`], [`draw_text`] and [`blur`]
+/// cover the usual marks.
+#[derive(Default)]
+pub struct Annotator<'a> {
+    callbacks: Vec<DrawCallback<'a>>,
+}
+
+impl<'a> Annotator<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a draw callback, callbacks run in the order they are registered and the first
+    /// failing one fails the capture.
+    pub fn register(
+        mut self,
+        callback: impl Fn(&mut RgbaImage, &Placement) -> Result<(), Box<dyn Error>> + 'a,
+    ) -> Self {
+        self.callbacks.push(Box::new(callback));
+        self
+    }
+}
+
+impl PostProcessor for Annotator<'_> {
+    fn name(&self) -> &'static str {
+        "annotator"
+    }
+
+    fn process(
+        &self,
+        mut image: RgbaImage,
+        placement: &Placement,
+    ) -> Result<RgbaImage, Box<dyn Error>> {
+        for callback in &self.callbacks {
+            callback(&mut image, placement)?;
+        }
+        Ok(image)
+    }
+}
+
+/// Draw the outline of `region`, in pixels, `thickness` pixels wide and inside of it, like
+/// the rectangles of --draw.
+pub fn draw_rect(image: &mut RgbaImage, region: Region, color: Rgba<u8>, thickness: u32) {
+    let shape = Shape::Rect {
+        x: region.x,
+        y: region.y,
+        width: region.width,
+        height: region.height,
+    };
+    draw_annotations(
+        image,
+        &[Annotation {
+            shape,
+            color,
+            size: thickness,
+        }],
+    );
+}
+
+/// Draw `text` with its top left corner at `x`, `y` in pixels, with the 8x8 bitmap font of
+/// --draw enlarged `scale` times. Lines are separated by newlines.
+pub fn draw_text(image: &mut RgbaImage, x: i32, y: i32, text: &str, color: Rgba<u8>, scale: u32) {
+    let shape = Shape::Text {
+        x,
+        y,
+        text: text.to_string(),
+    };
+    draw_annotations(
+        image,
+        &[Annotation {
+            shape,
+            color,
+            size: scale,
+        }],
+    );
+}
+
+/// Blur `region`, in pixels, beyond recognition like --redact-style blur. Parts outside the
+/// image are ignored.
+pub fn blur(image: &mut RgbaImage, region: Region) {
+    postprocess::redact(image, region, RedactStyle::Blur);
+}
@@ -0,0 +1,281 @@
+use std::{
+    error::Error,
+    ffi::OsStr,
+    fs::File,
+    io::{BufReader, BufWriter, ErrorKind, Read, Write},
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use image::RgbaImage;
+
+use crate::backend::{write_to_file, EncodingFormat};
+
+/// Magic bytes at the start of every wayshot frame archive (.wfz).
+const ARCHIVE_MAGIC: &[u8; 4] = b"WFZ1";
+
+/// Zstd level used for frames. Low levels keep capture fast, which is the whole point of
+/// archiving raw frames instead of encoding them straight away.
+const ARCHIVE_COMPRESSION_LEVEL: i32 = 1;
+
+/// Largest decompressed frame accepted when reading an archive, 1GiB, eg: 16384x16384 pixels.
+/// Sizes come from the archive itself, which may be corrupt or hostile.
+const MAX_FRAME_BYTES: u64 = 1 << 30;
+
+/// A single raw frame stored in an archive along with its metadata.
+#[derive(Debug)]
+pub struct ArchiveFrame {
+    /// Milliseconds since the UNIX epoch at which the frame was captured.
+    pub timestamp_ms: u64,
+    /// Free form label, eg: the output the frame was captured from.
+    pub label: String,
+    /// Rgba8 image data of the frame.
+    pub image: RgbaImage,
+}
+
+/// Writes raw Rgba8 frames into a zstd compressed archive.
+///
+/// Layout: the magic bytes followed by any number of frames, each consisting of
+/// `width: u32`, `height: u32`, `timestamp_ms: u64`, `label_len: u16`, `label`,
+/// `payload_len: u64` and the zstd compressed pixel payload. All integers are little endian.
+pub struct ArchiveWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> ArchiveWriter<W> {
+    /// Create a new archive, writing the header to `writer` right away.
+    pub fn new(mut writer: W) -> Result<Self, Box<dyn Error>> {
+        writer.write_all(ARCHIVE_MAGIC)?;
+        Ok(Self { writer })
+    }
+
+    /// Append a frame to the archive, stamped with the current time.
+    pub fn write_frame(&mut self, image: &RgbaImage, label: &str) -> Result<(), Box<dyn Error>> {
+        let timestamp_ms = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as u64;
+        self.write_frame_at(image, label, timestamp_ms)
+    }
+
+    /// Append a frame to the archive with an explicit timestamp.
+    pub fn write_frame_at(
+        &mut self,
+        image: &RgbaImage,
+        label: &str,
+        timestamp_ms: u64,
+    ) -> Result<(), Box<dyn Error>> {
+        let label_len: u16 = label
+            .len()
+            .try_into()
+            .map_err(|_| "Archive frame label is too long")?;
+        let payload = zstd::bulk::compress(image.as_raw(), ARCHIVE_COMPRESSION_LEVEL)?;
+
+        self.writer.write_all(&image.width().to_le_bytes())?;
+        self.writer.write_all(&image.height().to_le_bytes())?;
+        self.writer.write_all(&timestamp_ms.to_le_bytes())?;
+        self.writer.write_all(&label_len.to_le_bytes())?;
+        self.writer.write_all(label.as_bytes())?;
+        self.writer
+            .write_all(&(payload.len() as u64).to_le_bytes())?;
+        self.writer.write_all(&payload)?;
+        Ok(())
+    }
+
+    /// Flush and return the underlying writer.
+    pub fn finish(mut self) -> Result<W, Box<dyn Error>> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Reads frames back out of an archive created by [`ArchiveWriter`].
+pub struct ArchiveReader<R: Read> {
+    reader: R,
+}
+
+impl<R: Read> ArchiveReader<R> {
+    /// Open an archive, validating the header.
+    pub fn new(mut reader: R) -> Result<Self, Box<dyn Error>> {
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != ARCHIVE_MAGIC {
+            return Err("Not a wayshot frame archive".into());
+        }
+        Ok(Self { reader })
+    }
+
+    /// Read the next frame, returns None once the end of the archive is reached.
+    pub fn next_frame(&mut self) -> Result<Option<ArchiveFrame>, Box<dyn Error>> {
+        let mut width = [0u8; 4];
+        match self.reader.read_exact(&mut width) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e.into()),
+        }
+        let width = u32::from_le_bytes(width);
+        let height = u32::from_le_bytes(read_array(&mut self.reader)?);
+        let timestamp_ms = u64::from_le_bytes(read_array(&mut self.reader)?);
+        let label_len = u16::from_le_bytes(read_array(&mut self.reader)?);
+        let mut label = vec![0u8; label_len as usize];
+        self.reader.read_exact(&mut label)?;
+        let payload_len = u64::from_le_bytes(read_array(&mut self.reader)?);
+        let raw_len = u64::from(width)
+            .checked_mul(u64::from(height))
+            .and_then(|pixels| pixels.checked_mul(4))
+            .filter(|&raw_len| raw_len <= MAX_FRAME_BYTES)
+            .ok_or("Archive frame dimensions are too large")?;
+
+        // Both buffers grow with the data actually read, so lengths in the archive do not
+        // allocate anything by themselves.
+        let mut payload = Vec::new();
+        (&mut self.reader)
+            .take(payload_len)
+            .read_to_end(&mut payload)?;
+        if payload.len() as u64 != payload_len {
+            return Err("Archive frame is truncated".into());
+        }
+        let mut raw = Vec::new();
+        zstd::stream::read::Decoder::new(payload.as_slice())?
+            .take(raw_len + 1)
+            .read_to_end(&mut raw)?;
+        let image = RgbaImage::from_raw(width, height, raw)
+            .filter(|image| image.as_raw().len() as u64 == raw_len)
+            .ok_or("Archive frame size does not match its dimensions")?;
+
+        Ok(Some(ArchiveFrame {
+            timestamp_ms,
+            label: String::from_utf8(label)?,
+            image,
+        }))
+    }
+}
+
+fn read_array<R: Read, const N: usize>(reader: &mut R) -> std::io::Result<[u8; N]> {
+    let mut buf = [0u8; N];
+    reader.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+/// Label of an archive frame as it goes in a file name, with path separators and ".." replaced
+/// so that it can not point outside of the export directory.
+fn file_name_label(label: &str) -> String {
+    label.replace(['/', '\\', '\0'], "_").replace("..", "_")
+}
+
+/// Convert every frame of the archive at `archive_path` into a png file inside `directory`.
+/// Files are named after the archive, the frame index and the frame label if it has one.
+/// Returns the number of exported frames.
+pub fn export_to_png(archive_path: &Path, directory: &Path) -> Result<usize, Box<dyn Error>> {
+    let mut archive = ArchiveReader::new(BufReader::new(File::open(archive_path)?))?;
+    let stem = archive_path.file_stem().unwrap_or(OsStr::new("wayshot"));
+
+    let mut exported = 0;
+    while let Some(frame) = archive.next_frame()? {
+        let mut file_name = stem.to_os_string();
+        if frame.label.is_empty() {
+            file_name.push(format!("-{:04}.png", exported));
+        } else {
+            file_name.push(format!(
+                "-{:04}-{}.png",
+                exported,
+                file_name_label(&frame.label)
+            ));
+        }
+        let path = directory.join(file_name);
+        tracing::debug!(
+            "Exporting frame taken at {}ms to {:?}",
+            frame.timestamp_ms,
+            path
+        );
+        write_to_file(
+            BufWriter::new(File::create(path)?),
+            EncodingFormat::Png,
+            frame.image,
+        )?;
+        exported += 1;
+    }
+    Ok(exported)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io::Cursor};
+
+    use image::Rgba;
+
+    use super::*;
+    use crate::testing::TempDirectory;
+
+    /// Archive header of a frame with the given fields and no label, without its payload.
+    fn frame_header(width: u32, height: u32, payload_len: u64) -> Vec<u8> {
+        let mut data = ARCHIVE_MAGIC.to_vec();
+        data.extend(width.to_le_bytes());
+        data.extend(height.to_le_bytes());
+        data.extend(0u64.to_le_bytes());
+        data.extend(0u16.to_le_bytes());
+        data.extend(payload_len.to_le_bytes());
+        data
+    }
+
+    #[test]
+    fn frames_round_trip() {
+        let image = RgbaImage::from_fn(3, 2, |x, y| Rgba([x as u8, y as u8, 7, 255]));
+        let mut archive = ArchiveWriter::new(Vec::new()).unwrap();
+        archive.write_frame_at(&image, "DP-1", 42).unwrap();
+        archive.write_frame_at(&image, "", 43).unwrap();
+        let data = archive.finish().unwrap();
+
+        let mut archive = ArchiveReader::new(Cursor::new(data)).unwrap();
+        let frame = archive.next_frame().unwrap().unwrap();
+        assert_eq!(frame.timestamp_ms, 42);
+        assert_eq!(frame.label, "DP-1");
+        assert_eq!(frame.image, image);
+        assert_eq!(archive.next_frame().unwrap().unwrap().timestamp_ms, 43);
+        assert!(archive.next_frame().unwrap().is_none());
+    }
+
+    #[test]
+    fn oversized_dimensions_are_rejected() {
+        let data = frame_header(u32::MAX, u32::MAX, 0);
+        let mut archive = ArchiveReader::new(Cursor::new(data)).unwrap();
+        assert!(archive.next_frame().is_err());
+    }
+
+    #[test]
+    fn payload_longer_than_archive_is_rejected() {
+        let mut data = frame_header(1, 1, u64::MAX);
+        data.extend([0u8; 16]);
+        let mut archive = ArchiveReader::new(Cursor::new(data)).unwrap();
+        assert!(archive.next_frame().is_err());
+    }
+
+    #[test]
+    fn payload_not_matching_dimensions_is_rejected() {
+        let payload = zstd::bulk::compress(&[0u8; 8], ARCHIVE_COMPRESSION_LEVEL).unwrap();
+        let mut data = frame_header(4, 4, payload.len() as u64);
+        data.extend(payload);
+        let mut archive = ArchiveReader::new(Cursor::new(data)).unwrap();
+        assert!(archive.next_frame().is_err());
+    }
+
+    #[test]
+    fn labels_stay_in_the_export_directory() {
+        assert_eq!(file_name_label("DP-1"), "DP-1");
+        assert_eq!(file_name_label("../../etc/passwd"), "____etc_passwd");
+        assert!(!file_name_label("a/..\\..b").contains(['/', '\\']));
+
+        let directory = TempDirectory::new();
+        let archive_path = directory.path().join("shot.wfz");
+        let mut archive = ArchiveWriter::new(File::create(&archive_path).unwrap()).unwrap();
+        let image = RgbaImage::new(1, 1);
+        archive.write_frame_at(&image, "../escape", 0).unwrap();
+        archive.finish().unwrap();
+
+        let export = directory.path().join("export");
+        fs::create_dir(&export).unwrap();
+        assert_eq!(export_to_png(&archive_path, &export).unwrap(), 1);
+        let names: Vec<_> = fs::read_dir(&export)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(names, ["shot-0000-__escape.png"]);
+    }
+}
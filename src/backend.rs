@@ -1,9 +1,15 @@
 use std::{
+    env,
     error::Error,
-    ffi::CStr,
-    io::Write,
-    os::unix::prelude::RawFd,
-    time::{SystemTime, UNIX_EPOCH},
+    fs::{self, File, OpenOptions},
+    io::{BufWriter, Write},
+    os::unix::{
+        fs::OpenOptionsExt,
+        prelude::{IntoRawFd, RawFd},
+    },
+    path::PathBuf,
+    process,
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 use nix::{
@@ -12,17 +18,34 @@ use nix::{
     unistd,
 };
 
+use memmap2::MmapMut;
+
 use image::{
     codecs::{
-        jpeg::JpegEncoder,
-        png::PngEncoder,
+        bmp::BmpEncoder,
+        ico::{IcoEncoder, IcoFrame},
         pnm::{self, PnmEncoder},
     },
     ColorType, ImageEncoder, RgbaImage,
 };
-use memmap2::MmapMut;
+#[cfg(feature = "tiff")]
+use tiff::encoder::{
+    colortype::{self, ColorType as TiffColorType},
+    compression::{Deflate, Lzw},
+    TiffEncoder, TiffValue,
+};
+
+use crate::adam7::write_interlaced_png;
+#[cfg(feature = "archive")]
+use crate::archive::ArchiveWriter;
+use crate::convert::{convert_to_rgba16, create_converter, Convert};
+use crate::output::OutputMode;
+use crate::palette::{quantize, MAX_PALETTE_COLORS};
+use crate::postprocess;
+use crate::Region;
 
 use wayland_client::protocol::wl_shm::Format;
+use wayshot_core::Transform;
 
 /// Type of frame supported by the compositor. For now we only support Argb8888, Xrgb8888, and
 /// Xbgr8888.
@@ -34,6 +57,22 @@ pub struct FrameFormat {
     pub stride: u32,
 }
 
+impl FrameFormat {
+    /// Size in bytes of a shm buffer holding the frame, or None if the stride is too short for
+    /// a row of 4 byte pixels or the buffer is too large for wl_shm, whose sizes are i32s.
+    /// Frames are described with u32s, so very large virtual outputs could otherwise overflow
+    /// into a buffer smaller than the frame.
+    pub fn buffer_size(&self) -> Option<i32> {
+        if u64::from(self.stride) < u64::from(self.width) * 4 {
+            return None;
+        }
+        for value in [self.width, self.height, self.stride] {
+            i32::try_from(value).ok()?;
+        }
+        i32::try_from(u64::from(self.stride) * u64::from(self.height)).ok()
+    }
+}
+
 /// State of the frame after attemting to copy it's data to a wl_buffer.
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub enum FrameState {
@@ -41,15 +80,8 @@ pub enum FrameState {
     Failed,
     /// Compositor sent a Ready event on calling `frame.copy`.
     Finished,
-}
-
-/// The copied frame comprising of the FrameFormat, ColorType (Rgba8), and a memory backed shm
-/// file that holds the image data in it.
-#[derive(Debug)]
-pub struct FrameCopy {
-    pub frame_format: FrameFormat,
-    pub frame_color_type: ColorType,
-    pub frame_mmap: MmapMut,
+    /// Compositor sent neither before --output-timeout passed.
+    TimedOut,
 }
 
 /// Struct to store region capture details.
@@ -65,6 +97,47 @@ pub struct CaptureRegion {
     pub height: i32,
 }
 
+/// Frame data of a single output as copied by the compositor, before format conversion.
+#[derive(Debug)]
+pub struct RawCapture {
+    /// Name of the output the frame was captured from.
+    pub name: String,
+    /// Position and size of the captured area in global logical coordinates.
+    pub region: Region,
+    pub frame_format: FrameFormat,
+    /// Shm buffer holding the pixels.
+    pub frame_mmap: MmapMut,
+    /// Orientation of the pixels in the buffer, undone when converting them, see
+    /// [`wayshot_core::ConversionPlan`].
+    pub transform: Transform,
+    /// CLOCK_MONOTONIC time at which the captured frame was presented, if the compositor said.
+    pub presented_at: Option<Duration>,
+    /// Current mode of the output, if the compositor advertised one.
+    pub mode: Option<OutputMode>,
+}
+
+impl RawCapture {
+    /// Width and height of the frame once its transform is undone, before scaling.
+    pub fn shown_size(&self) -> (u32, u32) {
+        if self.transform.swaps_axes() {
+            (self.frame_format.height, self.frame_format.width)
+        } else {
+            (self.frame_format.width, self.frame_format.height)
+        }
+    }
+
+    /// Offset in the buffer of the row showing row `y` of the frame, for frames which are
+    /// upright or upside down, see [`can_composite_raw`].
+    fn row_start(&self, y: u32) -> usize {
+        let row = if self.transform == Transform::Flipped180 {
+            self.frame_format.height - 1 - y
+        } else {
+            y
+        };
+        row as usize * self.frame_format.stride as usize
+    }
+}
+
 /// Supported image encoding formats.
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum EncodingFormat {
@@ -74,18 +147,178 @@ pub enum EncodingFormat {
     Png,
     /// Ppm encoder
     Ppm,
+    /// Zstd compressed raw frame archive, see [`ArchiveWriter`].
+    Wfz,
+    /// Bmp encoder, 24 bits per pixel for opaque images and 32 otherwise.
+    Bmp,
+    /// Tiff encoder, see [`TiffCompression`] and [`EncodingOptions::tiff_16bit`].
+    Tiff,
+    /// Windows icon holding the image at every size of [`ICO_SIZES`].
+    Ico,
+}
+
+impl EncodingFormat {
+    /// File extension used for images encoded in this format.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            EncodingFormat::Jpg => "jpg",
+            EncodingFormat::Png => "png",
+            EncodingFormat::Ppm => "ppm",
+            EncodingFormat::Wfz => "wfz",
+            EncodingFormat::Bmp => "bmp",
+            EncodingFormat::Tiff => "tiff",
+            EncodingFormat::Ico => "ico",
+        }
+    }
+
+    /// Whether the encoder for this format was compiled in, see the Cargo features.
+    pub fn is_available(&self) -> bool {
+        match self {
+            EncodingFormat::Jpg => cfg!(feature = "jpeg"),
+            EncodingFormat::Png
+            | EncodingFormat::Ppm
+            | EncodingFormat::Bmp
+            | EncodingFormat::Ico => true,
+            EncodingFormat::Wfz => cfg!(feature = "archive"),
+            EncodingFormat::Tiff => cfg!(feature = "tiff"),
+        }
+    }
+}
+
+/// Chroma subsampling of the jpeg encoder.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum JpegSubsampling {
+    /// Full resolution chroma, keeps small colored text crisp.
+    Yuv444,
+    /// Chroma halved horizontally.
+    Yuv422,
+    /// Chroma halved horizontally and vertically, the smallest files.
+    Yuv420,
+}
+
+/// Scanline filter strategy of the png encoder.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PngFilter {
+    NoFilter,
+    Sub,
+    Up,
+    Avg,
+    Paeth,
+    /// Pick the best filter for every scanline.
+    Adaptive,
+}
+
+/// Sides in pixels of the square images of icons, see [`postprocess::icon`].
+pub const ICO_SIZES: [u32; 4] = [16, 32, 64, 128];
+
+/// Compression of the strips of tiff images, all of them lossless.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TiffCompression {
+    /// Uncompressed, readable by every tiff decoder.
+    None,
+    Lzw,
+    /// Smaller than lzw, though some older readers lack it.
+    Deflate,
+}
+
+/// Tunables for the image encoders. Options that do not apply to the chosen
+/// [`EncodingFormat`] are ignored.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct EncodingOptions {
+    /// Quality of jpeg images, from 1 to 100.
+    pub jpeg_quality: u8,
+    /// Chroma subsampling of jpeg images.
+    pub jpeg_subsampling: JpegSubsampling,
+    /// Write progressive jpeg images which render coarse to fine.
+    pub jpeg_progressive: bool,
+    /// Write png images with Adam7 interlacing so they render progressively.
+    pub png_interlaced: bool,
+    /// Tag png images as sRGB, emitting the sRGB, gAMA and cHRM chunks.
+    pub png_srgb: bool,
+    /// Scanline filter strategy of png images.
+    pub png_filter: PngFilter,
+    /// Quantize png images to an indexed palette of at most this many colors, see
+    /// [`quantize`].
+    pub png_palette: Option<u16>,
+    /// Compression of tiff images.
+    pub tiff_compression: TiffCompression,
+    /// Write tiff images with 16 bits per channel. Images from the pipeline hold 8 bits per
+    /// channel, see [`write_deep_tiff`] to keep the precision of 10 bit captures.
+    pub tiff_16bit: bool,
+    /// Leave out timestamps, so that identical pixels always give identical files. Only wfz
+    /// archives hold one.
+    pub deterministic: bool,
+}
+
+impl Default for EncodingOptions {
+    fn default() -> Self {
+        Self {
+            jpeg_quality: 75,
+            jpeg_subsampling: JpegSubsampling::Yuv444,
+            jpeg_progressive: false,
+            png_interlaced: false,
+            png_srgb: false,
+            png_filter: PngFilter::Adaptive,
+            png_palette: None,
+            tiff_compression: TiffCompression::None,
+            tiff_16bit: false,
+            deterministic: false,
+        }
+    }
 }
 
 /// Return a RawFd to a shm file. We use memfd create on linux and shm_open for BSD support.
+/// Systems with neither, eg: without /dev/shm mounted, get an unlinked file in XDG_RUNTIME_DIR.
 /// You don't need to mess around with this function, it is only used by
 /// capture_output_frame.
 pub fn create_shm_fd() -> std::io::Result<RawFd> {
+    if let Some(fd) = create_memory_fd()? {
+        return Ok(fd);
+    }
+
+    // Last resort: an unlinked file in the runtime directory, which is usually a tmpfs.
+    let directory = env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(env::temp_dir);
+    tracing::debug!(
+        "Shared memory is unavailable, using a file in {:?}",
+        directory
+    );
+    loop {
+        let path = directory.join(format!(
+            "wayshot-{}-{}",
+            process::id(),
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .subsec_nanos()
+        ));
+        match OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&path)
+        {
+            Ok(file) => {
+                fs::remove_file(&path)?;
+                return Ok(file.into_raw_fd());
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Return a RawFd to memory shared through memfd create or shm_open, None on systems with
+/// neither. Unlike [`create_shm_fd`], never falls back to a file.
+pub fn create_memory_fd() -> std::io::Result<Option<RawFd>> {
     // Only try memfd on linux and freebsd.
     #[cfg(any(target_os = "linux", target_os = "freebsd"))]
     loop {
         // Create a file that closes on succesful execution and seal it's operations.
         match memfd::memfd_create(
-            CStr::from_bytes_with_nul(b"wayshot\0").unwrap(),
+            c"wayshot",
             memfd::MemFdCreateFlag::MFD_CLOEXEC | memfd::MemFdCreateFlag::MFD_ALLOW_SEALING,
         ) {
             Ok(fd) => {
@@ -98,7 +331,7 @@ pub fn create_shm_fd() -> std::io::Result<RawFd> {
                         fcntl::SealFlag::F_SEAL_SHRINK | fcntl::SealFlag::F_SEAL_SEAL,
                     ),
                 );
-                return Ok(fd);
+                return Ok(Some(fd));
             }
             Err(nix::errno::Errno::EINTR) => continue,
             Err(nix::errno::Errno::ENOSYS) => break,
@@ -128,7 +361,7 @@ pub fn create_shm_fd() -> std::io::Result<RawFd> {
             stat::Mode::S_IRUSR | stat::Mode::S_IWUSR,
         ) {
             Ok(fd) => match mman::shm_unlink(mem_file_handle.as_str()) {
-                Ok(_) => return Ok(fd),
+                Ok(_) => return Ok(Some(fd)),
                 Err(errno) => match unistd::close(fd) {
                     Ok(_) => return Err(std::io::Error::from(errno)),
                     Err(errno) => return Err(std::io::Error::from(errno)),
@@ -143,39 +376,197 @@ pub fn create_shm_fd() -> std::io::Result<RawFd> {
                 continue;
             }
             Err(nix::errno::Errno::EINTR) => continue,
+            // No shm_open or no /dev/shm mounted, eg: on minimal kiosk images.
+            Err(nix::errno::Errno::ENOSYS | nix::errno::Errno::ENOENT) => break,
             Err(errno) => return Err(std::io::Error::from(errno)),
         }
     }
+    Ok(None)
 }
 
-/// Write an instance of FrameCopy to anything that implements Write trait. Eg: Stdout or a file
-/// on the disk.
+/// Write an image to anything that implements Write trait. Eg: Stdout or a file on the disk.
 pub fn write_to_file(
-    mut output_file: impl Write,
+    output_file: impl Write,
     encoding_format: EncodingFormat,
     image: RgbaImage,
 ) -> Result<(), Box<dyn Error>> {
-    log::debug!(
+    write_to_file_with_options(
+        output_file,
+        encoding_format,
+        &EncodingOptions::default(),
+        image,
+    )
+}
+
+/// Same as [`write_to_file`], with control over the encoder through [`EncodingOptions`].
+#[tracing::instrument(skip_all, fields(format = ?encoding_format))]
+pub fn write_to_file_with_options(
+    output_file: impl Write,
+    encoding_format: EncodingFormat,
+    encoding_options: &EncodingOptions,
+    image: RgbaImage,
+) -> Result<(), Box<dyn Error>> {
+    tracing::debug!(
         "Writing to disk with encoding format: {:#?}",
         encoding_format
     );
+    encode_to(output_file, encoding_format, encoding_options, &image)
+}
+
+/// Ppm images at least this many bytes large are written through a memory map of the output
+/// file by [`write_to_disk_with_options`].
+pub const MAPPED_WRITE_THRESHOLD: u64 = 100 * 1024 * 1024;
+
+/// Same as [`write_to_file_with_options`] for an empty file on disk, opened for reading and
+/// writing. Ppm images of at least [`MAPPED_WRITE_THRESHOLD`] bytes are converted straight into
+/// a memory map of the file, skipping the intermediate rgb buffer and the write syscalls of
+/// buffered output.
+pub fn write_to_disk_with_options(
+    file: File,
+    encoding_format: EncodingFormat,
+    encoding_options: &EncodingOptions,
+    image: RgbaImage,
+) -> Result<(), Box<dyn Error>> {
+    if encoding_format == EncodingFormat::Ppm
+        && ppm_size(image.width(), image.height()) >= MAPPED_WRITE_THRESHOLD
+    {
+        return write_ppm_mapped(&file, &image);
+    }
+    write_to_file_with_options(
+        BufWriter::new(file),
+        encoding_format,
+        encoding_options,
+        image,
+    )
+}
+
+#[tracing::instrument(skip_all)]
+pub fn write_ppm_mapped(file: &File, image: &RgbaImage) -> Result<(), Box<dyn Error>> {
+    let header = ppm_header(image.width(), image.height());
+    let size = ppm_size(image.width(), image.height());
+    tracing::debug!("Writing {} byte ppm through a memory map", size);
+
+    file.set_len(size)?;
+    let mut file_mmap = unsafe { MmapMut::map_mut(file)? };
+
+    let (header_bytes, pixels) = file_mmap.split_at_mut(header.len());
+    header_bytes.copy_from_slice(header.as_bytes());
+    for (rgb, rgba) in pixels
+        .chunks_exact_mut(3)
+        .zip(image.as_raw().chunks_exact(4))
+    {
+        rgb.copy_from_slice(&rgba[..3]);
+    }
+    file_mmap.flush()?;
+
+    Ok(())
+}
+
+/// Header of a binary ppm, matching the one written by the image crate.
+fn ppm_header(width: u32, height: u32) -> String {
+    format!("P6\n{} {} 255\n", width, height)
+}
+
+/// Size in bytes of a binary ppm, header included.
+fn ppm_size(width: u32, height: u32) -> u64 {
+    ppm_header(width, height).len() as u64 + 3 * width as u64 * height as u64
+}
+
+/// Encode an image into memory, eg: to upload it or place it on the clipboard without going
+/// through a temporary file.
+pub fn encode(
+    image: &RgbaImage,
+    encoding_format: EncodingFormat,
+    encoding_options: &EncodingOptions,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut data = Vec::new();
+    encode_to(&mut data, encoding_format, encoding_options, image)?;
+    Ok(data)
+}
+
+/// Number of horizontal strips sampled by [`estimate_size`].
+const ESTIMATE_STRIPS: u32 = 8;
+
+/// Height of the strips sampled by [`estimate_size`], a multiple of the jpeg block size.
+const ESTIMATE_STRIP_HEIGHT: u32 = 16;
+
+/// Quickly estimate the size in bytes of `image` once encoded, so callers can pick a format
+/// before paying for a full encode. Ppm sizes are exact, other formats are extrapolated from
+/// encoding a few evenly spaced horizontal strips of the image.
+pub fn estimate_size(
+    image: &RgbaImage,
+    encoding_format: EncodingFormat,
+    encoding_options: &EncodingOptions,
+) -> Result<u64, Box<dyn Error>> {
+    if encoding_format == EncodingFormat::Ppm {
+        return Ok(ppm_size(image.width(), image.height()));
+    }
+
+    let sample_height = ESTIMATE_STRIPS * ESTIMATE_STRIP_HEIGHT;
+    if image.height() <= 2 * sample_height {
+        return Ok(encode(image, encoding_format, encoding_options)?.len() as u64);
+    }
+
+    let strip_spacing = image.height() / ESTIMATE_STRIPS;
+    let mut sample = RgbaImage::new(image.width(), sample_height);
+    for strip in 0..ESTIMATE_STRIPS {
+        let source_y = strip * strip_spacing + (strip_spacing - ESTIMATE_STRIP_HEIGHT) / 2;
+        for row in 0..ESTIMATE_STRIP_HEIGHT {
+            for x in 0..image.width() {
+                let pixel = *image.get_pixel(x, source_y + row);
+                sample.put_pixel(x, strip * ESTIMATE_STRIP_HEIGHT + row, pixel);
+            }
+        }
+    }
+
+    let sample_size = encode(&sample, encoding_format, encoding_options)?.len() as u64;
+    Ok(sample_size * image.height() as u64 / sample_height as u64)
+}
+
+fn encode_to(
+    mut output_file: impl Write,
+    encoding_format: EncodingFormat,
+    encoding_options: &EncodingOptions,
+    image: &RgbaImage,
+) -> Result<(), Box<dyn Error>> {
     match encoding_format {
+        #[cfg(feature = "jpeg")]
         EncodingFormat::Jpg => {
-            JpegEncoder::new(&mut output_file).write_image(
-                image.as_raw(),
-                image.width(),
-                image.height(),
-                ColorType::Rgba8,
-            )?;
+            let (width, height) = match (image.width().try_into(), image.height().try_into()) {
+                (Ok(width), Ok(height)) => (width, height),
+                _ => return Err("Jpeg images can not be larger than 65535x65535 pixels".into()),
+            };
+            let mut encoder =
+                jpeg_encoder::Encoder::new(&mut output_file, encoding_options.jpeg_quality);
+            encoder.set_sampling_factor(match encoding_options.jpeg_subsampling {
+                JpegSubsampling::Yuv444 => jpeg_encoder::SamplingFactor::R_4_4_4,
+                JpegSubsampling::Yuv422 => jpeg_encoder::SamplingFactor::R_4_2_2,
+                JpegSubsampling::Yuv420 => jpeg_encoder::SamplingFactor::R_4_2_0,
+            });
+            encoder.set_progressive(encoding_options.jpeg_progressive);
+            encoder.encode(image.as_raw(), width, height, jpeg_encoder::ColorType::Rgba)?;
             output_file.flush()?;
         }
+        EncodingFormat::Png if encoding_options.png_interlaced => {
+            write_interlaced_png(&mut output_file, image, encoding_options)?;
+        }
+        EncodingFormat::Png if encoding_options.png_palette.is_some() => {
+            write_indexed_png(&mut output_file, image, encoding_options)?;
+        }
         EncodingFormat::Png => {
-            PngEncoder::new(&mut output_file).write_image(
-                image.as_raw(),
+            let mut writer = png_encoder(
+                &mut output_file,
                 image.width(),
                 image.height(),
-                ColorType::Rgba8,
-            )?;
+                encoding_options,
+            )
+            .write_header()?;
+            // Unlike write_image_data, the stream writer emits the compressed data as it goes
+            // instead of holding all of it in memory, so writes track the encoding progress.
+            let mut stream = writer.stream_writer()?;
+            stream.write_all(image.as_raw())?;
+            stream.finish()?;
+            writer.finish()?;
             output_file.flush()?;
         }
         EncodingFormat::Ppm => {
@@ -193,7 +584,358 @@ pub fn write_to_file(
                 .write_image(&rgb8_data, image.width(), image.height(), ColorType::Rgb8)?;
             output_file.flush()?;
         }
+        #[cfg(feature = "archive")]
+        EncodingFormat::Wfz => {
+            let mut archive = ArchiveWriter::new(&mut output_file)?;
+            if encoding_options.deterministic {
+                archive.write_frame_at(image, "", 0)?;
+            } else {
+                archive.write_frame(image, "")?;
+            }
+            archive.finish()?;
+        }
+        EncodingFormat::Bmp => {
+            // Legacy readers often only know 24 bit bitmaps, so only keep alpha when it is used.
+            if image.pixels().all(|pixel| pixel[3] == u8::MAX) {
+                let rgb8_data: Vec<u8> = image
+                    .as_raw()
+                    .chunks_exact(4)
+                    .flat_map(|chunk| [chunk[0], chunk[1], chunk[2]])
+                    .collect();
+                BmpEncoder::new(&mut output_file).write_image(
+                    &rgb8_data,
+                    image.width(),
+                    image.height(),
+                    ColorType::Rgb8,
+                )?;
+            } else {
+                BmpEncoder::new(&mut output_file).write_image(
+                    image.as_raw(),
+                    image.width(),
+                    image.height(),
+                    ColorType::Rgba8,
+                )?;
+            }
+            output_file.flush()?;
+        }
+        EncodingFormat::Ico => {
+            let frames = ICO_SIZES
+                .iter()
+                .map(|&side| {
+                    let icon = postprocess::icon(image, side);
+                    IcoFrame::as_png(icon.as_raw(), side, side, ColorType::Rgba8)
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            IcoEncoder::new(&mut output_file).encode_images(&frames)?;
+            output_file.flush()?;
+        }
+        #[cfg(feature = "tiff")]
+        EncodingFormat::Tiff if encoding_options.tiff_16bit => {
+            let rgba16_data: Vec<u16> = image
+                .as_raw()
+                .iter()
+                .map(|&sample| sample as u16 * 257)
+                .collect();
+            write_tiff::<colortype::RGBA16>(
+                &mut output_file,
+                image.width(),
+                image.height(),
+                encoding_options.tiff_compression,
+                &rgba16_data,
+            )?;
+        }
+        #[cfg(feature = "tiff")]
+        EncodingFormat::Tiff => {
+            write_tiff::<colortype::RGBA8>(
+                &mut output_file,
+                image.width(),
+                image.height(),
+                encoding_options.tiff_compression,
+                image.as_raw(),
+            )?;
+        }
+        #[cfg(not(all(feature = "jpeg", feature = "archive", feature = "tiff")))]
+        _ => {
+            return Err(format!(
+                "wayshot was built without the {} encoder",
+                encoding_format.extension()
+            )
+            .into())
+        }
+    }
+
+    Ok(())
+}
+
+/// Write `data`, rows of `C` pixels, as a tiff image.
+#[cfg(feature = "tiff")]
+fn write_tiff<C: TiffColorType>(
+    mut output_file: impl Write,
+    width: u32,
+    height: u32,
+    compression: TiffCompression,
+    data: &[C::Inner],
+) -> Result<(), Box<dyn Error>>
+where
+    [C::Inner]: TiffValue,
+{
+    // The encoder seeks back to fill in offsets, which pipes such as stdout can not do.
+    let mut buffer = std::io::Cursor::new(Vec::new());
+    let mut encoder = TiffEncoder::new(&mut buffer)?;
+    match compression {
+        TiffCompression::None => encoder.write_image::<C>(width, height, data)?,
+        TiffCompression::Lzw => {
+            encoder.write_image_with_compression::<C, _>(width, height, Lzw, data)?
+        }
+        TiffCompression::Deflate => {
+            encoder.write_image_with_compression::<C, _>(width, height, Deflate::default(), data)?
+        }
+    }
+    output_file.write_all(buffer.get_ref())?;
+    output_file.flush()?;
+    Ok(())
+}
+
+/// Write `image` quantized to the palette size of `encoding_options` as an indexed png.
+fn write_indexed_png(
+    mut output_file: impl Write,
+    image: &RgbaImage,
+    encoding_options: &EncodingOptions,
+) -> Result<(), Box<dyn Error>> {
+    let colors = encoding_options.png_palette.unwrap_or(MAX_PALETTE_COLORS);
+    let indexed = quantize(image, colors);
+    tracing::debug!("Quantized to {} colors", indexed.palette.len());
+
+    let mut encoder = png_encoder(
+        &mut output_file,
+        indexed.width,
+        indexed.height,
+        encoding_options,
+    );
+    encoder.set_color(png::ColorType::Indexed);
+    let rgb: Vec<u8> = indexed
+        .palette
+        .iter()
+        .flat_map(|c| [c[0], c[1], c[2]])
+        .collect();
+    encoder.set_palette(rgb);
+    // Fully opaque palettes, the usual case for screenshots, do not need a tRNS chunk.
+    if indexed.palette.iter().any(|color| color[3] != u8::MAX) {
+        let alpha: Vec<u8> = indexed.palette.iter().map(|color| color[3]).collect();
+        encoder.set_trns(alpha);
+    }
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&indexed.indices)?;
+    writer.finish()?;
+    output_file.flush()?;
+    Ok(())
+}
+
+/// Png encoder configured from `encoding_options`, shared by the regular and streaming paths.
+fn png_encoder<W: Write>(
+    writer: W,
+    width: u32,
+    height: u32,
+    encoding_options: &EncodingOptions,
+) -> png::Encoder<'static, W> {
+    let mut encoder = png::Encoder::new(writer, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_compression(png::Compression::Fast);
+    let (filter, adaptive_filter) = match encoding_options.png_filter {
+        PngFilter::NoFilter => (
+            png::FilterType::NoFilter,
+            png::AdaptiveFilterType::NonAdaptive,
+        ),
+        PngFilter::Sub => (png::FilterType::Sub, png::AdaptiveFilterType::NonAdaptive),
+        PngFilter::Up => (png::FilterType::Up, png::AdaptiveFilterType::NonAdaptive),
+        PngFilter::Avg => (png::FilterType::Avg, png::AdaptiveFilterType::NonAdaptive),
+        PngFilter::Paeth => (png::FilterType::Paeth, png::AdaptiveFilterType::NonAdaptive),
+        PngFilter::Adaptive => (png::FilterType::Sub, png::AdaptiveFilterType::Adaptive),
+    };
+    encoder.set_filter(filter);
+    encoder.set_adaptive_filter(adaptive_filter);
+    if encoding_options.png_srgb {
+        encoder.set_source_srgb(png::SrgbRenderingIntent::Perceptual);
+    }
+    encoder
+}
+
+/// Whether [`write_streamed`] can encode these captures. Streaming needs an encoder which
+/// accepts rows one at a time and frames which can be used without scaling.
+pub fn can_stream(
+    encoding_format: EncodingFormat,
+    encoding_options: &EncodingOptions,
+    captures: &[RawCapture],
+) -> bool {
+    let encoder_streams = match encoding_format {
+        EncodingFormat::Png => {
+            !encoding_options.png_interlaced && encoding_options.png_palette.is_none()
+        }
+        EncodingFormat::Ppm => true,
+        EncodingFormat::Jpg
+        | EncodingFormat::Wfz
+        | EncodingFormat::Bmp
+        | EncodingFormat::Tiff
+        | EncodingFormat::Ico => false,
+    };
+    encoder_streams && can_composite_raw(captures)
+}
+
+/// Whether these captures can be composited straight from their shm buffers, which needs
+/// frames which can be used without scaling or turning, other than upside down, and a known
+/// pixel format.
+pub fn can_composite_raw(captures: &[RawCapture]) -> bool {
+    captures.iter().all(|capture| {
+        capture.frame_format.width as i32 == capture.region.width
+            && capture.frame_format.height as i32 == capture.region.height
+            && matches!(capture.transform, Transform::Normal | Transform::Flipped180)
+            && create_converter(capture.frame_format.format).is_some()
+    })
+}
+
+/// Composite and encode captures row by row, converting every row in place inside the shm
+/// buffers it was copied to. Peak memory stays at a couple of rows instead of the whole image.
+#[tracing::instrument(skip_all, fields(format = ?encoding_format))]
+pub fn write_streamed(
+    mut output_file: impl Write,
+    encoding_format: EncodingFormat,
+    encoding_options: &EncodingOptions,
+    captures: &mut [RawCapture],
+) -> Result<(), Box<dyn Error>> {
+    if !can_stream(encoding_format, encoding_options, captures) {
+        return Err("These captures can not be streamed".into());
+    }
+
+    let net_x1 = captures.iter().map(|c| c.region.x).min().unwrap_or(0);
+    let net_y1 = captures.iter().map(|c| c.region.y).min().unwrap_or(0);
+    let net_x2 = captures
+        .iter()
+        .map(|c| c.region.x + c.region.width)
+        .max()
+        .unwrap_or(0);
+    let net_y2 = captures
+        .iter()
+        .map(|c| c.region.y + c.region.height)
+        .max()
+        .unwrap_or(0);
+    let width = (net_x2 - net_x1) as u32;
+    let height = (net_y2 - net_y1) as u32;
+    tracing::debug!(
+        "Streaming {}x{} image with encoding format: {:#?}",
+        width,
+        height,
+        encoding_format
+    );
+
+    let converters: Vec<&dyn Convert> = captures
+        .iter()
+        .map(|capture| create_converter(capture.frame_format.format).unwrap())
+        .collect();
+    let mut row = vec![0u8; width as usize * 4];
+
+    match encoding_format {
+        EncodingFormat::Png => {
+            let mut writer =
+                png_encoder(&mut output_file, width, height, encoding_options).write_header()?;
+            let mut stream = writer.stream_writer()?;
+            for y in net_y1..net_y2 {
+                composite_row(captures, &converters, net_x1, y, &mut row);
+                stream.write_all(&row)?;
+            }
+            stream.finish()?;
+            writer.finish()?;
+        }
+        EncodingFormat::Ppm => {
+            output_file.write_all(ppm_header(width, height).as_bytes())?;
+            let mut rgb8_row = Vec::with_capacity(width as usize * 3);
+            for y in net_y1..net_y2 {
+                composite_row(captures, &converters, net_x1, y, &mut row);
+                rgb8_row.clear();
+                for chunk in row.chunks_exact(4) {
+                    rgb8_row.extend_from_slice(&chunk[..3]);
+                }
+                output_file.write_all(&rgb8_row)?;
+            }
+        }
+        EncodingFormat::Jpg
+        | EncodingFormat::Wfz
+        | EncodingFormat::Bmp
+        | EncodingFormat::Tiff
+        | EncodingFormat::Ico => unreachable!(),
     }
+    output_file.flush()?;
 
     Ok(())
 }
+
+/// Composite captures into a tiff image with 16 bits per channel, converting from the shm
+/// buffers directly so that 10 bit frames keep their full precision instead of going through
+/// the 8 bit pipeline.
+#[tracing::instrument(skip_all)]
+#[cfg_attr(not(feature = "tiff"), allow(unused_variables))]
+pub fn write_deep_tiff(
+    output_file: impl Write,
+    encoding_options: &EncodingOptions,
+    captures: &[RawCapture],
+) -> Result<(), Box<dyn Error>> {
+    if !can_composite_raw(captures) {
+        return Err("These captures can not be composited from their buffers".into());
+    }
+
+    let region = crate::bounding_region(captures.iter().map(|capture| &capture.region));
+    let (width, height) = (region.width as usize, region.height as usize);
+    tracing::debug!(
+        "Writing {}x{} 16 bit tiff from the capture buffers",
+        width,
+        height
+    );
+
+    let mut data = vec![0u16; width * height * 4];
+    for capture in captures {
+        let row_bytes = capture.region.width as usize * 4;
+        let x = (capture.region.x - region.x) as usize;
+        for y in 0..capture.region.height as usize {
+            let start = capture.row_start(y as u32);
+            let dest_start = ((capture.region.y - region.y) as usize + y) * width * 4 + x * 4;
+            convert_to_rgba16(
+                capture.frame_format.format,
+                &capture.frame_mmap[start..start + row_bytes],
+                &mut data[dest_start..dest_start + row_bytes],
+            );
+        }
+    }
+    #[cfg(feature = "tiff")]
+    return write_tiff::<colortype::RGBA16>(
+        output_file,
+        width as u32,
+        height as u32,
+        encoding_options.tiff_compression,
+        &data,
+    );
+    #[cfg(not(feature = "tiff"))]
+    Err("wayshot was built without the tiff encoder".into())
+}
+
+/// Assemble row `y` (in global logical coordinates) of the composite into `row`.
+fn composite_row(
+    captures: &mut [RawCapture],
+    converters: &[&dyn Convert],
+    net_x1: i32,
+    y: i32,
+    row: &mut [u8],
+) {
+    row.fill(0);
+    for (capture, converter) in captures.iter_mut().zip(converters) {
+        if y < capture.region.y || y >= capture.region.y + capture.region.height {
+            continue;
+        }
+        let row_bytes = capture.region.width as usize * 4;
+        let start = capture.row_start((y - capture.region.y) as u32);
+        let source = &mut capture.frame_mmap[start..start + row_bytes];
+        converter.convert_inplace(source);
+
+        let dest_start = (capture.region.x - net_x1) as usize * 4;
+        row[dest_start..dest_start + row_bytes].copy_from_slice(source);
+    }
+}
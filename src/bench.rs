@@ -0,0 +1,139 @@
+use std::{
+    error::Error,
+    fs::{self, File, OpenOptions},
+    io::{BufWriter, Write},
+    path::Path,
+    process,
+    time::{Duration, Instant},
+};
+
+use image::{Rgba, RgbaImage};
+
+use crate::backend::{
+    write_ppm_mapped, write_to_file_with_options, EncodingFormat, EncodingOptions,
+};
+
+/// Ways of writing a ppm compared by [`bench_io`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WriteMethod {
+    /// Encoded by the image crate into a buffered writer, as ppm images smaller than
+    /// [`MAPPED_WRITE_THRESHOLD`] are.
+    ///
+    /// [`MAPPED_WRITE_THRESHOLD`]: crate::MAPPED_WRITE_THRESHOLD
+    Buffered,
+    /// Converted straight into a memory map of the file, as larger ones are.
+    Mapped,
+}
+
+impl WriteMethod {
+    const ALL: [WriteMethod; 2] = [WriteMethod::Buffered, WriteMethod::Mapped];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            WriteMethod::Buffered => "buffered",
+            WriteMethod::Mapped => "mapped",
+        }
+    }
+
+    fn write(self, file: &File, image: RgbaImage) -> Result<(), Box<dyn Error>> {
+        match self {
+            WriteMethod::Buffered => {
+                let mut writer = BufWriter::new(file);
+                let options = EncodingOptions::default();
+                write_to_file_with_options(&mut writer, EncodingFormat::Ppm, &options, image)?;
+                writer.flush()?;
+                Ok(())
+            }
+            WriteMethod::Mapped => write_ppm_mapped(file, &image),
+        }
+    }
+}
+
+/// Fastest of the writes of [`bench_io`] with a [`WriteMethod`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WriteTiming {
+    pub method: WriteMethod,
+    /// Size of the written ppm, header included.
+    pub bytes: u64,
+    pub fastest: Duration,
+}
+
+impl WriteTiming {
+    pub fn megabytes_per_second(&self) -> f64 {
+        self.bytes as f64 / (1024.0 * 1024.0) / self.fastest.as_secs_f64()
+    }
+}
+
+/// MiB written by `wayshot bench` by default, above [`MAPPED_WRITE_THRESHOLD`] like the
+/// multi-output captures memory mapped writes are for.
+///
+/// [`MAPPED_WRITE_THRESHOLD`]: crate::MAPPED_WRITE_THRESHOLD
+pub const DEFAULT_BENCH_SIZE: u64 = 256;
+
+/// Writes `wayshot bench` times with each method by default.
+pub const DEFAULT_BENCH_RUNS: u32 = 3;
+
+/// Width of the images written by [`bench_io`], that of an 8K output.
+const BENCH_WIDTH: u32 = 7680;
+
+/// Write a ppm of about `megabytes` MiB `runs` times with every [`WriteMethod`] and time the
+/// fastest write of each, eg: to check whether memory mapped writes pay off on a file system.
+/// Every write goes to the same file in `directory`, removed afterwards, and is synced to disk
+/// as the memory map is when flushed, so that the page cache does not hide the cost of either.
+pub fn bench_io(
+    directory: &Path,
+    megabytes: u64,
+    runs: u32,
+) -> Result<Vec<WriteTiming>, Box<dyn Error>> {
+    let height = (megabytes * 1024 * 1024 / (3 * BENCH_WIDTH as u64)).max(1) as u32;
+    let image = RgbaImage::from_fn(BENCH_WIDTH, height, |x, y| {
+        Rgba([x as u8, y as u8, (x ^ y) as u8, 255])
+    });
+    let path = directory.join(format!(".wayshot-bench-{}.ppm", process::id()));
+    let timings = WriteMethod::ALL
+        .into_iter()
+        .map(|method| {
+            let mut fastest = Duration::MAX;
+            for _ in 0..runs {
+                let file = OpenOptions::new()
+                    .read(true)
+                    .write(true)
+                    .create(true)
+                    .truncate(true)
+                    .open(&path)?;
+                let image = image.clone();
+                let start = Instant::now();
+                method.write(&file, image)?;
+                file.sync_all()?;
+                fastest = fastest.min(start.elapsed());
+            }
+            Ok(WriteTiming {
+                method,
+                bytes: fs::metadata(&path)?.len(),
+                fastest,
+            })
+        })
+        .collect();
+    // Removed whether the writes succeeded or not, a missing file means none was written.
+    let _ = fs::remove_file(&path);
+    timings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_method_writes_the_same_ppm() {
+        let directory = crate::testing::TempDirectory::new();
+        let timings = bench_io(directory.path(), 1, 2).unwrap();
+        let methods: Vec<WriteMethod> = timings.iter().map(|timing| timing.method).collect();
+        assert_eq!(methods, WriteMethod::ALL);
+        let header = format!("P6\n{} 45 255\n", BENCH_WIDTH);
+        for timing in &timings {
+            assert_eq!(timing.bytes, (header.len() + 3 * 7680 * 45) as u64);
+            assert!(timing.megabytes_per_second() > 0.0);
+        }
+        assert_eq!(fs::read_dir(directory.path()).unwrap().count(), 0);
+    }
+}
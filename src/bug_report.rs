@@ -0,0 +1,159 @@
+use std::{env, fmt::Write, fs::File, io, path::Path};
+
+use wayshot_core::Transform;
+
+use crate::{
+    backend::{FrameFormat, RawCapture},
+    capabilities::json_string,
+    pipeline::{create_dir, create_file},
+    Capabilities, OutputMode, Quirks, Region, QUIRK_NAMES,
+};
+
+/// Name of the file the logs of the run are written to in a --bug-report directory.
+pub const BUG_REPORT_LOG: &str = "wayshot.log";
+
+/// Name of the file [`Provenance`] is written to in a --bug-report directory.
+pub const BUG_REPORT_PROVENANCE: &str = "provenance.json";
+
+/// Environment variables telling sessions and compositors apart.
+const PROVENANCE_ENVIRONMENT: [&str; 6] = [
+    "WAYLAND_DISPLAY",
+    "XDG_CURRENT_DESKTOP",
+    "XDG_SESSION_DESKTOP",
+    "XDG_SESSION_TYPE",
+    "DESKTOP_SESSION",
+    "LANG",
+];
+
+/// Create the --bug-report `directory` and the log file in it, see [`BUG_REPORT_LOG`].
+pub fn create_bug_report(directory: &Path, private: bool) -> io::Result<File> {
+    create_dir(directory, private)?;
+    create_file(&directory.join(BUG_REPORT_LOG), private)
+}
+
+/// A frame as the compositor copied it, before it was converted.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrameProvenance {
+    pub output: String,
+    /// Captured area in global logical coordinates.
+    pub region: Region,
+    pub format: FrameFormat,
+    pub transform: Transform,
+    pub mode: Option<OutputMode>,
+}
+
+impl FrameProvenance {
+    pub fn from_capture(capture: &RawCapture) -> Self {
+        Self {
+            output: capture.name.clone(),
+            region: capture.region,
+            format: capture.frame_format,
+            transform: capture.transform,
+            mode: capture.mode,
+        }
+    }
+}
+
+/// Where a capture comes from, saved by --bug-report next to the screenshot and the logs so that
+/// reports can be reproduced: the build of wayshot, the compositor and the globals it
+/// advertises with their versions, the quirks in use, the command line, the session and the
+/// frames of every output.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Provenance {
+    pub capabilities: Capabilities,
+    pub quirks: Quirks,
+    pub arguments: Vec<String>,
+    /// Variables of [`PROVENANCE_ENVIRONMENT`], None when they are not set.
+    pub environment: Vec<(&'static str, Option<String>)>,
+    pub frames: Vec<FrameProvenance>,
+    /// Why the capture failed, if it did, in which case there are no frames.
+    pub error: Option<String>,
+}
+
+impl Provenance {
+    /// Provenance of this run, before anything is captured.
+    pub fn collect(quirks: Quirks) -> Self {
+        Self {
+            capabilities: Capabilities::detect(),
+            quirks,
+            arguments: env::args_os()
+                .map(|argument| argument.to_string_lossy().into_owned())
+                .collect(),
+            environment: PROVENANCE_ENVIRONMENT
+                .iter()
+                .map(|&name| (name, env::var_os(name).map(|v| v.to_string_lossy().into())))
+                .collect(),
+            frames: Vec::new(),
+            error: None,
+        }
+    }
+
+    /// The provenance as a JSON object, eg: `{"capabilities": {...}, "quirks": {"y-invert":
+    /// true, ...}, "arguments": ["wayshot", ...], "environment": {"WAYLAND_DISPLAY":
+    /// "wayland-1", ...}, "frames": [{"output": "DP-1", "x": 0, "y": 0, "width": 1920,
+    /// "height": 1080, "format": "Xrgb8888", "buffer_width": 1920, "buffer_height": 1080,
+    /// "stride": 7680, "transform": "Normal", "mode": "1920x1080@60.000Hz"}], "error": null}`,
+    /// see [`Capabilities::to_json`].
+    pub fn to_json(&self) -> String {
+        let mut json = format!(
+            "{{\"capabilities\": {}, \"quirks\": {{",
+            self.capabilities.to_json()
+        );
+        for (index, name) in QUIRK_NAMES.iter().enumerate() {
+            let separator = if index == 0 { "" } else { ", " };
+            let enabled = self.quirks.get(name).unwrap_or_default();
+            let _ = write!(json, "{}{}: {}", separator, json_string(name), enabled);
+        }
+        json.push_str("}, \"arguments\": [");
+        let arguments: Vec<String> = self.arguments.iter().map(|a| json_string(a)).collect();
+        json.push_str(&arguments.join(", "));
+        json.push_str("], \"environment\": {");
+        for (index, (name, value)) in self.environment.iter().enumerate() {
+            let separator = if index == 0 { "" } else { ", " };
+            let value = value.as_deref().map_or("null".to_string(), json_string);
+            let _ = write!(json, "{}{}: {}", separator, json_string(name), value);
+        }
+        json.push_str("}, \"frames\": [");
+        for (index, frame) in self.frames.iter().enumerate() {
+            let separator = if index == 0 { "" } else { ", " };
+            let mode = frame
+                .mode
+                .map_or("null".to_string(), |mode| json_string(&mode.to_string()));
+            let _ = write!(
+                json,
+                "{}{{\"output\": {}, \"x\": {}, \"y\": {}, \"width\": {}, \"height\": {}, \
+                 \"format\": {}, \"buffer_width\": {}, \"buffer_height\": {}, \"stride\": {}, \
+                 \"transform\": {}, \"mode\": {}}}",
+                separator,
+                json_string(&frame.output),
+                frame.region.x,
+                frame.region.y,
+                frame.region.width,
+                frame.region.height,
+                json_string(&format!("{:?}", frame.format.format)),
+                frame.format.width,
+                frame.format.height,
+                frame.format.stride,
+                json_string(&format!("{:?}", frame.transform)),
+                mode
+            );
+        }
+        let error = self
+            .error
+            .as_deref()
+            .map_or("null".to_string(), json_string);
+        let _ = write!(json, "], \"error\": {}}}", error);
+        json
+    }
+}
+
+/// Write `provenance` to the --bug-report `directory`, see [`BUG_REPORT_PROVENANCE`].
+pub fn write_provenance(
+    directory: &Path,
+    provenance: &Provenance,
+    private: bool,
+) -> io::Result<()> {
+    let mut file = create_file(&directory.join(BUG_REPORT_PROVENANCE), private)?;
+    io::Write::write_all(&mut file, provenance.to_json().as_bytes())?;
+    io::Write::write_all(&mut file, b"\n")
+}
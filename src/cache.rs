@@ -0,0 +1,247 @@
+use std::{
+    env,
+    error::Error,
+    fs,
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
+
+use wayland_client::protocol::wl_output::Transform;
+
+use crate::{
+    output::{OutputInfo, OutputMode, OutputPositioning},
+    pipeline,
+};
+
+/// Geometry of an output remembered by --fast.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CachedOutput {
+    /// Name of its wl_output global, which the compositor changes whenever it is plugged in.
+    pub global: u32,
+    pub name: String,
+    pub dimensions: OutputPositioning,
+    pub mode: Option<OutputMode>,
+    pub transform: Transform,
+    pub scale: i32,
+}
+
+impl CachedOutput {
+    pub fn from_info(output: &OutputInfo) -> Self {
+        Self {
+            global: output.global,
+            name: output.name.clone(),
+            dimensions: output.dimensions.clone(),
+            mode: output.mode,
+            transform: output.transform,
+            scale: output.scale,
+        }
+    }
+
+    /// Fill in `output` as if the compositor had described it again.
+    pub fn apply(&self, output: &mut OutputInfo) {
+        output.name = self.name.clone();
+        output.dimensions = self.dimensions.clone();
+        output.mode = self.mode;
+        output.transform = self.transform;
+        output.scale = self.scale;
+        output.wl_position = (self.dimensions.x, self.dimensions.y);
+        output.wl_ready = true;
+        output.xdg_ready = true;
+    }
+}
+
+/// How long the cache is trusted after it is written. Outputs moved, rotated or rescaled
+/// without being plugged again do not change the [`layout_hash`], so the geometry learned
+/// before is only kept for bursts of captures.
+pub const OUTPUT_CACHE_TTL: Duration = Duration::from_secs(60);
+
+/// Output geometry of the last run with --fast, along with the [`layout_hash`] of the outputs
+/// it was learned from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputCache {
+    pub layout_hash: u32,
+    pub outputs: Vec<CachedOutput>,
+}
+
+/// `$XDG_STATE_HOME/wayshot/outputs`, or `~/.local/state/wayshot/outputs` when it is not set.
+pub fn output_cache_path() -> PathBuf {
+    let directory = match env::var_os("XDG_STATE_HOME") {
+        Some(directory) if !directory.is_empty() => PathBuf::from(directory),
+        _ => PathBuf::from(env::var_os("HOME").unwrap_or_default()).join(".local/state"),
+    };
+    directory.join("wayshot/outputs")
+}
+
+/// Hash of the names of the wl_output globals of the compositor and of the display they belong
+/// to. Changes whenever an output is plugged in or out, which is all the first roundtrip tells,
+/// but not when outputs are moved or rescaled.
+pub fn layout_hash(globals: &[u32]) -> u32 {
+    let mut hasher = crc32fast::Hasher::new();
+    let display = env::var_os("WAYLAND_DISPLAY").unwrap_or_default();
+    hasher.update(display.as_encoded_bytes());
+    let mut globals = globals.to_vec();
+    globals.sort_unstable();
+    for name in globals {
+        hasher.update(&name.to_le_bytes());
+    }
+    hasher.finalize()
+}
+
+/// Read the cache written by [`save_output_cache`], None if there is none, it is older than
+/// [`OUTPUT_CACHE_TTL`] or it can not be parsed, eg: after an upgrade changed its format.
+pub fn load_output_cache() -> Option<OutputCache> {
+    let path = output_cache_path();
+    let written = fs::metadata(&path)
+        .and_then(|metadata| metadata.modified())
+        .ok()?;
+    if !is_fresh(written, SystemTime::now()) {
+        tracing::debug!("Ignoring the output cache {:?}, it expired", path);
+        return None;
+    }
+    let text = fs::read_to_string(&path).ok()?;
+    let cache = parse_output_cache(&text);
+    if cache.is_none() {
+        tracing::debug!("Ignoring the invalid output cache {:?}", path);
+    }
+    cache
+}
+
+/// Whether a cache written at `written` can still be trusted at `now`. Caches written in the
+/// future, eg: before the clock was set back, are not.
+fn is_fresh(written: SystemTime, now: SystemTime) -> bool {
+    now.duration_since(written)
+        .map(|age| age <= OUTPUT_CACHE_TTL)
+        .unwrap_or(false)
+}
+
+/// Write `cache` for the next run with --fast, creating its directory if needed.
+pub fn save_output_cache(cache: &OutputCache) -> Result<(), Box<dyn Error>> {
+    let path = output_cache_path();
+    if let Some(directory) = path.parent() {
+        pipeline::create_dir(directory, false)
+            .map_err(|e| format!("Failed to create {:?}: {}", directory, e))?;
+    }
+    fs::write(&path, format_output_cache(cache))
+        .map_err(|e| format!("Failed to write {:?}: {}", path, e).into())
+}
+
+/// The cache as text, a "hash HASH" line followed by a line per output: its global, name,
+/// logical position and size, mode, transform and scale, eg:
+/// "output 42 DP-1 0,0 1920x1080 3840x2160@60000 0 2". Outputs without a known mode have -
+/// in its place.
+fn format_output_cache(cache: &OutputCache) -> String {
+    let mut text = format!("hash {:08x}\n", cache.layout_hash);
+    for output in &cache.outputs {
+        let mode = match output.mode {
+            Some(mode) => format!("{}x{}@{}", mode.width, mode.height, mode.refresh),
+            None => "-".to_string(),
+        };
+        let dimensions = &output.dimensions;
+        text.push_str(&format!(
+            "output {} {} {},{} {}x{} {} {} {}\n",
+            output.global,
+            output.name,
+            dimensions.x,
+            dimensions.y,
+            dimensions.width,
+            dimensions.height,
+            mode,
+            output.transform as u32,
+            output.scale
+        ));
+    }
+    text
+}
+
+fn parse_output_cache(text: &str) -> Option<OutputCache> {
+    let mut lines = text.lines();
+    let layout_hash = u32::from_str_radix(lines.next()?.strip_prefix("hash ")?, 16).ok()?;
+    let outputs = lines
+        .map(|line| {
+            let fields: Vec<&str> = line.strip_prefix("output ")?.split(' ').collect();
+            if let [global, name, position, size, mode, transform, scale] = fields[..] {
+                let (x, y) = position.split_once(',')?;
+                let (width, height) = size.split_once('x')?;
+                let mode = match mode {
+                    "-" => None,
+                    mode => {
+                        let (size, refresh) = mode.split_once('@')?;
+                        let (width, height) = size.split_once('x')?;
+                        Some(OutputMode {
+                            width: width.parse().ok()?,
+                            height: height.parse().ok()?,
+                            refresh: refresh.parse().ok()?,
+                        })
+                    }
+                };
+                Some(CachedOutput {
+                    global: global.parse().ok()?,
+                    name: name.to_string(),
+                    dimensions: OutputPositioning {
+                        x: x.parse().ok()?,
+                        y: y.parse().ok()?,
+                        width: width.parse().ok()?,
+                        height: height.parse().ok()?,
+                    },
+                    mode,
+                    transform: Transform::try_from(transform.parse::<u32>().ok()?).ok()?,
+                    scale: scale.parse().ok()?,
+                })
+            } else {
+                None
+            }
+        })
+        .collect::<Option<_>>()?;
+    Some(OutputCache {
+        layout_hash,
+        outputs,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn caches_expire() {
+        let written = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+        assert!(is_fresh(written, written));
+        assert!(is_fresh(written, written + OUTPUT_CACHE_TTL));
+        assert!(!is_fresh(
+            written,
+            written + OUTPUT_CACHE_TTL + Duration::from_secs(1)
+        ));
+        assert!(!is_fresh(written, written - Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn caches_round_trip_through_text() {
+        let cache = OutputCache {
+            layout_hash: 0xdead_beef,
+            outputs: vec![CachedOutput {
+                global: 42,
+                name: "DP-1".to_string(),
+                dimensions: OutputPositioning {
+                    x: -1920,
+                    y: 0,
+                    width: 1920,
+                    height: 1080,
+                },
+                mode: Some(OutputMode {
+                    width: 3840,
+                    height: 2160,
+                    refresh: 60000,
+                }),
+                transform: Transform::_90,
+                scale: 2,
+            }],
+        };
+        let text = format_output_cache(&cache);
+        assert_eq!(
+            text,
+            "hash deadbeef\noutput 42 DP-1 -1920,0 1920x1080 3840x2160@60000 1 2\n"
+        );
+        assert_eq!(parse_output_cache(&text), Some(cache));
+        assert_eq!(parse_output_cache("hash deadbeef\noutput 42 DP-1\n"), None);
+    }
+}
@@ -0,0 +1,164 @@
+use std::{error::Error, fmt::Write};
+
+use wayland_client::{protocol::wl_registry, Connection, Dispatch, QueueHandle};
+
+use crate::{dispatch_error, Compositor, EncodingFormat};
+
+/// Cargo features of wayshot along with whether this build has them.
+pub const FEATURES: [(&str, bool); 7] = [
+    ("annotations", cfg!(feature = "annotations")),
+    ("archive", cfg!(feature = "archive")),
+    ("chrome-trace", cfg!(feature = "chrome-trace")),
+    ("compositor-ipc", cfg!(feature = "compositor-ipc")),
+    ("jpeg", cfg!(feature = "jpeg")),
+    ("scripting", cfg!(feature = "scripting")),
+    ("tiff", cfg!(feature = "tiff")),
+];
+
+/// Every encoder, whether it was compiled in or not.
+const ENCODERS: [EncodingFormat; 7] = [
+    EncodingFormat::Png,
+    EncodingFormat::Jpg,
+    EncodingFormat::Ppm,
+    EncodingFormat::Bmp,
+    EncodingFormat::Tiff,
+    EncodingFormat::Ico,
+    EncodingFormat::Wfz,
+];
+
+/// What this build of wayshot and the compositor it runs on support, reported by `wayshot
+/// capabilities` so that frontends can adapt to them instead of trying invocations out.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Capabilities {
+    pub version: &'static str,
+    pub features: Vec<(&'static str, bool)>,
+    /// Extensions of the encoders compiled in.
+    pub encoders: Vec<&'static str>,
+    pub compositor: Compositor,
+    /// Interface and version of every global the compositor advertises, None if it can not be
+    /// reached.
+    pub globals: Option<Vec<(String, u32)>>,
+}
+
+impl Capabilities {
+    /// Capabilities of this build, asking the compositor of WAYLAND_DISPLAY for its globals.
+    pub fn detect() -> Self {
+        let globals = match list_globals() {
+            Ok(globals) => Some(globals),
+            Err(e) => {
+                tracing::debug!("Failed to list the globals of the compositor: {}", e);
+                None
+            }
+        };
+        Self {
+            version: env!("CARGO_PKG_VERSION"),
+            features: FEATURES.to_vec(),
+            encoders: ENCODERS
+                .iter()
+                .filter(|format| format.is_available())
+                .map(EncodingFormat::extension)
+                .collect(),
+            compositor: Compositor::detect(),
+            globals,
+        }
+    }
+
+    /// Whether screenshots can be taken, which needs the compositor to advertise screencopy
+    /// and shared memory buffers.
+    pub fn can_capture(&self) -> bool {
+        let has = |interface: &str| {
+            self.globals
+                .iter()
+                .flatten()
+                .any(|(name, _)| name == interface)
+        };
+        has("zwlr_screencopy_manager_v1") && has("wl_shm")
+    }
+
+    /// The capabilities as a JSON object, eg: `{"version": "1.2.2", "features": {"jpeg":
+    /// true, ...}, "encoders": ["png", ...], "compositor": "sway", "can_capture": true,
+    /// "globals": {"wl_shm": 1, ...}}`. Globals are null when the compositor can not be reached.
+    pub fn to_json(&self) -> String {
+        let mut json = format!(
+            "{{\"version\": {}, \"features\": {{",
+            json_string(self.version)
+        );
+        for (index, (name, enabled)) in self.features.iter().enumerate() {
+            let separator = if index == 0 { "" } else { ", " };
+            let _ = write!(json, "{}{}: {}", separator, json_string(name), enabled);
+        }
+        json.push_str("}, \"encoders\": [");
+        let encoders: Vec<String> = self.encoders.iter().map(|name| json_string(name)).collect();
+        json.push_str(&encoders.join(", "));
+        let _ = write!(
+            json,
+            "], \"compositor\": {}, \"can_capture\": {}, \"globals\": ",
+            json_string(&self.compositor.to_string()),
+            self.can_capture()
+        );
+        match &self.globals {
+            Some(globals) => {
+                json.push('{');
+                for (index, (interface, version)) in globals.iter().enumerate() {
+                    let separator = if index == 0 { "" } else { ", " };
+                    let _ = write!(json, "{}{}: {}", separator, json_string(interface), version);
+                }
+                json.push('}');
+            }
+            None => json.push_str("null"),
+        }
+        json.push('}');
+        json
+    }
+}
+
+/// `text` as a quoted JSON string.
+pub(crate) fn json_string(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            c if c.is_control() => {
+                let _ = write!(quoted, "\\u{:04x}", c as u32);
+            }
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Globals advertised by the compositor, in the order it lists them. Nothing is bound.
+#[derive(Default)]
+struct Globals(Vec<(String, u32)>);
+
+impl Dispatch<wl_registry::WlRegistry, ()> for Globals {
+    fn event(
+        state: &mut Self,
+        _: &wl_registry::WlRegistry,
+        event: wl_registry::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let wl_registry::Event::Global {
+            interface, version, ..
+        } = event
+        {
+            state.0.push((interface, version));
+        }
+    }
+}
+
+fn list_globals() -> Result<Vec<(String, u32)>, Box<dyn Error>> {
+    let conn = Connection::connect_to_env()?;
+    let mut event_queue = conn.new_event_queue();
+    let _registry = conn.display().get_registry(&event_queue.handle(), ());
+    let mut globals = Globals::default();
+    event_queue
+        .roundtrip(&mut globals)
+        .map_err(dispatch_error)?;
+    Ok(globals.0)
+}
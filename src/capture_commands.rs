@@ -0,0 +1,90 @@
+use std::{
+    error::Error,
+    io::stderr,
+    os::fd::AsFd,
+    process::{Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::i18n::{tr_args, Message};
+
+/// Time --pre-capture and --post-capture commands get before they are killed, unless
+/// --hook-timeout says otherwise.
+pub const DEFAULT_HOOK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How often a running capture command is checked on.
+const HOOK_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// What to do when a capture command fails, exits with an error or times out.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum HookFailure {
+    /// Give up on the screenshot.
+    Abort,
+    /// Log a warning and go on.
+    #[default]
+    Warn,
+    /// Go on, only logging at debug level.
+    Ignore,
+}
+
+/// Shell command run right before the frames are captured or right after, eg: to hide a bar
+/// and show it again.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CaptureCommand {
+    /// Run with `sh -c`.
+    pub command: String,
+    /// Time after which the shell is killed. Commands it started in the background keep
+    /// running.
+    pub timeout: Duration,
+    pub failure: HookFailure,
+}
+
+impl CaptureCommand {
+    /// Run the command as the `name` hook with `env` added to its environment, handling
+    /// failures as `failure` says. Its output goes to stderr, so that it does not end up in
+    /// images written to stdout.
+    pub fn run(&self, name: &str, env: &[(&str, String)]) -> Result<(), Box<dyn Error>> {
+        tracing::debug!("Running the {} command {:?}", name, self.command);
+        let error = match self.execute(env) {
+            Ok(()) => return Ok(()),
+            Err(e) => tr_args(
+                Message::CaptureCommandFailed,
+                &[("name", name.into()), ("error", e.to_string().into())],
+            ),
+        };
+        match self.failure {
+            HookFailure::Abort => return Err(error.into()),
+            HookFailure::Warn => tracing::warn!("{}", error),
+            HookFailure::Ignore => tracing::debug!("{}", error),
+        }
+        Ok(())
+    }
+
+    fn execute(&self, env: &[(&str, String)]) -> Result<(), Box<dyn Error>> {
+        let output = stderr().as_fd().try_clone_to_owned()?;
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg(&self.command)
+            .envs(env.iter().map(|(name, value)| (name, value)))
+            .stdin(Stdio::null())
+            .stdout(output)
+            .spawn()
+            .map_err(|e| format!("failed to spawn sh: {}", e))?;
+        let deadline = Instant::now() + self.timeout;
+        loop {
+            if let Some(status) = child.try_wait()? {
+                if !status.success() {
+                    return Err(format!("exited with {}", status).into());
+                }
+                return Ok(());
+            }
+            if Instant::now() >= deadline {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(format!("timed out after {} ms", self.timeout.as_millis()).into());
+            }
+            thread::sleep(HOOK_POLL_INTERVAL);
+        }
+    }
+}
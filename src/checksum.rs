@@ -0,0 +1,82 @@
+use std::{
+    fs::File,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use sha2::{Digest, Sha256};
+
+use crate::pipeline::create_file;
+
+/// Hash written next to every saved file by --checksum, see [`write_checksum`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Checksum {
+    Sha256,
+}
+
+impl Checksum {
+    /// Extension of sidecar files, which is also the name of the tool checking them without
+    /// its sum suffix, eg: `sha256sum -c shot.png.sha256`.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Checksum::Sha256 => "sha256",
+        }
+    }
+
+    /// Hash of the file at `path` as lowercase hex.
+    pub fn of_file(self, path: &Path) -> io::Result<String> {
+        match self {
+            Checksum::Sha256 => {
+                let mut hasher = Sha256::new();
+                io::copy(&mut File::open(path)?, &mut hasher)?;
+                Ok(hasher
+                    .finalize()
+                    .iter()
+                    .map(|byte| format!("{:02x}", byte))
+                    .collect())
+            }
+        }
+    }
+}
+
+/// Hash the file at `path` and write it to a sidecar next to it, eg: shot.png.sha256, in the
+/// format of sha256sum. Returns the hash.
+pub fn write_checksum(checksum: Checksum, path: &Path, private: bool) -> io::Result<String> {
+    let hash = checksum.of_file(path)?;
+    let mut sidecar_name = path.file_name().unwrap_or_default().to_os_string();
+    sidecar_name.push(".");
+    sidecar_name.push(checksum.extension());
+    let sidecar = PathBuf::from(path).with_file_name(sidecar_name);
+    tracing::debug!(
+        "Writing the {} of {:?} to {:?}",
+        checksum.extension(),
+        path,
+        sidecar
+    );
+    let mut file = create_file(&sidecar, private)?;
+    file.write_all(hash.as_bytes())?;
+    file.write_all(b"  ")?;
+    file.write_all(path.file_name().unwrap_or_default().as_encoded_bytes())?;
+    file.write_all(b"\n")?;
+    Ok(hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::testing::TempDirectory;
+
+    #[test]
+    fn sidecars_hold_the_hash_and_name_like_sha256sum() {
+        let directory = TempDirectory::new();
+        let path = directory.path().join("shot.png");
+        fs::write(&path, b"abc").unwrap();
+        let abc = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+
+        assert_eq!(write_checksum(Checksum::Sha256, &path, false).unwrap(), abc);
+        let sidecar = fs::read_to_string(directory.path().join("shot.png.sha256")).unwrap();
+        assert_eq!(sidecar, format!("{}  shot.png\n", abc));
+    }
+}
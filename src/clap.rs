@@ -1,34 +1,273 @@
-use clap::{arg, Command};
+use std::{ffi::OsString, path::Path};
+
+use clap::{arg, ArgGroup, ArgMatches, Command};
+
+use crate::options::{
+    ANALYSES, CHECKSUMS, COLLISIONS, COLOR_VISIONS, CURSOR_MODES, ENCODINGS, HOOK_FAILURES,
+    JPEG_SUBSAMPLINGS, PNG_FILTERS, REDACT_STYLES, STACK_MODES, TIFF_COMPRESSIONS,
+    TONE_MAP_OPERATORS, ZOOM_FILTERS,
+};
 
 pub fn set_flags() -> Command<'static> {
     let app = Command::new("wayshot")
         .version(env!("CARGO_PKG_VERSION"))
         .author(env!("CARGO_PKG_AUTHORS"))
         .about("Screenshot tool for compositors implementing zwlr_screencopy_v1.")
+        .arg(
+            arg!(--"capture-on-present")
+                .required(false)
+                .takes_value(false)
+                .help("Wait for the next presented frame that changed instead of copying the last one"),
+        )
+        .arg(
+            arg!(--verify)
+                .required(false)
+                .takes_value(false)
+                .help("Fail with exit status 3 instead of saving frames of a single color"),
+        )
+        .arg(
+            arg!(--stack <N>)
+                .required(false)
+                .takes_value(true)
+                .help("Capture N frames in a row and combine them to reduce dithering noise"),
+        )
+        .arg(
+            arg!(--"stack-mode" <MODE>)
+                .required(false)
+                .takes_value(true)
+                .requires("stack")
+                .help(STACK_MODES.help("How stacked frames are combined: {}")),
+        )
+        .arg(
+            arg!(--"no-hooks")
+                .required(false)
+                .takes_value(false)
+                .help("Do not run the hooks of ~/.config/wayshot/hooks.rhai"),
+        )
+        .arg(
+            arg!(--"max-duration" <MILLISECONDS>)
+                .required(false)
+                .takes_value(true)
+                .help("Give up if capturing and writing take longer than MILLISECONDS"),
+        )
+        .arg(
+            arg!(--"output-timeout" <MILLISECONDS>)
+                .required(false)
+                .takes_value(true)
+                .help("Leave out outputs whose frame is not copied within MILLISECONDS"),
+        )
+        .arg(
+            arg!(--"pre-capture" <COMMAND>)
+                .required(false)
+                .takes_value(true)
+                .env("WAYSHOT_PRE_CAPTURE")
+                .help("Run COMMAND with sh right before the frames are captured, eg: to hide a bar"),
+        )
+        .arg(
+            arg!(--"post-capture" <COMMAND>)
+                .required(false)
+                .takes_value(true)
+                .env("WAYSHOT_POST_CAPTURE")
+                .help("Run COMMAND with sh right after the frames are captured, eg: to show a bar again"),
+        )
+        .arg(
+            arg!(--"hook-timeout" <MILLISECONDS>)
+                .required(false)
+                .takes_value(true)
+                .help("Kill --pre-capture and --post-capture commands after MILLISECONDS (defaults to 5000)"),
+        )
+        .arg(
+            arg!(--"hook-failure" <POLICY>)
+                .required(false)
+                .takes_value(true)
+                .help(HOOK_FAILURES.help("What to do when a capture command fails: {}")),
+        )
+        .arg(
+            arg!(--dnd)
+                .required(false)
+                .takes_value(false)
+                .help("Turn on do-not-disturb of dunst or mako while capturing or serving"),
+        )
+        .arg(
+            arg!(--quirks <QUIRKS>)
+                .required(false)
+                .takes_value(true)
+                .allow_hyphen_values(true)
+                .help("Turn compositor workarounds on or off, eg: -y-invert, or show them with list"),
+        )
+        .arg(
+            arg!(--"no-lock")
+                .required(false)
+                .takes_value(false)
+                .help("Capture right away instead of waiting for other running wayshot instances"),
+        )
         .arg(
             arg!(-d - -debug)
                 .required(false)
                 .takes_value(false)
                 .help("Enable debug mode"),
         )
+        .arg(
+            arg!(-q - -quiet)
+                .required(false)
+                .takes_value(false)
+                .conflicts_with("debug")
+                .help("Only print warnings and errors, without progress bars"),
+        )
+        .arg(
+            arg!(--"trace-output" <FILE>)
+                .required(false)
+                .takes_value(true)
+                .allow_invalid_utf8(true)
+                .help("Record a Chrome trace of the capture stages to FILE for profiling"),
+        )
         .arg(
             arg!(-s --slurp <GEOMETRY>)
                 .required(false)
                 .takes_value(true)
                 .help("Choose a portion of your display to screenshot using slurp"),
         )
+        .arg(
+            arg!(-g --geometry <GEOMETRY>)
+                .required(false)
+                .takes_value(true)
+                .conflicts_with_all(&["slurp", "output", "region-provider"])
+                .help("Output name, output relative region or global region, eg: \"DP-1:100,100 300x300\", \"-\" reads it from stdin"),
+        )
+        .arg(
+            arg!(-r --"region-provider" <PROVIDER>)
+                .required(false)
+                .takes_value(true)
+                .possible_values(crate::region::PROVIDER_NAMES)
+                .conflicts_with_all(&["slurp", "output"])
+                .help("Pick the region with slurp, read it from stdin or use the focused window"),
+        )
+        .arg(
+            arg!(--"app-id" <APP_ID>)
+                .required(false)
+                .takes_value(true)
+                .conflicts_with_all(&[
+                    "slurp",
+                    "geometry",
+                    "region-provider",
+                    "output",
+                    "stdout",
+                    "stream",
+                    "separate-outputs",
+                    "all-clones",
+                ])
+                .help("Capture every visible window of an application to its own file"),
+        )
+        .arg(
+            arg!(--crop <CROP>)
+                .required(false)
+                .takes_value(true)
+                .multiple_occurrences(true)
+                .conflicts_with_all(&[
+                    "slurp",
+                    "geometry",
+                    "region-provider",
+                    "app-id",
+                    "output",
+                    "stdout",
+                    "stream",
+                    "separate-outputs",
+                    "all-clones",
+                    "layout",
+                ])
+                .help("Write the region GEOMETRY of the capture to its own file named after NAME, given as NAME=GEOMETRY"),
+        )
+        .arg(
+            arg!(--nth <N>)
+                .required(false)
+                .takes_value(true)
+                .requires("app-id")
+                .help("Capture only the Nth window of --app-id, counting from 1"),
+        )
+        .arg(
+            arg!(--"with-decorations")
+                .required(false)
+                .takes_value(false)
+                .requires("window")
+                .conflicts_with("without-decorations")
+                .help("Capture windows with their server side title bar and borders"),
+        )
+        .arg(
+            arg!(--"without-decorations")
+                .required(false)
+                .takes_value(false)
+                .requires("window")
+                .alias("exclude-decorations")
+                .help("Capture windows without title bar, borders and shadows"),
+        )
+        .group(
+            ArgGroup::new("window")
+                .args(&["region-provider", "app-id"])
+                .multiple(true),
+        )
         .arg(
             arg!(-f - -file <FILE_PATH>)
                 .required(false)
                 .conflicts_with("stdout")
                 .takes_value(true)
+                .allow_invalid_utf8(true)
                 .help("Mention a custom file path"),
         )
         .arg(
-            arg!(-c - -cursor)
+            arg!(-c --cursor [MODE])
+                .required(false)
+                .min_values(0)
+                .require_equals(true)
+                .env("WAYSHOT_CURSOR")
+                .help(CURSOR_MODES.help("Enable cursor in screenshots: {}, force draws it from the cursor theme")),
+        )
+        .arg(
+            arg!(--"filename-format" <FORMAT>)
+                .required(false)
+                .takes_value(true)
+                .env("WAYSHOT_FILENAME_FORMAT")
+                .help("strftime format of generated file names in the local timezone, eg: %Y%m%d-%H%M%S-wayshot"),
+        )
+        .arg(
+            arg!(--"auto-degrade")
                 .required(false)
                 .takes_value(false)
-                .help("Enable cursor in screenshots"),
+                .help("Write images in a more compressed format when they would not fit on the disk"),
+        )
+        .arg(
+            arg!(--"max-size" <SIZE>)
+                .required(false)
+                .takes_value(true)
+                .conflicts_with("dzi")
+                .help("Lower the jpg quality until images fit in SIZE, eg: 2MB, switching other formats to jpg"),
+        )
+        .arg(
+            arg!(--checksum <ALGORITHM>)
+                .required(false)
+                .takes_value(true)
+                .conflicts_with("dzi")
+                .help(CHECKSUMS.help("Write the {} of every saved file next to it, eg: shot.png.sha256")),
+        )
+        .arg(
+            arg!(--deterministic)
+                .required(false)
+                .takes_value(false)
+                .help("Leave timestamps out of images so identical pixels give identical files"),
+        )
+        .arg(
+            arg!(--collision <POLICY>)
+                .required(false)
+                .takes_value(true)
+                .env("WAYSHOT_COLLISION")
+                .help(COLLISIONS.help("What to do when a file exists: {}")),
+        )
+        .arg(
+            arg!(--dir <DIRECTORY>)
+                .required(false)
+                .takes_value(true)
+                .env("WAYSHOT_DIR")
+                .allow_invalid_utf8(true)
+                .help("Directory for screenshots without an explicit --file path"),
         )
         .arg(
             arg!(--stdout)
@@ -37,11 +276,92 @@ pub fn set_flags() -> Command<'static> {
                 .takes_value(false)
                 .help("Output the image data to standard out"),
         )
+        .arg(
+            arg!(--"bug-report" <DIRECTORY>)
+                .required(false)
+                .takes_value(true)
+                .conflicts_with_all(&["file", "stdout"])
+                .allow_invalid_utf8(true)
+                .help("Save the screenshot to DIRECTORY along with the logs and details of the capture for bug reports"),
+        )
+        .arg(
+            arg!(--analyze <ANALYSES>)
+                .required(false)
+                .takes_value(true)
+                .conflicts_with_all(&["stdout", "stream"])
+                .help(ANALYSES.help("Print measurements of the image as JSON on stdout, comma separated: {}")),
+        )
+        .arg(
+            arg!(--"analyze-only")
+                .required(false)
+                .takes_value(false)
+                .requires("analyze")
+                .help("Print the measurements of --analyze without saving the image"),
+        )
         .arg(
             arg!(-e --extension <FILE_EXTENSION>)
                 .required(false)
                 .takes_value(true)
-                .help("Set image encoder (Png is default)"),
+                .multiple_occurrences(true)
+                .env("WAYSHOT_FORMAT")
+                .help(ENCODINGS.help("Set image encoder: {}, or jpg:QUALITY or auto. Given again to also save the composite with others")),
+        )
+        .arg(
+            arg!(--"also-save" <ENCODER_PATH>)
+                .required(false)
+                .takes_value(true)
+                .multiple_occurrences(true)
+                .conflicts_with("dzi")
+                .help("Also encode the composite with ENCODER to PATH, given as ENCODER=PATH"),
+        )
+        .arg(
+            arg!(--"jpeg-subsampling" <SUBSAMPLING>)
+                .required(false)
+                .takes_value(true)
+                .help(JPEG_SUBSAMPLINGS.help("Jpeg chroma subsampling: {}")),
+        )
+        .arg(
+            arg!(--"jpeg-progressive")
+                .required(false)
+                .takes_value(false)
+                .help("Write progressive jpeg images"),
+        )
+        .arg(
+            arg!(--"png-interlace")
+                .required(false)
+                .takes_value(false)
+                .help("Write Adam7 interlaced png images which render progressively"),
+        )
+        .arg(
+            arg!(--"png-srgb")
+                .required(false)
+                .takes_value(false)
+                .help("Tag png images as sRGB by emitting the sRGB, gAMA and cHRM chunks"),
+        )
+        .arg(
+            arg!(--"png-filter" <FILTER>)
+                .required(false)
+                .takes_value(true)
+                .help(PNG_FILTERS.help("Png scanline filter: {}")),
+        )
+        .arg(
+            arg!(--palette <COLORS>)
+                .required(false)
+                .takes_value(true)
+                .conflicts_with("png-interlace")
+                .help("Write indexed png images with at most COLORS colors (2-256)"),
+        )
+        .arg(
+            arg!(--"tiff-compression" <COMPRESSION>)
+                .required(false)
+                .takes_value(true)
+                .help(TIFF_COMPRESSIONS.help("Tiff compression: {}")),
+        )
+        .arg(
+            arg!(--"tiff-16bit")
+                .required(false)
+                .takes_value(false)
+                .help("Write tiff images with 16 bits per channel"),
         )
         .arg(
             arg!(-l - -listoutputs)
@@ -49,12 +369,621 @@ pub fn set_flags() -> Command<'static> {
                 .takes_value(false)
                 .help("List all valid outputs"),
         )
+        .arg(
+            arg!(--"list-formats")
+                .required(false)
+                .takes_value(false)
+                .help("List the buffer format the compositor offers for each output"),
+        )
+        .arg(
+            arg!(--"hdr-outputs" <OUTPUTS>)
+                .required(false)
+                .takes_value(true)
+                .help("Tone map the frames of these HDR outputs to SDR, or auto for 10 bit outputs next to 8 bit ones"),
+        )
+        .arg(
+            arg!(--"tone-map" <OPERATOR>)
+                .required(false)
+                .takes_value(true)
+                .requires("hdr-outputs")
+                .help(TONE_MAP_OPERATORS.help("How HDR frames are tone mapped: {}")),
+        )
+        .arg(
+            arg!(--"force-format" <FORMAT>)
+                .required(false)
+                .takes_value(true)
+                .help("Read frames as this wl_shm format, eg: xbgr8888, to debug or work around colors"),
+        )
         .arg(
             arg!(-o --output <OUTPUT>)
                 .required(false)
                 .takes_value(true)
                 .conflicts_with("slurp")
                 .help("Choose a particular display to screenshot"),
+        )
+        .arg(
+            arg!(--layout <FILE>)
+                .required(false)
+                .takes_value(true)
+                .conflicts_with_all(&["app-id", "separate-outputs", "all-clones"])
+                .allow_invalid_utf8(true)
+                .help("Place outputs in the image at the positions listed in FILE, eg: \"DP-1 1940,0\""),
+        )
+        .arg(
+            arg!(--scale <SCALE>)
+                .required(false)
+                .takes_value(true)
+                .help("Pixels per logical pixel of the image, or max for the largest output scale"),
+        )
+        .arg(
+            arg!(--zoom <FACTOR>)
+                .required(false)
+                .takes_value(true)
+                .help("Enlarge the image FACTOR times, eg: to inspect a small region pixel by pixel"),
+        )
+        .arg(
+            arg!(--"zoom-filter" <FILTER>)
+                .required(false)
+                .takes_value(true)
+                .requires("zoom")
+                .help(ZOOM_FILTERS.help("Zoom interpolation: {}")),
+        )
+        .arg(
+            arg!(--"zoom-grid")
+                .required(false)
+                .takes_value(false)
+                .requires("zoom")
+                .help("Draw a grid between the pixels of zoomed images"),
+        )
+        .arg(
+            arg!(--gamma <GAMMA>)
+                .required(false)
+                .takes_value(true)
+                .help("Gamma correction of the image, above 1 brightens mid tones (default 1)"),
+        )
+        .arg(
+            arg!(--brightness <BRIGHTNESS>)
+                .required(false)
+                .takes_value(true)
+                .allow_hyphen_values(true)
+                .help("Add BRIGHTNESS, from -1 to 1, to every color channel (default 0)"),
+        )
+        .arg(
+            arg!(--contrast <CONTRAST>)
+                .required(false)
+                .takes_value(true)
+                .help("Scale the distance of colors from mid gray CONTRAST times (default 1)"),
+        )
+        .arg(
+            arg!(--invert)
+                .required(false)
+                .takes_value(false)
+                .help("Invert the colors of the image, eg: to print screenshots of dark themes"),
+        )
+        .arg(
+            arg!(--simulate <DEFICIENCY>)
+                .required(false)
+                .takes_value(true)
+                .help(COLOR_VISIONS.help("Show the image as it looks with {}")),
+        )
+        .arg(
+            arg!(--redact <FILE>)
+                .required(false)
+                .takes_value(true)
+                .env("WAYSHOT_REDACT")
+                .conflicts_with("layout")
+                .allow_invalid_utf8(true)
+                .help("Hide the windows matching FILE, eg: \"app_id org.keepassxc.KeePassXC\" or \"title *Bank*\""),
+        )
+        .arg(
+            arg!(--"redact-style" <STYLE>)
+                .required(false)
+                .takes_value(true)
+                .requires("redact")
+                .help(REDACT_STYLES.help("How --redact hides windows: {}")),
+        )
+        .arg(
+            arg!(--"separate-outputs")
+                .required(false)
+                .takes_value(false)
+                .conflicts_with("stdout")
+                .help("Write every captured output to its own file instead of compositing them"),
+        )
+        .arg(
+            arg!(--"all-clones")
+                .required(false)
+                .takes_value(false)
+                .conflicts_with("stdout")
+                .help("Capture every mirrored output to its own file instead of only the first one"),
+        )
+        .arg(
+            arg!(--fast)
+                .required(false)
+                .takes_value(false)
+                .help("Reuse the output geometry cached by the last run within a minute if no output was plugged in or out"),
+        )
+        .arg(
+            arg!(--flash)
+                .required(false)
+                .takes_value(false)
+                .help("Briefly flash the captured area once the frames are copied"),
+        )
+        .arg(
+            arg!(--"shutter-sound")
+                .required(false)
+                .takes_value(false)
+                .help("Play the camera shutter sound of the sound theme when capturing"),
+        )
+        .arg(
+            arg!(--private)
+                .required(false)
+                .takes_value(false)
+                .help("Create files readable by their owner only and do not run hooks"),
+        )
+        .arg(
+            arg!(--encrypt <COMMAND>)
+                .required(false)
+                .takes_value(true)
+                .conflicts_with_all(&["stream", "thumbnail"])
+                .help("Pipe images through COMMAND before writing them, eg: \"age -r KEY\""),
+        )
+        .arg(
+            arg!(--stream)
+                .required(false)
+                .takes_value(false)
+                .conflicts_with_all(&["separate-outputs", "all-clones"])
+                .help("Encode the image row by row to bound memory use (png and ppm only)"),
+        )
+        .arg(
+            arg!(--draw <ANNOTATIONS>)
+                .required(false)
+                .takes_value(true)
+                .conflicts_with("stream")
+                .help("Draw shapes and text, eg: \"rect:10,10,200,100:red:3; text:20,250:'Step 1'\""),
+        )
+        .arg(
+            arg!(--dzi <DIRECTORY>)
+                .required(false)
+                .takes_value(true)
+                .conflicts_with_all(&["file", "stdout", "stream", "thumbnail", "encrypt"])
+                .allow_invalid_utf8(true)
+                .help("Write a Deep Zoom tile pyramid to DIRECTORY for zooming in web viewers"),
+        )
+        .arg(
+            arg!(--thumbnail <SIZE>)
+                .required(false)
+                .takes_value(true)
+                .conflicts_with("stream")
+                .help("Also write a png preview fitting within SIZE, eg: 320x180"),
+        )
+        .arg(
+            arg!(--"auto-trim")
+                .required(false)
+                .takes_value(false)
+                .conflicts_with("stream")
+                .help("Remove uniform color borders from the final image"),
+        )
+        .arg(
+            arg!(--"trim-tolerance" <TOLERANCE>)
+                .required(false)
+                .takes_value(true)
+                .requires("auto-trim")
+                .help("Per channel difference still considered border color by --auto-trim (0-255, default 0)"),
+        )
+        .arg(
+            arg!(--"output-format" <OVERRIDES>)
+                .required(false)
+                .takes_value(true)
+                .requires("separate-outputs")
+                .help("Per-output encoders for --separate-outputs, eg: DP-1=png,HDMI-A-1=jpg:80"),
+        )
+        .subcommand(
+            Command::new("serve")
+                .about("Serve an MJPEG stream of the capture over HTTP, eg: to watch it in a browser")
+                .arg(
+                    arg!(--port <PORT>)
+                        .required(false)
+                        .takes_value(true)
+                        .help("Port to listen on (defaults to 8080)"),
+                )
+                .arg(
+                    arg!(--bind <ADDRESS>)
+                        .required(false)
+                        .takes_value(true)
+                        .help("Address to listen on (defaults to 127.0.0.1, only this machine)"),
+                )
+                .arg(
+                    arg!(--fps <FPS>)
+                        .required(false)
+                        .takes_value(true)
+                        .help("Most frames captured per second, up to 60 (defaults to 5)"),
+                )
+                .arg(
+                    arg!(--"battery-fps" <FPS>)
+                        .required(false)
+                        .takes_value(true)
+                        .env("WAYSHOT_BATTERY_FPS")
+                        .help("Most frames captured per second while on battery, 0 pauses"),
+                ),
+        )
+        .subcommand(
+            Command::new("watch")
+                .about("Keep running and capture the screen whenever the compositor does what a rule describes")
+                .arg(
+                    arg!(--rules <FILE>)
+                        .required(true)
+                        .takes_value(true)
+                        .allow_invalid_utf8(true)
+                        .help("Rules, one per line: \"output-connected NAME\" or \"fullscreen APP_ID\", * matches any"),
+                ),
+        )
+        .subcommand(
+            Command::new("diff")
+                .about("Capture and outline what changed since an earlier screenshot")
+                .arg(
+                    arg!(--highlight <IMAGE>)
+                        .required(true)
+                        .takes_value(true)
+                        .allow_invalid_utf8(true)
+                        .help("Earlier screenshot of the same area to compare against"),
+                )
+                .arg(
+                    arg!(--tolerance <TOLERANCE>)
+                        .required(false)
+                        .takes_value(true)
+                        .help("Per channel difference still considered unchanged (0-255, default 0)"),
+                ),
+        )
+        .subcommand(
+            Command::new("ci-shot")
+                .about("Screenshot an application in a headless compositor started for it, eg: in CI")
+                .arg(
+                    arg!(--command <COMMAND>)
+                        .required(true)
+                        .takes_value(true)
+                        .help("Application to run, through sh -c"),
+                )
+                .arg(
+                    arg!(--compositor <COMMAND>)
+                        .required(false)
+                        .takes_value(true)
+                        .help("wlroots compositor to start headless (defaults to sway)"),
+                )
+                .arg(
+                    arg!(--timeout <MILLISECONDS>)
+                        .required(false)
+                        .takes_value(true)
+                        .help("How long to wait for the application to show up (defaults to 10000)"),
+                ),
+        )
+        .subcommand(
+            Command::new("testpattern").about(
+                "Show a calibration pattern on the outputs, capture it and report how much the \
+                 colors differ",
+            ),
+        )
+        .subcommand(
+            Command::new("bench")
+                .about("Time writing a large ppm buffered and through a memory map, eg: to tune the file system screenshots go to")
+                .arg(
+                    arg!(--size <MEGABYTES>)
+                        .required(false)
+                        .takes_value(true)
+                        .help("Size of the ppm in MiB (defaults to 256)"),
+                )
+                .arg(
+                    arg!(--runs <RUNS>)
+                        .required(false)
+                        .takes_value(true)
+                        .help("Writes timed with each method, the fastest is reported (defaults to 3)"),
+                )
+                .arg(
+                    arg!(-D --directory <DIRECTORY>)
+                        .required(false)
+                        .takes_value(true)
+                        .allow_invalid_utf8(true)
+                        .help("Directory to write the ppm to (defaults to the current one)"),
+                ),
+        )
+        .subcommand(
+            Command::new("capabilities")
+                .about("Report the features, encoders and compositor protocols wayshot can use")
+                .arg(
+                    arg!(--json)
+                        .required(false)
+                        .takes_value(false)
+                        .help("Print the report as a JSON object on stdout, eg: for frontends"),
+                ),
+        )
+        .subcommand(
+            Command::new("export")
+                .about("Convert the frames of a wfz archive into png files")
+                .arg(
+                    arg!(<ARCHIVE>)
+                        .allow_invalid_utf8(true)
+                        .help("Path to the wfz archive"),
+                )
+                .arg(
+                    arg!(-D --directory <DIRECTORY>)
+                        .required(false)
+                        .takes_value(true)
+                        .allow_invalid_utf8(true)
+                        .help("Directory to write the png files to (defaults to the current one)"),
+                ),
         );
     app
 }
+
+/// File names grim generates when no output file is given.
+const GRIM_FILENAME_FORMAT: &str = "%Y%m%d_%Hh%Mm%Ss_grim";
+
+/// Flags of grim, accepted when wayshot is invoked as `grim` or with `--grim` as its first
+/// argument so that scripts written for grim keep working.
+pub fn grim_flags() -> Command<'static> {
+    Command::new("grim")
+        .about("Grim compatible interface of wayshot")
+        .arg(
+            arg!(scale: -s <FACTOR>)
+                .required(false)
+                .help("Set the output image scale factor"),
+        )
+        .arg(
+            arg!(geometry: -g <GEOMETRY>)
+                .required(false)
+                .help("Set the region to capture, \"-\" reads it from stdin"),
+        )
+        .arg(
+            arg!(type: -t <TYPE>)
+                .required(false)
+                .help("Set the output filetype: png, ppm or jpeg"),
+        )
+        .arg(
+            arg!(quality: -q <QUALITY>)
+                .required(false)
+                .help("Set the jpeg quality (0-100)"),
+        )
+        .arg(
+            arg!(level: -l <LEVEL>)
+                .required(false)
+                .help("Set the png compression level, accepted for compatibility"),
+        )
+        .arg(
+            arg!(output: -o <OUTPUT>)
+                .required(false)
+                .help("Set the output name to capture"),
+        )
+        .arg(
+            arg!(cursor: -c)
+                .required(false)
+                .help("Include cursors in the screenshot"),
+        )
+        .arg(
+            arg!([FILE])
+                .allow_invalid_utf8(true)
+                .help("File to write to, \"-\" writes to stdout"),
+        )
+}
+
+/// Whether `args` ask for the grim compatible interface.
+pub fn is_grim_invocation(args: &[OsString]) -> bool {
+    let program = args
+        .first()
+        .and_then(|program| Path::new(program).file_name())
+        .map(|name| name == "grim")
+        .unwrap_or(false);
+    program || args.get(1).map(|arg| arg == "--grim").unwrap_or(false)
+}
+
+/// Parse grim arguments and translate them into the equivalent wayshot arguments.
+pub fn grim_to_wayshot_args(mut args: Vec<OsString>) -> Vec<OsString> {
+    if args.get(1).map(|arg| arg == "--grim").unwrap_or(false) {
+        args.remove(1);
+    }
+    translate_grim_matches(&grim_flags().get_matches_from(args))
+}
+
+fn translate_grim_matches(matches: &ArgMatches) -> Vec<OsString> {
+    let mut args = vec![OsString::from("wayshot")];
+    if let Some(geometry) = matches.value_of("geometry") {
+        args.extend(["--geometry".into(), geometry.into()]);
+    }
+    if let Some(output) = matches.value_of("output") {
+        args.extend(["--output".into(), output.into()]);
+    }
+    if let Some(factor) = matches.value_of("scale") {
+        args.extend(["--scale".into(), factor.into()]);
+    }
+    if matches.is_present("cursor") {
+        args.push("--cursor".into());
+    }
+    // The png compression level (-l) has no wayshot equivalent and is ignored.
+
+    let extension = match (matches.value_of("type"), matches.value_of("quality")) {
+        (Some("jpeg"), Some(quality)) => {
+            // grim accepts a quality of 0, the lowest the jpeg encoder takes is 1.
+            let quality = match quality.parse::<u8>() {
+                Ok(0) => "1",
+                _ => quality,
+            };
+            Some(format!("jpg:{}", quality))
+        }
+        (Some(kind), _) => Some(kind.to_string()),
+        (None, _) => None,
+    };
+    if let Some(extension) = extension {
+        args.extend(["--extension".into(), extension.into()]);
+    }
+
+    match matches.value_of_os("FILE") {
+        Some(file) if file == "-" => args.push("--stdout".into()),
+        Some(file) => args.extend(["--file".into(), file.into()]),
+        None => args.extend(["--filename-format".into(), GRIM_FILENAME_FORMAT.into()]),
+    }
+    args
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, ffi::OsStr, sync::Mutex, thread};
+
+    use super::*;
+    use crate::config::apply_config;
+
+    /// Held by tests setting WAYSHOT_ variables, the environment is shared by every test thread.
+    static ENV_LOCK: Mutex<()> = Mutex::new(());
+
+    /// Stack of the main thread of the binary.
+    const PARSE_STACK_SIZE: usize = 8 << 20;
+
+    const VARIABLES: [&str; 3] = ["WAYSHOT_FORMAT", "WAYSHOT_DIR", "WAYSHOT_CURSOR"];
+
+    /// Parse `args` with the WAYSHOT_ variables of [`VARIABLES`] set to `values`, None unsetting
+    /// them.
+    fn matches_with_env(args: &[&str], values: [Option<&str>; 3]) -> ArgMatches {
+        matches_with_config("", args, values)
+    }
+
+    /// [`matches_with_env`] with the TOML `config` applied to the command first.
+    fn matches_with_config(config: &str, args: &[&str], values: [Option<&str>; 3]) -> ArgMatches {
+        let config: toml::Table = config.parse().unwrap();
+        let _lock = ENV_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        for (name, value) in VARIABLES.iter().zip(values) {
+            match value {
+                Some(value) => env::set_var(name, value),
+                None => env::remove_var(name),
+            }
+        }
+        // Building the whole command takes more stack than test threads get in debug builds.
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        let matches = thread::Builder::new()
+            .stack_size(PARSE_STACK_SIZE)
+            .spawn(move || {
+                apply_config(set_flags(), &config)
+                    .unwrap()
+                    .try_get_matches_from(args)
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+        for name in VARIABLES {
+            env::remove_var(name);
+        }
+        matches.unwrap()
+    }
+
+    #[test]
+    fn defaults_without_flags_or_environment() {
+        let matches = matches_with_env(&["wayshot"], [None; 3]);
+        assert_eq!(matches.value_of("extension"), None);
+        assert_eq!(matches.value_of_os("dir"), None);
+        assert!(!matches.is_present("cursor"));
+    }
+
+    #[test]
+    fn environment_overrides_defaults() {
+        let matches = matches_with_env(
+            &["wayshot"],
+            [Some("jpg"), Some("/tmp/shots"), Some("force")],
+        );
+        assert_eq!(matches.value_of("extension"), Some("jpg"));
+        assert_eq!(matches.value_of_os("dir"), Some(OsStr::new("/tmp/shots")));
+        assert!(matches.is_present("cursor"));
+        assert_eq!(matches.value_of("cursor"), Some("force"));
+    }
+
+    #[test]
+    fn flags_override_environment() {
+        let matches = matches_with_env(
+            &["wayshot", "-e", "ppm", "--dir", "/srv", "--cursor=off"],
+            [Some("jpg"), Some("/tmp/shots"), Some("force")],
+        );
+        assert_eq!(matches.value_of("extension"), Some("ppm"));
+        assert_eq!(matches.value_of_os("dir"), Some(OsStr::new("/srv")));
+        assert_eq!(matches.value_of("cursor"), Some("off"));
+    }
+
+    const CONFIG: &str = r#"
+extension = "png"
+dir = "/home/user/Pictures"
+cursor = "off"
+crop = ["face=0,0 10x10", "hand=10,10 5x5"]
+
+[serve]
+battery-fps = 2
+"#;
+
+    #[test]
+    fn config_overrides_defaults() {
+        let matches = matches_with_config(CONFIG, &["wayshot"], [None; 3]);
+        assert_eq!(matches.value_of("extension"), Some("png"));
+        assert_eq!(
+            matches.value_of_os("dir"),
+            Some(OsStr::new("/home/user/Pictures"))
+        );
+        assert_eq!(matches.value_of("cursor"), Some("off"));
+        let crops: Vec<&str> = matches.values_of("crop").unwrap().collect();
+        assert_eq!(crops, ["face=0,0 10x10", "hand=10,10 5x5"]);
+
+        let matches = matches_with_config(CONFIG, &["wayshot", "serve"], [None; 3]);
+        let (_, serve) = matches.subcommand().unwrap();
+        assert_eq!(serve.value_of("battery-fps"), Some("2"));
+    }
+
+    #[test]
+    fn environment_overrides_config() {
+        let matches = matches_with_config(CONFIG, &["wayshot"], [Some("jpg"), None, Some("force")]);
+        assert_eq!(matches.value_of("extension"), Some("jpg"));
+        assert_eq!(
+            matches.value_of_os("dir"),
+            Some(OsStr::new("/home/user/Pictures"))
+        );
+        assert_eq!(matches.value_of("cursor"), Some("force"));
+    }
+
+    #[test]
+    fn flags_override_config_and_environment() {
+        let matches = matches_with_config(
+            CONFIG,
+            &[
+                "wayshot",
+                "-e",
+                "ppm",
+                "--dir",
+                "/srv",
+                "--crop",
+                "eye=1,1 2x2",
+            ],
+            [Some("jpg"), Some("/tmp/shots"), None],
+        );
+        assert_eq!(matches.value_of("extension"), Some("ppm"));
+        assert_eq!(matches.value_of_os("dir"), Some(OsStr::new("/srv")));
+        assert_eq!(matches.value_of("cursor"), Some("off"));
+        let crops: Vec<&str> = matches.values_of("crop").unwrap().collect();
+        assert_eq!(crops, ["eye=1,1 2x2"]);
+
+        let matches = matches_with_config(
+            CONFIG,
+            &["wayshot", "serve", "--battery-fps", "0"],
+            [None; 3],
+        );
+        let (_, serve) = matches.subcommand().unwrap();
+        assert_eq!(serve.value_of("battery-fps"), Some("0"));
+    }
+
+    fn grim_extension(quality: &str) -> OsString {
+        let args = ["grim", "-t", "jpeg", "-q", quality, "shot.jpg"];
+        let translated = grim_to_wayshot_args(args.iter().map(OsString::from).collect());
+        let position = translated
+            .iter()
+            .position(|arg| arg == "--extension")
+            .unwrap();
+        translated[position + 1].clone()
+    }
+
+    #[test]
+    fn grim_quality_zero_is_the_lowest_jpeg_quality() {
+        assert_eq!(grim_extension("0"), "jpg:1");
+        assert_eq!(grim_extension("1"), "jpg:1");
+        assert_eq!(grim_extension("100"), "jpg:100");
+    }
+}
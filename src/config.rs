@@ -0,0 +1,221 @@
+use std::{
+    env,
+    error::Error,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use clap::Command;
+use toml::{Table, Value};
+
+use crate::i18n::{tr_args, Message};
+
+/// `$XDG_CONFIG_HOME/wayshot`, or `~/.config/wayshot` when it is not set.
+pub fn config_dir() -> PathBuf {
+    match env::var_os("XDG_CONFIG_HOME") {
+        Some(directory) if !directory.is_empty() => PathBuf::from(directory).join("wayshot"),
+        _ => PathBuf::from(env::var_os("HOME").unwrap_or_default()).join(".config/wayshot"),
+    }
+}
+
+/// The config file, WAYSHOT_CONFIG or `config.toml` in the [`config_dir`].
+pub fn config_path() -> PathBuf {
+    match env::var_os("WAYSHOT_CONFIG") {
+        Some(path) if !path.is_empty() => PathBuf::from(path),
+        _ => config_dir().join("config.toml"),
+    }
+}
+
+/// Read the config file at `path`, see [`apply_config`]. A missing file is an empty config.
+pub fn load_config(path: &Path) -> Result<Table, Box<dyn Error>> {
+    let path_arg = || path.display().to_string().into();
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Table::new()),
+        Err(e) => {
+            return Err(tr_args(
+                Message::ConfigUnreadable,
+                &[("path", path_arg()), ("error", e.to_string().into())],
+            )
+            .into())
+        }
+    };
+    text.parse::<Table>().map_err(|e| {
+        tr_args(
+            Message::ConfigInvalid,
+            &[("path", path_arg()), ("error", e.to_string().into())],
+        )
+        .into()
+    })
+}
+
+/// Make the values of `config` the defaults of the options of `command`, so that flags and then
+/// environment variables still take precedence over them. Keys are the long names of options
+/// taking a value, tables hold the options of the subcommand they are named after, eg:
+///
+/// ```toml
+/// extension = "jpg"
+/// dir = "/home/user/Pictures"
+///
+/// [serve]
+/// battery-fps = 1
+/// ```
+///
+/// Strings, numbers and, for options which can be repeated, arrays of them are accepted.
+pub fn apply_config(command: Command<'static>, config: &Table) -> Result<Command<'static>, String> {
+    apply_table(command, config, "")
+}
+
+/// [`apply_config`] for the table of `command`, whose keys are named with `prefix` in errors.
+fn apply_table(
+    mut command: Command<'static>,
+    config: &Table,
+    prefix: &str,
+) -> Result<Command<'static>, String> {
+    for (key, value) in config {
+        let option = format!("{}{}", prefix, key);
+        if let Value::Table(table) = value {
+            if command.get_subcommands().all(|sub| sub.get_name() != key) {
+                return Err(tr_args(
+                    Message::ConfigUnknownOption,
+                    &[("option", option.into())],
+                ));
+            }
+            let prefix = format!("{}.", option);
+            let mut result = Ok(());
+            command = command.mut_subcommand(key.as_str(), |sub| {
+                match apply_table(sub.clone(), table, &prefix) {
+                    Ok(sub) => sub,
+                    Err(e) => {
+                        result = Err(e);
+                        sub
+                    }
+                }
+            });
+            result?;
+            continue;
+        }
+
+        let arg = command
+            .get_arguments()
+            .find(|arg| arg.get_long() == Some(key) && arg.is_takes_value_set());
+        let (id, repeatable) = match arg {
+            Some(arg) => (
+                arg.get_id(),
+                arg.is_multiple_occurrences_set() || arg.is_multiple_values_set(),
+            ),
+            None => {
+                return Err(tr_args(
+                    Message::ConfigUnknownOption,
+                    &[("option", option.into())],
+                ))
+            }
+        };
+        let values = match value {
+            Value::Array(values) if repeatable => values.iter().map(config_value).collect(),
+            value => vec![config_value(value)],
+        };
+        let values = match values.into_iter().collect::<Option<Vec<_>>>() {
+            Some(values) => values,
+            None => {
+                return Err(tr_args(
+                    Message::ConfigValueInvalid,
+                    &[("option", option.into())],
+                ))
+            }
+        };
+        // Clap keeps defaults for the life of the command, which is the life of wayshot.
+        let values: &'static [&'static str] = Box::leak(
+            values
+                .into_iter()
+                .map(|value| &*Box::leak(value.into_boxed_str()))
+                .collect(),
+        );
+        command = command.mut_arg(id, |arg| arg.default_values(values));
+    }
+    Ok(command)
+}
+
+/// The command line form of a config value.
+fn config_value(value: &Value) -> Option<String> {
+    match value {
+        Value::String(value) => Some(value.clone()),
+        Value::Integer(value) => Some(value.to_string()),
+        Value::Float(value) => Some(value.to_string()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::arg;
+
+    use super::*;
+
+    fn command() -> Command<'static> {
+        Command::new("wayshot")
+            .arg(arg!(--dir <DIR>).required(false))
+            .arg(
+                arg!(--crop <CROP>)
+                    .required(false)
+                    .multiple_occurrences(true),
+            )
+            .arg(arg!(--quiet))
+            .subcommand(Command::new("serve").arg(arg!(--fps <FPS>).required(false)))
+    }
+
+    fn apply(config: &str) -> Result<Command<'static>, String> {
+        apply_config(command(), &config.parse().unwrap())
+    }
+
+    #[test]
+    fn numbers_and_arrays_become_defaults() {
+        let matches = apply("dir = 'shots'\ncrop = ['a=0,0 1x1', 'b=1,1 1x1']\n[serve]\nfps = 2.5")
+            .unwrap()
+            .try_get_matches_from(["wayshot", "serve"])
+            .unwrap();
+        assert_eq!(matches.value_of("dir"), Some("shots"));
+        assert_eq!(matches.values_of("crop").unwrap().count(), 2);
+        assert_eq!(
+            matches.subcommand_matches("serve").unwrap().value_of("fps"),
+            Some("2.5")
+        );
+    }
+
+    #[test]
+    fn unknown_options_and_flags_are_rejected() {
+        for config in [
+            "quiet = 1",
+            "verbose = 'yes'",
+            "[serve]\ndir = 'shots'",
+            "[watch]",
+        ] {
+            assert!(apply(config).is_err(), "{}", config);
+        }
+        let error = apply("[serve]\nport = 8080").unwrap_err();
+        assert!(error.contains("serve.port"), "{}", error);
+    }
+
+    #[test]
+    fn values_must_be_strings_or_numbers() {
+        for config in [
+            "dir = true",
+            "dir = ['a', 'b']",
+            "crop = [1, true]",
+            "dir = 1979-05-27",
+        ] {
+            assert!(apply(config).is_err(), "{}", config);
+        }
+    }
+
+    #[test]
+    fn missing_files_are_empty_configs() {
+        let directory = crate::testing::TempDirectory::new();
+        let path = directory.path().join("config.toml");
+        assert!(load_config(&path).unwrap().is_empty());
+        fs::write(&path, "dir = 'shots'\n").unwrap();
+        assert_eq!(load_config(&path).unwrap()["dir"].as_str(), Some("shots"));
+        fs::write(&path, "dir = \n").unwrap();
+        assert!(load_config(&path).is_err());
+    }
+}
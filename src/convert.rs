@@ -1,75 +1,96 @@
-use image::ColorType;
-use wayland_client::protocol::wl_shm;
+use wayland_client::protocol::{wl_output, wl_shm};
+pub use wayshot_core::Convert;
+use wayshot_core::{PixelFormat, Transform};
 
-pub trait Convert {
-    /// Convert raw image data into output type, return said type
-    fn convert_inplace(&self, data: &mut [u8]) -> ColorType;
+/// Creates format converter based of input format, return None if conversion
+/// isn't possible. Conversion is happening inplace, see [`wayshot_core::create_converter`].
+pub fn create_converter(format: wl_shm::Format) -> Option<&'static dyn Convert> {
+    wayshot_core::create_converter(pixel_format(format)?)
 }
 
-#[derive(Default)]
-struct ConvertBGR10 {}
-
-#[derive(Default)]
-struct ConvertNone {}
-
-#[derive(Default)]
-struct ConvertRGB8 {}
-
-const SHIFT10BITS_1: u32 = 20;
-const SHIFT10BITS_2: u32 = 10;
-
-/// Creates format converter based of input format, return None if conversion
-/// isn't possible. Conversion is happening inplace.
-pub fn create_converter(format: wl_shm::Format) -> Option<Box<dyn Convert>> {
+/// The wayshot-core counterpart of a wl_shm format, or None if it can not be converted.
+pub fn pixel_format(format: wl_shm::Format) -> Option<PixelFormat> {
     match format {
-        wl_shm::Format::Xbgr8888 | wl_shm::Format::Abgr8888 => {
-            Some(Box::new(ConvertNone::default()))
-        }
-        wl_shm::Format::Xrgb8888 | wl_shm::Format::Argb8888 => {
-            Some(Box::new(ConvertRGB8::default()))
-        }
-        wl_shm::Format::Xbgr2101010 | wl_shm::Format::Abgr2101010 => {
-            Some(Box::new(ConvertBGR10::default()))
-        }
+        wl_shm::Format::Xbgr8888 => Some(PixelFormat::Xbgr8888),
+        wl_shm::Format::Abgr8888 => Some(PixelFormat::Abgr8888),
+        wl_shm::Format::Xrgb8888 => Some(PixelFormat::Xrgb8888),
+        wl_shm::Format::Argb8888 => Some(PixelFormat::Argb8888),
+        wl_shm::Format::Xbgr2101010 => Some(PixelFormat::Xbgr2101010),
+        wl_shm::Format::Abgr2101010 => Some(PixelFormat::Abgr2101010),
+        wl_shm::Format::Xrgb2101010 => Some(PixelFormat::Xrgb2101010),
+        wl_shm::Format::Argb2101010 => Some(PixelFormat::Argb2101010),
         _ => None,
     }
 }
 
-impl Convert for ConvertNone {
-    fn convert_inplace(&self, _data: &mut [u8]) -> ColorType {
-        ColorType::Rgba8
+/// Orientation of the frames of an output with this transform. Screencopy frames hold the
+/// pixels the way they are scanned out, ie: the output contents with the transform applied.
+pub fn frame_transform(transform: wl_output::Transform) -> Transform {
+    match transform {
+        wl_output::Transform::_90 => Transform::Rotate90,
+        wl_output::Transform::_180 => Transform::Rotate180,
+        wl_output::Transform::_270 => Transform::Rotate270,
+        wl_output::Transform::Flipped => Transform::Flipped,
+        wl_output::Transform::Flipped90 => Transform::Flipped90,
+        wl_output::Transform::Flipped180 => Transform::Flipped180,
+        wl_output::Transform::Flipped270 => Transform::Flipped270,
+        _ => Transform::Normal,
     }
 }
 
-impl Convert for ConvertRGB8 {
-    fn convert_inplace(&self, data: &mut [u8]) -> ColorType {
-        for chunk in data.chunks_exact_mut(4) {
-            chunk.swap(0, 2);
-        }
-        ColorType::Rgba8
+/// Convert a row of raw pixels in `format` into Rgba16, widening 8 bit channels and keeping
+/// the full precision of 10 bit ones. Formats without alpha come out opaque. Formats without a
+/// converter, see [`create_converter`], leave `row` untouched.
+pub fn convert_to_rgba16(format: wl_shm::Format, data: &[u8], row: &mut [u16]) {
+    for (chunk, pixel) in data.chunks_exact(4).zip(row.chunks_exact_mut(4)) {
+        let value = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        let widen8 = |shift: u32| ((value >> shift) & 0xff) as u16 * 257;
+        let widen10 = |shift: u32| {
+            let channel = ((value >> shift) & 0x3ff) as u16;
+            channel << 6 | channel >> 4
+        };
+        let rgba = match format {
+            wl_shm::Format::Xbgr8888 => [widen8(0), widen8(8), widen8(16), u16::MAX],
+            wl_shm::Format::Abgr8888 => [widen8(0), widen8(8), widen8(16), widen8(24)],
+            wl_shm::Format::Xrgb8888 => [widen8(16), widen8(8), widen8(0), u16::MAX],
+            wl_shm::Format::Argb8888 => [widen8(16), widen8(8), widen8(0), widen8(24)],
+            wl_shm::Format::Xbgr2101010 => [widen10(0), widen10(10), widen10(20), u16::MAX],
+            wl_shm::Format::Abgr2101010 => [
+                widen10(0),
+                widen10(10),
+                widen10(20),
+                (value >> 30) as u16 * 0x5555,
+            ],
+            wl_shm::Format::Xrgb2101010 => [widen10(20), widen10(10), widen10(0), u16::MAX],
+            wl_shm::Format::Argb2101010 => [
+                widen10(20),
+                widen10(10),
+                widen10(0),
+                (value >> 30) as u16 * 0x5555,
+            ],
+            _ => return,
+        };
+        pixel.copy_from_slice(&rgba);
     }
 }
 
-/// Simple conversion from 10 to 8 bits for one channel
-fn convert10_to_8(color: u32) -> u8 {
-    ((color >> 2) & 255) as u8
-}
+/// Formats a converter exists for, by their name in the wl_shm protocol.
+pub const CONVERTIBLE_FORMATS: [(&str, wl_shm::Format); 8] = [
+    ("xrgb8888", wl_shm::Format::Xrgb8888),
+    ("argb8888", wl_shm::Format::Argb8888),
+    ("xbgr8888", wl_shm::Format::Xbgr8888),
+    ("abgr8888", wl_shm::Format::Abgr8888),
+    ("xbgr2101010", wl_shm::Format::Xbgr2101010),
+    ("abgr2101010", wl_shm::Format::Abgr2101010),
+    ("xrgb2101010", wl_shm::Format::Xrgb2101010),
+    ("argb2101010", wl_shm::Format::Argb2101010),
+];
 
-impl Convert for ConvertBGR10 {
-    fn convert_inplace(&self, data: &mut [u8]) -> ColorType {
-        for chunk in data.chunks_exact_mut(4) {
-            let pixel = ((chunk[3] as u32) << 24)
-                | ((chunk[2] as u32) << 16)
-                | ((chunk[1] as u32) << 8)
-                | chunk[0] as u32;
-            let r = convert10_to_8(pixel >> SHIFT10BITS_1);
-            let g = convert10_to_8(pixel >> SHIFT10BITS_2);
-            let b = convert10_to_8(pixel);
-            chunk[0] = b;
-            chunk[1] = g;
-            chunk[2] = r;
-            chunk[3] = 255;
-        }
-        ColorType::Rgba8
-    }
+/// The convertible format called `name`, ignoring case, eg: "xbgr8888" for --force-format.
+pub fn parse_shm_format(name: &str) -> Option<wl_shm::Format> {
+    let name = name.trim().to_lowercase();
+    CONVERTIBLE_FORMATS
+        .iter()
+        .find(|(format_name, _)| *format_name == name)
+        .map(|&(_, format)| format)
 }
@@ -0,0 +1,190 @@
+use std::{
+    env,
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use image::{imageops::overlay, RgbaImage};
+
+/// Chunk type of cursor images inside an Xcursor file.
+const XCURSOR_IMAGE_TYPE: u32 = 0xfffd_0002;
+
+/// Cursor names tried in order, the first one found in the theme is drawn.
+const CURSOR_NAMES: [&str; 2] = ["default", "left_ptr"];
+
+/// A cursor image loaded from an Xcursor theme.
+#[derive(Debug)]
+pub struct CursorImage {
+    pub image: RgbaImage,
+    /// Offset of the pointer position inside the image.
+    pub hotspot: (u32, u32),
+}
+
+/// Load the default pointer of the cursor theme set in XCURSOR_THEME, at the size set in
+/// XCURSOR_SIZE.
+pub fn load_cursor() -> Result<CursorImage, Box<dyn Error>> {
+    let theme = env::var("XCURSOR_THEME").unwrap_or_else(|_| "default".to_string());
+    let size = env::var("XCURSOR_SIZE")
+        .ok()
+        .and_then(|size| size.parse().ok())
+        .unwrap_or(24);
+
+    let directories = theme_directories();
+    // Themes are searched breadth first, following the Inherits key of their index.theme.
+    let mut themes = vec![theme.clone()];
+    let mut searched = 0;
+    while searched < themes.len() {
+        let current = themes[searched].clone();
+        searched += 1;
+        for directory in &directories {
+            for name in CURSOR_NAMES {
+                let path = directory.join(&current).join("cursors").join(name);
+                if let Ok(data) = fs::read(&path) {
+                    tracing::debug!("Loading cursor from {:?}", path);
+                    return parse_xcursor(&data, size);
+                }
+            }
+            for parent in inherited_themes(&directory.join(&current).join("index.theme")) {
+                if !themes.contains(&parent) {
+                    themes.push(parent);
+                }
+            }
+        }
+    }
+    Err(format!("Cursor theme {} not found", theme).into())
+}
+
+fn inherited_themes(index: &Path) -> Vec<String> {
+    fs::read_to_string(index)
+        .ok()
+        .and_then(|index| {
+            index
+                .lines()
+                .find_map(|line| line.trim().strip_prefix("Inherits=").map(str::to_string))
+        })
+        .map(|themes| {
+            themes
+                .split(',')
+                .map(|theme| theme.trim().to_string())
+                .filter(|theme| !theme.is_empty())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn theme_directories() -> Vec<PathBuf> {
+    if let Ok(path) = env::var("XCURSOR_PATH") {
+        return env::split_paths(&path).collect();
+    }
+
+    let mut directories = Vec::new();
+    if let Some(home) = env::var_os("HOME").map(PathBuf::from) {
+        directories.push(home.join(".local/share/icons"));
+        directories.push(home.join(".icons"));
+    }
+    directories.push(PathBuf::from("/usr/share/icons"));
+    directories.push(PathBuf::from("/usr/share/pixmaps"));
+    directories
+}
+
+/// Parse an Xcursor file, picking the image whose nominal size is closest to `size`.
+pub fn parse_xcursor(data: &[u8], size: u32) -> Result<CursorImage, Box<dyn Error>> {
+    let read_u32 = |offset: usize| -> Result<u32, Box<dyn Error>> {
+        let bytes = data
+            .get(offset..offset + 4)
+            .ok_or("Truncated Xcursor file")?;
+        Ok(u32::from_le_bytes(bytes.try_into()?))
+    };
+
+    if data.get(..4) != Some(b"Xcur") {
+        return Err("Not an Xcursor file".into());
+    }
+    let toc_length = read_u32(12)? as usize;
+
+    // Table of contents entries are type, nominal size and position.
+    let mut best: Option<(u32, usize)> = None;
+    for entry in 0..toc_length {
+        let offset = 16 + entry * 12;
+        if read_u32(offset)? != XCURSOR_IMAGE_TYPE {
+            continue;
+        }
+        let nominal_size = read_u32(offset + 4)?;
+        let position = read_u32(offset + 8)? as usize;
+        if best.is_none_or(|(best_size, _)| nominal_size.abs_diff(size) < best_size.abs_diff(size))
+        {
+            best = Some((nominal_size, position));
+        }
+    }
+    let (_, position) = best.ok_or("Xcursor file contains no images")?;
+
+    // Image chunks are header length, type, nominal size, version, width, height, x hotspot,
+    // y hotspot and delay followed by premultiplied ARGB pixels.
+    let width = read_u32(position + 16)?;
+    let height = read_u32(position + 20)?;
+    let hotspot = (read_u32(position + 24)?, read_u32(position + 28)?);
+    let pixels_start = position + 36;
+    let pixels_end = pixels_start + width as usize * height as usize * 4;
+    let pixels = data
+        .get(pixels_start..pixels_end)
+        .ok_or("Truncated Xcursor image")?;
+
+    let mut rgba = Vec::with_capacity(pixels.len());
+    for pixel in pixels.chunks_exact(4) {
+        let (b, g, r, a) = (pixel[0], pixel[1], pixel[2], pixel[3]);
+        let unpremultiply = |channel: u8| {
+            if a == 0 {
+                0
+            } else {
+                (channel as u32 * 255 / a as u32).min(255) as u8
+            }
+        };
+        rgba.extend_from_slice(&[unpremultiply(r), unpremultiply(g), unpremultiply(b), a]);
+    }
+
+    Ok(CursorImage {
+        image: RgbaImage::from_raw(width, height, rgba).ok_or("Invalid Xcursor image size")?,
+        hotspot,
+    })
+}
+
+/// Ask the compositor where the pointer is in global logical coordinates. Only Hyprland
+/// exposes this over its IPC.
+#[cfg(feature = "compositor-ipc")]
+pub fn pointer_position() -> Result<(i32, i32), Box<dyn Error>> {
+    if env::var_os("HYPRLAND_INSTANCE_SIGNATURE").is_none() {
+        return Err("The pointer position is only available on Hyprland".into());
+    }
+
+    let output = std::process::Command::new("hyprctl")
+        .args(["-j", "cursorpos"])
+        .output()?;
+    if !output.status.success() {
+        return Err(format!("hyprctl exited with {}", output.status).into());
+    }
+    let position: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    match (position["x"].as_i64(), position["y"].as_i64()) {
+        (Some(x), Some(y)) => Ok((x as i32, y as i32)),
+        _ => Err("Unexpected hyprctl cursorpos output".into()),
+    }
+}
+
+#[cfg(not(feature = "compositor-ipc"))]
+pub fn pointer_position() -> Result<(i32, i32), Box<dyn Error>> {
+    Err("wayshot was built without compositor IPC support".into())
+}
+
+/// Draw `cursor` onto `image`, whose top left corner is at `origin` in global logical
+/// coordinates and which has `scale` pixels per logical pixel, with its hotspot at `position`.
+pub fn draw_cursor(
+    image: &mut RgbaImage,
+    origin: (i32, i32),
+    position: (i32, i32),
+    scale: f64,
+    cursor: &CursorImage,
+) {
+    let to_pixels = |coordinate: i32| (coordinate as f64 * scale).round() as i64;
+    let x = to_pixels(position.0) - to_pixels(origin.0) - cursor.hotspot.0 as i64;
+    let y = to_pixels(position.1) - to_pixels(origin.1) - cursor.hotspot.1 as i64;
+    overlay(image, &cursor.image, x, y);
+}
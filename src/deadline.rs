@@ -0,0 +1,96 @@
+use std::{
+    error::Error,
+    fmt,
+    process::exit,
+    sync::{
+        atomic::{AtomicU8, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::i18n::{tr_args, Message};
+
+/// Parts of a capture a [`Deadline`] reports as the one which ran out of time.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Stage {
+    /// Waiting for other instances holding the capture lock.
+    Lock,
+    /// Binding globals and learning the outputs and their geometry.
+    Discovery,
+    /// Waiting for the compositor to copy the frames.
+    Capture,
+    /// Rendering, encoding and writing the images.
+    Encode,
+}
+
+impl Stage {
+    const ALL: [Stage; 4] = [Stage::Lock, Stage::Discovery, Stage::Capture, Stage::Encode];
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Stage::Lock => "lock",
+            Stage::Discovery => "discovery",
+            Stage::Capture => "capture",
+            Stage::Encode => "encode",
+        })
+    }
+}
+
+/// Time limit of a whole capture, for --max-duration. Stages check it as they go and
+/// [`Deadline::spawn_watchdog`] ends the process when one is stuck, eg: in a roundtrip with an
+/// unresponsive compositor. Clones share the current stage.
+#[derive(Debug, Clone)]
+pub struct Deadline {
+    start: Instant,
+    limit: Duration,
+    stage: Arc<AtomicU8>,
+}
+
+impl Deadline {
+    /// A deadline `limit` from now, starting in [`Stage::Lock`].
+    pub fn new(limit: Duration) -> Self {
+        Self {
+            start: Instant::now(),
+            limit,
+            stage: Arc::new(AtomicU8::new(Stage::Lock as u8)),
+        }
+    }
+
+    pub fn stage(&self) -> Stage {
+        Stage::ALL[self.stage.load(Ordering::Relaxed) as usize]
+    }
+
+    /// Record that `stage` starts, failing if the deadline already passed.
+    pub fn enter(&self, stage: Stage) -> Result<(), Box<dyn Error>> {
+        self.stage.store(stage as u8, Ordering::Relaxed);
+        self.check()
+    }
+
+    /// Fail with the current stage if the deadline passed.
+    pub fn check(&self) -> Result<(), Box<dyn Error>> {
+        if self.start.elapsed() >= self.limit {
+            return Err(self.error().into());
+        }
+        Ok(())
+    }
+
+    /// Exit once the deadline passes, even while the capture is blocked, so callers waiting on
+    /// wayshot get an answer in time. An image being written at that moment is left incomplete.
+    pub fn spawn_watchdog(&self) {
+        let deadline = self.clone();
+        thread::spawn(move || {
+            thread::sleep(deadline.limit.saturating_sub(deadline.start.elapsed()));
+            tracing::error!("{}", deadline.error());
+            exit(1);
+        });
+    }
+
+    fn error(&self) -> String {
+        let stage = self.stage().to_string().into();
+        tr_args(Message::DeadlineExceeded, &[("stage", stage)])
+    }
+}
@@ -0,0 +1,141 @@
+use std::{
+    error::Error,
+    io,
+    process::{exit, Command, Stdio},
+    thread,
+    time::Duration,
+};
+
+use nix::sys::signal::{SigSet, Signal};
+
+use crate::i18n::{tr_args, Message};
+
+/// Time given to the notification daemon to hide its popups once do-not-disturb is turned on,
+/// before the frames are captured.
+pub const DND_SETTLE_DURATION: Duration = Duration::from_millis(200);
+
+/// Mode of mako hiding notifications, which its configuration has to define, eg:
+/// `[mode=do-not-disturb]` followed by `invisible=1`.
+const MAKO_DND_MODE: &str = "do-not-disturb";
+
+/// Notification daemon whose do-not-disturb state --dnd controls, through its control tool
+/// which talks to it over D-Bus.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum NotificationDaemon {
+    Dunst,
+    Mako,
+}
+
+impl NotificationDaemon {
+    /// The daemon answering on the session bus, trying dunst then mako. None if neither runs,
+    /// or their control tools are not installed.
+    pub fn detect() -> Option<Self> {
+        [NotificationDaemon::Dunst, NotificationDaemon::Mako]
+            .into_iter()
+            .find(|daemon| daemon.is_enabled().is_ok())
+    }
+
+    /// Whether the daemon currently holds back notifications.
+    pub fn is_enabled(self) -> Result<bool, Box<dyn Error>> {
+        match self {
+            NotificationDaemon::Dunst => Ok(run("dunstctl", &["is-paused"])?.trim() == "true"),
+            NotificationDaemon::Mako => Ok(run("makoctl", &["mode"])?
+                .lines()
+                .any(|mode| mode.trim() == MAKO_DND_MODE)),
+        }
+    }
+
+    pub fn set_enabled(self, enabled: bool) -> Result<(), Box<dyn Error>> {
+        match self {
+            NotificationDaemon::Dunst => {
+                run("dunstctl", &["set-paused", &enabled.to_string()])?;
+            }
+            NotificationDaemon::Mako => {
+                let flag = if enabled { "-a" } else { "-r" };
+                run("makoctl", &["mode", flag, MAKO_DND_MODE])?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Do-not-disturb turned on by --dnd, put back the way it was once dropped, so that popups do
+/// not end up in screenshots and streams.
+#[derive(Debug)]
+pub struct DoNotDisturb {
+    daemon: NotificationDaemon,
+    /// Whether it was already on, in which case it is left on.
+    was_enabled: bool,
+}
+
+impl DoNotDisturb {
+    /// Turn on do-not-disturb of the running notification daemon, waiting for its popups to
+    /// be hidden. None if no supported daemon runs.
+    pub fn enable() -> Result<Option<Self>, Box<dyn Error>> {
+        let daemon = match NotificationDaemon::detect() {
+            Some(daemon) => daemon,
+            None => return Ok(None),
+        };
+        let was_enabled = daemon.is_enabled()?;
+        if !was_enabled {
+            tracing::debug!("Turning on do-not-disturb of {:?}", daemon);
+            daemon.set_enabled(true)?;
+            thread::sleep(DND_SETTLE_DURATION);
+        }
+        Ok(Some(Self {
+            daemon,
+            was_enabled,
+        }))
+    }
+
+    /// Keep do-not-disturb on until wayshot is interrupted or terminated, restoring it before
+    /// exiting with 128 plus the signal number like a shell would report it. SIGINT and
+    /// SIGTERM are blocked on the calling thread, which threads spawned after inherit, so call
+    /// it before spawning any.
+    pub fn restore_on_exit_signal(self) -> Result<(), Box<dyn Error>> {
+        let mut signals = SigSet::empty();
+        signals.add(Signal::SIGINT);
+        signals.add(Signal::SIGTERM);
+        signals.thread_block()?;
+        thread::spawn(move || {
+            let signal = signals.wait();
+            drop(self);
+            exit(128 + signal.unwrap_or(Signal::SIGTERM) as i32);
+        });
+        Ok(())
+    }
+}
+
+impl Drop for DoNotDisturb {
+    fn drop(&mut self) {
+        if self.was_enabled {
+            return;
+        }
+        tracing::debug!("Turning off do-not-disturb of {:?}", self.daemon);
+        if let Err(e) = self.daemon.set_enabled(false) {
+            let error = e.to_string().into();
+            tracing::warn!(
+                "{}",
+                tr_args(Message::DndRestoreFailed, &[("error", error)])
+            );
+        }
+    }
+}
+
+/// Run a control tool, returning what it printed. Fails when it is missing or exits with an
+/// error, which they do when their daemon does not run.
+fn run(tool: &str, args: &[&str]) -> Result<String, Box<dyn Error>> {
+    let output = Command::new(tool)
+        .args(args)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => format!("{} is not installed", tool),
+            _ => format!("failed to spawn {}: {}", tool, e),
+        })?;
+    if !output.status.success() {
+        return Err(format!("{} {} exited with {}", tool, args.join(" "), output.status).into());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
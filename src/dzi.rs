@@ -0,0 +1,133 @@
+use std::{error::Error, ffi::OsStr, io::Write, path::PathBuf};
+
+use image::{
+    imageops::{crop_imm, resize, FilterType},
+    RgbaImage,
+};
+
+use crate::{
+    analyze::choose_encoding,
+    backend::encode,
+    pipeline::{claim_path, create_dir, create_file},
+    Collision, EncodingFormat, EncodingOptions, Placement, Sink, Subject,
+};
+
+/// Side of the tiles without their overlap. With an overlap of 1 pixel on both sides, tiles
+/// are 256 pixels wide, the size viewers such as OpenSeadragon expect by default.
+pub const DZI_TILE_SIZE: u32 = 254;
+
+/// Pixels tiles share with each of their neighbours, so that viewers can blend their edges.
+pub const DZI_OVERLAP: u32 = 1;
+
+/// Writes images as Deep Zoom pyramids for --dzi: a `{stem}.dzi` descriptor along with the
+/// tiles of every level in `{stem}_files/{level}/{column}_{row}.{extension}`. Level 0 is a
+/// single pixel and every level doubles the size of the previous one, up to the full image.
+/// Pyramids are named after their subject like [`crate::FileSink`] does.
+pub struct DziSink {
+    /// Directory the pyramids are written to, created if missing.
+    pub directory: PathBuf,
+    /// Stem of the descriptor names.
+    pub stem: String,
+    /// Encoder of the tiles, png or jpg.
+    pub format: EncodingFormat,
+    pub options: EncodingOptions,
+    /// Pick the encoder of every pyramid from the content of its image, see
+    /// [`choose_encoding`].
+    pub auto_format: bool,
+    /// Create files and directories accessible by their owner only.
+    pub private: bool,
+    /// How descriptor names which are taken are resolved.
+    pub collision: Collision,
+}
+
+impl Sink for DziSink {
+    fn write(
+        &mut self,
+        subject: Subject,
+        image: RgbaImage,
+        _placement: &Placement,
+    ) -> Result<(), Box<dyn Error>> {
+        let (format, options) = if self.auto_format {
+            choose_encoding(&image, self.options)
+        } else {
+            (self.format, self.options)
+        };
+        let stem = match subject {
+            Subject::Composite => self.stem.clone(),
+            Subject::Output(name) | Subject::Crop(name) => format!("{}-{}", self.stem, name),
+            Subject::Window { app_id, number } => format!("{}-{}-{}", self.stem, app_id, number),
+        };
+        create_dir(&self.directory, self.private)?;
+        let descriptor = claim_path(
+            &self.directory,
+            OsStr::new(&stem),
+            OsStr::new("dzi"),
+            self.collision,
+        )?;
+        let mut tiles_name = descriptor.file_stem().unwrap_or_default().to_os_string();
+        tiles_name.push("_files");
+        let tiles = descriptor.with_file_name(tiles_name);
+        tracing::debug!(
+            "Writing {:?} as a Deep Zoom pyramid to {:?}",
+            subject,
+            descriptor
+        );
+
+        let (width, height) = image.dimensions();
+        let mut level_image = image;
+        for level in (0..=dzi_max_level(width, height)).rev() {
+            let level_directory = tiles.join(level.to_string());
+            create_dir(&level_directory, self.private)?;
+            let (level_width, level_height) = level_image.dimensions();
+            for row in 0..level_height.div_ceil(DZI_TILE_SIZE) {
+                for column in 0..level_width.div_ceil(DZI_TILE_SIZE) {
+                    let (x, tile_width) = dzi_tile_span(column, level_width);
+                    let (y, tile_height) = dzi_tile_span(row, level_height);
+                    let tile = crop_imm(&level_image, x, y, tile_width, tile_height).to_image();
+                    let path =
+                        level_directory.join(format!("{}_{}.{}", column, row, format.extension()));
+                    create_file(&path, self.private)?
+                        .write_all(&encode(&tile, format, &options)?)?;
+                }
+            }
+            if level > 0 {
+                level_image = resize(
+                    &level_image,
+                    level_width.div_ceil(2),
+                    level_height.div_ceil(2),
+                    FilterType::Triangle,
+                );
+            }
+        }
+
+        create_file(&descriptor, self.private)?.write_all(
+            format!(
+                "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+                 <Image xmlns=\"http://schemas.microsoft.com/deepzoom/2008\" Format=\"{}\" \
+                 Overlap=\"{}\" TileSize=\"{}\">\n  <Size Width=\"{}\" Height=\"{}\"/>\n</Image>\n",
+                format.extension(),
+                DZI_OVERLAP,
+                DZI_TILE_SIZE,
+                width,
+                height
+            )
+            .as_bytes(),
+        )?;
+        Ok(())
+    }
+}
+
+/// Level of the full size image in a pyramid of a `width` by `height` image, the number of
+/// times its largest side can be halved, rounding up, before reaching a single pixel.
+pub fn dzi_max_level(width: u32, height: u32) -> u32 {
+    let side = width.max(height).max(1);
+    u32::BITS - (side - 1).leading_zeros()
+}
+
+/// Start and length of the tile `index` along a side of `length` pixels, overlapping its
+/// neighbours by [`DZI_OVERLAP`].
+fn dzi_tile_span(index: u32, length: u32) -> (u32, u32) {
+    let start = (index * DZI_TILE_SIZE).saturating_sub(DZI_OVERLAP);
+    let end = ((index + 1) * DZI_TILE_SIZE + DZI_OVERLAP).min(length);
+    (start, end - start)
+}
@@ -0,0 +1,365 @@
+use std::{
+    error::Error, fs::File, os::unix::prelude::FromRawFd, process::Command, thread, time::Duration,
+};
+
+use memmap2::MmapMut;
+use nix::unistd;
+use wayland_client::{
+    delegate_noop,
+    protocol::{
+        wl_buffer::WlBuffer, wl_compositor::WlCompositor, wl_output, wl_region::WlRegion,
+        wl_registry, wl_shm, wl_shm_pool::WlShmPool, wl_surface::WlSurface,
+    },
+    Connection, Dispatch, QueueHandle,
+};
+use wayland_protocols::xdg::xdg_output::zv1::client::{
+    zxdg_output_manager_v1::ZxdgOutputManagerV1, zxdg_output_v1,
+};
+use wayland_protocols_wlr::layer_shell::v1::client::{
+    zwlr_layer_shell_v1::{self, ZwlrLayerShellV1},
+    zwlr_layer_surface_v1::{self, ZwlrLayerSurfaceV1},
+};
+
+use wayshot_core::intersection;
+
+use crate::{
+    backend::create_shm_fd,
+    i18n::{tr, tr_args, Message},
+    test_pattern, FrameArgs, Region,
+};
+
+/// How long the flash stays on screen.
+pub const FLASH_DURATION: Duration = Duration::from_millis(150);
+
+/// Premultiplied Argb8888 pixel of the flash, white at half opacity.
+const FLASH_PIXEL: [u8; 4] = [0x80, 0x80, 0x80, 0x80];
+
+/// Sound theme event played by --shutter-sound, see the freedesktop sound naming spec.
+const SHUTTER_SOUND: &str = "camera-shutter";
+
+struct OverlayOutput {
+    wl_output: wl_output::WlOutput,
+    /// Sent by wl_output from version 4 on, or else by xdg-output from version 2 on. Empty when
+    /// neither does.
+    name: String,
+    region: Region,
+}
+
+#[derive(Default)]
+struct OverlayState {
+    compositor: Option<WlCompositor>,
+    shm: Option<wl_shm::WlShm>,
+    layer_shell: Option<ZwlrLayerShellV1>,
+    xdg_output_manager: Option<ZxdgOutputManagerV1>,
+    outputs: Vec<OverlayOutput>,
+    /// Size each layer surface was configured with, by index.
+    configured: Vec<Option<(u32, u32)>>,
+}
+
+/// Briefly cover the captured area of `outputs`, picked by `args` like when capturing, with a
+/// white overlay. It is drawn once the frames have been copied, so it never shows up in them.
+pub fn flash(
+    args: &FrameArgs,
+    outputs: &[String],
+    duration: Duration,
+) -> Result<(), Box<dyn Error>> {
+    let select = |overlay_outputs: &[OverlayOutput]| {
+        if overlay_outputs.iter().any(|output| output.name.is_empty()) {
+            tracing::warn!("{}", tr(Message::FlashOutputUnnamed));
+        }
+        // Regions given along with an output are relative to it, like in capture_raw_frames.
+        let region = match (args.region, &args.output) {
+            (Some(mut region), Some(name)) => {
+                if let Some(output) = overlay_outputs.iter().find(|output| output.name == *name) {
+                    region.x += output.region.x;
+                    region.y += output.region.y;
+                }
+                Some(region)
+            }
+            (region, _) => region,
+        };
+        overlay_outputs
+            .iter()
+            .enumerate()
+            .filter(|(_, output)| outputs.contains(&output.name))
+            .map(|(index, output)| match region {
+                Some(region) => (index, intersection(&output.region, &region)),
+                None => (index, output.region),
+            })
+            .collect()
+    };
+    let paint = |_: u32, _: u32, pixels: &mut [u8]| {
+        for pixel in pixels.chunks_exact_mut(4) {
+            pixel.copy_from_slice(&FLASH_PIXEL);
+        }
+    };
+    with_overlays("wayshot-flash", select, paint, || thread::sleep(duration))
+}
+
+/// Cover the whole of every output, or only the one called `output`, with the calibration
+/// pattern of [`test_pattern`] while `while_shown` runs, eg: to capture it.
+pub fn with_test_pattern<T>(
+    output: Option<&str>,
+    while_shown: impl FnOnce() -> T,
+) -> Result<T, Box<dyn Error>> {
+    let select = |overlay_outputs: &[OverlayOutput]| {
+        overlay_outputs
+            .iter()
+            .enumerate()
+            .filter(|(_, overlay_output)| output.is_none_or(|name| overlay_output.name == name))
+            .map(|(index, overlay_output)| (index, overlay_output.region))
+            .collect()
+    };
+    let paint = |width: u32, height: u32, pixels: &mut [u8]| {
+        let pattern = test_pattern(width, height);
+        for (pixel, color) in pixels.chunks_exact_mut(4).zip(pattern.pixels()) {
+            let [red, green, blue, alpha] = color.0;
+            pixel.copy_from_slice(&[blue, green, red, alpha]);
+        }
+    };
+    with_overlays("wayshot-testpattern", select, paint, while_shown)
+}
+
+/// Show layer-shell surfaces in `namespace` on the overlay layer over the areas `select` picks,
+/// as output indices and global logical regions, with Argb8888 pixels drawn by `paint`, run
+/// `while_shown` and remove them again. The surfaces let clicks through.
+fn with_overlays<T>(
+    namespace: &str,
+    select: impl FnOnce(&[OverlayOutput]) -> Vec<(usize, Region)>,
+    paint: impl Fn(u32, u32, &mut [u8]),
+    while_shown: impl FnOnce() -> T,
+) -> Result<T, Box<dyn Error>> {
+    let conn = Connection::connect_to_env()?;
+    let mut event_queue = conn.new_event_queue();
+    let qh = event_queue.handle();
+    let _registry = conn.display().get_registry(&qh, ());
+
+    let mut state = OverlayState::default();
+    event_queue.roundtrip(&mut state)?;
+    let (compositor, shm, layer_shell, xdg_output_manager) = match (
+        state.compositor.clone(),
+        state.shm.clone(),
+        state.layer_shell.clone(),
+        state.xdg_output_manager.clone(),
+    ) {
+        (Some(compositor), Some(shm), Some(layer_shell), Some(xdg_output_manager)) => {
+            (compositor, shm, layer_shell, xdg_output_manager)
+        }
+        _ => return Err(tr(Message::LayerShellUnsupported).into()),
+    };
+    for (index, output) in state.outputs.iter().enumerate() {
+        xdg_output_manager.get_xdg_output(&output.wl_output, &qh, index);
+    }
+    event_queue.roundtrip(&mut state)?;
+
+    let mut surfaces = Vec::new();
+    for (index, area) in select(&state.outputs) {
+        if area.width <= 0 || area.height <= 0 {
+            continue;
+        }
+        let output = &state.outputs[index];
+
+        let surface = compositor.create_surface(&qh, ());
+        // An empty input region lets clicks through to the windows below.
+        let input_region = compositor.create_region(&qh, ());
+        surface.set_input_region(Some(&input_region));
+        let layer_surface = layer_shell.get_layer_surface(
+            &surface,
+            Some(&output.wl_output),
+            zwlr_layer_shell_v1::Layer::Overlay,
+            namespace.to_string(),
+            &qh,
+            surfaces.len(),
+        );
+        layer_surface
+            .set_anchor(zwlr_layer_surface_v1::Anchor::Top | zwlr_layer_surface_v1::Anchor::Left);
+        layer_surface.set_margin(area.y - output.region.y, 0, 0, area.x - output.region.x);
+        layer_surface.set_size(area.width as u32, area.height as u32);
+        layer_surface.set_exclusive_zone(-1);
+        surface.commit();
+        state.configured.push(None);
+        surfaces.push((
+            surface,
+            layer_surface,
+            (area.width as u32, area.height as u32),
+        ));
+    }
+    if surfaces.is_empty() {
+        return Ok(while_shown());
+    }
+    event_queue.roundtrip(&mut state)?;
+
+    let mut buffers = Vec::with_capacity(surfaces.len());
+    for (index, (surface, _, size)) in surfaces.iter().enumerate() {
+        let (width, height) = match state.configured[index] {
+            Some((width, height)) if width > 0 && height > 0 => (width, height),
+            _ => *size,
+        };
+        let bytes = width as usize * height as usize * 4;
+        let fd = create_shm_fd()?;
+        unistd::ftruncate(fd, bytes as i64)?;
+        let file = unsafe { File::from_raw_fd(fd) };
+        let mut pixels = unsafe { MmapMut::map_mut(&file)? };
+        paint(width, height, &mut pixels);
+
+        let pool = shm.create_pool(fd, bytes as i32, &qh, ());
+        let buffer = pool.create_buffer(
+            0,
+            width as i32,
+            height as i32,
+            width as i32 * 4,
+            wl_shm::Format::Argb8888,
+            &qh,
+            (),
+        );
+        surface.attach(Some(&buffer), 0, 0);
+        surface.damage(0, 0, width as i32, height as i32);
+        surface.commit();
+        buffers.push((buffer, pool, file));
+    }
+    event_queue.roundtrip(&mut state)?;
+
+    let shown = while_shown();
+    for (surface, layer_surface, _) in surfaces {
+        layer_surface.destroy();
+        surface.destroy();
+    }
+    for (buffer, pool, _) in buffers {
+        buffer.destroy();
+        pool.destroy();
+    }
+    event_queue.roundtrip(&mut state)?;
+    Ok(shown)
+}
+
+/// Play the camera shutter sound of the sound theme through libcanberra, without waiting for it
+/// to finish.
+pub fn play_shutter_sound() -> Result<(), Box<dyn Error>> {
+    Command::new("canberra-gtk-play")
+        .args(["--id", SHUTTER_SOUND])
+        .spawn()
+        .map_err(|e| {
+            tr_args(
+                Message::CommandSpawnFailed,
+                &[
+                    ("program", "canberra-gtk-play".into()),
+                    ("error", e.to_string().into()),
+                ],
+            )
+        })?;
+    Ok(())
+}
+
+impl Dispatch<wl_registry::WlRegistry, ()> for OverlayState {
+    fn event(
+        state: &mut Self,
+        registry: &wl_registry::WlRegistry,
+        event: wl_registry::Event,
+        _: &(),
+        _: &Connection,
+        qh: &QueueHandle<Self>,
+    ) {
+        if let wl_registry::Event::Global {
+            name,
+            interface,
+            version,
+        } = event
+        {
+            match interface.as_str() {
+                "wl_compositor" => {
+                    state.compositor = Some(registry.bind(name, version.min(4), qh, ()));
+                }
+                "wl_shm" => state.shm = Some(registry.bind(name, 1, qh, ())),
+                "zwlr_layer_shell_v1" => {
+                    state.layer_shell = Some(registry.bind(name, version.min(3), qh, ()));
+                }
+                "zxdg_output_manager_v1" => {
+                    state.xdg_output_manager = Some(registry.bind(name, version.min(2), qh, ()));
+                }
+                "wl_output" => state.outputs.push(OverlayOutput {
+                    wl_output: registry.bind(name, version.min(4), qh, ()),
+                    name: String::new(),
+                    region: Region::default(),
+                }),
+                _ => {}
+            }
+        }
+    }
+}
+
+impl Dispatch<wl_output::WlOutput, ()> for OverlayState {
+    fn event(
+        state: &mut Self,
+        wl_output: &wl_output::WlOutput,
+        event: wl_output::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let wl_output::Event::Name { name } = event {
+            if let Some(output) = state
+                .outputs
+                .iter_mut()
+                .find(|output| output.wl_output == *wl_output)
+            {
+                output.name = name;
+            }
+        }
+    }
+}
+
+impl Dispatch<zxdg_output_v1::ZxdgOutputV1, usize> for OverlayState {
+    fn event(
+        state: &mut Self,
+        _: &zxdg_output_v1::ZxdgOutputV1,
+        event: zxdg_output_v1::Event,
+        index: &usize,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        let output = &mut state.outputs[*index];
+        match event {
+            zxdg_output_v1::Event::LogicalPosition { x, y } => {
+                output.region.x = x;
+                output.region.y = y;
+            }
+            zxdg_output_v1::Event::LogicalSize { width, height } => {
+                output.region.width = width;
+                output.region.height = height;
+            }
+            // Outputs older than version 4 of wl_output are only named by xdg-output.
+            zxdg_output_v1::Event::Name { name } if output.name.is_empty() => output.name = name,
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<ZwlrLayerSurfaceV1, usize> for OverlayState {
+    fn event(
+        state: &mut Self,
+        layer_surface: &ZwlrLayerSurfaceV1,
+        event: zwlr_layer_surface_v1::Event,
+        index: &usize,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let zwlr_layer_surface_v1::Event::Configure {
+            serial,
+            width,
+            height,
+        } = event
+        {
+            layer_surface.ack_configure(serial);
+            state.configured[*index] = Some((width, height));
+        }
+    }
+}
+
+delegate_noop!(OverlayState: WlCompositor);
+delegate_noop!(OverlayState: WlRegion);
+delegate_noop!(OverlayState: WlShmPool);
+delegate_noop!(OverlayState: ZwlrLayerShellV1);
+delegate_noop!(OverlayState: ZxdgOutputManagerV1);
+delegate_noop!(OverlayState: ignore wl_shm::WlShm);
+delegate_noop!(OverlayState: ignore WlSurface);
+delegate_noop!(OverlayState: ignore WlBuffer);
@@ -0,0 +1,173 @@
+use std::{
+    collections::HashSet,
+    env,
+    error::Error,
+    fs, io,
+    os::unix::process::CommandExt,
+    path::{Path, PathBuf},
+    process::{Child, Command},
+    thread,
+    time::{Duration, Instant},
+};
+
+use image::RgbaImage;
+use nix::libc;
+
+use crate::{
+    capture_outputs,
+    i18n::{tr_args, Message},
+    FrameArgs,
+};
+
+/// Compositor `wayshot ci-shot` starts by default.
+pub const DEFAULT_CI_COMPOSITOR: &str = "sway";
+
+/// How long `wayshot ci-shot` waits by default for the compositor to start and for the
+/// application to show up.
+pub const DEFAULT_CI_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Time given to the compositor to create its outputs once it listens, and to the application
+/// to finish drawing once it shows up.
+const SETTLE_DURATION: Duration = Duration::from_millis(500);
+
+/// How often the runtime directory and the screen are checked while waiting.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A compositor started without any real output or input device, and the application running
+/// in it. The compositor is terminated along with wayshot, however it exits, and the application
+/// loses its connection along with it.
+pub struct Headless {
+    pub compositor: Child,
+    pub app: Child,
+    /// Name of the socket of the compositor in XDG_RUNTIME_DIR, eg: wayland-1.
+    pub socket: String,
+}
+
+/// Start `compositor` with the headless wlroots backend, point this process at it through
+/// WAYLAND_DISPLAY, run `app` in it and wait until it shows up on screen. Fails if either
+/// exits early or `timeout` passes first.
+pub fn start_headless(
+    compositor: &str,
+    app: &str,
+    args: &FrameArgs,
+    timeout: Duration,
+) -> Result<Headless, Box<dyn Error>> {
+    let deadline = Instant::now() + timeout;
+    let runtime_dir =
+        PathBuf::from(env::var_os("XDG_RUNTIME_DIR").ok_or("XDG_RUNTIME_DIR is not set")?);
+    let existing = wayland_sockets(&runtime_dir)?;
+
+    // Replacing the shell, so that the compositor itself receives SIGTERM along with wayshot.
+    let mut compositor_process = shell(&format!("exec {}", compositor))
+        .env("WLR_BACKENDS", "headless")
+        .env("WLR_LIBINPUT_NO_DEVICES", "1")
+        .env_remove("WAYLAND_DISPLAY")
+        .spawn()
+        .map_err(|e| {
+            tr_args(
+                Message::CiShotSpawnFailed,
+                &[
+                    ("program", compositor.into()),
+                    ("error", e.to_string().into()),
+                ],
+            )
+        })?;
+    let socket = loop {
+        if let Some(socket) = wayland_sockets(&runtime_dir)?
+            .into_iter()
+            .find(|socket| !existing.contains(socket))
+        {
+            break socket;
+        }
+        wait(&mut compositor_process, compositor, deadline)?;
+    };
+    tracing::debug!("{} is listening on {}", compositor, socket);
+    env::set_var("WAYLAND_DISPLAY", &socket);
+    // Outputs are created after the socket, capturing before then fails for good.
+    thread::sleep(SETTLE_DURATION);
+
+    // The desktop as it looks before the application shows up.
+    let before = wait_for_capture(args, &mut compositor_process, compositor, deadline)?;
+    let mut app_process = shell(app).spawn().map_err(|e| {
+        tr_args(
+            Message::CiShotSpawnFailed,
+            &[("program", app.into()), ("error", e.to_string().into())],
+        )
+    })?;
+    loop {
+        wait(&mut app_process, app, deadline)?;
+        let captures = capture_outputs(args)?;
+        if captures.len() != before.len()
+            || captures
+                .iter()
+                .zip(&before)
+                .any(|(capture, image)| capture.image != *image)
+        {
+            break;
+        }
+    }
+    thread::sleep(SETTLE_DURATION);
+
+    Ok(Headless {
+        compositor: compositor_process,
+        app: app_process,
+        socket,
+    })
+}
+
+/// `command` run through the shell, receiving SIGTERM once wayshot exits so that nothing is
+/// left running, eg: when the capture fails.
+fn shell(command: &str) -> Command {
+    let mut process = Command::new("sh");
+    process.args(["-c", command]);
+    unsafe {
+        process.pre_exec(|| {
+            if libc::prctl(libc::PR_SET_PDEATHSIG, libc::SIGTERM) == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+    process
+}
+
+/// Names of the Wayland sockets in `runtime_dir`.
+fn wayland_sockets(runtime_dir: &Path) -> io::Result<HashSet<String>> {
+    Ok(fs::read_dir(runtime_dir)?
+        .flatten()
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .filter(|name| name.starts_with("wayland-") && !name.ends_with(".lock"))
+        .collect())
+}
+
+/// Sleep before checking again, failing if `process` exited or `deadline` passed.
+fn wait(process: &mut Child, command: &str, deadline: Instant) -> Result<(), Box<dyn Error>> {
+    if let Some(status) = process.try_wait()? {
+        let args = [
+            ("command", command.into()),
+            ("status", status.to_string().into()),
+        ];
+        return Err(tr_args(Message::CiShotExited, &args).into());
+    }
+    if Instant::now() >= deadline {
+        return Err(tr_args(Message::CiShotTimeout, &[("command", command.into())]).into());
+    }
+    thread::sleep(POLL_INTERVAL);
+    Ok(())
+}
+
+/// Capture the screen of the compositor as soon as it has outputs to capture.
+fn wait_for_capture(
+    args: &FrameArgs,
+    compositor: &mut Child,
+    command: &str,
+    deadline: Instant,
+) -> Result<Vec<RgbaImage>, Box<dyn Error>> {
+    loop {
+        match capture_outputs(args) {
+            Ok(captures) => return Ok(captures.into_iter().map(|capture| capture.image).collect()),
+            Err(e) => tracing::debug!("{} is not ready yet: {}", command, e),
+        }
+        wait(compositor, command, deadline)?;
+    }
+}
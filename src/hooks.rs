@@ -0,0 +1,293 @@
+use std::{error::Error, path::Path};
+
+use image::{imageops::crop_imm, Rgba, RgbaImage};
+use rhai::{Array, Dynamic, Engine, EvalAltResult, Map, Scope, AST};
+
+use crate::{
+    config::config_dir,
+    i18n::{tr_args, Message},
+    Region,
+};
+
+/// Details of a capture passed to the `on_capture` and `filename` hooks.
+#[derive(Debug, Clone)]
+pub struct CaptureInfo {
+    /// Names of the captured outputs.
+    pub outputs: Vec<String>,
+    /// Current modes of the captured outputs, eg: "2560x1440@143.912Hz", empty if unknown.
+    pub modes: Vec<String>,
+    /// Captured area in global logical coordinates.
+    pub region: Region,
+    /// Size in pixels of the captured image.
+    pub width: u32,
+    pub height: u32,
+    /// Extension of the encoder the image is saved with, or "auto" with --extension auto.
+    pub format: &'static str,
+}
+
+impl CaptureInfo {
+    fn to_map(&self) -> Map {
+        let outputs: Array = self.outputs.iter().cloned().map(Dynamic::from).collect();
+        let mut map = Map::new();
+        let modes: Array = self.modes.iter().cloned().map(Dynamic::from).collect();
+        map.insert("outputs".into(), outputs.into());
+        map.insert("modes".into(), modes.into());
+        map.insert("x".into(), (self.region.x as i64).into());
+        map.insert("y".into(), (self.region.y as i64).into());
+        map.insert("width".into(), (self.width as i64).into());
+        map.insert("height".into(), (self.height as i64).into());
+        map.insert("format".into(), self.format.into());
+        map
+    }
+}
+
+/// User hooks written in Rhai, each of them optional:
+///
+/// - `on_capture(info)` runs once the frames are captured, eg: to log them.
+/// - `filename(info)` returns the stem of generated file names, replacing --filename-format.
+/// - `post_process(image)` returns the image to save, eg: after redacting part of it.
+///
+/// `info` is a map with the `outputs`, their `modes`, `x`, `y`, `width`, `height` and `format` of
+/// the capture.
+/// Images have `width()`, `height()`, `get_pixel(x, y)` and `set_pixel(x, y, [r, g, b, a])`
+/// methods along with `crop(x, y, width, height)`, which returns a new image.
+pub struct Hooks {
+    engine: Engine,
+    ast: AST,
+}
+
+impl Hooks {
+    /// Load `hooks.rhai` from the wayshot config directory, if there is one.
+    pub fn load() -> Result<Option<Self>, Box<dyn Error>> {
+        let path = config_dir().join("hooks.rhai");
+        if !path.exists() {
+            return Ok(None);
+        }
+        tracing::debug!("Loading hooks from {:?}", path);
+        Self::load_from(&path).map(Some)
+    }
+
+    /// Compile the hooks in the script at `path`.
+    pub fn load_from(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let mut engine = Engine::new();
+        register_image_api(&mut engine);
+        let ast = engine.compile_file(path.to_path_buf()).map_err(|e| {
+            tr_args(
+                Message::HooksUnloadable,
+                &[
+                    ("path", path.display().to_string().into()),
+                    ("error", e.to_string().into()),
+                ],
+            )
+        })?;
+        Ok(Self { engine, ast })
+    }
+
+    /// Whether the script defines the hook `name` taking a single argument.
+    pub fn has_hook(&self, name: &str) -> bool {
+        self.ast
+            .iter_functions()
+            .any(|function| function.name == name && function.params.len() == 1)
+    }
+
+    pub fn on_capture(&self, info: &CaptureInfo) -> Result<(), Box<dyn Error>> {
+        if self.has_hook("on_capture") {
+            let _ = self.call("on_capture", info.to_map())?;
+        }
+        Ok(())
+    }
+
+    /// The file name stem returned by the `filename` hook, if the script defines one. Stems
+    /// have to name a file in the output directory: they can not be empty, "." or "..", nor
+    /// hold slashes or NUL bytes.
+    pub fn filename(&self, info: &CaptureInfo) -> Result<Option<String>, Box<dyn Error>> {
+        if !self.has_hook("filename") {
+            return Ok(None);
+        }
+        let stem = self
+            .call("filename", info.to_map())?
+            .into_string()
+            .map_err(|kind| {
+                tr_args(
+                    Message::FilenameHookNotString,
+                    &[("type", kind.to_string().into())],
+                )
+            })?;
+        if matches!(stem.as_str(), "" | "." | "..") || stem.contains(['/', '\0']) {
+            return Err(tr_args(
+                Message::FilenameHookInvalid,
+                &[("name", format!("{:?}", stem).into())],
+            )
+            .into());
+        }
+        Ok(Some(stem))
+    }
+
+    pub fn post_process(&self, image: RgbaImage) -> Result<RgbaImage, Box<dyn Error>> {
+        if !self.has_hook("post_process") {
+            return Ok(image);
+        }
+        let result = self.call("post_process", Dynamic::from(image))?;
+        let kind = result.type_name();
+        result.try_cast::<RgbaImage>().ok_or_else(|| {
+            tr_args(
+                Message::PostProcessHookNotImage,
+                &[("type", kind.to_string().into())],
+            )
+            .into()
+        })
+    }
+
+    fn call(&self, name: &str, argument: impl Into<Dynamic>) -> Result<Dynamic, Box<dyn Error>> {
+        let argument: Dynamic = argument.into();
+        self.engine
+            .call_fn::<Dynamic>(&mut Scope::new(), &self.ast, name, (argument,))
+            .map_err(|e| {
+                tr_args(
+                    Message::HookFailed,
+                    &[("hook", name.into()), ("error", e.to_string().into())],
+                )
+                .into()
+            })
+    }
+}
+
+fn register_image_api(engine: &mut Engine) {
+    engine
+        .register_type_with_name::<RgbaImage>("Image")
+        .register_fn("width", |image: &mut RgbaImage| image.width() as i64)
+        .register_fn("height", |image: &mut RgbaImage| image.height() as i64)
+        .register_fn(
+            "get_pixel",
+            |image: &mut RgbaImage, x: i64, y: i64| -> Result<Array, Box<EvalAltResult>> {
+                let (x, y) = pixel_position(image, x, y)?;
+                Ok(image
+                    .get_pixel(x, y)
+                    .0
+                    .iter()
+                    .map(|&channel| Dynamic::from(channel as i64))
+                    .collect())
+            },
+        )
+        .register_fn(
+            "set_pixel",
+            |image: &mut RgbaImage,
+             x: i64,
+             y: i64,
+             color: Array|
+             -> Result<(), Box<EvalAltResult>> {
+                let (x, y) = pixel_position(image, x, y)?;
+                let channels = color
+                    .iter()
+                    .map(|channel| channel.as_int().ok().and_then(|c| u8::try_from(c).ok()))
+                    .collect::<Option<Vec<_>>>()
+                    .filter(|channels| channels.len() == 4)
+                    .ok_or("Colors are arrays of four channels from 0 to 255")?;
+                image.put_pixel(
+                    x,
+                    y,
+                    Rgba([channels[0], channels[1], channels[2], channels[3]]),
+                );
+                Ok(())
+            },
+        )
+        .register_fn(
+            "crop",
+            |image: &mut RgbaImage,
+             x: i64,
+             y: i64,
+             width: i64,
+             height: i64|
+             -> Result<RgbaImage, Box<EvalAltResult>> {
+                let (x, y) = pixel_position(image, x, y)?;
+                let width = u32::try_from(width).map_err(|_| "Negative crop width")?;
+                let height = u32::try_from(height).map_err(|_| "Negative crop height")?;
+                Ok(crop_imm(image, x, y, width, height).to_image())
+            },
+        );
+}
+
+fn pixel_position(image: &RgbaImage, x: i64, y: i64) -> Result<(u32, u32), Box<EvalAltResult>> {
+    match (u32::try_from(x), u32::try_from(y)) {
+        (Ok(x), Ok(y)) if x < image.width() && y < image.height() => Ok((x, y)),
+        _ => Err(format!("Pixel {},{} is outside of the image", x, y).into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::testing::TempDirectory;
+
+    fn hooks(script: &str) -> Hooks {
+        let directory = TempDirectory::new();
+        let path = directory.path().join("hooks.rhai");
+        fs::write(&path, script).unwrap();
+        Hooks::load_from(&path).unwrap()
+    }
+
+    fn info() -> CaptureInfo {
+        CaptureInfo {
+            outputs: vec!["DP-1".to_string()],
+            modes: vec!["2560x1440@143.912Hz".to_string()],
+            region: Region {
+                x: 0,
+                y: 0,
+                width: 2560,
+                height: 1440,
+            },
+            width: 2560,
+            height: 1440,
+            format: "png",
+        }
+    }
+
+    fn filename(body: &str) -> Result<Option<String>, Box<dyn Error>> {
+        hooks(&format!("fn filename(info) {{ {} }}", body)).filename(&info())
+    }
+
+    #[test]
+    fn filename_hooks_name_the_file() {
+        assert_eq!(
+            filename(r#"info.outputs[0] + "-" + info.width"#).unwrap(),
+            Some("DP-1-2560".to_string())
+        );
+        assert_eq!(
+            filename(r#"".hidden""#).unwrap(),
+            Some(".hidden".to_string())
+        );
+        assert_eq!(hooks("fn other(x) { x }").filename(&info()).unwrap(), None);
+    }
+
+    #[test]
+    fn filename_hooks_can_not_leave_the_directory() {
+        for name in [
+            r#""""#,
+            r#"".""#,
+            r#""..""#,
+            r#""a/b""#,
+            r#""/tmp/x""#,
+            r#""a\x00b""#,
+        ] {
+            assert!(filename(name).is_err(), "{}", name);
+        }
+        assert!(filename("42").is_err());
+    }
+
+    #[test]
+    fn failing_hooks_are_errors() {
+        assert!(filename(r#"throw "no name""#).is_err());
+        let hooks = hooks("fn post_process(image) { 1 }");
+        assert!(hooks.post_process(RgbaImage::new(1, 1)).is_err());
+    }
+
+    #[test]
+    fn scripts_which_do_not_compile_are_errors() {
+        let directory = TempDirectory::new();
+        let path = directory.path().join("hooks.rhai");
+        fs::write(&path, "fn filename(info) {").unwrap();
+        assert!(Hooks::load_from(&path).is_err());
+    }
+}
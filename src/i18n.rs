@@ -0,0 +1,336 @@
+use std::{env, sync::OnceLock};
+
+use fluent_bundle::{concurrent::FluentBundle, FluentArgs, FluentResource, FluentValue};
+use unic_langid::LanguageIdentifier;
+
+/// Languages user facing messages are translated to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Locale {
+    English,
+    French,
+    German,
+}
+
+impl Locale {
+    const ALL: [Locale; 3] = [Locale::English, Locale::French, Locale::German];
+
+    /// Language of the locale, which its messages are in the i18n directory of.
+    fn language(self) -> &'static str {
+        match self {
+            Locale::English => "en",
+            Locale::French => "fr",
+            Locale::German => "de",
+        }
+    }
+
+    /// Messages of the locale in the Fluent syntax, built into wayshot.
+    fn messages(self) -> &'static str {
+        match self {
+            Locale::English => include_str!("../i18n/en/wayshot.ftl"),
+            Locale::French => include_str!("../i18n/fr/wayshot.ftl"),
+            Locale::German => include_str!("../i18n/de/wayshot.ftl"),
+        }
+    }
+}
+
+/// User facing messages, translated in i18n/<language>/wayshot.ftl under their name in kebab
+/// case, eg: `invalid-fps` for [`Message::InvalidFps`]. Values such as paths or limits are
+/// named arguments of the message given to [`tr_args`], so translations place them where their
+/// language wants them. Messages of optional features go unused when those are disabled.
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Message {
+    MissingShm,
+    MissingScreencopy,
+    OutputWithoutName,
+    RegionOutsideOutputs,
+    MissingFrameFormat,
+    FrameCopyFailed,
+    UnsupportedBufferFormat,
+    FeatureRequest,
+    CompositeFailed,
+    ExportedFrames,
+    GeometryNotReceived,
+    InvalidGeometry,
+    SelectionCancelled,
+    NoFocusedWindow,
+    UnsupportedCompositorIpc,
+    InvalidJpegSubsampling,
+    InvalidPngFilter,
+    InvalidExtension,
+    InvalidOutputFormats,
+    InvalidTrimTolerance,
+    InvalidCursorMode,
+    InvalidScale,
+    InvalidAnnotations,
+    InvalidThumbnailSize,
+    CursorUnavailable,
+    ValidArguments,
+    InvalidFilenameFormat,
+    StreamFallback,
+    CopyWithDamageUnsupported,
+    CompositorUnavailable,
+    CompositorDisconnected,
+    CompositorProtocolError,
+    FrameAlreadyCopied,
+    InvalidFrameBuffer,
+    InvalidShmFormat,
+    InvalidShmStride,
+    InvalidShmFd,
+    InvalidRequest,
+    CompositorOutOfMemory,
+    CompositorInternalError,
+    FeatureUnavailable,
+    NoAppWindows,
+    InvalidWindowIndex,
+    InvalidStackCount,
+    InvalidStackMode,
+    StackOutputsChanged,
+    LogicalGeometryEstimated,
+    LogicalGeometryMissing,
+    FlashFailed,
+    ShutterSoundFailed,
+    InvalidFrameSize,
+    InvalidPaletteSize,
+    AutoFormatStream,
+    InvalidMaxDuration,
+    DeadlineExceeded,
+    ScreencopySandboxed,
+    InvalidQuirk,
+    DetectedCompositor,
+    BlankFrame,
+    InvalidPort,
+    InvalidFps,
+    ServeBindFailed,
+    Serving,
+    ServeCaptureFailed,
+    InvalidDziFormat,
+    ReferenceUnreadable,
+    InvalidDiffTolerance,
+    DiffSizeMismatch,
+    ChangedRegions,
+    InvalidZoom,
+    InvalidZoomFilter,
+    TestPatternFailed,
+    TestPatternDeltas,
+    TestPatternMismatch,
+    InvalidForceFormat,
+    ForcedFormatMismatch,
+    InvalidBatteryFps,
+    ServeOnBattery,
+    ServeOnAc,
+    InvalidCollision,
+    FileExists,
+    CiShotSpawnFailed,
+    CiShotExited,
+    CiShotTimeout,
+    InvalidCiTimeout,
+    CiShotFailed,
+    InvalidTiffCompression,
+    InvalidOutputTimeout,
+    FrameTimedOut,
+    NoFrameCopied,
+    InvalidGamma,
+    InvalidBrightness,
+    InvalidContrast,
+    InvalidCrop,
+    DuplicateCropName,
+    Encoders,
+    NotEnoughSpace,
+    EncodingDegraded,
+    ToplevelsUnavailable,
+    WatchTriggered,
+    WatchCaptureFailed,
+    InvalidRedactStyle,
+    InvalidSimulation,
+    InvalidAnalysis,
+    OutputCacheFailed,
+    InvalidAlsoSave,
+    InvalidChecksum,
+    CaptureBudgetExceeded,
+    CaptureCommandFailed,
+    InvalidHookTimeout,
+    InvalidHookFailure,
+    DndUnavailable,
+    DndFailed,
+    DndRestoreFailed,
+    BugReportFailed,
+    InvalidToneMap,
+    InvalidHdrOutputs,
+    InvalidMaxSize,
+    QualityLowered,
+    ImageTooLarge,
+    CompositorConnectionFailed,
+    GeometryUnreadable,
+    CursorUnavailableWithLayout,
+    FrameBudgetExceeded,
+    RegionSkipped,
+    CommandSpawnFailed,
+    CommandFailed,
+    RedactionsUnreadable,
+    RedactionsInvalid,
+    RedactionLineInvalid,
+    RedactionRegexInvalid,
+    RulesUnreadable,
+    RulesInvalid,
+    ConfigUnreadable,
+    ConfigInvalid,
+    ConfigUnknownOption,
+    ConfigValueInvalid,
+    LayerShellUnsupported,
+    FlashOutputUnnamed,
+    HooksUnloadable,
+    FilenameHookNotString,
+    FilenameHookInvalid,
+    PostProcessHookNotImage,
+    HookFailed,
+    CaptureWorkerPanicked,
+    InvalidBenchSize,
+    InvalidBenchRuns,
+    BenchFailed,
+    BenchWriteTiming,
+}
+
+impl Message {
+    /// Fluent id of the message, its name in kebab case.
+    fn id(self) -> String {
+        let mut id = String::new();
+        for (index, c) in format!("{:?}", self).chars().enumerate() {
+            if c.is_ascii_uppercase() && index > 0 {
+                id.push('-');
+            }
+            id.push(c.to_ascii_lowercase());
+        }
+        id
+    }
+}
+
+static LOCALE: OnceLock<Locale> = OnceLock::new();
+
+static BUNDLES: [OnceLock<FluentBundle<FluentResource>>; Locale::ALL.len()] =
+    [const { OnceLock::new() }; Locale::ALL.len()];
+
+/// Detect the locale from LC_ALL, LC_MESSAGES and LANG, in the same order gettext uses.
+/// Unknown languages fall back to English.
+pub fn detect_locale() -> Locale {
+    let value = ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|name| env::var(name).ok())
+        .find(|value| !value.is_empty())
+        .unwrap_or_default();
+    parse_locale(&value)
+}
+
+/// Parse a POSIX locale name such as `de_DE.UTF-8@euro`.
+pub fn parse_locale(value: &str) -> Locale {
+    let language = value.split(['_', '.', '@']).next().unwrap_or_default();
+    match language {
+        "de" => Locale::German,
+        "fr" => Locale::French,
+        _ => Locale::English,
+    }
+}
+
+/// Translate `message`, which takes no arguments, to the locale of the current process.
+pub fn tr(message: Message) -> String {
+    tr_args(message, &[])
+}
+
+/// Translate `message` to the locale of the current process, filling in its named arguments,
+/// eg: `tr_args(Message::InvalidFps, &[("max", MAX_FPS.into())])`.
+pub fn tr_args(message: Message, args: &[(&str, FluentValue)]) -> String {
+    translate(*LOCALE.get_or_init(detect_locale), message, args)
+}
+
+/// Translate `message` to `locale`, falling back to English for messages it lacks and to the id
+/// of the message if English lacks it too.
+pub fn translate(locale: Locale, message: Message, args: &[(&str, FluentValue)]) -> String {
+    let id = message.id();
+    let mut fluent_args = FluentArgs::new();
+    for (name, value) in args {
+        fluent_args.set(*name, value.clone());
+    }
+    for locale in [locale, Locale::English] {
+        let bundle = bundle(locale);
+        let pattern = match bundle.get_message(&id).and_then(|message| message.value()) {
+            Some(pattern) => pattern,
+            None => continue,
+        };
+        let mut errors = Vec::new();
+        let text = bundle.format_pattern(pattern, Some(&fluent_args), &mut errors);
+        if !errors.is_empty() {
+            tracing::debug!("Failed to format {} in {:?}: {:?}", id, locale, errors);
+        }
+        return text.into_owned();
+    }
+    id
+}
+
+/// Messages of `locale`, parsed the first time they are needed.
+fn bundle(locale: Locale) -> &'static FluentBundle<FluentResource> {
+    BUNDLES[locale as usize].get_or_init(|| {
+        let language: LanguageIdentifier = locale.language().parse().unwrap();
+        let mut bundle = FluentBundle::new_concurrent(vec![language]);
+        // Unicode isolation marks around arguments show up as garbage in terminals and logs.
+        bundle.set_use_isolating(false);
+        let resource = FluentResource::try_new(locale.messages().to_string()).unwrap_or_else(
+            |(resource, errors)| {
+                tracing::debug!("Invalid messages in {:?}: {:?}", locale, errors);
+                resource
+            },
+        );
+        if let Err(errors) = bundle.add_resource(resource) {
+            tracing::debug!("Duplicate messages in {:?}: {:?}", locale, errors);
+        }
+        bundle
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message_ids(locale: Locale) -> Vec<String> {
+        if let Err((_, errors)) = FluentResource::try_new(locale.messages().to_string()) {
+            panic!("invalid messages in {:?}: {:?}", locale, errors);
+        }
+        // Message definitions are the only lines that start with an identifier.
+        let mut ids: Vec<String> = locale
+            .messages()
+            .lines()
+            .filter(|line| line.starts_with(|c: char| c.is_ascii_lowercase()))
+            .filter_map(|line| line.split_once(" =").map(|(id, _)| id.to_string()))
+            .collect();
+        ids.sort();
+        ids
+    }
+
+    #[test]
+    fn locales_define_the_same_messages() {
+        let english = message_ids(Locale::English);
+        assert!(english.contains(&Message::ExportedFrames.id()));
+        for locale in [Locale::French, Locale::German] {
+            assert_eq!(message_ids(locale), english, "{:?}", locale);
+        }
+    }
+
+    #[test]
+    fn named_arguments_are_substituted() {
+        let args = [("output", "DP-1".into()), ("color", "#000000ff".into())];
+        let text = translate(Locale::English, Message::BlankFrame, &args);
+        assert!(text.contains("DP-1"), "{}", text);
+        assert!(text.contains("#000000ff"), "{}", text);
+        assert!(!text.contains('{'), "{}", text);
+    }
+
+    #[test]
+    fn plurals_follow_the_count() {
+        let exported = |locale, count: usize| {
+            let args = [("count", count.into()), ("directory", "/tmp".into())];
+            translate(locale, Message::ExportedFrames, &args)
+        };
+        assert_eq!(exported(Locale::French, 1), "1 image exportée vers /tmp");
+        assert_eq!(exported(Locale::French, 3), "3 images exportées vers /tmp");
+        assert_ne!(exported(Locale::German, 1), exported(Locale::German, 2));
+    }
+}
@@ -0,0 +1,74 @@
+use std::{collections::HashMap, error::Error, fs, path::Path};
+
+/// Where an output of a [`Layout`] is drawn.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct LayoutEntry {
+    /// Logical position of its top left corner, None to keep the one of the compositor.
+    pub position: Option<(i32, i32)>,
+    /// Logical offset added to the position, eg: to leave the gap of monitor bezels between
+    /// outputs, or to compensate for them with negative amounts.
+    pub bezel: (i32, i32),
+}
+
+impl LayoutEntry {
+    /// Position the output is drawn at, given its position in the compositor.
+    pub fn origin(&self, compositor: (i32, i32)) -> (i32, i32) {
+        let (x, y) = self.position.unwrap_or(compositor);
+        (x + self.bezel.0, y + self.bezel.1)
+    }
+}
+
+/// Where outputs are drawn, by output name.
+pub type Layout = HashMap<String, LayoutEntry>;
+
+/// Read a layout file, see [`parse_layout`].
+pub fn load_layout(path: &Path) -> Result<Layout, Box<dyn Error>> {
+    let text =
+        fs::read_to_string(path).map_err(|e| format!("Failed to read layout {:?}: {}", path, e))?;
+    parse_layout(&text).map_err(|e| format!("Invalid layout {:?}: {}", path, e).into())
+}
+
+/// Parse a layout made of one output per line, its name followed by the position it is drawn
+/// at in the saved image, eg: "DP-1 1940,0", by a bezel offset from its position in the
+/// compositor, eg: "DP-1 bezel 20,0", or by both, eg: "DP-1 1920,0 bezel 20,0". Blank lines
+/// and lines starting with # are ignored.
+pub fn parse_layout(text: &str) -> Result<Layout, String> {
+    let mut layout = Layout::new();
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let entry = split_pair(line).map(|(head, pair)| match head.strip_suffix("bezel") {
+            Some(head) if head.ends_with(char::is_whitespace) => {
+                match split_pair(head.trim_end()) {
+                    Some((name, position)) => (name, Some(position), pair),
+                    None => (head.trim_end(), None, pair),
+                }
+            }
+            _ => (head, Some(pair), (0, 0)),
+        });
+        match entry {
+            Some((name, position, bezel)) if !name.is_empty() => {
+                let entry = LayoutEntry { position, bezel };
+                if layout.insert(name.to_string(), entry).is_some() {
+                    return Err(format!("line {}: {} is placed twice", index + 1, name));
+                }
+            }
+            _ => {
+                return Err(format!(
+                    "line {}: expected \"NAME X,Y\", \"NAME bezel X,Y\" or \"NAME X,Y bezel X,Y\"",
+                    index + 1
+                ))
+            }
+        }
+    }
+    Ok(layout)
+}
+
+/// Split the "X,Y" pair ending `text` from what comes before it.
+fn split_pair(text: &str) -> Option<(&str, (i32, i32))> {
+    let (head, pair) = text.rsplit_once(char::is_whitespace)?;
+    let (x, y) = pair.split_once(',')?;
+    Some((head.trim_end(), (x.parse().ok()?, y.parse().ok()?)))
+}
@@ -0,0 +1,56 @@
+use std::{
+    env,
+    error::Error,
+    fs::{File, OpenOptions},
+    os::unix::prelude::AsRawFd,
+    path::PathBuf,
+};
+
+use nix::{
+    errno::Errno,
+    fcntl::{flock, FlockArg},
+    unistd::getuid,
+};
+
+/// Exclusive per-user lock, so instances started together, eg: by a double pressed keybinding,
+/// capture and write their files one after the other instead of racing. The lock is released
+/// when this is dropped.
+pub struct CaptureLock {
+    _file: File,
+}
+
+impl CaptureLock {
+    /// Take the lock, waiting for any other instance holding it to finish.
+    pub fn acquire() -> Result<Self, Box<dyn Error>> {
+        let path = lock_path();
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)
+            .map_err(|e| format!("Failed to open lock file {:?}: {}", path, e))?;
+
+        match flock(file.as_raw_fd(), FlockArg::LockExclusiveNonblock) {
+            Ok(()) => {}
+            Err(Errno::EWOULDBLOCK) => {
+                tracing::debug!("Waiting for another wayshot instance holding {:?}", path);
+                flock(file.as_raw_fd(), FlockArg::LockExclusive)?;
+            }
+            // Some filesystems, eg: NFS without a lock daemon, can not lock at all.
+            Err(errno @ (Errno::ENOLCK | Errno::ENOSYS | Errno::EOPNOTSUPP)) => {
+                tracing::debug!("Capturing without a lock, flock failed: {}", errno);
+            }
+            Err(errno) => return Err(errno.into()),
+        }
+        Ok(Self { _file: file })
+    }
+}
+
+/// The lock lives in XDG_RUNTIME_DIR, which is private to the user. The shared temporary
+/// directory is used without one, with the user id in the name.
+fn lock_path() -> PathBuf {
+    match env::var_os("XDG_RUNTIME_DIR") {
+        Some(directory) if !directory.is_empty() => PathBuf::from(directory).join("wayshot.lock"),
+        _ => env::temp_dir().join(format!("wayshot-{}.lock", getuid())),
+    }
+}
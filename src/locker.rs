@@ -0,0 +1,80 @@
+use std::{error::Error, num::NonZeroU64, sync::atomic::AtomicU64};
+
+use image::RgbaImage;
+
+use crate::{
+    bounding_region, capture_raw_frames_in, composite_captures,
+    convert::pixel_format,
+    convert_raw_capture,
+    i18n::{tr_args, Message},
+    worker::FrameMemory,
+    Compositor, FrameArgs, Quirks,
+};
+
+/// What a screen locker can ask of [`capture_for_locker`]. Unlike [`FrameArgs`], nothing in it
+/// names a file, a command or an unbounded amount of memory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LockerArgs {
+    /// Name of the output to capture, every output if None.
+    pub output: Option<String>,
+    pub cursor: bool,
+    /// Most bytes the pixels of the capture may take, frames and images together. There is no
+    /// default, lockers pick one fitting the outputs they expect.
+    pub max_bytes: NonZeroU64,
+}
+
+/// Capture the outputs of `args` into one image for a screen locker, eg: to show it blurred
+/// behind the lock prompt, so that lockers can depend on wayshot without widening their attack
+/// surface. The capture:
+///
+/// - never writes to the file system, not even for frames when shared memory is unavailable,
+/// - never spawns a process, runs hooks or reads configuration files,
+/// - never exits the process, failures are returned,
+/// - fails before the frames, the output images and the composite would take more than
+///   `max_bytes` together. Bookkeeping allocations, which do not grow with the size of
+///   outputs, are not counted.
+///
+/// Nothing is logged beyond tracing events, and no subscriber is installed.
+pub fn capture_for_locker(args: &LockerArgs) -> Result<RgbaImage, Box<dyn Error>> {
+    let max_bytes = args.max_bytes.get();
+    let frame_args = FrameArgs {
+        cursor: args.cursor,
+        output: args.output.clone(),
+        quirks: Quirks::for_compositor(Compositor::detect()),
+        ..Default::default()
+    };
+    // The frames alone may take every byte, the rest is checked once their sizes are known.
+    let memory = FrameMemory::Confined(AtomicU64::new(max_bytes));
+    let captures = capture_raw_frames_in(&frame_args, &memory)?;
+
+    // The frames stay mapped while they are converted into an image per output, which are
+    // then composited.
+    let pixel_bytes = |width: i32, height: i32| width.max(0) as u64 * height.max(0) as u64 * 4;
+    let frame_bytes: u64 = captures
+        .iter()
+        .map(|capture| capture.frame_mmap.len() as u64)
+        .sum();
+    let image_bytes: u64 = captures
+        .iter()
+        .map(|capture| pixel_bytes(capture.region.width, capture.region.height))
+        .sum();
+    let canvas = bounding_region(captures.iter().map(|capture| &capture.region));
+    let needed = frame_bytes + image_bytes + pixel_bytes(canvas.width, canvas.height);
+    if needed > max_bytes {
+        let args = [("needed", needed.into()), ("max", max_bytes.into())];
+        return Err(tr_args(Message::CaptureBudgetExceeded, &args).into());
+    }
+    if let Some(capture) = captures
+        .iter()
+        .find(|capture| pixel_format(capture.frame_format.format).is_none())
+    {
+        let format = format!("{:?}", capture.frame_format.format).into();
+        return Err(tr_args(Message::UnsupportedBufferFormat, &[("format", format)]).into());
+    }
+
+    let captures = captures
+        .into_iter()
+        .map(convert_raw_capture)
+        .collect::<Result<Vec<_>, _>>()?;
+    composite_captures(&captures)
+}
@@ -0,0 +1,76 @@
+use std::{
+    error::Error,
+    fs::File,
+    io::{stderr, IsTerminal},
+    path::Path,
+    sync::Mutex,
+};
+
+use tracing::Level;
+#[cfg(feature = "chrome-trace")]
+use tracing_chrome::ChromeLayerBuilder;
+#[cfg(not(feature = "chrome-trace"))]
+use tracing_subscriber::layer::Identity;
+use tracing_subscriber::{filter::Targets, fmt, prelude::*};
+
+/// Writes the Chrome trace once dropped.
+#[cfg(feature = "chrome-trace")]
+pub type TraceGuard = tracing_chrome::FlushGuard;
+
+/// Never constructed, traces can not be recorded without the chrome-trace feature.
+#[cfg(not(feature = "chrome-trace"))]
+pub type TraceGuard = std::convert::Infallible;
+
+/// Install the global tracing subscriber, printing wayshot events of `level` and above to
+/// stderr. With a `trace_output` path, spans are also recorded in the Chrome trace event
+/// format, viewable in chrome://tracing or Perfetto. The trace is written once the returned
+/// guard is dropped. With a `log_file`, every wayshot event is written to it as well, whatever
+/// `level` is, eg: for --bug-report.
+///
+/// Calling this again once a subscriber is installed does nothing.
+pub fn init(
+    level: Level,
+    trace_output: Option<&Path>,
+    log_file: Option<File>,
+) -> Result<Option<TraceGuard>, Box<dyn Error>> {
+    let filter = Targets::new().with_target("wayshot", level);
+    #[cfg(feature = "chrome-trace")]
+    let (chrome_layer, guard) = match trace_output {
+        Some(path) => {
+            let (layer, guard) = ChromeLayerBuilder::new()
+                .file(path)
+                .include_args(true)
+                .build();
+            (Some(layer), Some(guard))
+        }
+        None => (None, None),
+    };
+    #[cfg(not(feature = "chrome-trace"))]
+    let (chrome_layer, guard) = match trace_output {
+        Some(_) => return Err("wayshot was built without Chrome trace support".into()),
+        None => (None::<Identity>, None),
+    };
+
+    let file_layer = log_file.map(|file| {
+        fmt::layer()
+            .with_ansi(false)
+            .with_writer(Mutex::new(file))
+            .with_filter(Targets::new().with_target("wayshot", Level::TRACE))
+    });
+
+    let installed = tracing_subscriber::registry()
+        .with(file_layer)
+        .with(
+            fmt::layer()
+                .without_time()
+                .with_ansi(stderr().is_terminal())
+                .with_writer(stderr)
+                .with_filter(filter.clone()),
+        )
+        .with(chrome_layer.with_filter(filter))
+        .try_init();
+    if installed.is_err() {
+        return Ok(None);
+    }
+    Ok(guard)
+}
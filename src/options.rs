@@ -0,0 +1,426 @@
+use std::{collections::HashMap, error::Error, path::PathBuf, str::FromStr};
+
+use clap::ArgMatches;
+
+use crate::{
+    i18n::{tr, Message},
+    Analysis, Checksum, Collision, ColorVision, EncodingFormat, EncodingOptions, HdrOutputs,
+    HookFailure, JpegSubsampling, PngFilter, RedactStyle, StackMode, TiffCompression,
+    ToneMapOperator, ZoomFilter,
+};
+
+/// Values of an option naming one of a few, from which both its help and the error for values
+/// naming none of them are built.
+#[derive(Debug)]
+pub struct Choices<T: 'static> {
+    /// Names of every value. The first one is listed, the others are accepted as well.
+    pub values: &'static [(&'static [&'static str], T)],
+    /// Name of the value used when the option is not given, marked in the help.
+    pub default: Option<&'static str>,
+    /// Error for values naming none of them, followed by the list of names.
+    pub invalid: Message,
+}
+
+impl<T: Copy> Choices<T> {
+    /// Value named `name`, ignoring case and surrounding whitespace.
+    pub fn get(&self, name: &str) -> Option<T> {
+        let name = name.trim().to_lowercase();
+        self.values
+            .iter()
+            .find(|(names, _)| names.contains(&name.as_str()))
+            .map(|(_, value)| *value)
+    }
+
+    /// Same as [`Choices::get`], failing with the names of the values.
+    pub fn parse(&self, name: &str) -> Result<T, Box<dyn Error>> {
+        self.get(name).ok_or_else(|| self.error().into())
+    }
+
+    /// Listed name of every value.
+    pub fn names(&self) -> impl Iterator<Item = &'static str> {
+        self.values.iter().map(|(names, _)| names[0])
+    }
+
+    pub fn error(&self) -> String {
+        format!("{}\n{}", tr(self.invalid), valid_arguments(self.names()))
+    }
+
+    /// `help` with `{}` replaced by the names, eg: "none, lzw or deflate (default)".
+    pub fn help(&self, help: &str) -> &'static str {
+        let names: Vec<String> = self
+            .names()
+            .map(|name| match self.default {
+                Some(default) if default == name => format!("{} (default)", name),
+                _ => name.to_string(),
+            })
+            .collect();
+        let list = match names.split_last() {
+            Some((last, [])) => last.clone(),
+            Some((last, rest)) => format!("{} or {}", rest.join(", "), last),
+            None => String::new(),
+        };
+        // Clap keeps help for the life of the command, which is the life of wayshot.
+        Box::leak(help.replace("{}", &list).into_boxed_str())
+    }
+}
+
+/// "Valid arguments:" followed by `names`, numbered one per line.
+pub fn valid_arguments(names: impl IntoIterator<Item = &'static str>) -> String {
+    let mut text = tr(Message::ValidArguments);
+    for (index, name) in names.into_iter().enumerate() {
+        text.push_str(&format!("\n{}) {}", index + 1, name));
+    }
+    text
+}
+
+/// Value of the option `name` among `choices`, None if it is not given.
+pub fn parse_choice<T: Copy>(
+    args: &ArgMatches,
+    name: &str,
+    choices: &Choices<T>,
+) -> Result<Option<T>, Box<dyn Error>> {
+    args.value_of(name)
+        .map(|value| choices.parse(value))
+        .transpose()
+}
+
+/// Value of the option `name`, None if it is not given. Values which do not parse or are not
+/// `valid` fail with `invalid()`.
+pub fn parse_value<T: FromStr>(
+    args: &ArgMatches,
+    name: &str,
+    valid: impl FnOnce(&T) -> bool,
+    invalid: impl FnOnce() -> String,
+) -> Result<Option<T>, Box<dyn Error>> {
+    match args.value_of(name).map(str::parse::<T>) {
+        None => Ok(None),
+        Some(Ok(value)) if valid(&value) => Ok(Some(value)),
+        Some(_) => Err(invalid().into()),
+    }
+}
+
+pub const ANALYSES: Choices<Analysis> = Choices {
+    values: &[
+        (&["palette"], Analysis::Palette),
+        (&["stats"], Analysis::Stats),
+    ],
+    default: None,
+    invalid: Message::InvalidAnalysis,
+};
+
+pub const CHECKSUMS: Choices<Checksum> = Choices {
+    values: &[(&["sha256"], Checksum::Sha256)],
+    default: None,
+    invalid: Message::InvalidChecksum,
+};
+
+pub const COLLISIONS: Choices<Collision> = Choices {
+    values: &[
+        (&["increment"], Collision::Increment),
+        (&["overwrite"], Collision::Overwrite),
+        (&["timestamp-ns"], Collision::TimestampNs),
+        (&["uuid"], Collision::Uuid),
+        (&["fail"], Collision::Fail),
+    ],
+    default: Some("increment"),
+    invalid: Message::InvalidCollision,
+};
+
+pub const COLOR_VISIONS: Choices<ColorVision> = Choices {
+    values: &[
+        (&["protanopia"], ColorVision::Protanopia),
+        (&["deuteranopia"], ColorVision::Deuteranopia),
+        (&["tritanopia"], ColorVision::Tritanopia),
+    ],
+    default: None,
+    invalid: Message::InvalidSimulation,
+};
+
+/// Whether the compositor should overlay the cursor and whether wayshot should draw it itself,
+/// for --cursor. The boolean values of WAYSHOT_CURSOR are accepted as well.
+pub const CURSOR_MODES: Choices<(bool, bool)> = Choices {
+    values: &[
+        (&["on", "", "1", "true", "yes", "y", "t"], (true, false)),
+        (&["off", "0", "false", "no", "n", "f"], (false, false)),
+        (&["force"], (false, true)),
+    ],
+    default: Some("on"),
+    invalid: Message::InvalidCursorMode,
+};
+
+/// Encoders of --extension, a jpg quality may follow, see [`parse_encoding`].
+pub const ENCODINGS: Choices<EncodingFormat> = Choices {
+    values: &[
+        (&["png"], EncodingFormat::Png),
+        (&["jpg", "jpeg"], EncodingFormat::Jpg),
+        (&["ppm"], EncodingFormat::Ppm),
+        (&["wfz"], EncodingFormat::Wfz),
+        (&["bmp"], EncodingFormat::Bmp),
+        (&["tiff", "tif"], EncodingFormat::Tiff),
+        (&["ico"], EncodingFormat::Ico),
+    ],
+    default: Some("png"),
+    invalid: Message::InvalidExtension,
+};
+
+pub const HOOK_FAILURES: Choices<HookFailure> = Choices {
+    values: &[
+        (&["abort"], HookFailure::Abort),
+        (&["warn"], HookFailure::Warn),
+        (&["ignore"], HookFailure::Ignore),
+    ],
+    default: Some("warn"),
+    invalid: Message::InvalidHookFailure,
+};
+
+pub const JPEG_SUBSAMPLINGS: Choices<JpegSubsampling> = Choices {
+    values: &[
+        (&["444", "4:4:4"], JpegSubsampling::Yuv444),
+        (&["422", "4:2:2"], JpegSubsampling::Yuv422),
+        (&["420", "4:2:0"], JpegSubsampling::Yuv420),
+    ],
+    default: Some("444"),
+    invalid: Message::InvalidJpegSubsampling,
+};
+
+pub const PNG_FILTERS: Choices<PngFilter> = Choices {
+    values: &[
+        (&["none"], PngFilter::NoFilter),
+        (&["sub"], PngFilter::Sub),
+        (&["up"], PngFilter::Up),
+        (&["avg"], PngFilter::Avg),
+        (&["paeth"], PngFilter::Paeth),
+        (&["adaptive"], PngFilter::Adaptive),
+    ],
+    default: Some("adaptive"),
+    invalid: Message::InvalidPngFilter,
+};
+
+pub const REDACT_STYLES: Choices<RedactStyle> = Choices {
+    values: &[
+        (&["black"], RedactStyle::Black),
+        (&["blur"], RedactStyle::Blur),
+    ],
+    default: Some("black"),
+    invalid: Message::InvalidRedactStyle,
+};
+
+pub const STACK_MODES: Choices<StackMode> = Choices {
+    values: &[
+        (&["mean"], StackMode::Mean),
+        (&["median"], StackMode::Median),
+    ],
+    default: Some("mean"),
+    invalid: Message::InvalidStackMode,
+};
+
+pub const TIFF_COMPRESSIONS: Choices<TiffCompression> = Choices {
+    values: &[
+        (&["none"], TiffCompression::None),
+        (&["lzw"], TiffCompression::Lzw),
+        (&["deflate"], TiffCompression::Deflate),
+    ],
+    default: Some("none"),
+    invalid: Message::InvalidTiffCompression,
+};
+
+pub const TONE_MAP_OPERATORS: Choices<ToneMapOperator> = Choices {
+    values: &[
+        (&["clip"], ToneMapOperator::Clip),
+        (&["reinhard"], ToneMapOperator::Reinhard),
+        (&["hable"], ToneMapOperator::Hable),
+    ],
+    default: Some("reinhard"),
+    invalid: Message::InvalidToneMap,
+};
+
+pub const ZOOM_FILTERS: Choices<ZoomFilter> = Choices {
+    values: &[
+        (&["nearest"], ZoomFilter::Nearest),
+        (&["linear"], ZoomFilter::Linear),
+        (&["cubic"], ZoomFilter::Cubic),
+        (&["lanczos"], ZoomFilter::Lanczos),
+    ],
+    default: Some("nearest"),
+    invalid: Message::InvalidZoomFilter,
+};
+
+/// Parse the comma separated measurements of --analyze, eg: "palette,stats".
+pub fn parse_analyses(analyses: &str) -> Result<Vec<Analysis>, Box<dyn Error>> {
+    analyses
+        .split(',')
+        .map(|name| ANALYSES.parse(name))
+        .collect()
+}
+
+/// Parse an encoder name, optionally followed by a jpeg quality, eg: "png" or "jpg:80".
+/// Options which are not part of `spec` are taken from `base_options`.
+pub fn parse_encoding(
+    spec: &str,
+    base_options: EncodingOptions,
+) -> Option<(EncodingFormat, EncodingOptions)> {
+    let (name, quality) = match spec.split_once(':') {
+        Some((name, quality)) => (name, Some(quality.trim().parse::<u8>().ok()?)),
+        None => (spec, None),
+    };
+    let format = ENCODINGS.get(name)?;
+
+    let mut options = base_options;
+    if let Some(quality) = quality {
+        if format != EncodingFormat::Jpg || !(1..=100).contains(&quality) {
+            return None;
+        }
+        options.jpeg_quality = quality;
+    }
+    Some((format, options))
+}
+
+/// Error for an encoder of --extension which [`parse_encoding`] rejects. `auto` is listed for
+/// the first one, which is the only one that can be picked once the image is rendered.
+pub fn invalid_encoding(auto: bool) -> String {
+    let extra: &[&'static str] = if auto {
+        &["jpg:80", "auto"]
+    } else {
+        &["jpg:80"]
+    };
+    let names = ENCODINGS.names().chain(extra.iter().copied());
+    format!(
+        "{}\n{}",
+        tr(Message::InvalidExtension),
+        valid_arguments(names)
+    )
+}
+
+/// Parse an extra encoder of the composite along with its path, eg: "jpg:80=/tmp/chat.jpg".
+pub fn parse_also_save(
+    spec: &str,
+    base_options: EncodingOptions,
+) -> Option<(EncodingFormat, EncodingOptions, PathBuf)> {
+    let (encoding, path) = spec.split_once('=')?;
+    if path.is_empty() {
+        return None;
+    }
+    let (format, options) = parse_encoding(encoding, base_options)?;
+    Some((format, options, PathBuf::from(path)))
+}
+
+/// Parse per-output encoder overrides of the form "DP-1=png,HDMI-A-1=jpg:80".
+pub fn parse_output_formats(
+    spec: &str,
+    base_options: EncodingOptions,
+) -> Option<HashMap<String, (EncodingFormat, EncodingOptions)>> {
+    spec.split(',')
+        .map(|entry| {
+            let (name, encoding) = entry.split_once('=')?;
+            Some((
+                name.trim().to_string(),
+                parse_encoding(encoding, base_options)?,
+            ))
+        })
+        .collect()
+}
+
+/// Parse a size in bytes with an optional unit, eg: "2MB", "1.5 MiB" or "500k". Units without
+/// an i are powers of 1000, those with one powers of 1024.
+pub fn parse_byte_size(size: &str) -> Option<u64> {
+    let size = size.trim().to_lowercase();
+    let split = size
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(size.len());
+    let (number, unit) = size.split_at(split);
+    let multiplier: u64 = match unit.trim() {
+        "" | "b" => 1,
+        "k" | "kb" => 1000,
+        "ki" | "kib" => 1 << 10,
+        "m" | "mb" => 1000 * 1000,
+        "mi" | "mib" => 1 << 20,
+        "g" | "gb" => 1000 * 1000 * 1000,
+        "gi" | "gib" => 1 << 30,
+        _ => return None,
+    };
+    let number: f64 = number.parse().ok()?;
+    Some((number * multiplier as f64) as u64)
+}
+
+/// Parse --hdr-outputs, auto or a comma separated list of output names.
+pub fn parse_hdr_outputs(outputs: &str) -> Option<HdrOutputs> {
+    if outputs.trim().eq_ignore_ascii_case("auto") {
+        return Some(HdrOutputs::Auto);
+    }
+    let names: Vec<String> = outputs
+        .split(',')
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .collect();
+    if names.is_empty() {
+        return None;
+    }
+    Some(HdrOutputs::Named(names))
+}
+
+/// Parse a size of the form "WIDTHxHEIGHT", eg: "320x180".
+pub fn parse_size(size: &str) -> Option<(u32, u32)> {
+    let (width, height) = size.trim().split_once('x')?;
+    let size = (width.parse().ok()?, height.parse().ok()?);
+    if size.0 == 0 || size.1 == 0 {
+        return None;
+    }
+    Some(size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn choices_ignore_case_and_accept_aliases() {
+        assert_eq!(PNG_FILTERS.get(" Paeth "), Some(PngFilter::Paeth));
+        assert_eq!(
+            JPEG_SUBSAMPLINGS.get("4:2:0"),
+            Some(JpegSubsampling::Yuv420)
+        );
+        assert_eq!(CURSOR_MODES.get(""), Some((true, false)));
+        assert_eq!(CURSOR_MODES.get("maybe"), None);
+    }
+
+    #[test]
+    fn errors_and_help_list_the_same_names() {
+        let error = TIFF_COMPRESSIONS.parse("zip").unwrap_err().to_string();
+        assert!(
+            error.ends_with("\n1) none\n2) lzw\n3) deflate"),
+            "{}",
+            error
+        );
+        assert_eq!(
+            TIFF_COMPRESSIONS.help("Tiff compression: {}"),
+            "Tiff compression: none (default), lzw or deflate"
+        );
+        assert_eq!(CHECKSUMS.help("One of {}"), "One of sha256");
+        // Aliases are accepted but not listed.
+        assert!(!JPEG_SUBSAMPLINGS.error().contains("4:4:4"));
+    }
+
+    #[test]
+    fn encodings_take_a_jpg_quality() {
+        let options = EncodingOptions::default();
+        let (format, jpg) = parse_encoding(" JPEG:80", options).unwrap();
+        assert_eq!((format, jpg.jpeg_quality), (EncodingFormat::Jpg, 80));
+        assert_eq!(
+            parse_encoding("tif", options).unwrap().0,
+            EncodingFormat::Tiff
+        );
+        for spec in ["png:80", "jpg:0", "jpg:101", "gif", "jpg:"] {
+            assert!(parse_encoding(spec, options).is_none(), "{}", spec);
+        }
+        assert!(invalid_encoding(true).ends_with("8) jpg:80\n9) auto"));
+    }
+
+    #[test]
+    fn analyses_are_comma_separated() {
+        assert_eq!(
+            parse_analyses("stats, Palette").unwrap(),
+            [Analysis::Stats, Analysis::Palette]
+        );
+        assert!(parse_analyses("stats,").is_err());
+    }
+}
@@ -1,23 +1,42 @@
-use crate::backend::{FrameFormat, FrameState};
-use std::os::unix::prelude::RawFd;
-use wayland_client::protocol::wl_output::WlOutput;
+use std::fmt;
+use wayland_client::protocol::wl_output::{Transform, WlOutput};
 
 use wayland_protocols::xdg::xdg_output::zv1::client::zxdg_output_v1::ZxdgOutputV1;
 
-use wayland_protocols_wlr::screencopy::v1::client::zwlr_screencopy_frame_v1::ZwlrScreencopyFrameV1;
-
 #[derive(Debug, Clone)]
 pub struct OutputInfo {
     pub wl_output: WlOutput,
+    pub global: u32, // name of the wl_output global
     pub xdg_output: Option<ZxdgOutputV1>,
     pub name: String,
     pub dimensions: OutputPositioning,
-    pub xdg_ready: bool, // has received ZxdgOutputV1::Event::Done
-    pub wl_ready: bool,  // has received WlOutput::Event::Done
-    pub frame: Option<ZwlrScreencopyFrameV1>,
-    pub frame_state: Option<FrameState>,
-    pub frame_format: Option<FrameFormat>,
-    pub mem_fd: Option<RawFd>,
+    pub xdg_ready: bool,          // has received ZxdgOutputV1::Event::Done
+    pub wl_ready: bool,           // has received WlOutput::Event::Done
+    pub mode: Option<OutputMode>, // current mode, from wl_output::Event::Mode
+    pub wl_position: (i32, i32),  // from wl_output::Event::Geometry
+    pub transform: Transform,     // from wl_output::Event::Geometry
+    pub scale: i32,               // from wl_output::Event::Scale
+}
+
+impl OutputInfo {
+    /// Logical position and size worked out from wl_output alone, for compositors which never
+    /// report them over xdg_output. Only exact for integer scales.
+    pub fn wl_dimensions(&self) -> Option<OutputPositioning> {
+        let mode = self.mode?;
+        let (width, height) = match self.transform {
+            Transform::_90 | Transform::_270 | Transform::Flipped90 | Transform::Flipped270 => {
+                (mode.height, mode.width)
+            }
+            _ => (mode.width, mode.height),
+        };
+        let scale = self.scale.max(1);
+        Some(OutputPositioning {
+            x: self.wl_position.0,
+            y: self.wl_position.1,
+            width: width / scale,
+            height: height / scale,
+        })
+    }
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Eq)]
@@ -27,3 +46,23 @@ pub struct OutputPositioning {
     pub width: i32,
     pub height: i32,
 }
+
+/// Current video mode of an output, as advertised by wl_output.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct OutputMode {
+    /// Size in physical pixels, before the output transform is applied.
+    pub width: i32,
+    pub height: i32,
+    /// Vertical refresh rate in mHz, 0 if the compositor does not know it.
+    pub refresh: i32,
+}
+
+impl fmt::Display for OutputMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}x{}", self.width, self.height)?;
+        if self.refresh > 0 {
+            write!(f, "@{:.3}Hz", self.refresh as f64 / 1000.0)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,82 @@
+use std::collections::HashMap;
+
+use color_quant::NeuQuant;
+use image::RgbaImage;
+
+/// Most colors of an indexed png.
+pub const MAX_PALETTE_COLORS: u16 = 256;
+
+/// Pixels NeuQuant skips between the samples it learns from, trading palette quality for speed
+/// on large captures. 10 is the value recommended by its author.
+const NEUQUANT_SAMPLE_FACTOR: i32 = 10;
+
+/// An image whose pixels are indices into a palette of Rgba8 colors.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexedImage {
+    pub width: u32,
+    pub height: u32,
+    pub palette: Vec<[u8; 4]>,
+    /// Palette index of every pixel, row by row.
+    pub indices: Vec<u8>,
+}
+
+/// Reduce `image` to at most `colors` colors, up to [`MAX_PALETTE_COLORS`]. Images which
+/// already have few enough colors, eg: screenshots of flat interfaces, keep them exactly, others
+/// get a palette learned with NeuQuant.
+pub fn quantize(image: &RgbaImage, colors: u16) -> IndexedImage {
+    let colors = colors.clamp(1, MAX_PALETTE_COLORS) as usize;
+    exact_palette(image, colors).unwrap_or_else(|| learned_palette(image, colors))
+}
+
+fn exact_palette(image: &RgbaImage, colors: usize) -> Option<IndexedImage> {
+    let mut palette = Vec::new();
+    let mut lookup = HashMap::new();
+    let mut indices = Vec::with_capacity(image.width() as usize * image.height() as usize);
+    for pixel in image.pixels() {
+        let index = match lookup.get(&pixel.0) {
+            Some(&index) => index,
+            None if palette.len() == colors => return None,
+            None => {
+                let index = palette.len() as u8;
+                palette.push(pixel.0);
+                lookup.insert(pixel.0, index);
+                index
+            }
+        };
+        indices.push(index);
+    }
+    Some(IndexedImage {
+        width: image.width(),
+        height: image.height(),
+        palette,
+        indices,
+    })
+}
+
+fn learned_palette(image: &RgbaImage, colors: usize) -> IndexedImage {
+    let quantizer = NeuQuant::new(NEUQUANT_SAMPLE_FACTOR, colors, image.as_raw());
+    let palette = quantizer
+        .color_map_rgba()
+        .chunks_exact(4)
+        .map(|color| [color[0], color[1], color[2], color[3]])
+        .collect();
+    // Neighbouring pixels of screenshots are often the same color, so only look up changes.
+    let mut previous = None;
+    let indices = image
+        .pixels()
+        .map(|pixel| match previous {
+            Some((color, index)) if color == pixel.0 => index,
+            _ => {
+                let index = quantizer.index_of(&pixel.0) as u8;
+                previous = Some((pixel.0, index));
+                index
+            }
+        })
+        .collect();
+    IndexedImage {
+        width: image.width(),
+        height: image.height(),
+        palette,
+        indices,
+    }
+}
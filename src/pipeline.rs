@@ -0,0 +1,1718 @@
+use std::{
+    cmp,
+    collections::HashMap,
+    error::Error,
+    ffi::OsStr,
+    fs::{DirBuilder, File, OpenOptions},
+    io::{self, stdout, BufWriter, ErrorKind, Read, Write},
+    os::unix::fs::{DirBuilderExt, OpenOptionsExt, PermissionsExt},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    rc::Rc,
+    thread,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use image::{imageops::crop_imm, RgbaImage};
+use nix::sys::statvfs::statvfs;
+
+#[cfg(feature = "annotations")]
+use crate::annotate::{draw_annotations, Annotation};
+#[cfg(feature = "scripting")]
+use crate::hooks::Hooks;
+use crate::{
+    analyze::{analysis_fields, choose_encoding, Analysis},
+    backend::{self, RawCapture},
+    bounding_region,
+    capabilities::json_string,
+    capture_raw_frames,
+    checksum::{write_checksum, Checksum},
+    composite_captures_at_scale, convert_raw_capture_at_scale,
+    cursor::{draw_cursor, CursorImage},
+    i18n::{tr_args, Message},
+    intersection, max_capture_scale, postprocess,
+    postprocess::{ColorVision, RedactStyle, ToneAdjustment, ZoomFilter},
+    progress::{Progress, ProgressWriter, PROGRESS_MIN_PIXELS},
+    redact::Redaction,
+    region::{self, Decorations},
+    scaled_span, stack_raw_captures, EncodingFormat, EncodingOptions, FrameArgs, JpegSubsampling,
+    Region, StackMode, MAX_PALETTE_COLORS,
+};
+
+/// Discovers the outputs, selects those overlapping the requested region and captures them.
+/// Tests can stand in for the compositor with a backend returning golden frames.
+pub trait CaptureBackend {
+    /// Short name used in logs.
+    fn name(&self) -> &'static str;
+
+    /// Capture every output selected by `args`, see [`FrameArgs`].
+    fn capture(&self, args: &FrameArgs) -> Result<Vec<RawCapture>, Box<dyn Error>>;
+}
+
+/// Captures through zwlr_screencopy_v1 on the compositor of WAYLAND_DISPLAY.
+pub struct WaylandBackend;
+
+impl CaptureBackend for WaylandBackend {
+    fn name(&self) -> &'static str {
+        "wayland"
+    }
+
+    fn capture(&self, args: &FrameArgs) -> Result<Vec<RawCapture>, Box<dyn Error>> {
+        capture_raw_frames(args)
+    }
+}
+
+/// Where an image lies in global logical coordinates.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Placement {
+    /// Logical position of the top left corner of the image.
+    pub origin: (i32, i32),
+    /// Pixels per logical pixel of the image.
+    pub scale: f64,
+    /// Names and logical regions of the outputs the image shows, cut to it.
+    pub outputs: Vec<(String, Region)>,
+}
+
+impl Placement {
+    /// `region`, in global logical coordinates, in pixels of the image.
+    pub fn to_pixels(&self, region: Region) -> Region {
+        let (origin_x, _) = scaled_span(self.origin.0, 0, self.scale);
+        let (origin_y, _) = scaled_span(self.origin.1, 0, self.scale);
+        let (x, width) = scaled_span(region.x, region.width, self.scale);
+        let (y, height) = scaled_span(region.y, region.height, self.scale);
+        Region {
+            x: (x - origin_x) as i32,
+            y: (y - origin_y) as i32,
+            width: width as i32,
+            height: height as i32,
+        }
+    }
+}
+
+/// Changes images once they are composited, eg: drawing the cursor or trimming borders.
+pub trait PostProcessor {
+    /// Short name used in logs.
+    fn name(&self) -> &'static str;
+
+    fn process(&self, image: RgbaImage, placement: &Placement)
+        -> Result<RgbaImage, Box<dyn Error>>;
+}
+
+impl<T: PostProcessor + ?Sized> PostProcessor for &T {
+    fn name(&self) -> &'static str {
+        (**self).name()
+    }
+
+    fn process(
+        &self,
+        image: RgbaImage,
+        placement: &Placement,
+    ) -> Result<RgbaImage, Box<dyn Error>> {
+        (**self).process(image, placement)
+    }
+}
+
+/// Draws a cursor theme image at the pointer position, for --cursor=force.
+pub struct ForcedCursor {
+    pub cursor: CursorImage,
+    /// Pointer position in global logical coordinates.
+    pub position: (i32, i32),
+}
+
+impl PostProcessor for ForcedCursor {
+    fn name(&self) -> &'static str {
+        "cursor"
+    }
+
+    fn process(
+        &self,
+        mut image: RgbaImage,
+        placement: &Placement,
+    ) -> Result<RgbaImage, Box<dyn Error>> {
+        draw_cursor(
+            &mut image,
+            placement.origin,
+            self.position,
+            placement.scale,
+            &self.cursor,
+        );
+        Ok(image)
+    }
+}
+
+/// Enlarges images for --zoom, see [`postprocess::zoom`].
+pub struct Zoom {
+    pub factor: f64,
+    pub filter: ZoomFilter,
+    /// Draw a grid between the source pixels, see [`postprocess::draw_pixel_grid`].
+    pub grid: bool,
+}
+
+impl PostProcessor for Zoom {
+    fn name(&self) -> &'static str {
+        "zoom"
+    }
+
+    fn process(
+        &self,
+        image: RgbaImage,
+        _placement: &Placement,
+    ) -> Result<RgbaImage, Box<dyn Error>> {
+        let mut image = postprocess::zoom(&image, self.factor, self.filter);
+        if self.grid {
+            postprocess::draw_pixel_grid(&mut image, self.factor);
+        }
+        Ok(image)
+    }
+}
+
+/// Darkens what did not change since `reference` and outlines what did, see
+/// [`postprocess::changed_regions`].
+pub struct HighlightChanges {
+    pub reference: RgbaImage,
+    /// Per channel difference still considered unchanged.
+    pub tolerance: u8,
+}
+
+impl PostProcessor for HighlightChanges {
+    fn name(&self) -> &'static str {
+        "highlight-changes"
+    }
+
+    fn process(
+        &self,
+        mut image: RgbaImage,
+        _placement: &Placement,
+    ) -> Result<RgbaImage, Box<dyn Error>> {
+        if image.dimensions() != self.reference.dimensions() {
+            let (reference, capture) = (self.reference.dimensions(), image.dimensions());
+            let args = [
+                (
+                    "reference",
+                    format!("{}x{}", reference.0, reference.1).into(),
+                ),
+                ("capture", format!("{}x{}", capture.0, capture.1).into()),
+            ];
+            return Err(tr_args(Message::DiffSizeMismatch, &args).into());
+        }
+        let regions = postprocess::changed_regions(&self.reference, &image, self.tolerance);
+        let count = regions.len().into();
+        tracing::info!("{}", tr_args(Message::ChangedRegions, &[("count", count)]));
+        for region in &regions {
+            tracing::debug!(
+                "Changed: {}x{}+{}+{}",
+                region.width,
+                region.height,
+                region.x,
+                region.y
+            );
+        }
+        postprocess::highlight_changes(&mut image, &regions);
+        Ok(image)
+    }
+}
+
+/// Draws the shapes and text of --draw.
+#[cfg(feature = "annotations")]
+pub struct Annotations(pub Vec<Annotation>);
+
+#[cfg(feature = "annotations")]
+impl PostProcessor for Annotations {
+    fn name(&self) -> &'static str {
+        "annotations"
+    }
+
+    fn process(
+        &self,
+        mut image: RgbaImage,
+        _placement: &Placement,
+    ) -> Result<RgbaImage, Box<dyn Error>> {
+        draw_annotations(&mut image, &self.0);
+        Ok(image)
+    }
+}
+
+/// Adjusts the tones of images for --gamma, --brightness, --contrast and --invert, see
+/// [`postprocess::adjust_tones`].
+pub struct AdjustTones(pub ToneAdjustment);
+
+impl PostProcessor for AdjustTones {
+    fn name(&self) -> &'static str {
+        "tones"
+    }
+
+    fn process(
+        &self,
+        mut image: RgbaImage,
+        _placement: &Placement,
+    ) -> Result<RgbaImage, Box<dyn Error>> {
+        postprocess::adjust_tones(&mut image, &self.0);
+        Ok(image)
+    }
+}
+
+/// Hides the windows listed with --redact, see [`postprocess::redact`]. Windows are listed
+/// again for every image, so that serve and watch follow them around.
+pub struct Redact {
+    pub redactions: Vec<Redaction>,
+    pub style: RedactStyle,
+}
+
+impl PostProcessor for Redact {
+    fn name(&self) -> &'static str {
+        "redact"
+    }
+
+    fn process(
+        &self,
+        mut image: RgbaImage,
+        placement: &Placement,
+    ) -> Result<RgbaImage, Box<dyn Error>> {
+        // Title bars show the title as well.
+        let windows = region::visible_windows(Decorations::Include)?;
+        for window in windows
+            .iter()
+            .filter(|window| self.redactions.iter().any(|r| r.matches(window)))
+        {
+            tracing::debug!("Redacting a window of {}", window.app_id);
+            postprocess::redact(&mut image, placement.to_pixels(window.region), self.style);
+        }
+        Ok(image)
+    }
+}
+
+/// Shows images as they look with a color vision deficiency for --simulate, see
+/// [`postprocess::simulate_color_vision`].
+pub struct SimulateColorVision(pub ColorVision);
+
+impl PostProcessor for SimulateColorVision {
+    fn name(&self) -> &'static str {
+        "simulate"
+    }
+
+    fn process(
+        &self,
+        mut image: RgbaImage,
+        _placement: &Placement,
+    ) -> Result<RgbaImage, Box<dyn Error>> {
+        postprocess::simulate_color_vision(&mut image, self.0);
+        Ok(image)
+    }
+}
+
+/// Removes uniform borders, see [`postprocess::auto_trim`].
+pub struct AutoTrim {
+    pub tolerance: u8,
+}
+
+impl PostProcessor for AutoTrim {
+    fn name(&self) -> &'static str {
+        "auto-trim"
+    }
+
+    fn process(
+        &self,
+        image: RgbaImage,
+        _placement: &Placement,
+    ) -> Result<RgbaImage, Box<dyn Error>> {
+        Ok(postprocess::auto_trim(&image, self.tolerance))
+    }
+}
+
+#[cfg(feature = "scripting")]
+impl PostProcessor for Hooks {
+    fn name(&self) -> &'static str {
+        "hooks"
+    }
+
+    fn process(
+        &self,
+        image: RgbaImage,
+        _placement: &Placement,
+    ) -> Result<RgbaImage, Box<dyn Error>> {
+        self.post_process(image)
+    }
+}
+
+/// What an image rendered by a [`Pipeline`] shows, sinks name files after it.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Subject<'a> {
+    /// Every captured output composited together.
+    Composite,
+    /// A single output, by name.
+    Output(&'a str),
+    /// The window `number` of the application `app_id`, counting from 1.
+    Window { app_id: &'a str, number: usize },
+    /// A region of the composite, by the name it was given with --crop.
+    Crop(&'a str),
+}
+
+/// Receives the images rendered by a [`Pipeline`].
+pub trait Sink {
+    fn write(
+        &mut self,
+        subject: Subject,
+        image: RgbaImage,
+        placement: &Placement,
+    ) -> Result<(), Box<dyn Error>>;
+
+    /// Checksum of the file the last image was written to, if the sink computes one.
+    fn last_checksum(&self) -> Option<(Checksum, &str)> {
+        None
+    }
+}
+
+/// Keeps images in memory, eg: to compare them against golden frames.
+impl Sink for Vec<RgbaImage> {
+    fn write(
+        &mut self,
+        _subject: Subject,
+        image: RgbaImage,
+        _placement: &Placement,
+    ) -> Result<(), Box<dyn Error>> {
+        self.push(image);
+        Ok(())
+    }
+}
+
+/// Prints what --analyze measures in every image as a line of JSON on stdout, eg:
+/// `{"subject": "output", "name": "DP-1", "average": "#1d1f21", ...}`, then hands the image on
+/// to `inner`, if any.
+pub struct AnalyzeSink<'a> {
+    pub analyses: Vec<Analysis>,
+    pub inner: Option<&'a mut dyn Sink>,
+}
+
+impl Sink for AnalyzeSink<'_> {
+    fn write(
+        &mut self,
+        subject: Subject,
+        image: RgbaImage,
+        placement: &Placement,
+    ) -> Result<(), Box<dyn Error>> {
+        let (kind, name) = match subject {
+            Subject::Composite => ("composite", None),
+            Subject::Output(name) => ("output", Some(name.to_string())),
+            Subject::Window { app_id, number } => {
+                ("window", Some(format!("{}-{}", app_id, number)))
+            }
+            Subject::Crop(name) => ("crop", Some(name.to_string())),
+        };
+        let mut fields = analysis_fields(&image, &self.analyses);
+        // The image is saved first, so that the checksum of its file can be printed with it.
+        if let Some(inner) = &mut self.inner {
+            inner.write(subject, image, placement)?;
+            if let Some((checksum, hash)) = inner.last_checksum() {
+                fields.push_str(&format!(", \"{}\": \"{}\"", checksum.extension(), hash));
+            }
+        }
+        writeln!(
+            stdout(),
+            "{{\"subject\": \"{}\", \"name\": {}, {}}}",
+            kind,
+            name.as_deref().map_or("null".to_string(), json_string),
+            fields
+        )?;
+        Ok(())
+    }
+}
+
+/// Writes images to files named after their subject, or composites to stdout.
+pub struct FileSink {
+    /// Directory generated file names are created in.
+    pub directory: PathBuf,
+    /// Stem of generated file names.
+    pub stem: String,
+    /// Path composites are written to instead of a generated name. Other subjects are written
+    /// next to it, eg: shot-DP-1.png for shot.png.
+    pub file: Option<PathBuf>,
+    /// Write composites to stdout.
+    pub stdout: bool,
+    pub format: EncodingFormat,
+    pub options: EncodingOptions,
+    /// Encoders of single outputs, by output name, overriding `format` and `options`.
+    pub output_formats: HashMap<String, (EncodingFormat, EncodingOptions)>,
+    /// Also write png thumbnails fitting within this size in logical pixels.
+    pub thumbnail: Option<(u32, u32)>,
+    /// Create files readable and writable by their owner only.
+    pub private: bool,
+    /// Shell command encoded images are piped through before being written, eg: to encrypt
+    /// them with `age -r KEY`. It is run with `sh -c`, like capture commands.
+    pub encrypt: Option<String>,
+    /// Pick the encoder of every image from its content with [`choose_encoding`] instead of
+    /// using `format`, except for outputs listed in `output_formats`. Paths given with `file`
+    /// get the extension of the picked encoder.
+    pub auto_format: bool,
+    /// How paths which are taken are resolved. By default a counter is appended to generated
+    /// names and paths given with `file` are overwritten.
+    pub collision: Option<Collision>,
+    /// Reports how far encoding large images got.
+    pub progress: Option<Rc<dyn Progress>>,
+    /// Fall back to a more compressed encoder, see [`degraded_encodings`], when an image would
+    /// not fit in the free space of the file system instead of failing.
+    pub auto_degrade: bool,
+    /// Other encoders composites are also written with, concurrently, each to its own path or
+    /// next to the composite with the extension of the encoder if None.
+    pub also_save: Vec<(EncodingFormat, EncodingOptions, Option<PathBuf>)>,
+    /// Write a checksum next to every file, see [`write_checksum`].
+    pub checksum: Option<Checksum>,
+    /// Checksum of the last image written to a file, along with its kind.
+    pub last_checksum: Option<(Checksum, String)>,
+    /// Most bytes an encoded image may take, see [`FileSink::fit_max_size`]. Encryption and
+    /// copies saved with other encoders are not counted.
+    pub max_size: Option<u64>,
+}
+
+impl FileSink {
+    fn encoding(&self, subject: Subject) -> (EncodingFormat, EncodingOptions) {
+        match subject {
+            Subject::Output(name) => self.output_formats.get(name).copied(),
+            _ => None,
+        }
+        .unwrap_or((self.format, self.options))
+    }
+
+    /// Create or truncate the file at `path`, see [`create_file`].
+    pub fn create(&self, path: &Path) -> io::Result<File> {
+        create_file(path, self.private)
+    }
+
+    /// Encoder of `image`, which is written for `subject`.
+    fn encoding_of(
+        &self,
+        subject: Subject,
+        image: &RgbaImage,
+    ) -> (EncodingFormat, EncodingOptions) {
+        let overridden = match subject {
+            Subject::Output(name) => self.output_formats.contains_key(name),
+            _ => false,
+        };
+        if self.auto_format && !overridden {
+            choose_encoding(image, self.options)
+        } else {
+            self.encoding(subject)
+        }
+    }
+
+    /// Encoder of `image` that fits in the free space of the file system it is written to,
+    /// checked before anything is written so that full disks do not leave half written files.
+    /// Images are only estimated, see [`backend::estimate_size`], when the disk is too full to
+    /// hold them at [`MAX_BYTES_PER_PIXEL`].
+    fn fit_free_space(
+        &self,
+        image: &RgbaImage,
+        format: EncodingFormat,
+        options: EncodingOptions,
+    ) -> Result<(EncodingFormat, EncodingOptions), Box<dyn Error>> {
+        let directory = match &self.file {
+            Some(file) => file.parent().unwrap_or(Path::new("")),
+            None => &self.directory,
+        };
+        let directory = if directory.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            directory
+        };
+        let available = match available_space(directory) {
+            Ok(available) => available,
+            Err(e) => {
+                tracing::debug!("Failed to check the free space in {:?}: {}", directory, e);
+                return Ok((format, options));
+            }
+        };
+        let pixels = image.width() as u64 * image.height() as u64;
+        if available >= pixels * MAX_BYTES_PER_PIXEL + SPACE_HEADROOM {
+            return Ok((format, options));
+        }
+
+        let needed = backend::estimate_size(image, format, &options)? + SPACE_HEADROOM;
+        if needed <= available {
+            return Ok((format, options));
+        }
+        if self.auto_degrade {
+            for (degraded_format, degraded_options) in degraded_encodings(options) {
+                let size = backend::estimate_size(image, degraded_format, &degraded_options)?;
+                if size + SPACE_HEADROOM <= available {
+                    let encoder = match degraded_format {
+                        EncodingFormat::Jpg => format!("jpg:{}", degraded_options.jpeg_quality),
+                        format => format.extension().to_string(),
+                    };
+                    tracing::warn!(
+                        "{}",
+                        tr_args(Message::EncodingDegraded, &[("encoder", encoder.into())])
+                    );
+                    return Ok((degraded_format, degraded_options));
+                }
+            }
+        }
+        let args = [
+            ("directory", directory.display().to_string().into()),
+            ("needed", needed.into()),
+            ("available", available.into()),
+        ];
+        Err(tr_args(Message::NotEnoughSpace, &args).into())
+    }
+
+    /// Encoder of `image` that encodes it in at most `max_size` bytes. Jpg images get the
+    /// highest quality up to the requested one that fits, found by a binary search encoding to
+    /// memory. Images of other formats which do not fit are written as jpg.
+    fn fit_max_size(
+        &self,
+        image: &RgbaImage,
+        format: EncodingFormat,
+        options: EncodingOptions,
+        max_size: u64,
+    ) -> Result<(EncodingFormat, EncodingOptions), Box<dyn Error>> {
+        let encoded_size = |format: EncodingFormat, options: &EncodingOptions| {
+            let size = backend::encode(image, format, options)?.len() as u64;
+            tracing::debug!(
+                "Encoded as {:?} at quality {} in {} bytes",
+                format,
+                options.jpeg_quality,
+                size
+            );
+            Ok::<_, Box<dyn Error>>(size)
+        };
+        let size = encoded_size(format, &options)?;
+        if size <= max_size {
+            return Ok((format, options));
+        }
+        let too_large = |size: u64| {
+            let args = [("size", size.into()), ("max", max_size.into())];
+            tr_args(Message::ImageTooLarge, &args)
+        };
+        if !EncodingFormat::Jpg.is_available() {
+            return Err(too_large(size).into());
+        }
+
+        let at_quality = |quality: u8| EncodingOptions {
+            jpeg_quality: quality,
+            ..options
+        };
+        // Jpg images at the requested quality are known not to fit.
+        let (mut low, mut high) = match format {
+            EncodingFormat::Jpg => (1, options.jpeg_quality.saturating_sub(1)),
+            _ => (1, options.jpeg_quality),
+        };
+        let (mut best, mut smallest) = (None, size);
+        while low <= high {
+            let quality = low + (high - low) / 2;
+            let size = encoded_size(EncodingFormat::Jpg, &at_quality(quality))?;
+            if size <= max_size {
+                best = Some(quality);
+                low = quality + 1;
+            } else {
+                smallest = smallest.min(size);
+                high = quality - 1;
+            }
+        }
+        match best {
+            Some(quality) => {
+                let args = [("quality", quality.into())];
+                tracing::info!("{}", tr_args(Message::QualityLowered, &args));
+                Ok((EncodingFormat::Jpg, at_quality(quality)))
+            }
+            None => Err(too_large(smallest).into()),
+        }
+    }
+
+    /// Path `subject` is written to. Generated names are claimed right away, so every call
+    /// returns a new one.
+    pub fn path(&self, subject: Subject) -> Result<PathBuf, Box<dyn Error>> {
+        self.path_with_format(subject, self.encoding(subject).0)
+    }
+
+    fn path_with_format(
+        &self,
+        subject: Subject,
+        format: EncodingFormat,
+    ) -> Result<PathBuf, Box<dyn Error>> {
+        let extension = format.extension();
+        let encoder_changed = self.auto_format || format != self.encoding(subject).0;
+        let suffix = match subject {
+            Subject::Composite => None,
+            Subject::Output(name) | Subject::Crop(name) => Some(name.to_string()),
+            Subject::Window { app_id, number } => match self.file {
+                Some(_) => Some(number.to_string()),
+                None => Some(format!("{}-{}", app_id, number)),
+            },
+        };
+
+        let path = match (&self.file, suffix) {
+            (Some(file), None) if encoder_changed => file.with_extension(extension),
+            (Some(file), None) => file.clone(),
+            (Some(file), Some(suffix)) => {
+                let mut name = file.file_stem().unwrap_or_default().to_os_string();
+                name.push(format!("-{}.{}", suffix, extension));
+                file.with_file_name(name)
+            }
+            (None, None) if self.stdout => {
+                return Ok(self.directory.join(format!("{}.{}", self.stem, extension)))
+            }
+            (None, suffix) => {
+                let stem = match suffix {
+                    Some(suffix) => format!("{}-{}", self.stem, suffix),
+                    None => self.stem.clone(),
+                };
+                // Generated names may hold directories, eg: --filename-format %Y-%m/%H%M%S.
+                if let Some(parent) = self.directory.join(&stem).parent() {
+                    create_dir(parent, self.private)?;
+                }
+                let collision = self.collision.unwrap_or_default();
+                return claim_path(
+                    &self.directory,
+                    OsStr::new(&stem),
+                    OsStr::new(extension),
+                    collision,
+                );
+            }
+        };
+        match self.collision {
+            // Paths given with `file` are overwritten unless asked otherwise.
+            None | Some(Collision::Overwrite) => Ok(path),
+            Some(collision) => claim_path(
+                path.parent().unwrap_or(Path::new("")),
+                path.file_stem().unwrap_or_default(),
+                path.extension().unwrap_or_default(),
+                collision,
+            ),
+        }
+    }
+
+    /// Claim the path of the thumbnail of the image written to `path`, taken paths are handled
+    /// like they are for that image.
+    fn claim_thumbnail_path(&self, path: &Path) -> Result<PathBuf, Box<dyn Error>> {
+        let thumbnail = thumbnail_path(path);
+        let collision = match (&self.file, self.collision) {
+            (Some(_), None) => Collision::Overwrite,
+            (_, collision) => collision.unwrap_or_default(),
+        };
+        claim_path(
+            thumbnail.parent().unwrap_or(Path::new("")),
+            thumbnail.file_stem().unwrap_or_default(),
+            thumbnail.extension().unwrap_or_default(),
+            collision,
+        )
+    }
+
+    /// Encoders and paths of the copies of the composite written to `path`, see `also_save`.
+    fn also_save_paths(&self, path: &Path) -> Vec<(EncodingFormat, EncodingOptions, PathBuf)> {
+        self.also_save
+            .iter()
+            .map(|(format, options, also)| {
+                let also = also
+                    .clone()
+                    .unwrap_or_else(|| path.with_extension(format.extension()));
+                (*format, *options, also)
+            })
+            .collect()
+    }
+
+    fn write_image(
+        &self,
+        subject: Subject,
+        image: RgbaImage,
+        path: &Path,
+        format: EncodingFormat,
+        options: &EncodingOptions,
+        to_stdout: bool,
+    ) -> Result<(), Box<dyn Error>> {
+        if let Some(command) = &self.encrypt {
+            let output = if to_stdout {
+                Stdio::inherit()
+            } else {
+                tracing::debug!("Writing {:?} to {:?} through {:?}", subject, path, command);
+                self.create(path)?.into()
+            };
+            let data = backend::encode(&image, format, options)?;
+            pipe_through(command, &data, output)
+        } else if to_stdout {
+            backend::write_to_file_with_options(BufWriter::new(stdout()), format, options, image)
+        } else {
+            tracing::debug!("Writing {:?} to {:?}", subject, path);
+            let file = self.create(path)?;
+            let pixels = image.width() as u64 * image.height() as u64;
+            match &self.progress {
+                // Ppm images are written in one go, large ones through a memory map, and icons
+                // are small whatever the size of the image.
+                Some(progress)
+                    if pixels >= PROGRESS_MIN_PIXELS
+                        && !matches!(format, EncodingFormat::Ppm | EncodingFormat::Ico) =>
+                {
+                    let total = backend::estimate_size(&image, format, options)?;
+                    let output = ProgressWriter::new(
+                        BufWriter::new(file),
+                        progress.as_ref(),
+                        "encode",
+                        total,
+                    );
+                    backend::write_to_file_with_options(output, format, options, image)
+                }
+                _ => backend::write_to_disk_with_options(file, format, options, image),
+            }
+        }
+    }
+}
+
+impl Sink for FileSink {
+    fn write(
+        &mut self,
+        subject: Subject,
+        image: RgbaImage,
+        placement: &Placement,
+    ) -> Result<(), Box<dyn Error>> {
+        let to_stdout = self.stdout && subject == Subject::Composite;
+        let (mut format, mut options) = self.encoding_of(subject, &image);
+        if let Some(max_size) = self.max_size {
+            (format, options) = self.fit_max_size(&image, format, options, max_size)?;
+        }
+        if !to_stdout {
+            (format, options) = self.fit_free_space(&image, format, options)?;
+        }
+        let path = self.path_with_format(subject, format)?;
+        if let Some(size) = self.thumbnail {
+            let path = self.claim_thumbnail_path(&path)?;
+            let file = self.create(&path)?;
+            write_thumbnail(&image, size, placement.scale, file, &path)?;
+        }
+
+        let also_save = match subject {
+            Subject::Composite => self.also_save_paths(&path),
+            _ => Vec::new(),
+        };
+        if also_save.is_empty() {
+            self.write_image(subject, image, &path, format, &options, to_stdout)?;
+        } else {
+            // Every other encoder runs on a thread of its own while the composite is written.
+            let copy = image.clone();
+            thread::scope(|scope| -> Result<(), Box<dyn Error>> {
+                let workers: Vec<_> = also_save
+                    .iter()
+                    .map(|(format, options, path)| {
+                        let (copy, encrypt, private) =
+                            (&copy, self.encrypt.as_deref(), self.private);
+                        scope.spawn(move || {
+                            save_copy(copy, *format, options, path, encrypt, private)
+                        })
+                    })
+                    .collect();
+                let written = self.write_image(subject, image, &path, format, &options, to_stdout);
+                let saved: Vec<_> = workers.into_iter().map(|worker| worker.join()).collect();
+                written?;
+                for result in saved {
+                    result.map_err(|_| "An encoder thread panicked")??;
+                }
+                Ok(())
+            })?;
+        }
+
+        self.last_checksum = None;
+        if let Some(checksum) = self.checksum {
+            for (_, _, copy) in &also_save {
+                write_checksum(checksum, copy, self.private)?;
+            }
+            if !to_stdout {
+                let hash = write_checksum(checksum, &path, self.private)?;
+                self.last_checksum = Some((checksum, hash));
+            }
+        }
+        Ok(())
+    }
+
+    fn last_checksum(&self) -> Option<(Checksum, &str)> {
+        self.last_checksum
+            .as_ref()
+            .map(|(checksum, hash)| (*checksum, hash.as_str()))
+    }
+}
+
+/// Write a copy of a composite for `also_save` of [`FileSink`], piped through `encrypt` if
+/// given. Errors are strings, as boxed errors can not be sent back from encoder threads.
+fn save_copy(
+    image: &RgbaImage,
+    format: EncodingFormat,
+    options: &EncodingOptions,
+    path: &Path,
+    encrypt: Option<&str>,
+    private: bool,
+) -> Result<(), String> {
+    tracing::debug!("Also writing the composite to {:?}", path);
+    let mut file = create_file(path, private).map_err(|e| format!("{:?}: {}", path, e))?;
+    let data = backend::encode(image, format, options).map_err(|e| e.to_string())?;
+    match encrypt {
+        Some(command) => pipe_through(command, &data, file.into()).map_err(|e| e.to_string()),
+        None => file
+            .write_all(&data)
+            .map_err(|e| format!("{:?}: {}", path, e)),
+    }
+}
+
+/// Run the shell command `command` with `data` on its standard input and its standard output
+/// going to `output`.
+fn pipe_through(command: &str, data: &[u8], output: Stdio) -> Result<(), Box<dyn Error>> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::piped())
+        .stdout(output)
+        .spawn()
+        .map_err(|e| {
+            tr_args(
+                Message::CommandSpawnFailed,
+                &[("program", "sh".into()), ("error", e.to_string().into())],
+            )
+        })?;
+    // Taking stdin closes it once written, so the program sees the end of the image.
+    let written = child.stdin.take().map(|mut stdin| stdin.write_all(data));
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(tr_args(
+            Message::CommandFailed,
+            &[
+                ("program", command.into()),
+                ("status", status.to_string().into()),
+            ],
+        )
+        .into());
+    }
+    written.transpose()?;
+    Ok(())
+}
+
+/// Path of the thumbnail written alongside `path`, eg: shot-thumb.png for shot.jpg.
+fn thumbnail_path(path: &Path) -> PathBuf {
+    let mut name = path.file_stem().unwrap_or_default().to_os_string();
+    name.push("-thumb.png");
+    path.with_file_name(name)
+}
+
+/// Write a png thumbnail fitting within `size` logical pixels to `file`, created at `path`. The
+/// size is multiplied by the capture scale, so previews of HiDPI captures stay sharp.
+fn write_thumbnail(
+    image: &RgbaImage,
+    size: (u32, u32),
+    scale: f64,
+    file: File,
+    path: &Path,
+) -> Result<(), Box<dyn Error>> {
+    let max_width = (size.0 as f64 * scale).round() as u32;
+    let max_height = (size.1 as f64 * scale).round() as u32;
+    let thumbnail = postprocess::thumbnail(image, max_width, max_height);
+    tracing::debug!(
+        "Writing {}x{} thumbnail to {:?}",
+        thumbnail.width(),
+        thumbnail.height(),
+        path
+    );
+    backend::write_to_file(BufWriter::new(file), EncodingFormat::Png, thumbnail)
+}
+
+/// Create or truncate the file at `path`, restricting it to its owner if `private`. The
+/// permissions are changed before anything is written, so they also apply to files which
+/// already existed. It is opened for reading too, so that it can be memory mapped.
+pub(crate) fn create_file(path: &Path, private: bool) -> io::Result<File> {
+    let mut options = OpenOptions::new();
+    options.read(true).write(true).create(true).truncate(true);
+    if private {
+        options.mode(0o600);
+    }
+    let file = options.open(path)?;
+    if private {
+        file.set_permissions(PermissionsExt::from_mode(0o600))?;
+    }
+    Ok(file)
+}
+
+/// Most bytes per pixel of encoded images, reached by 16 bit tiff images.
+pub const MAX_BYTES_PER_PIXEL: u64 = 8;
+
+/// Free space left on top of the size of images, for their headers and because sizes are
+/// estimated.
+const SPACE_HEADROOM: u64 = 1024 * 1024;
+
+/// Jpeg qualities [`degraded_encodings`] tries, from the best to the smallest files.
+const DEGRADED_JPEG_QUALITIES: [u8; 4] = [90, 75, 50, 25];
+
+/// Encoders --auto-degrade tries in order when an image does not fit on the disk, from the
+/// best quality to the smallest files: png, png quantized to a palette, then jpeg at
+/// [`DEGRADED_JPEG_QUALITIES`] if it was compiled in. Options which are not changed are taken
+/// from `options`.
+pub fn degraded_encodings(options: EncodingOptions) -> Vec<(EncodingFormat, EncodingOptions)> {
+    let mut encodings = vec![
+        (
+            EncodingFormat::Png,
+            EncodingOptions {
+                png_palette: None,
+                ..options
+            },
+        ),
+        (
+            EncodingFormat::Png,
+            EncodingOptions {
+                png_palette: Some(MAX_PALETTE_COLORS),
+                ..options
+            },
+        ),
+    ];
+    if EncodingFormat::Jpg.is_available() {
+        encodings.extend(DEGRADED_JPEG_QUALITIES.map(|quality| {
+            (
+                EncodingFormat::Jpg,
+                EncodingOptions {
+                    jpeg_quality: quality,
+                    jpeg_subsampling: JpegSubsampling::Yuv420,
+                    ..options
+                },
+            )
+        }));
+    }
+    encodings
+}
+
+/// Bytes available to unprivileged users on the file system holding `path`.
+pub fn available_space(path: &Path) -> io::Result<u64> {
+    let stats = statvfs(path)?;
+    Ok(stats.blocks_available() as u64 * stats.fragment_size() as u64)
+}
+
+/// Create `path` along with its missing parents, restricting the directories it creates to
+/// their owner if `private`.
+pub(crate) fn create_dir(path: &Path, private: bool) -> io::Result<()> {
+    let mut builder = DirBuilder::new();
+    builder.recursive(true);
+    if private {
+        builder.mode(0o700);
+    }
+    builder.create(path)
+}
+
+/// What to do when the path an image would be written to is taken, for --collision.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum Collision {
+    /// Append a counter to the stem until an unused name is found.
+    #[default]
+    Increment,
+    /// Write over the existing file.
+    Overwrite,
+    /// Append the current time in nanoseconds since the epoch to the stem.
+    TimestampNs,
+    /// Append a random UUID to the stem.
+    Uuid,
+    /// Give up with an error.
+    Fail,
+}
+
+/// Create an empty `{stem}.{extension}`, or `{stem}` without an extension, in `directory` to
+/// write a screenshot to. When the name
+/// is taken, eg: by another instance started within the same second, it is resolved according
+/// to `collision`. Names are claimed atomically, so concurrent instances never get the same
+/// one, except with [`Collision::Overwrite`]. Names which are not valid UTF-8 are kept as is.
+pub(crate) fn claim_path(
+    directory: &Path,
+    stem: &OsStr,
+    extension: &OsStr,
+    collision: Collision,
+) -> Result<PathBuf, Box<dyn Error>> {
+    let name = |suffix: &str| {
+        let mut name = stem.to_os_string();
+        name.push(suffix);
+        if !extension.is_empty() {
+            name.push(".");
+            name.push(extension);
+        }
+        directory.join(name)
+    };
+    let mut path = name("");
+    if collision == Collision::Overwrite {
+        return Ok(path);
+    }
+    let mut attempt = 0;
+    loop {
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(_) => return Ok(path),
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => attempt += 1,
+            Err(e) => return Err(e.into()),
+        }
+        let suffix = match collision {
+            Collision::Fail => {
+                let path = path.display().to_string().into();
+                return Err(tr_args(Message::FileExists, &[("path", path)]).into());
+            }
+            Collision::Increment | Collision::Overwrite => attempt.to_string(),
+            Collision::TimestampNs => SystemTime::now()
+                .duration_since(UNIX_EPOCH)?
+                .as_nanos()
+                .to_string(),
+            Collision::Uuid => random_uuid()?,
+        };
+        path = name(&format!("-{}", suffix));
+    }
+}
+
+/// A random version 4 UUID, eg: 0b6c4a2e-8d1f-4c3b-9a57-2f9e6d0c1b84.
+fn random_uuid() -> io::Result<String> {
+    let mut bytes = [0u8; 16];
+    File::open("/dev/urandom")?.read_exact(&mut bytes)?;
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+    Ok(format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    ))
+}
+
+/// Pixels per logical pixel of rendered images.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Scale {
+    Fixed(f64),
+    /// The largest scale of the captured outputs.
+    Max,
+}
+
+/// How the composite of every capture is cut into images.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Split {
+    /// A single image of everything captured.
+    Composite,
+    /// One image per output, which are never composited.
+    Outputs,
+    /// One image per window of the application `app_id`, along with its number. Windows
+    /// reaching past the edge of the outputs are cut to what is on screen.
+    Windows {
+        app_id: String,
+        windows: Vec<(Region, usize)>,
+    },
+    /// One image per region, in global logical coordinates, along with its name. Regions
+    /// reaching past the edge of the outputs are cut to what is on screen.
+    Crops(Vec<(String, Region)>),
+}
+
+/// Frames captured by [`Pipeline::capture`], still in the buffers the compositor copied them
+/// to.
+pub struct Captured {
+    pub captures: Vec<RawCapture>,
+    /// Scale the images are rendered at.
+    pub scale: f64,
+}
+
+/// The stages a screenshot goes through: the backend discovers, selects and captures outputs,
+/// the captures are converted to Rgba8 and composited, the composite is split into images,
+/// which are post processed in order and handed to a sink.
+pub struct Pipeline<'a> {
+    backend: Box<dyn CaptureBackend + 'a>,
+    stack: (usize, StackMode),
+    scale: Scale,
+    split: Split,
+    post_processors: Vec<Box<dyn PostProcessor + 'a>>,
+    progress: Option<Rc<dyn Progress + 'a>>,
+}
+
+impl<'a> Pipeline<'a> {
+    pub fn builder(backend: impl CaptureBackend + 'a) -> PipelineBuilder<'a> {
+        PipelineBuilder {
+            pipeline: Pipeline {
+                backend: Box::new(backend),
+                stack: (1, StackMode::Mean),
+                scale: Scale::Fixed(1.0),
+                split: Split::Composite,
+                post_processors: Vec::new(),
+                progress: None,
+            },
+        }
+    }
+
+    /// Capture and render a screenshot in one go.
+    pub fn run(&self, args: &FrameArgs, sink: &mut dyn Sink) -> Result<(), Box<dyn Error>> {
+        let captured = self.capture(args)?;
+        self.render(captured, sink)
+    }
+
+    /// Capture the frames of every selected output, stacking them if asked to.
+    #[tracing::instrument(skip_all, fields(backend = self.backend.name()))]
+    pub fn capture(&self, args: &FrameArgs) -> Result<Captured, Box<dyn Error>> {
+        // HDR frames are tone mapped before anything else reads them, stacking included.
+        let capture_frames = || {
+            let mut captures = self.backend.capture(args)?;
+            if let Some(tone_mapping) = &args.tone_mapping {
+                tone_mapping.apply(&mut captures);
+            }
+            Ok::<_, Box<dyn Error>>(captures)
+        };
+        let mut captures = capture_frames()?;
+        let (frames, mode) = self.stack;
+        if frames > 1 {
+            let frames = (1..frames)
+                .map(|_| capture_frames())
+                .collect::<Result<Vec<_>, _>>()?;
+            stack_raw_captures(&mut captures, frames, mode)?;
+        }
+
+        let scale = match self.scale {
+            Scale::Fixed(scale) => scale,
+            Scale::Max => max_capture_scale(&captures),
+        };
+        if scale != 1.0 {
+            tracing::debug!("Compositing at scale {}", scale);
+        }
+        Ok(Captured { captures, scale })
+    }
+
+    /// Whether `captured` could be encoded row by row straight from the capture buffers,
+    /// which needs a single unscaled image that is not post processed.
+    pub fn can_stream(
+        &self,
+        captured: &Captured,
+        format: EncodingFormat,
+        options: &EncodingOptions,
+    ) -> bool {
+        self.can_composite_raw(captured) && backend::can_stream(format, options, &captured.captures)
+    }
+
+    /// Whether `captured` could be composited straight from the capture buffers, eg: by
+    /// [`backend::write_deep_tiff`], which needs a single unscaled image that is not post
+    /// processed.
+    pub fn can_composite_raw(&self, captured: &Captured) -> bool {
+        self.split == Split::Composite
+            && self.post_processors.is_empty()
+            && captured.scale == 1.0
+            && backend::can_composite_raw(&captured.captures)
+    }
+
+    /// Convert, composite, split and post process `captured`, handing the images to `sink`.
+    #[tracing::instrument(skip_all)]
+    pub fn render(&self, captured: Captured, sink: &mut dyn Sink) -> Result<(), Box<dyn Error>> {
+        let rendered = self.render_images(captured, sink);
+        if let Some(progress) = &self.progress {
+            progress.finish();
+        }
+        rendered
+    }
+
+    fn render_images(&self, captured: Captured, sink: &mut dyn Sink) -> Result<(), Box<dyn Error>> {
+        let scale = captured.scale;
+        if self.split == Split::Outputs {
+            for raw_capture in captured.captures {
+                let capture = convert_raw_capture_at_scale(raw_capture, scale)?;
+                let placement = Placement {
+                    origin: (capture.region.x, capture.region.y),
+                    scale,
+                    outputs: vec![(capture.name.clone(), capture.region)],
+                };
+                let image = self.post_process(capture.image, &placement)?;
+                sink.write(Subject::Output(&capture.name), image, &placement)?;
+            }
+            return Ok(());
+        }
+
+        let captures = captured
+            .captures
+            .into_iter()
+            .map(|raw_capture| convert_raw_capture_at_scale(raw_capture, scale))
+            .collect::<Result<Vec<_>, _>>()?;
+        let composite = composite_captures_at_scale(&captures, scale)?;
+        let bounds = bounding_region(captures.iter().map(|capture| &capture.region));
+        let outputs: Vec<(String, Region)> = captures
+            .iter()
+            .map(|capture| (capture.name.clone(), capture.region))
+            .collect();
+        let placement = Placement {
+            origin: (bounds.x, bounds.y),
+            scale,
+            outputs: outputs.clone(),
+        };
+
+        match &self.split {
+            Split::Composite => {
+                let image = self.post_process(composite, &placement)?;
+                sink.write(Subject::Composite, image, &placement)
+            }
+            Split::Windows { app_id, windows } => {
+                for &(window, number) in windows {
+                    if let Some((image, placement)) =
+                        crop_region(&composite, bounds, &outputs, window, scale)
+                    {
+                        let image = self.post_process(image, &placement)?;
+                        sink.write(Subject::Window { app_id, number }, image, &placement)?;
+                    }
+                }
+                Ok(())
+            }
+            Split::Crops(crops) => {
+                for (name, region) in crops {
+                    if let Some((image, placement)) =
+                        crop_region(&composite, bounds, &outputs, *region, scale)
+                    {
+                        let image = self.post_process(image, &placement)?;
+                        sink.write(Subject::Crop(name), image, &placement)?;
+                    }
+                }
+                Ok(())
+            }
+            Split::Outputs => unreachable!(),
+        }
+    }
+
+    fn post_process(
+        &self,
+        mut image: RgbaImage,
+        placement: &Placement,
+    ) -> Result<RgbaImage, Box<dyn Error>> {
+        let steps = self.post_processors.len() as u64;
+        for (step, post_processor) in self.post_processors.iter().enumerate() {
+            if let Some(progress) = &self.progress {
+                progress.update("post-process", step as u64, steps);
+            }
+            let _span =
+                tracing::debug_span!("post_process", step = post_processor.name()).entered();
+            image = post_processor.process(image, placement)?;
+        }
+        if let (Some(progress), true) = (&self.progress, steps > 0) {
+            progress.update("post-process", steps, steps);
+        }
+        Ok(image)
+    }
+}
+
+/// Cut `region`, in global logical coordinates, out of `composite`, which covers `bounds` at
+/// `scale` and shows `outputs`. Regions reaching past the edge of the composite are cut to it,
+/// those entirely outside of it are skipped with a warning.
+fn crop_region(
+    composite: &RgbaImage,
+    bounds: Region,
+    outputs: &[(String, Region)],
+    region: Region,
+    scale: f64,
+) -> Option<(RgbaImage, Placement)> {
+    let (origin_x, _) = scaled_span(bounds.x, 0, scale);
+    let (origin_y, _) = scaled_span(bounds.y, 0, scale);
+    let (width, height) = (composite.width() as i64, composite.height() as i64);
+    let (x, region_width) = scaled_span(region.x, region.width, scale);
+    let (y, region_height) = scaled_span(region.y, region.height, scale);
+    let x1 = (x - origin_x).clamp(0, width) as u32;
+    let y1 = (y - origin_y).clamp(0, height) as u32;
+    let x2 = (x - origin_x + region_width as i64).clamp(0, width) as u32;
+    let y2 = (y - origin_y + region_height as i64).clamp(0, height) as u32;
+    if x1 == x2 || y1 == y2 {
+        let region = format!(
+            "{},{} {}x{}",
+            region.x, region.y, region.width, region.height
+        );
+        tracing::warn!(
+            "{}",
+            tr_args(Message::RegionSkipped, &[("region", region.into())])
+        );
+        return None;
+    }
+
+    let placement = Placement {
+        origin: (cmp::max(region.x, bounds.x), cmp::max(region.y, bounds.y)),
+        scale,
+        outputs: outputs
+            .iter()
+            .map(|(name, output)| (name.clone(), intersection(output, &region)))
+            .filter(|(_, shown)| shown.width > 0 && shown.height > 0)
+            .collect(),
+    };
+    let image = crop_imm(composite, x1, y1, x2 - x1, y2 - y1).to_image();
+    Some((image, placement))
+}
+
+/// Assembles a [`Pipeline`], every stage but the backend has a default: a single unscaled
+/// composite of one frame per output without any post processing.
+pub struct PipelineBuilder<'a> {
+    pipeline: Pipeline<'a>,
+}
+
+impl<'a> PipelineBuilder<'a> {
+    /// Capture `frames` frames in a row and combine them, see [`crate::stack_frames`].
+    pub fn stack(mut self, frames: usize, mode: StackMode) -> Self {
+        self.pipeline.stack = (frames, mode);
+        self
+    }
+
+    pub fn scale(mut self, scale: Scale) -> Self {
+        self.pipeline.scale = scale;
+        self
+    }
+
+    pub fn split(mut self, split: Split) -> Self {
+        self.pipeline.split = split;
+        self
+    }
+
+    /// Append a post processing step, steps run in the order they are added.
+    pub fn post_process(mut self, post_processor: impl PostProcessor + 'a) -> Self {
+        self.pipeline.post_processors.push(Box::new(post_processor));
+        self
+    }
+
+    /// Report how far post processing got to `progress`.
+    pub fn progress(mut self, progress: Rc<dyn Progress + 'a>) -> Self {
+        self.pipeline.progress = Some(progress);
+        self
+    }
+
+    pub fn build(self) -> Pipeline<'a> {
+        self.pipeline
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, os::unix::ffi::OsStrExt};
+
+    use image::Rgba;
+    use memmap2::MmapMut;
+    use wayland_client::protocol::wl_shm::Format;
+    use wayshot_core::Transform;
+
+    use super::*;
+    use crate::{backend::FrameFormat, testing::TempDirectory};
+
+    /// Stands in for the compositor, every capture returns the same golden frames.
+    struct GoldenFrames(Vec<(&'static str, Region, RgbaImage)>);
+
+    impl CaptureBackend for GoldenFrames {
+        fn name(&self) -> &'static str {
+            "golden"
+        }
+
+        fn capture(&self, _args: &FrameArgs) -> Result<Vec<RawCapture>, Box<dyn Error>> {
+            self.0
+                .iter()
+                .map(|(name, region, image)| {
+                    // Xrgb8888 is stored as BGRA bytes, which the conversion has to undo.
+                    let mut frame_mmap = MmapMut::map_anon(image.as_raw().len())?;
+                    for (pixel, bytes) in image.pixels().zip(frame_mmap.chunks_exact_mut(4)) {
+                        let [r, g, b, a] = pixel.0;
+                        bytes.copy_from_slice(&[b, g, r, a]);
+                    }
+                    Ok(RawCapture {
+                        name: name.to_string(),
+                        region: *region,
+                        frame_format: FrameFormat {
+                            format: Format::Xrgb8888,
+                            width: image.width(),
+                            height: image.height(),
+                            stride: image.width() * 4,
+                        },
+                        frame_mmap,
+                        transform: Transform::Normal,
+                        presented_at: None,
+                        mode: None,
+                    })
+                })
+                .collect()
+        }
+    }
+
+    const TRANSPARENT: Rgba<u8> = Rgba([0, 0, 0, 0]);
+
+    /// Pixel of output `index` at `x`, `y` in its frame, distinct for every output and
+    /// position so misplaced pixels show up.
+    fn golden_pixel(index: u8, x: u32, y: u32) -> Rgba<u8> {
+        Rgba([index, x as u8, y as u8, 255])
+    }
+
+    fn golden_output(index: u8, region: Region) -> (&'static str, Region, RgbaImage) {
+        let name = ["DP-1", "DP-2"][index as usize];
+        let image = RgbaImage::from_fn(region.width as u32, region.height as u32, |x, y| {
+            golden_pixel(index, x, y)
+        });
+        (name, region, image)
+    }
+
+    /// DP-1, 4x2 at the origin, and DP-2, 2x2 to its right and one pixel lower.
+    fn two_outputs() -> GoldenFrames {
+        GoldenFrames(vec![
+            golden_output(
+                0,
+                Region {
+                    x: 0,
+                    y: 0,
+                    width: 4,
+                    height: 2,
+                },
+            ),
+            golden_output(
+                1,
+                Region {
+                    x: 4,
+                    y: 1,
+                    width: 2,
+                    height: 2,
+                },
+            ),
+        ])
+    }
+
+    fn render(pipeline: Pipeline) -> Vec<RgbaImage> {
+        let mut images = Vec::new();
+        pipeline.run(&FrameArgs::default(), &mut images).unwrap();
+        images
+    }
+
+    #[test]
+    fn outputs_are_composited_at_their_position() {
+        let images = render(Pipeline::builder(two_outputs()).build());
+        assert_eq!(images.len(), 1);
+        let expected = RgbaImage::from_fn(6, 3, |x, y| match (x, y) {
+            (0..=3, 0..=1) => golden_pixel(0, x, y),
+            (4..=5, 1..=2) => golden_pixel(1, x - 4, y - 1),
+            _ => TRANSPARENT,
+        });
+        assert_eq!(images[0], expected);
+    }
+
+    #[test]
+    fn images_larger_than_their_region_fail_to_composite() {
+        let capture = crate::OutputCapture {
+            name: "DP-1".to_string(),
+            region: Region {
+                x: 0,
+                y: 0,
+                width: 4,
+                height: 2,
+            },
+            image: RgbaImage::new(5, 2),
+            presented_at: None,
+            mode: None,
+        };
+        assert!(composite_captures_at_scale(&[capture], 1.0).is_err());
+    }
+
+    #[test]
+    fn outputs_are_split_without_compositing() {
+        let images = render(
+            Pipeline::builder(two_outputs())
+                .split(Split::Outputs)
+                .build(),
+        );
+        let frames: Vec<RgbaImage> = two_outputs().0.into_iter().map(|frame| frame.2).collect();
+        assert_eq!(images, frames);
+    }
+
+    #[test]
+    fn crops_are_cut_from_the_composite() {
+        let crops = vec![
+            (
+                "both".to_string(),
+                Region {
+                    x: 3,
+                    y: 1,
+                    width: 2,
+                    height: 1,
+                },
+            ),
+            (
+                "past-the-edge".to_string(),
+                Region {
+                    x: 5,
+                    y: 2,
+                    width: 4,
+                    height: 4,
+                },
+            ),
+            (
+                "outside".to_string(),
+                Region {
+                    x: 10,
+                    y: 10,
+                    width: 2,
+                    height: 2,
+                },
+            ),
+        ];
+        let images = render(
+            Pipeline::builder(two_outputs())
+                .split(Split::Crops(crops))
+                .build(),
+        );
+        assert_eq!(images.len(), 2);
+        assert_eq!(images[0].dimensions(), (2, 1));
+        assert_eq!(images[0].get_pixel(0, 0), &golden_pixel(0, 3, 1));
+        assert_eq!(images[0].get_pixel(1, 0), &golden_pixel(1, 0, 0));
+        assert_eq!(images[1].dimensions(), (1, 1));
+        assert_eq!(images[1].get_pixel(0, 0), &golden_pixel(1, 1, 1));
+    }
+
+    #[test]
+    fn auto_trim_removes_the_border_around_the_composite() {
+        let border = Rgba([255, 255, 255, 255]);
+        let mut image = RgbaImage::from_pixel(6, 5, border);
+        for (x, y) in [(2, 1), (3, 1), (2, 2), (3, 3)] {
+            image.put_pixel(x, y, golden_pixel(0, x, y));
+        }
+        let region = Region {
+            x: 0,
+            y: 0,
+            width: 6,
+            height: 5,
+        };
+        let backend = GoldenFrames(vec![("DP-1", region, image.clone())]);
+        let images = render(
+            Pipeline::builder(backend)
+                .post_process(AutoTrim { tolerance: 0 })
+                .build(),
+        );
+        assert_eq!(images.len(), 1);
+        assert_eq!(images[0], crop_imm(&image, 2, 1, 2, 3).to_image());
+    }
+
+    /// Stem of file names which are not valid UTF-8.
+    const STEM: &[u8] = b"shot-\xff";
+
+    fn file_sink(directory: &Path, file: Option<PathBuf>) -> FileSink {
+        FileSink {
+            directory: directory.to_path_buf(),
+            stem: "shot".to_string(),
+            file,
+            stdout: false,
+            format: EncodingFormat::Png,
+            options: EncodingOptions::default(),
+            output_formats: HashMap::new(),
+            thumbnail: None,
+            private: false,
+            encrypt: None,
+            auto_format: false,
+            collision: None,
+            progress: None,
+            auto_degrade: false,
+            also_save: Vec::new(),
+            checksum: None,
+            last_checksum: None,
+            max_size: None,
+        }
+    }
+
+    /// Bytes of the file name of `path`.
+    fn name(path: &Path) -> &[u8] {
+        path.file_name().unwrap().as_bytes()
+    }
+
+    #[test]
+    fn thumbnails_keep_names_which_are_not_utf8() {
+        let path = Path::new(OsStr::from_bytes(b"/tmp/shot-\xff.jpg"));
+        assert_eq!(name(&thumbnail_path(path)), b"shot-\xff-thumb.png");
+    }
+
+    #[test]
+    fn claimed_names_which_are_not_utf8_are_kept() {
+        let temp = TempDirectory::new();
+        let directory = temp.path();
+        let stem = OsStr::from_bytes(STEM);
+        let extension = OsStr::new("png");
+        let claim = |collision| claim_path(directory, stem, extension, collision);
+
+        let first = claim(Collision::Increment).unwrap();
+        assert_eq!(first, directory.join(OsStr::from_bytes(b"shot-\xff.png")));
+        assert!(first.exists());
+        let second = claim(Collision::Increment).unwrap();
+        assert_eq!(name(&second), b"shot-\xff-1.png");
+        assert_eq!(claim(Collision::Overwrite).unwrap(), first);
+        assert!(claim(Collision::Fail).is_err());
+        for collision in [Collision::TimestampNs, Collision::Uuid] {
+            let path = claim(collision).unwrap();
+            assert!(name(&path).starts_with(b"shot-\xff-"), "{:?}", path);
+            assert!(name(&path).ends_with(b".png"), "{:?}", path);
+        }
+    }
+
+    #[test]
+    fn names_derived_from_a_file_which_is_not_utf8_are_kept() {
+        let directory = TempDirectory::new();
+        let file = directory.path().join(OsStr::from_bytes(b"shot-\xff.png"));
+        let mut sink = file_sink(directory.path(), Some(file.clone()));
+
+        assert_eq!(sink.path(Subject::Composite).unwrap(), file);
+        let output = sink.path(Subject::Output("DP-1")).unwrap();
+        assert_eq!(name(&output), b"shot-\xff-DP-1.png");
+
+        // Taken names get a counter after the stem, which is kept as is.
+        sink.collision = Some(Collision::Increment);
+        fs::write(&file, b"").unwrap();
+        let renamed = sink.path(Subject::Composite).unwrap();
+        assert_eq!(name(&renamed), b"shot-\xff-1.png");
+        let renamed = sink.path(Subject::Composite).unwrap();
+        assert_eq!(name(&renamed), b"shot-\xff-2.png");
+    }
+
+    #[test]
+    fn thumbnails_do_not_overwrite_taken_names() {
+        let directory = TempDirectory::new();
+        let taken = directory.path().join("shot-thumb.png");
+        fs::write(&taken, b"kept").unwrap();
+        let mut sink = file_sink(directory.path(), None);
+        sink.thumbnail = Some((2, 2));
+        let placement = Placement {
+            origin: (0, 0),
+            scale: 1.0,
+            outputs: Vec::new(),
+        };
+        let image = RgbaImage::from_pixel(8, 4, Rgba([10, 20, 30, 255]));
+        sink.write(Subject::Composite, image.clone(), &placement)
+            .unwrap();
+
+        assert_eq!(fs::read(&taken).unwrap(), b"kept");
+        let written = image::open(directory.path().join("shot.png")).unwrap();
+        assert_eq!(written.to_rgba8(), image);
+        let thumbnail = image::open(directory.path().join("shot-thumb-1.png")).unwrap();
+        assert_eq!((thumbnail.width(), thumbnail.height()), (2, 1));
+    }
+
+    #[test]
+    fn images_are_encrypted_through_the_shell() {
+        let directory = TempDirectory::new();
+        let mut sink = file_sink(directory.path(), None);
+        let placement = Placement {
+            origin: (0, 0),
+            scale: 1.0,
+            outputs: Vec::new(),
+        };
+        let image = RgbaImage::from_pixel(2, 2, Rgba([1, 2, 3, 255]));
+        sink.encrypt = Some("head -c 4 | tr 'PNG' 'png'".to_string());
+        sink.write(Subject::Composite, image.clone(), &placement)
+            .unwrap();
+        assert_eq!(
+            fs::read(directory.path().join("shot.png")).unwrap(),
+            b"\x89png"
+        );
+
+        sink.encrypt = Some("cat >/dev/null; exit 3".to_string());
+        assert!(sink.write(Subject::Composite, image, &placement).is_err());
+    }
+
+    #[test]
+    fn large_ppm_images_are_written_through_a_map_of_the_created_file() {
+        let directory = TempDirectory::new();
+        let mut sink = file_sink(directory.path(), None);
+        sink.format = EncodingFormat::Ppm;
+        let placement = Placement {
+            origin: (0, 0),
+            scale: 1.0,
+            outputs: Vec::new(),
+        };
+        // Just above the threshold once the header is counted.
+        let width = 8192;
+        let height = (backend::MAPPED_WRITE_THRESHOLD / 3 / width + 1) as u32;
+        let image = RgbaImage::from_fn(width as u32, height, |x, y| {
+            Rgba([x as u8, y as u8, 7, 255])
+        });
+        sink.write(Subject::Composite, image, &placement).unwrap();
+
+        let written = fs::read(directory.path().join("shot.ppm")).unwrap();
+        let header = format!("P6\n{} {} 255\n", width, height);
+        assert!(written.starts_with(header.as_bytes()));
+        assert_eq!(
+            written.len() as u64,
+            header.len() as u64 + 3 * width * height as u64
+        );
+        let last = &written[written.len() - 3..];
+        assert_eq!(last, [(width - 1) as u8, (height - 1) as u8, 7]);
+    }
+}
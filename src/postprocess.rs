@@ -0,0 +1,496 @@
+use image::{
+    imageops::{self, crop_imm, resize, thumbnail as resize_thumbnail, FilterType},
+    Rgba, RgbaImage,
+};
+
+use crate::Region;
+
+/// Remove uniform borders from `image`. The border color is taken from the top left pixel and
+/// rows or columns are trimmed from every side as long as all of their pixels are within
+/// `tolerance` of it in every channel. Images which are uniform all over are returned as is.
+pub fn auto_trim(image: &RgbaImage, tolerance: u8) -> RgbaImage {
+    if image.width() == 0 || image.height() == 0 {
+        return image.clone();
+    }
+
+    let border = *image.get_pixel(0, 0);
+    let is_border = |x: u32, y: u32| matches_color(image.get_pixel(x, y), &border, tolerance);
+    let row_is_border = |y: u32| (0..image.width()).all(|x| is_border(x, y));
+
+    let top = match (0..image.height()).find(|&y| !row_is_border(y)) {
+        Some(top) => top,
+        None => return image.clone(),
+    };
+    let bottom = (top..image.height())
+        .rev()
+        .find(|&y| !row_is_border(y))
+        .unwrap_or(top);
+
+    let column_is_border = |x: u32| (top..=bottom).all(|y| is_border(x, y));
+    let left = (0..image.width())
+        .find(|&x| !column_is_border(x))
+        .unwrap_or(0);
+    let right = (left..image.width())
+        .rev()
+        .find(|&x| !column_is_border(x))
+        .unwrap_or(left);
+
+    tracing::debug!(
+        "Trimmed borders to {}x{}+{}+{}",
+        right - left + 1,
+        bottom - top + 1,
+        left,
+        top
+    );
+    crop_imm(image, left, top, right - left + 1, bottom - top + 1).to_image()
+}
+
+fn matches_color(pixel: &Rgba<u8>, color: &Rgba<u8>, tolerance: u8) -> bool {
+    pixel
+        .0
+        .iter()
+        .zip(color.0.iter())
+        .all(|(a, b)| a.abs_diff(*b) <= tolerance)
+}
+
+/// Scale `image` down to fit within `max_width` x `max_height`, keeping its aspect ratio.
+/// Images which already fit are returned as is.
+pub fn thumbnail(image: &RgbaImage, max_width: u32, max_height: u32) -> RgbaImage {
+    if image.width() <= max_width && image.height() <= max_height {
+        return image.clone();
+    }
+
+    let ratio = f64::min(
+        max_width as f64 / image.width() as f64,
+        max_height as f64 / image.height() as f64,
+    );
+    let width = ((image.width() as f64 * ratio).round() as u32).max(1);
+    let height = ((image.height() as f64 * ratio).round() as u32).max(1);
+    resize_thumbnail(image, width, height)
+}
+
+/// Square icon of `image`, `side` pixels wide: the image scaled to fit, keeping its aspect
+/// ratio, and centered on a transparent background. Unlike [`thumbnail`], small images are
+/// scaled up.
+pub fn icon(image: &RgbaImage, side: u32) -> RgbaImage {
+    let ratio = f64::min(
+        side as f64 / image.width() as f64,
+        side as f64 / image.height() as f64,
+    );
+    let width = ((image.width() as f64 * ratio).round() as u32).clamp(1, side);
+    let height = ((image.height() as f64 * ratio).round() as u32).clamp(1, side);
+    let scaled = if ratio < 1.0 {
+        resize_thumbnail(image, width, height)
+    } else {
+        resize(image, width, height, FilterType::CatmullRom)
+    };
+
+    let mut icon = RgbaImage::new(side, side);
+    imageops::replace(
+        &mut icon,
+        &scaled,
+        ((side - width) / 2) as i64,
+        ((side - height) / 2) as i64,
+    );
+    icon
+}
+
+/// Side of the square cells [`changed_regions`] compares images in, in pixels. Changes in
+/// neighbouring cells are reported as one region.
+pub const CHANGE_CELL_SIZE: u32 = 16;
+
+/// Color of the outlines drawn by [`highlight_changes`].
+const CHANGE_OUTLINE: Rgba<u8> = Rgba([255, 0, 0, 255]);
+
+/// Thickness of the outlines drawn by [`highlight_changes`], in pixels.
+const CHANGE_OUTLINE_WIDTH: u32 = 2;
+
+/// Bounding boxes of the areas where `image` differs from `reference` by more than `tolerance`
+/// in any channel, in pixels of the images, which must have the same size. Images are compared
+/// in cells of [`CHANGE_CELL_SIZE`] pixels and touching cells with changes are merged, so small
+/// changes next to each other, eg: the letters of an edited word, end up in a single box.
+pub fn changed_regions(reference: &RgbaImage, image: &RgbaImage, tolerance: u8) -> Vec<Region> {
+    let columns = image.width().div_ceil(CHANGE_CELL_SIZE) as usize;
+    let rows = image.height().div_ceil(CHANGE_CELL_SIZE) as usize;
+    let mut changed = vec![false; columns * rows];
+    for (x, y, pixel) in image.enumerate_pixels() {
+        let cell = (y / CHANGE_CELL_SIZE) as usize * columns + (x / CHANGE_CELL_SIZE) as usize;
+        if !changed[cell] && !matches_color(pixel, reference.get_pixel(x, y), tolerance) {
+            changed[cell] = true;
+        }
+    }
+
+    let mut regions = Vec::new();
+    for start in 0..changed.len() {
+        if !changed[start] {
+            continue;
+        }
+        // Flood fill the cells touching this one, including diagonally.
+        changed[start] = false;
+        let (mut left, mut top) = (start % columns, start / columns);
+        let (mut right, mut bottom) = (left, top);
+        let mut pending = vec![start];
+        while let Some(cell) = pending.pop() {
+            let (column, row) = (cell % columns, cell / columns);
+            left = left.min(column);
+            right = right.max(column);
+            top = top.min(row);
+            bottom = bottom.max(row);
+            for neighbour_row in row.saturating_sub(1)..(row + 2).min(rows) {
+                for neighbour_column in column.saturating_sub(1)..(column + 2).min(columns) {
+                    let neighbour = neighbour_row * columns + neighbour_column;
+                    if changed[neighbour] {
+                        changed[neighbour] = false;
+                        pending.push(neighbour);
+                    }
+                }
+            }
+        }
+        let x = left as u32 * CHANGE_CELL_SIZE;
+        let y = top as u32 * CHANGE_CELL_SIZE;
+        regions.push(Region {
+            x: x as i32,
+            y: y as i32,
+            width: (((right + 1) as u32 * CHANGE_CELL_SIZE).min(image.width()) - x) as i32,
+            height: (((bottom + 1) as u32 * CHANGE_CELL_SIZE).min(image.height()) - y) as i32,
+        });
+    }
+    regions
+}
+
+/// Darken everything but `regions` of `image` and outline them in red, so changes found with
+/// [`changed_regions`] stand out.
+pub fn highlight_changes(image: &mut RgbaImage, regions: &[Region]) {
+    let inside = |x: u32, y: u32, region: &Region| {
+        (x as i32) >= region.x
+            && (x as i32) < region.x + region.width
+            && (y as i32) >= region.y
+            && (y as i32) < region.y + region.height
+    };
+    for (x, y, pixel) in image.enumerate_pixels_mut() {
+        if !regions.iter().any(|region| inside(x, y, region)) {
+            for channel in &mut pixel.0[..3] {
+                *channel /= 2;
+            }
+        }
+    }
+
+    for region in regions {
+        let (x, y) = (region.x as u32, region.y as u32);
+        let (width, height) = (region.width as u32, region.height as u32);
+        for dy in 0..height {
+            for dx in 0..width {
+                let border = dx.min(width - 1 - dx).min(dy).min(height - 1 - dy);
+                if border < CHANGE_OUTLINE_WIDTH {
+                    image.put_pixel(x + dx, y + dy, CHANGE_OUTLINE);
+                }
+            }
+        }
+    }
+}
+
+/// Most times --zoom enlarges an image.
+pub const MAX_ZOOM: f64 = 64.0;
+
+/// Interpolation used by [`zoom`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum ZoomFilter {
+    /// Every pixel becomes a block of the same color, eg: to inspect pixel art or antialiasing.
+    #[default]
+    Nearest,
+    Linear,
+    Cubic,
+    Lanczos,
+}
+
+/// Enlarge `image` `factor` times with `filter`. Nearest neighbour zooms by integer factors
+/// copy every pixel into an exact square block.
+pub fn zoom(image: &RgbaImage, factor: f64, filter: ZoomFilter) -> RgbaImage {
+    let width = ((image.width() as f64 * factor).round() as u32).max(1);
+    let height = ((image.height() as f64 * factor).round() as u32).max(1);
+    let filter = match filter {
+        ZoomFilter::Nearest if factor.fract() == 0.0 => {
+            let factor = factor as u32;
+            return RgbaImage::from_fn(width, height, |x, y| {
+                *image.get_pixel(x / factor, y / factor)
+            });
+        }
+        ZoomFilter::Nearest => FilterType::Nearest,
+        ZoomFilter::Linear => FilterType::Triangle,
+        ZoomFilter::Cubic => FilterType::CatmullRom,
+        ZoomFilter::Lanczos => FilterType::Lanczos3,
+    };
+    resize(image, width, height, filter)
+}
+
+/// Darken the left and top edges of the blocks source pixels cover in an image enlarged
+/// `factor` times by [`zoom`], drawing a grid between them. Factors below 2 leave no room for
+/// a grid and draw nothing.
+pub fn draw_pixel_grid(image: &mut RgbaImage, factor: f64) {
+    if factor < 2.0 {
+        return;
+    }
+    let on_grid = |position: u32| {
+        let source = (position as f64 / factor).floor();
+        position == (source * factor).round() as u32
+    };
+    let columns: Vec<bool> = (0..image.width()).map(on_grid).collect();
+    let rows: Vec<bool> = (0..image.height()).map(on_grid).collect();
+    for (x, y, pixel) in image.enumerate_pixels_mut() {
+        if columns[x as usize] || rows[y as usize] {
+            for channel in &mut pixel.0[..3] {
+                *channel = (*channel as u16 * 3 / 4) as u8;
+            }
+            pixel.0[3] = u8::MAX;
+        }
+    }
+}
+
+/// Largest --gamma and --contrast.
+pub const MAX_TONE_FACTOR: f64 = 10.0;
+
+/// Changes to the tones of an image, applied to the color channels in this order: gamma,
+/// contrast, brightness then inversion. Alpha is left as is.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ToneAdjustment {
+    /// Normalized channels are raised to the power of its inverse, values above 1 brighten
+    /// mid tones.
+    pub gamma: f64,
+    /// Added to normalized channels, from -1 to 1.
+    pub brightness: f64,
+    /// Factor of the distance of normalized channels from mid gray.
+    pub contrast: f64,
+    /// Turn every channel into its complement, eg: to make dark themes print friendly.
+    pub invert: bool,
+}
+
+impl Default for ToneAdjustment {
+    fn default() -> Self {
+        Self {
+            gamma: 1.0,
+            brightness: 0.0,
+            contrast: 1.0,
+            invert: false,
+        }
+    }
+}
+
+impl ToneAdjustment {
+    /// Whether the adjustment leaves every image as is.
+    pub fn is_identity(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Adjusted value of every 8 bit channel value.
+    pub fn lookup_table(&self) -> [u8; 256] {
+        let mut table = [0; 256];
+        for (value, adjusted) in table.iter_mut().enumerate() {
+            let mut tone = (value as f64 / 255.0).powf(1.0 / self.gamma);
+            tone = (tone - 0.5) * self.contrast + 0.5 + self.brightness;
+            tone = tone.clamp(0.0, 1.0);
+            if self.invert {
+                tone = 1.0 - tone;
+            }
+            *adjusted = (tone * 255.0).round() as u8;
+        }
+        table
+    }
+}
+
+/// Apply `adjustment` to the color channels of `image`, through a lookup table computed once
+/// for the whole image.
+pub fn adjust_tones(image: &mut RgbaImage, adjustment: &ToneAdjustment) {
+    let table = adjustment.lookup_table();
+    for pixel in image.pixels_mut() {
+        for channel in &mut pixel.0[..3] {
+            *channel = table[*channel as usize];
+        }
+    }
+}
+
+/// How [`redact`] hides what a region shows.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum RedactStyle {
+    /// Opaque black.
+    #[default]
+    Black,
+    /// Blurred beyond recognition, keeping its rough colors.
+    Blur,
+}
+
+/// Factor [`RedactStyle::Blur`] shrinks regions by before enlarging them back.
+pub const REDACT_BLUR_FACTOR: u32 = 32;
+
+/// Hide `region` of `image`, in pixels. Parts outside the image are ignored.
+pub fn redact(image: &mut RgbaImage, region: Region, style: RedactStyle) {
+    let clamp_x = |x: i32| x.clamp(0, image.width() as i32) as u32;
+    let clamp_y = |y: i32| y.clamp(0, image.height() as i32) as u32;
+    let (left, right) = (clamp_x(region.x), clamp_x(region.x + region.width));
+    let (top, bottom) = (clamp_y(region.y), clamp_y(region.y + region.height));
+    if left >= right || top >= bottom {
+        return;
+    }
+    let (width, height) = (right - left, bottom - top);
+    match style {
+        RedactStyle::Black => {
+            for y in top..bottom {
+                for x in left..right {
+                    image.put_pixel(x, y, Rgba([0, 0, 0, u8::MAX]));
+                }
+            }
+        }
+        RedactStyle::Blur => {
+            let area = crop_imm(image, left, top, width, height).to_image();
+            let shrunk = resize(
+                &area,
+                (width / REDACT_BLUR_FACTOR).max(1),
+                (height / REDACT_BLUR_FACTOR).max(1),
+                FilterType::Triangle,
+            );
+            let blurred = resize(&shrunk, width, height, FilterType::Triangle);
+            imageops::replace(image, &blurred, left as i64, top as i64);
+        }
+    }
+}
+
+/// Color vision deficiency simulated by [`simulate_color_vision`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ColorVision {
+    /// No red cones.
+    Protanopia,
+    /// No green cones.
+    Deuteranopia,
+    /// No blue cones.
+    Tritanopia,
+}
+
+impl ColorVision {
+    /// Linear RGB transform of the deficiency at full severity, from Machado, Oliveira and
+    /// Fernandes, "A Physiologically-based Model for Simulation of Color Vision Deficiency"
+    /// (2009).
+    pub fn matrix(&self) -> [[f64; 3]; 3] {
+        match self {
+            ColorVision::Protanopia => [
+                [0.152286, 1.052583, -0.204868],
+                [0.114503, 0.786281, 0.099216],
+                [-0.003882, -0.048116, 1.051998],
+            ],
+            ColorVision::Deuteranopia => [
+                [0.367322, 0.860646, -0.227968],
+                [0.280085, 0.672501, 0.047413],
+                [-0.011820, 0.042940, 0.968881],
+            ],
+            ColorVision::Tritanopia => [
+                [1.255528, -0.076749, -0.178779],
+                [-0.078411, 0.930809, 0.147602],
+                [0.004733, 0.691367, 0.303900],
+            ],
+        }
+    }
+}
+
+/// Entries of the table [`simulate_color_vision`] converts linear values back to sRGB with,
+/// enough for every 8 bit sRGB value to have its own.
+const LINEAR_TABLE_SIZE: usize = 4096;
+
+/// Turn `image` into how it looks with the color vision deficiency `vision`, transforming its
+/// colors in linear RGB. Alpha is left as is.
+pub fn simulate_color_vision(image: &mut RgbaImage, vision: ColorVision) {
+    let to_linear: Vec<f64> = (0..=u8::MAX)
+        .map(|value| {
+            let value = value as f64 / 255.0;
+            if value <= 0.04045 {
+                value / 12.92
+            } else {
+                ((value + 0.055) / 1.055).powf(2.4)
+            }
+        })
+        .collect();
+    let to_srgb: Vec<u8> = (0..LINEAR_TABLE_SIZE)
+        .map(|index| {
+            let value = index as f64 / (LINEAR_TABLE_SIZE - 1) as f64;
+            let value = if value <= 0.0031308 {
+                value * 12.92
+            } else {
+                1.055 * value.powf(1.0 / 2.4) - 0.055
+            };
+            (value * 255.0).round() as u8
+        })
+        .collect();
+    let matrix = vision.matrix();
+    for pixel in image.pixels_mut() {
+        let linear = [0, 1, 2].map(|channel| to_linear[pixel.0[channel] as usize]);
+        for (channel, row) in matrix.iter().enumerate() {
+            let value = row[0] * linear[0] + row[1] * linear[1] + row[2] * linear[2];
+            let index = (value.clamp(0.0, 1.0) * (LINEAR_TABLE_SIZE - 1) as f64).round();
+            pixel.0[channel] = to_srgb[index as usize];
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BORDER: Rgba<u8> = Rgba([255, 255, 255, 255]);
+    const CONTENT: Rgba<u8> = Rgba([200, 30, 30, 255]);
+
+    /// `width` x `height` image of `BORDER` with a `CONTENT` rectangle at `content`.
+    fn bordered(width: u32, height: u32, content: (u32, u32, u32, u32)) -> RgbaImage {
+        let (x, y, w, h) = content;
+        RgbaImage::from_fn(width, height, |px, py| {
+            if (x..x + w).contains(&px) && (y..y + h).contains(&py) {
+                CONTENT
+            } else {
+                BORDER
+            }
+        })
+    }
+
+    #[test]
+    fn uniform_borders_are_trimmed() {
+        let image = bordered(10, 8, (2, 3, 5, 4));
+        let trimmed = auto_trim(&image, 0);
+        assert_eq!(trimmed.dimensions(), (5, 4));
+        assert!(trimmed.pixels().all(|pixel| *pixel == CONTENT));
+    }
+
+    #[test]
+    fn uneven_borders_are_trimmed_on_every_side() {
+        let trimmed = auto_trim(&bordered(10, 8, (0, 7, 1, 1)), 0);
+        assert_eq!(trimmed.dimensions(), (1, 1));
+        let trimmed = auto_trim(&bordered(10, 8, (9, 0, 1, 8)), 0);
+        assert_eq!(trimmed.dimensions(), (1, 8));
+    }
+
+    #[test]
+    fn tolerance_decides_what_counts_as_border() {
+        let mut image = bordered(6, 6, (2, 2, 2, 2));
+        // Noise in the border that differs from it by exactly 3 in one channel.
+        image.put_pixel(0, 5, Rgba([252, 255, 255, 255]));
+        assert_eq!(auto_trim(&image, 3).dimensions(), (2, 2));
+        assert_eq!(auto_trim(&image, 2).dimensions(), (4, 4));
+        assert_eq!(
+            auto_trim(&image, 2).get_pixel(0, 3),
+            &Rgba([252, 255, 255, 255])
+        );
+    }
+
+    #[test]
+    fn uniform_images_are_kept() {
+        let image = RgbaImage::from_pixel(7, 5, BORDER);
+        assert_eq!(auto_trim(&image, 0), image);
+
+        // Within tolerance of the top left pixel everywhere is uniform too.
+        let mut image = RgbaImage::from_pixel(7, 5, BORDER);
+        image.put_pixel(3, 3, Rgba([250, 250, 250, 255]));
+        assert_eq!(auto_trim(&image, 10), image);
+    }
+
+    #[test]
+    fn images_without_border_are_kept() {
+        let image = RgbaImage::from_fn(4, 3, |x, y| Rgba([x as u8, y as u8, 0, 255]));
+        assert_eq!(auto_trim(&image, 0), image);
+        assert_eq!(auto_trim(&RgbaImage::new(0, 0), 0).dimensions(), (0, 0));
+    }
+}
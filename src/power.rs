@@ -0,0 +1,122 @@
+use std::{fs, path::Path};
+
+/// Power supplies as exposed by the kernel, the same information UPower reports over D-Bus.
+const POWER_SUPPLY_DIRECTORY: &str = "/sys/class/power_supply";
+
+/// Where the machine currently draws its power from.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PowerSource {
+    /// Plugged in, or a machine without batteries.
+    Ac,
+    Battery,
+}
+
+/// Current power source: on battery when there is a system battery and no online mains, USB or
+/// wireless supply. Machines without batteries, or whose supplies cannot be read, count as
+/// plugged in.
+pub fn power_source() -> PowerSource {
+    power_source_in(Path::new(POWER_SUPPLY_DIRECTORY))
+}
+
+fn power_source_in(directory: &Path) -> PowerSource {
+    let entries = match fs::read_dir(directory) {
+        Ok(entries) => entries,
+        Err(_) => return PowerSource::Ac,
+    };
+    let read = |supply: &Path, attribute: &str| {
+        fs::read_to_string(supply.join(attribute))
+            .map(|value| value.trim().to_string())
+            .unwrap_or_default()
+    };
+
+    let mut has_battery = false;
+    for entry in entries.flatten() {
+        let supply = entry.path();
+        match read(&supply, "type").as_str() {
+            // Batteries of mice and other peripherals have their scope set to "Device".
+            "Battery" if read(&supply, "scope") != "Device" => has_battery = true,
+            "Mains" | "USB" | "Wireless" if read(&supply, "online") == "1" => {
+                return PowerSource::Ac
+            }
+            _ => {}
+        }
+    }
+    if has_battery {
+        PowerSource::Battery
+    } else {
+        PowerSource::Ac
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDirectory;
+
+    /// Add a supply with `attributes` to a directory shaped like /sys/class/power_supply.
+    fn supply(directory: &TempDirectory, name: &str, attributes: &[(&str, &str)]) {
+        let supply = directory.path().join(name);
+        fs::create_dir(&supply).unwrap();
+        for (attribute, value) in attributes {
+            fs::write(supply.join(attribute), format!("{}\n", value)).unwrap();
+        }
+    }
+
+    fn laptop(ac_online: &str) -> TempDirectory {
+        let directory = TempDirectory::new();
+        supply(
+            &directory,
+            "AC",
+            &[("type", "Mains"), ("online", ac_online)],
+        );
+        supply(
+            &directory,
+            "BAT0",
+            &[("type", "Battery"), ("scope", "System"), ("status", "Full")],
+        );
+        directory
+    }
+
+    #[test]
+    fn laptops_follow_their_ac_adapter() {
+        assert_eq!(power_source_in(laptop("1").path()), PowerSource::Ac);
+        assert_eq!(power_source_in(laptop("0").path()), PowerSource::Battery);
+    }
+
+    #[test]
+    fn usb_chargers_count_as_plugged_in() {
+        let directory = laptop("0");
+        supply(
+            &directory,
+            "ucsi-source-psy-USBC000:001",
+            &[("type", "USB"), ("online", "1")],
+        );
+        assert_eq!(power_source_in(directory.path()), PowerSource::Ac);
+    }
+
+    #[test]
+    fn machines_with_only_a_battery_are_on_battery() {
+        let directory = TempDirectory::new();
+        supply(&directory, "BAT0", &[("type", "Battery")]);
+        assert_eq!(power_source_in(directory.path()), PowerSource::Battery);
+    }
+
+    #[test]
+    fn peripheral_batteries_are_ignored() {
+        let directory = TempDirectory::new();
+        supply(
+            &directory,
+            "hidpp_battery_0",
+            &[("type", "Battery"), ("scope", "Device")],
+        );
+        assert_eq!(power_source_in(directory.path()), PowerSource::Ac);
+    }
+
+    #[test]
+    fn machines_without_supplies_are_plugged_in() {
+        let directory = TempDirectory::new();
+        assert_eq!(power_source_in(directory.path()), PowerSource::Ac);
+        let missing = directory.path().join("power_supply");
+        assert_eq!(power_source_in(&missing), PowerSource::Ac);
+    }
+}
@@ -0,0 +1,131 @@
+use std::{
+    io::{self, stderr, IsTerminal, Write},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// Images with at least this many pixels report how far their encoding got, smaller ones are
+/// encoded before a progress bar would show up.
+pub const PROGRESS_MIN_PIXELS: u64 = 16 * 1024 * 1024;
+
+/// How long a step runs before [`ProgressBar`] draws it, so quick screenshots show nothing.
+const PROGRESS_DELAY: Duration = Duration::from_millis(300);
+
+/// Shortest time between two redraws of [`ProgressBar`].
+const PROGRESS_REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Width of [`ProgressBar`] in characters, without its label and percentage.
+const PROGRESS_BAR_WIDTH: usize = 30;
+
+/// Receives how far the slow steps of a screenshot got, eg: post processing and encoding, to
+/// show it to the user.
+pub trait Progress {
+    /// `done` out of `total` units of the step called `step` are complete. Totals may be
+    /// estimates, which `done` can exceed.
+    fn update(&self, step: &str, done: u64, total: u64);
+
+    /// Every step is complete.
+    fn finish(&self);
+}
+
+#[derive(Default)]
+struct BarState {
+    /// Step being shown and when it started.
+    step: Option<(String, Instant)>,
+    last_drawn: Option<Instant>,
+}
+
+/// Progress bar redrawn in place on stderr, eg: `encode [#########.....................]  30%`.
+pub struct ProgressBar {
+    state: Mutex<BarState>,
+}
+
+impl ProgressBar {
+    /// A progress bar on stderr, or None when stderr is not a terminal.
+    pub fn stderr() -> Option<Self> {
+        stderr().is_terminal().then(|| Self {
+            state: Mutex::new(BarState::default()),
+        })
+    }
+}
+
+impl Progress for ProgressBar {
+    fn update(&self, step: &str, done: u64, total: u64) {
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+        let started = match &state.step {
+            Some((current, started)) if current == step => *started,
+            _ => {
+                state.step = Some((step.to_string(), now));
+                now
+            }
+        };
+        if now - started < PROGRESS_DELAY
+            || state
+                .last_drawn
+                .is_some_and(|drawn| now - drawn < PROGRESS_REDRAW_INTERVAL)
+        {
+            return;
+        }
+        state.last_drawn = Some(now);
+
+        let fraction = if total == 0 {
+            0.0
+        } else {
+            (done as f64 / total as f64).min(1.0)
+        };
+        let filled = (fraction * PROGRESS_BAR_WIDTH as f64) as usize;
+        // Errors writing to the terminal are not worth failing the screenshot over.
+        let _ = write!(
+            stderr(),
+            "\r\x1b[2K{} [{}{}] {:3.0}%",
+            step,
+            "#".repeat(filled),
+            ".".repeat(PROGRESS_BAR_WIDTH - filled),
+            fraction * 100.0
+        );
+    }
+
+    fn finish(&self) {
+        let mut state = self.state.lock().unwrap();
+        if state.last_drawn.take().is_some() {
+            let _ = write!(stderr(), "\r\x1b[2K");
+        }
+        state.step = None;
+    }
+}
+
+/// Writer passing everything on to `inner`, reporting the bytes written so far out of an
+/// expected `total` as the step called `step`.
+pub struct ProgressWriter<'a, W> {
+    inner: W,
+    progress: &'a dyn Progress,
+    step: &'static str,
+    written: u64,
+    total: u64,
+}
+
+impl<'a, W: Write> ProgressWriter<'a, W> {
+    pub fn new(inner: W, progress: &'a dyn Progress, step: &'static str, total: u64) -> Self {
+        Self {
+            inner,
+            progress,
+            step,
+            written: 0,
+            total,
+        }
+    }
+}
+
+impl<W: Write> Write for ProgressWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.written += written as u64;
+        self.progress.update(self.step, self.written, self.total);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
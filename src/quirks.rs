@@ -0,0 +1,136 @@
+use std::{env, fmt};
+
+/// Compositors wayshot knows workarounds for.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Compositor {
+    Sway,
+    Hyprland,
+    River,
+    Wayfire,
+    Niri,
+    /// Any other compositor, or one which could not be told apart.
+    Other,
+}
+
+impl Compositor {
+    /// Tell the compositor apart from the IPC sockets it exports to its clients, falling back
+    /// to XDG_CURRENT_DESKTOP.
+    pub fn detect() -> Self {
+        if env::var_os("HYPRLAND_INSTANCE_SIGNATURE").is_some() {
+            return Compositor::Hyprland;
+        }
+        if env::var_os("SWAYSOCK").is_some() {
+            return Compositor::Sway;
+        }
+        if env::var_os("NIRI_SOCKET").is_some() {
+            return Compositor::Niri;
+        }
+        if env::var_os("WAYFIRE_SOCKET").is_some() {
+            return Compositor::Wayfire;
+        }
+        let desktop = env::var("XDG_CURRENT_DESKTOP").unwrap_or_default();
+        desktop
+            .split(':')
+            .find_map(|name| match name.to_lowercase().as_str() {
+                "hyprland" => Some(Compositor::Hyprland),
+                "sway" => Some(Compositor::Sway),
+                "river" => Some(Compositor::River),
+                "wayfire" => Some(Compositor::Wayfire),
+                "niri" => Some(Compositor::Niri),
+                _ => None,
+            })
+            .unwrap_or(Compositor::Other)
+    }
+}
+
+impl fmt::Display for Compositor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Compositor::Sway => "sway",
+            Compositor::Hyprland => "Hyprland",
+            Compositor::River => "river",
+            Compositor::Wayfire => "Wayfire",
+            Compositor::Niri => "niri",
+            Compositor::Other => "other",
+        })
+    }
+}
+
+/// Names of the quirks, as listed by --quirks list and accepted by [`Quirks::apply`].
+pub const QUIRK_NAMES: [&str; 4] = [
+    "y-invert",
+    "rotate",
+    "software-cursor",
+    "no-copy-with-damage",
+];
+
+/// Workarounds for the behaviour of particular compositors, picked with
+/// [`Quirks::for_compositor`] and overridden with --quirks.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Quirks {
+    /// Flip frames upside down when the compositor flags them as y-inverted. Turned off for
+    /// compositors which flag frames they already flipped.
+    pub y_invert: bool,
+    /// Turn frames of rotated or flipped outputs the way the outputs show them. Turned off for
+    /// compositors which copy frames already turned.
+    pub rotate: bool,
+    /// Draw the cursor from the cursor theme when it is requested, like --cursor=force, as the
+    /// compositor leaves hardware cursor planes out of captures.
+    pub software_cursor: bool,
+    /// Copy frames right away instead of with copy_with_damage for --capture-on-present, for
+    /// compositors which advertise screencopy version 2 but never send the frame.
+    pub no_copy_with_damage: bool,
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Self::for_compositor(Compositor::Other)
+    }
+}
+
+impl Quirks {
+    /// Workarounds known to be needed by `compositor`.
+    pub fn for_compositor(compositor: Compositor) -> Self {
+        Self {
+            y_invert: true,
+            rotate: true,
+            software_cursor: compositor == Compositor::Hyprland,
+            no_copy_with_damage: false,
+        }
+    }
+
+    /// Whether the quirk called `name` is turned on, or None if there is no such quirk.
+    pub fn get(&self, name: &str) -> Option<bool> {
+        match name {
+            "y-invert" => Some(self.y_invert),
+            "rotate" => Some(self.rotate),
+            "software-cursor" => Some(self.software_cursor),
+            "no-copy-with-damage" => Some(self.no_copy_with_damage),
+            _ => None,
+        }
+    }
+
+    /// Turn quirks on or off from a comma separated list of names, each prefixed with "-" to
+    /// turn it off and optionally "+" to turn it on. Returns the first unknown name.
+    pub fn apply(&mut self, overrides: &str) -> Result<(), String> {
+        for item in overrides
+            .split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+        {
+            let (name, enabled) = match item.strip_prefix('-') {
+                Some(name) => (name, false),
+                None => (item.strip_prefix('+').unwrap_or(item), true),
+            };
+            let quirk = match name {
+                "y-invert" => &mut self.y_invert,
+                "rotate" => &mut self.rotate,
+                "software-cursor" => &mut self.software_cursor,
+                "no-copy-with-damage" => &mut self.no_copy_with_damage,
+                _ => return Err(name.to_string()),
+            };
+            *quirk = enabled;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,172 @@
+use std::{error::Error, fs, path::Path};
+
+use regex::Regex;
+
+use crate::{
+    i18n::{tr_args, Message},
+    region::Window,
+};
+
+/// Windows hidden from every screenshot by --redact, see [`parse_redactions`].
+#[derive(Debug, Clone)]
+pub enum Redaction {
+    /// Windows whose app_id, or class for Xwayland windows, matches the regex.
+    AppId(Regex),
+    /// Windows whose title matches the regex.
+    Title(Regex),
+}
+
+impl Redaction {
+    pub fn matches(&self, window: &Window) -> bool {
+        match self {
+            Redaction::AppId(regex) => regex.is_match(&window.app_id),
+            Redaction::Title(regex) => regex.is_match(&window.title),
+        }
+    }
+}
+
+/// Read a redaction list, see [`parse_redactions`].
+pub fn load_redactions(path: &Path) -> Result<Vec<Redaction>, Box<dyn Error>> {
+    let path_arg = || path.display().to_string().into();
+    let text = fs::read_to_string(path).map_err(|e| {
+        tr_args(
+            Message::RedactionsUnreadable,
+            &[("path", path_arg()), ("error", e.to_string().into())],
+        )
+    })?;
+    parse_redactions(&text).map_err(|e| {
+        tr_args(
+            Message::RedactionsInvalid,
+            &[("path", path_arg()), ("error", e.into())],
+        )
+        .into()
+    })
+}
+
+/// Parse a redaction list made of one window regex per line, either "app_id REGEX" or
+/// "title REGEX", eg: "app_id ^org\.keepassxc\.KeePassXC$" or "title Online Banking". Like
+/// the criteria of sway, a regex matches anywhere in the app_id or title unless anchored.
+/// Blank lines and lines starting with # are ignored.
+pub fn parse_redactions(text: &str) -> Result<Vec<Redaction>, String> {
+    let mut redactions = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (key, pattern) = match line.split_once(char::is_whitespace) {
+            Some((key, pattern)) if key == "app_id" || key == "title" => (key, pattern.trim()),
+            _ => {
+                return Err(tr_args(
+                    Message::RedactionLineInvalid,
+                    &[("line", (index + 1).into())],
+                ))
+            }
+        };
+        let regex = Regex::new(pattern).map_err(|e| {
+            tr_args(
+                Message::RedactionRegexInvalid,
+                &[
+                    ("line", (index + 1).into()),
+                    ("error", e.to_string().into()),
+                ],
+            )
+        })?;
+        redactions.push(match key {
+            "app_id" => Redaction::AppId(regex),
+            _ => Redaction::Title(regex),
+        });
+    }
+    Ok(redactions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Region;
+
+    fn window(app_id: &str, title: &str) -> Window {
+        Window {
+            app_id: app_id.to_string(),
+            title: title.to_string(),
+            region: Region::default(),
+        }
+    }
+
+    fn redaction(line: &str) -> Redaction {
+        let mut redactions = parse_redactions(line).unwrap();
+        assert_eq!(redactions.len(), 1);
+        redactions.remove(0)
+    }
+
+    #[test]
+    fn lists_hold_one_redaction_per_line() {
+        let text = "# password managers\n\napp_id ^org\\.keepassxc\\.KeePassXC$\n  title   Online Banking  \n";
+        let redactions = parse_redactions(text).unwrap();
+        assert_eq!(redactions.len(), 2);
+        assert!(
+            matches!(&redactions[0], Redaction::AppId(regex) if regex.as_str() == "^org\\.keepassxc\\.KeePassXC$")
+        );
+        assert!(
+            matches!(&redactions[1], Redaction::Title(regex) if regex.as_str() == "Online Banking")
+        );
+        assert!(parse_redactions("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn app_ids_and_titles_are_matched_separately() {
+        let keepass = window("org.keepassxc.KeePassXC", "Passwords - KeePassXC");
+        assert!(redaction("app_id ^org\\.keepassxc\\.KeePassXC$").matches(&keepass));
+        assert!(!redaction("title ^org\\.keepassxc").matches(&keepass));
+        assert!(redaction("title KeePassXC$").matches(&keepass));
+        assert!(!redaction("app_id KeePassXC.").matches(&keepass));
+    }
+
+    #[test]
+    fn regexes_match_anywhere_unless_anchored() {
+        let bank = window("firefox", "My Online Banking - Firefox");
+        assert!(redaction("title Online Banking").matches(&bank));
+        assert!(redaction("title ^My").matches(&bank));
+        assert!(redaction("title Firefox$").matches(&bank));
+        assert!(!redaction("title ^Online").matches(&bank));
+        assert!(!redaction("title Banking$").matches(&bank));
+        assert!(redaction("title ^My Onl.ne B.nking").matches(&bank));
+        assert!(!redaction("title ^My.Online.Banking$").matches(&bank));
+        assert!(redaction("title (?i)online banking").matches(&bank));
+    }
+
+    #[test]
+    fn non_ascii_titles_match_by_character() {
+        let title = window("org.gnome.Evolution", "Überweisung – Sparkasse 🏦");
+        assert!(redaction("title ^Überweisung").matches(&title));
+        assert!(redaction("title ^.berweisung").matches(&title));
+        assert!(redaction("title Sparkasse .$").matches(&title));
+        assert!(redaction("title \\p{Dash}").matches(&title));
+        assert!(!redaction("title ^Uberweisung").matches(&title));
+    }
+
+    #[test]
+    fn lines_without_a_known_key_or_a_regex_are_rejected() {
+        for text in [
+            "app_id",
+            "title   ",
+            "class firefox",
+            "app-id firefox",
+            "firefox",
+        ] {
+            let error = parse_redactions(text).unwrap_err();
+            assert!(error.contains('1'), "{:?}: {}", text, error);
+        }
+        let error = parse_redactions("# comment\napp_id firefox\ntitle\n").unwrap_err();
+        assert!(error.contains('3'), "{}", error);
+    }
+
+    #[test]
+    fn invalid_regexes_are_rejected() {
+        for text in ["title (Banking", "app_id [a-", "title *"] {
+            assert!(parse_redactions(text).is_err(), "{:?}", text);
+        }
+        let error = parse_redactions("app_id firefox\n\ntitle a{2,1}").unwrap_err();
+        assert!(error.contains('3'), "{}", error);
+    }
+}
@@ -0,0 +1,675 @@
+use std::{
+    error::Error,
+    io::{self, stdin, BufRead},
+    process::Command,
+};
+
+use nix::{
+    errno::Errno,
+    libc::STDIN_FILENO,
+    poll::{poll, PollFd, PollFlags},
+    sys::stat::{fstat, SFlag},
+};
+
+#[cfg(feature = "compositor-ipc")]
+use serde_json::Value;
+
+use crate::{
+    i18n::{tr, tr_args, Message},
+    Region,
+};
+
+/// Source of the region to capture, eg: a geometry given on the command line, an interactive
+/// selection or the focused window reported by the compositor.
+pub trait RegionProvider {
+    /// Short name used on the command line and in logs.
+    fn name(&self) -> &'static str;
+
+    /// Return the region to capture in global logical coordinates.
+    fn region(&self) -> Result<Region, Box<dyn Error>>;
+}
+
+/// Names accepted by [`provider_from_name`].
+pub const PROVIDER_NAMES: [&str; 3] = ["slurp", "stdin", "active-window"];
+
+/// Look up one of the built in providers by the name it is selected with on the command line.
+pub fn provider_from_name(name: &str) -> Option<Box<dyn RegionProvider>> {
+    match name {
+        "slurp" => Some(Box::new(SlurpProvider::default())),
+        "stdin" => Some(Box::new(StdinProvider)),
+        "active-window" => Some(Box::new(ActiveWindowProvider::default())),
+        _ => None,
+    }
+}
+
+/// Parse a geometry of the form "%x,%y %wx%h" or "%x %y %w %h", as printed by slurp. Empty
+/// regions and regions reaching past the `i32` coordinate space are rejected.
+pub fn parse_geometry(g: &str) -> Option<Region> {
+    let tail = g.trim();
+    let x: i32;
+    let y: i32;
+    let width: i32;
+    let height: i32;
+
+    if tail.contains(',') {
+        // this accepts: "%d,%d %dx%d"
+        let (head, tail) = tail.split_once(',')?;
+        x = head.parse::<i32>().ok()?;
+        let (head, tail) = tail.split_once(' ')?;
+        y = head.parse::<i32>().ok()?;
+        let (head, tail) = tail.split_once('x')?;
+        width = head.parse::<i32>().ok()?;
+        height = tail.parse::<i32>().ok()?;
+    } else {
+        // this accepts: "%d %d %d %d"
+        let (head, tail) = tail.split_once(' ')?;
+        x = head.parse::<i32>().ok()?;
+        let (head, tail) = tail.split_once(' ')?;
+        y = head.parse::<i32>().ok()?;
+        let (head, tail) = tail.split_once(' ')?;
+        width = head.parse::<i32>().ok()?;
+        height = tail.parse::<i32>().ok()?;
+    }
+
+    if width <= 0 || height <= 0 {
+        return None;
+    }
+    x.checked_add(width)?;
+    y.checked_add(height)?;
+
+    Some(Region {
+        x,
+        y,
+        width,
+        height,
+    })
+}
+
+/// Parse a grim style geometry: either an output name ("DP-1"), an output name followed by a
+/// region relative to that output ("DP-1:100,100 300x300") or a region in global coordinates.
+pub fn parse_output_geometry(spec: &str) -> Option<(Option<String>, Option<Region>)> {
+    let spec = spec.trim();
+    if let Some((output, geometry)) = spec.split_once(':') {
+        if output.is_empty() {
+            return None;
+        }
+        return Some((Some(output.to_string()), Some(parse_geometry(geometry)?)));
+    }
+    if let Some(region) = parse_geometry(spec) {
+        return Some((None, Some(region)));
+    }
+    if spec.is_empty() || spec.contains(char::is_whitespace) {
+        return None;
+    }
+    Some((Some(spec.to_string()), None))
+}
+
+/// Parse a named crop of --crop, "NAME=GEOMETRY" with a geometry accepted by
+/// [`parse_geometry`]. Names end up in file names, so they can not contain slashes.
+pub fn parse_crop(spec: &str) -> Option<(String, Region)> {
+    let (name, geometry) = spec.split_once('=')?;
+    let name = name.trim();
+    if name.is_empty() || name.contains('/') {
+        return None;
+    }
+    Some((name.to_string(), parse_geometry(geometry)?))
+}
+
+/// A fixed geometry, eg: `wayshot -s "$(slurp)"`.
+pub struct GeometryProvider {
+    pub geometry: String,
+}
+
+impl RegionProvider for GeometryProvider {
+    fn name(&self) -> &'static str {
+        "geometry"
+    }
+
+    fn region(&self) -> Result<Region, Box<dyn Error>> {
+        if self.geometry.is_empty() {
+            return Err(tr(Message::GeometryNotReceived).into());
+        }
+        parse_geometry(&self.geometry).ok_or_else(|| tr(Message::InvalidGeometry).into())
+    }
+}
+
+/// Reads a single geometry line from standard input, eg: `slurp | wayshot -r stdin`.
+pub struct StdinProvider;
+
+impl RegionProvider for StdinProvider {
+    fn name(&self) -> &'static str {
+        "stdin"
+    }
+
+    fn region(&self) -> Result<Region, Box<dyn Error>> {
+        GeometryProvider {
+            geometry: read_stdin_line()?,
+        }
+        .region()
+    }
+}
+
+/// Read the first line of standard input, eg: the geometry of `slurp | wayshot -g -`. Empty at
+/// the end of input.
+pub fn read_stdin_line() -> io::Result<String> {
+    let mut line = String::new();
+    stdin().lock().read_line(&mut line)?;
+    Ok(line)
+}
+
+/// Whether standard input is a pipe, rather than a terminal, a file or /dev/null.
+pub fn stdin_is_pipe() -> bool {
+    fstat(STDIN_FILENO)
+        .map(|stat| SFlag::from_bits_truncate(stat.st_mode) & SFlag::S_IFMT == SFlag::S_IFIFO)
+        .unwrap_or(false)
+}
+
+/// How long [`piped_geometry`] waits for something to be piped, in milliseconds.
+const PIPED_GEOMETRY_TIMEOUT: i32 = 200;
+
+/// Whether standard input has data or reached its end within `timeout` milliseconds, so
+/// reading it does not block on a writer which never writes.
+fn stdin_is_readable(timeout: i32) -> bool {
+    let mut fds = [PollFd::new(STDIN_FILENO, PollFlags::POLLIN)];
+    loop {
+        match poll(&mut fds, timeout) {
+            Ok(ready) => return ready > 0,
+            Err(Errno::EINTR) => continue,
+            Err(e) => {
+                tracing::debug!("Failed to poll standard input: {}", e);
+                return false;
+            }
+        }
+    }
+}
+
+/// Geometry piped to wayshot without -g, eg: `slurp -o | wayshot`. Reads the first line when
+/// standard input is a pipe which becomes readable within [`PIPED_GEOMETRY_TIMEOUT`] and keeps
+/// it if it is a region, optionally prefixed with an output like for -g. Anything else piped
+/// is ignored.
+pub fn piped_geometry() -> Option<(Option<String>, Region)> {
+    if !stdin_is_pipe() {
+        return None;
+    }
+    if !stdin_is_readable(PIPED_GEOMETRY_TIMEOUT) {
+        tracing::debug!("Ignoring standard input, nothing was piped");
+        return None;
+    }
+    match read_stdin_line() {
+        Ok(line) => piped_line_geometry(&line),
+        Err(e) => {
+            tracing::debug!("Failed to read standard input: {}", e);
+            None
+        }
+    }
+}
+
+/// The region of a line piped to wayshot, see [`piped_geometry`].
+fn piped_line_geometry(line: &str) -> Option<(Option<String>, Region)> {
+    match parse_output_geometry(line) {
+        Some((output, Some(region))) => Some((output, region)),
+        _ => {
+            tracing::debug!("Ignoring standard input, not a geometry: {:?}", line.trim());
+            None
+        }
+    }
+}
+
+/// Error for a helper program which could not be started.
+fn spawn_failed(program: &str, error: io::Error) -> Box<dyn Error> {
+    tr_args(
+        Message::CommandSpawnFailed,
+        &[
+            ("program", program.into()),
+            ("error", error.to_string().into()),
+        ],
+    )
+    .into()
+}
+
+/// Spawns slurp and waits for the user to select a region.
+#[derive(Default)]
+pub struct SlurpProvider {
+    /// Extra arguments passed on to slurp.
+    pub args: Vec<String>,
+}
+
+impl RegionProvider for SlurpProvider {
+    fn name(&self) -> &'static str {
+        "slurp"
+    }
+
+    fn region(&self) -> Result<Region, Box<dyn Error>> {
+        let output = Command::new("slurp")
+            .args(&self.args)
+            .output()
+            .map_err(|e| spawn_failed("slurp", e))?;
+        if !output.status.success() {
+            return Err(tr(Message::SelectionCancelled).into());
+        }
+        GeometryProvider {
+            geometry: String::from_utf8(output.stdout)?,
+        }
+        .region()
+    }
+}
+
+/// What part of a window the compositor IPC regions cover.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum Decorations {
+    /// What the compositor reports: sway containers with their title bar and borders, Hyprland
+    /// windows without their borders.
+    #[default]
+    Compositor,
+    /// The window along with its server side title bar and borders.
+    Include,
+    /// Only the xdg-shell window geometry, leaving out server side decorations and client
+    /// side shadows.
+    Exclude,
+}
+
+/// Asks the compositor for the focused window over its IPC, sway and Hyprland are supported.
+#[derive(Default)]
+pub struct ActiveWindowProvider {
+    pub decorations: Decorations,
+}
+
+impl RegionProvider for ActiveWindowProvider {
+    fn name(&self) -> &'static str {
+        "active-window"
+    }
+
+    #[cfg(feature = "compositor-ipc")]
+    fn region(&self) -> Result<Region, Box<dyn Error>> {
+        if std::env::var_os("SWAYSOCK").is_some() {
+            let tree = run_json("swaymsg", &["-t", "get_tree", "-r"])?;
+            find_focused_sway_window(&tree, self.decorations)
+                .ok_or_else(|| tr(Message::NoFocusedWindow).into())
+        } else if std::env::var_os("HYPRLAND_INSTANCE_SIGNATURE").is_some() {
+            let border = hyprland_border_size(self.decorations)?;
+            let window = run_json("hyprctl", &["-j", "activewindow"])?;
+            hyprland_window_region(&window, border)
+                .ok_or_else(|| tr(Message::NoFocusedWindow).into())
+        } else {
+            Err(tr(Message::UnsupportedCompositorIpc).into())
+        }
+    }
+
+    #[cfg(not(feature = "compositor-ipc"))]
+    fn region(&self) -> Result<Region, Box<dyn Error>> {
+        Err(tr_args(
+            Message::FeatureUnavailable,
+            &[("feature", self.name().into())],
+        )
+        .into())
+    }
+}
+
+/// Ask the compositor over its IPC for the visible windows of the application `app_id`, eg:
+/// `org.gnome.Nautilus`. Windows on hidden workspaces are left out, they can not be captured.
+#[cfg(feature = "compositor-ipc")]
+pub fn app_windows(app_id: &str, decorations: Decorations) -> Result<Vec<Region>, Box<dyn Error>> {
+    if std::env::var_os("SWAYSOCK").is_some() {
+        let tree = run_json("swaymsg", &["-t", "get_tree", "-r"])?;
+        let mut windows = Vec::new();
+        find_sway_app_windows(&tree, app_id, decorations, &mut windows);
+        Ok(windows)
+    } else if std::env::var_os("HYPRLAND_INSTANCE_SIGNATURE").is_some() {
+        let border = hyprland_border_size(decorations)?;
+        Ok(hyprland_visible_clients()?
+            .iter()
+            .filter(|client| {
+                client["class"].as_str() == Some(app_id)
+                    || client["initialClass"].as_str() == Some(app_id)
+            })
+            .filter_map(|client| hyprland_window_region(client, border))
+            .collect())
+    } else {
+        Err(tr(Message::UnsupportedCompositorIpc).into())
+    }
+}
+
+#[cfg(not(feature = "compositor-ipc"))]
+pub fn app_windows(
+    _app_id: &str,
+    _decorations: Decorations,
+) -> Result<Vec<Region>, Box<dyn Error>> {
+    Err(tr_args(
+        Message::FeatureUnavailable,
+        &[("feature", "--app-id".into())],
+    )
+    .into())
+}
+
+/// A window the compositor IPC reports as visible.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Window {
+    /// Wayland app_id, or the class of Xwayland windows.
+    pub app_id: String,
+    pub title: String,
+    /// Global logical coordinates.
+    pub region: Region,
+}
+
+/// Ask the compositor over its IPC for every visible window, in stacking order for sway and
+/// in the order Hyprland lists them.
+#[cfg(feature = "compositor-ipc")]
+pub fn visible_windows(decorations: Decorations) -> Result<Vec<Window>, Box<dyn Error>> {
+    if std::env::var_os("SWAYSOCK").is_some() {
+        let tree = run_json("swaymsg", &["-t", "get_tree", "-r"])?;
+        let mut windows = Vec::new();
+        find_sway_windows(&tree, decorations, &mut windows);
+        Ok(windows)
+    } else if std::env::var_os("HYPRLAND_INSTANCE_SIGNATURE").is_some() {
+        let border = hyprland_border_size(decorations)?;
+        Ok(hyprland_visible_clients()?
+            .iter()
+            .filter_map(|client| {
+                Some(Window {
+                    app_id: client["class"].as_str().unwrap_or_default().to_string(),
+                    title: client["title"].as_str().unwrap_or_default().to_string(),
+                    region: hyprland_window_region(client, border)?,
+                })
+            })
+            .collect())
+    } else {
+        Err(tr(Message::UnsupportedCompositorIpc).into())
+    }
+}
+
+#[cfg(not(feature = "compositor-ipc"))]
+pub fn visible_windows(_decorations: Decorations) -> Result<Vec<Window>, Box<dyn Error>> {
+    Err(tr_args(
+        Message::FeatureUnavailable,
+        &[("feature", "--redact".into())],
+    )
+    .into())
+}
+
+#[cfg(feature = "compositor-ipc")]
+fn run_json(program: &str, args: &[&str]) -> Result<Value, Box<dyn Error>> {
+    let output = Command::new(program)
+        .args(args)
+        .output()
+        .map_err(|e| spawn_failed(program, e))?;
+    if !output.status.success() {
+        return Err(tr_args(
+            Message::CommandFailed,
+            &[
+                ("program", program.into()),
+                ("status", output.status.to_string().into()),
+            ],
+        )
+        .into());
+    }
+    Ok(serde_json::from_slice(&output.stdout)?)
+}
+
+/// Walk a sway tree looking for the focused view.
+#[cfg(feature = "compositor-ipc")]
+fn find_focused_sway_window(node: &Value, decorations: Decorations) -> Option<Region> {
+    if node["focused"].as_bool() == Some(true) && node["pid"].is_number() {
+        return sway_window_region(node, decorations);
+    }
+
+    sway_children(node).find_map(|child| find_focused_sway_window(child, decorations))
+}
+
+/// Collect the visible views of `app_id` in a sway tree, in tree order. Xwayland windows are
+/// matched by their class.
+#[cfg(feature = "compositor-ipc")]
+fn find_sway_app_windows(
+    node: &Value,
+    app_id: &str,
+    decorations: Decorations,
+    windows: &mut Vec<Region>,
+) {
+    let matches = node["app_id"].as_str() == Some(app_id)
+        || node["window_properties"]["class"].as_str() == Some(app_id);
+    if matches && node["visible"].as_bool() == Some(true) {
+        windows.extend(sway_window_region(node, decorations));
+    }
+    for child in sway_children(node) {
+        find_sway_app_windows(child, app_id, decorations, windows);
+    }
+}
+
+/// Collect every visible view of a sway tree, in tree order.
+#[cfg(feature = "compositor-ipc")]
+fn find_sway_windows(node: &Value, decorations: Decorations, windows: &mut Vec<Window>) {
+    if node["pid"].is_number() && node["visible"].as_bool() == Some(true) {
+        let app_id = node["app_id"]
+            .as_str()
+            .or_else(|| node["window_properties"]["class"].as_str());
+        if let Some(region) = sway_window_region(node, decorations) {
+            windows.push(Window {
+                app_id: app_id.unwrap_or_default().to_string(),
+                title: node["name"].as_str().unwrap_or_default().to_string(),
+                region,
+            });
+        }
+    }
+    for child in sway_children(node) {
+        find_sway_windows(child, decorations, windows);
+    }
+}
+
+#[cfg(feature = "compositor-ipc")]
+fn sway_children(node: &Value) -> impl Iterator<Item = &Value> {
+    ["nodes", "floating_nodes"]
+        .into_iter()
+        .filter_map(|key| node[key].as_array())
+        .flatten()
+}
+
+/// The container rect includes the title bar and borders, the window rect is the xdg-shell
+/// geometry relative to it.
+#[cfg(feature = "compositor-ipc")]
+fn sway_window_region(node: &Value, decorations: Decorations) -> Option<Region> {
+    let rect = &node["rect"];
+    if decorations != Decorations::Exclude {
+        return json_region(rect);
+    }
+    let window_rect = &node["window_rect"];
+    Some(Region {
+        x: (rect["x"].as_i64()? + window_rect["x"].as_i64()?) as i32,
+        y: (rect["y"].as_i64()? + window_rect["y"].as_i64()?) as i32,
+        width: window_rect["width"].as_i64()? as i32,
+        height: window_rect["height"].as_i64()? as i32,
+    })
+}
+
+#[cfg(feature = "compositor-ipc")]
+fn json_region(rect: &Value) -> Option<Region> {
+    Some(Region {
+        x: rect["x"].as_i64()? as i32,
+        y: rect["y"].as_i64()? as i32,
+        width: rect["width"].as_i64()? as i32,
+        height: rect["height"].as_i64()? as i32,
+    })
+}
+
+/// Hyprland reports the xdg-shell geometry, its borders and shadows are never part of it. The
+/// borders, which Hyprland draws around it, are added back when `border` is not 0.
+#[cfg(feature = "compositor-ipc")]
+fn hyprland_window_region(window: &Value, border: i32) -> Option<Region> {
+    Some(Region {
+        x: window["at"][0].as_i64()? as i32 - border,
+        y: window["at"][1].as_i64()? as i32 - border,
+        width: window["size"][0].as_i64()? as i32 + 2 * border,
+        height: window["size"][1].as_i64()? as i32 + 2 * border,
+    })
+}
+
+/// Mapped clients of Hyprland on the workspaces shown on its monitors, the others can not be
+/// captured.
+#[cfg(feature = "compositor-ipc")]
+fn hyprland_visible_clients() -> Result<Vec<Value>, Box<dyn Error>> {
+    let monitors = run_json("hyprctl", &["-j", "monitors"])?;
+    let active_workspaces: Vec<i64> = monitors
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|monitor| monitor["activeWorkspace"]["id"].as_i64())
+        .collect();
+    let clients = run_json("hyprctl", &["-j", "clients"])?;
+    Ok(clients
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|client| {
+            client["mapped"].as_bool() == Some(true)
+                && client["hidden"].as_bool() != Some(true)
+                && client["workspace"]["id"]
+                    .as_i64()
+                    .is_some_and(|id| active_workspaces.contains(&id))
+        })
+        .cloned()
+        .collect())
+}
+
+/// Width of the borders Hyprland draws around windows when they are included, 0 otherwise.
+#[cfg(feature = "compositor-ipc")]
+fn hyprland_border_size(decorations: Decorations) -> Result<i32, Box<dyn Error>> {
+    if decorations != Decorations::Include {
+        return Ok(0);
+    }
+    let option = run_json("hyprctl", &["-j", "getoption", "general:border_size"])?;
+    Ok(option["int"].as_i64().unwrap_or(0) as i32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn region(x: i32, y: i32, width: i32, height: i32) -> Region {
+        Region {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    #[test]
+    fn geometries_are_parsed_in_both_slurp_forms() {
+        assert_eq!(parse_geometry("10,20 30x40"), Some(region(10, 20, 30, 40)));
+        assert_eq!(parse_geometry("10 20 30 40"), Some(region(10, 20, 30, 40)));
+        assert_eq!(
+            parse_geometry(" 10,20 30x40\n"),
+            Some(region(10, 20, 30, 40))
+        );
+        assert_eq!(
+            parse_geometry("-10,-20 30x40"),
+            Some(region(-10, -20, 30, 40))
+        );
+        assert_eq!(
+            parse_geometry("-10 -20 30 40"),
+            Some(region(-10, -20, 30, 40))
+        );
+    }
+
+    #[test]
+    fn empty_and_overflowing_geometries_are_rejected() {
+        assert_eq!(parse_geometry("0,0 0x40"), None);
+        assert_eq!(parse_geometry("0,0 30x0"), None);
+        assert_eq!(parse_geometry("0,0 -30x40"), None);
+        assert_eq!(parse_geometry("0,0 4294967296x40"), None);
+        assert_eq!(parse_geometry("2147483000,0 1000x40"), None);
+        assert_eq!(parse_geometry("0 2147483000 40 1000"), None);
+        assert_eq!(
+            parse_geometry("2147483000,0 647x1"),
+            Some(region(2147483000, 0, 647, 1))
+        );
+    }
+
+    #[test]
+    fn malformed_geometries_are_rejected() {
+        for spec in [
+            "",
+            "10,20",
+            "10,20 30",
+            "10,20 30x",
+            "10,20x30x40",
+            "10;20 30x40",
+            "a,b cxd",
+            "10 20 30",
+            "10 20 30 40 50",
+            "1.5,2 30x40",
+        ] {
+            assert_eq!(parse_geometry(spec), None, "{:?}", spec);
+        }
+    }
+
+    #[test]
+    fn output_geometries_follow_grim() {
+        assert_eq!(
+            parse_output_geometry("DP-1:100,100 300x300"),
+            Some((Some("DP-1".to_string()), Some(region(100, 100, 300, 300))))
+        );
+        assert_eq!(
+            parse_output_geometry("DP-1:-5,-5 10x10"),
+            Some((Some("DP-1".to_string()), Some(region(-5, -5, 10, 10))))
+        );
+        assert_eq!(
+            parse_output_geometry("DP-1"),
+            Some((Some("DP-1".to_string()), None))
+        );
+        assert_eq!(
+            parse_output_geometry("100,100 300x300\n"),
+            Some((None, Some(region(100, 100, 300, 300))))
+        );
+    }
+
+    #[test]
+    fn malformed_output_geometries_are_rejected() {
+        for spec in [
+            "",
+            " ",
+            ":100,100 300x300",
+            "DP-1:",
+            "DP-1:100,100",
+            "DP-1:100,100 0x300",
+            "DP 1",
+        ] {
+            assert_eq!(parse_output_geometry(spec), None, "{:?}", spec);
+        }
+    }
+
+    #[test]
+    fn crops_need_a_name_and_a_region() {
+        assert_eq!(
+            parse_crop("face=10,20 30x40"),
+            Some(("face".to_string(), region(10, 20, 30, 40)))
+        );
+        assert_eq!(
+            parse_crop(" face =10 20 30 40"),
+            Some(("face".to_string(), region(10, 20, 30, 40)))
+        );
+        for spec in [
+            "10,20 30x40",
+            "=10,20 30x40",
+            "a/b=10,20 30x40",
+            "face=",
+            "face=10,20 0x40",
+            "face=DP-1",
+        ] {
+            assert_eq!(parse_crop(spec), None, "{:?}", spec);
+        }
+    }
+
+    #[test]
+    fn piped_lines_must_hold_a_region() {
+        assert_eq!(
+            piped_line_geometry("10,20 30x40\n"),
+            Some((None, region(10, 20, 30, 40)))
+        );
+        assert_eq!(
+            piped_line_geometry("DP-1:10,20 30x40\n"),
+            Some((Some("DP-1".to_string()), region(10, 20, 30, 40)))
+        );
+        assert_eq!(piped_line_geometry("DP-1\n"), None);
+        assert_eq!(piped_line_geometry("hello world\n"), None);
+        assert_eq!(piped_line_geometry(""), None);
+    }
+}
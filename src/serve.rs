@@ -0,0 +1,466 @@
+use std::{
+    error::Error,
+    io::{self, BufRead, BufReader, ErrorKind, Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Condvar, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use image::RgbaImage;
+
+use crate::{
+    backend::encode,
+    i18n::{tr_args, Message},
+    power::{power_source, PowerSource},
+    EncodingFormat, EncodingOptions, FrameArgs, Pipeline,
+};
+
+/// Port `wayshot serve` listens on by default.
+pub const DEFAULT_PORT: u16 = 8080;
+
+/// Address `wayshot serve` listens on by default, only reachable from this machine.
+pub const DEFAULT_BIND_ADDRESS: &str = "127.0.0.1";
+
+/// Frames captured per second by default, low enough to keep the compositor and encoder idle
+/// most of the time.
+pub const DEFAULT_FPS: u32 = 5;
+
+/// Most frames captured per second.
+pub const MAX_FPS: u32 = 60;
+
+/// How often the power source is checked when frames are throttled on battery.
+const POWER_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Separates the jpeg images of the MJPEG stream.
+const BOUNDARY: &str = "wayshot-frame";
+
+/// Most connections served at once, each has its own thread. Others are turned away with a
+/// 503 response.
+pub const MAX_CONNECTIONS: usize = 16;
+
+/// How long a client may take to send its request, and to take each write of the response,
+/// before it is disconnected, so stalled clients do not hold on to a connection.
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long a client waits for a frame, eg: while captures are paused on battery or keep
+/// failing. /frame.png is answered with a 503 response then, streams are closed.
+const FRAME_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Longest request line or header line read, in bytes.
+const MAX_LINE_BYTES: u64 = 8 * 1024;
+
+/// Most header lines read before a request is rejected.
+const MAX_HEADERS: usize = 64;
+
+#[derive(Clone)]
+struct Frame {
+    /// Number of the capture, counting from 1.
+    sequence: u64,
+    image: Arc<RgbaImage>,
+    jpeg: Arc<Vec<u8>>,
+}
+
+/// State shared by the capture loop and the connections.
+#[derive(Default)]
+struct Latest {
+    frame: Option<Frame>,
+    /// Connections waiting for frames. Nothing is captured while there are none.
+    viewers: usize,
+}
+
+#[derive(Default)]
+struct Shared {
+    latest: Mutex<Latest>,
+    /// Notified when a frame is captured or a viewer connects.
+    changed: Condvar,
+    /// Connections being served, at most [`MAX_CONNECTIONS`].
+    connections: AtomicUsize,
+}
+
+/// Serve the images of `pipeline` over HTTP on `listener`: an MJPEG stream on / for browsers
+/// and a single png on /frame.png. Frames are captured at most `fps` times per second, only
+/// while someone is watching, and encoded with `options`. Only the first image is served when
+/// the pipeline splits captures into several. With `battery_fps`, at most that many frames are
+/// captured per second while running on battery instead, none with 0. At most
+/// [`MAX_CONNECTIONS`] clients are served at once. Never returns.
+pub fn serve(
+    listener: TcpListener,
+    pipeline: &Pipeline,
+    args: &FrameArgs,
+    fps: u32,
+    battery_fps: Option<u32>,
+    options: EncodingOptions,
+) -> Result<(), Box<dyn Error>> {
+    let shared = Arc::new(Shared::default());
+    let accepting = Arc::clone(&shared);
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    tracing::debug!("Failed to accept a connection: {}", e);
+                    continue;
+                }
+            };
+            let connection = match Connection::open(&accepting) {
+                Some(connection) => connection,
+                None => {
+                    tracing::debug!("Turning a connection away, {} are open", MAX_CONNECTIONS);
+                    reject(stream, "503 Service Unavailable");
+                    continue;
+                }
+            };
+            thread::spawn(move || {
+                if let Err(e) = handle_connection(stream, &connection.shared, &options) {
+                    tracing::debug!("Connection closed: {}", e);
+                }
+            });
+        }
+    });
+
+    let interval = Duration::from_secs(1) / fps.clamp(1, MAX_FPS);
+    let mut sequence = 0;
+    let mut on_battery = false;
+    let mut power_checked: Option<Instant> = None;
+    loop {
+        let mut latest = shared.latest.lock().unwrap();
+        while latest.viewers == 0 {
+            latest = shared.changed.wait(latest).unwrap();
+        }
+        drop(latest);
+
+        let mut frame_interval = interval;
+        if let Some(battery_fps) = battery_fps {
+            if power_checked.is_none_or(|checked| checked.elapsed() >= POWER_CHECK_INTERVAL) {
+                power_checked = Some(Instant::now());
+                let now_on_battery = power_source() == PowerSource::Battery;
+                if now_on_battery && !on_battery {
+                    let fps = battery_fps.into();
+                    tracing::info!("{}", tr_args(Message::ServeOnBattery, &[("fps", fps)]));
+                } else if !now_on_battery && on_battery {
+                    tracing::info!("{}", tr_args(Message::ServeOnAc, &[("fps", fps.into())]));
+                }
+                on_battery = now_on_battery;
+            }
+            if on_battery {
+                if battery_fps == 0 {
+                    thread::sleep(POWER_CHECK_INTERVAL);
+                    continue;
+                }
+                frame_interval = Duration::from_secs(1) / battery_fps.min(MAX_FPS);
+            }
+        }
+
+        let started = Instant::now();
+        match capture_frame(pipeline, args, &options) {
+            Ok((image, jpeg)) => {
+                sequence += 1;
+                shared.latest.lock().unwrap().frame = Some(Frame {
+                    sequence,
+                    image: Arc::new(image),
+                    jpeg: Arc::new(jpeg),
+                });
+                shared.changed.notify_all();
+            }
+            Err(e) => tracing::warn!(
+                "{}",
+                tr_args(
+                    Message::ServeCaptureFailed,
+                    &[("error", e.to_string().into())]
+                )
+            ),
+        }
+        thread::sleep(frame_interval.saturating_sub(started.elapsed()));
+    }
+}
+
+fn capture_frame(
+    pipeline: &Pipeline,
+    args: &FrameArgs,
+    options: &EncodingOptions,
+) -> Result<(RgbaImage, Vec<u8>), Box<dyn Error>> {
+    let mut images = Vec::new();
+    pipeline.render(pipeline.capture(args)?, &mut images)?;
+    let image = match images.into_iter().next() {
+        Some(image) => image,
+        None => return Err("Nothing was captured".into()),
+    };
+    let jpeg = encode(&image, EncodingFormat::Jpg, options)?;
+    Ok((image, jpeg))
+}
+
+/// A connection waiting for frames, keeping the capture loop running until it is dropped.
+struct Viewer<'a> {
+    shared: &'a Shared,
+    /// Sequence of the last frame handed out, frames captured before connecting are skipped.
+    seen: u64,
+}
+
+impl<'a> Viewer<'a> {
+    fn join(shared: &'a Shared) -> Self {
+        let mut latest = shared.latest.lock().unwrap();
+        latest.viewers += 1;
+        let seen = latest.frame.as_ref().map_or(0, |frame| frame.sequence);
+        shared.changed.notify_all();
+        Self { shared, seen }
+    }
+
+    /// Wait up to `timeout` for a frame captured after the last one, None if none was.
+    fn next_frame(&mut self, timeout: Duration) -> Option<Frame> {
+        let deadline = Instant::now() + timeout;
+        let mut latest = self.shared.latest.lock().unwrap();
+        loop {
+            match &latest.frame {
+                Some(frame) if frame.sequence > self.seen => {
+                    self.seen = frame.sequence;
+                    return Some(frame.clone());
+                }
+                _ => {
+                    let left = deadline.checked_duration_since(Instant::now())?;
+                    latest = self.shared.changed.wait_timeout(latest, left).unwrap().0;
+                }
+            }
+        }
+    }
+}
+
+impl Drop for Viewer<'_> {
+    fn drop(&mut self) {
+        self.shared.latest.lock().unwrap().viewers -= 1;
+    }
+}
+
+/// A connection counted against [`MAX_CONNECTIONS`] until it is dropped.
+struct Connection {
+    shared: Arc<Shared>,
+}
+
+impl Connection {
+    /// Count a new connection, None if [`MAX_CONNECTIONS`] are already open.
+    fn open(shared: &Arc<Shared>) -> Option<Self> {
+        shared
+            .connections
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |open| {
+                (open < MAX_CONNECTIONS).then_some(open + 1)
+            })
+            .ok()?;
+        Some(Self {
+            shared: Arc::clone(shared),
+        })
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        self.shared.connections.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+/// Answer `stream` with an empty response of `status` and close it.
+fn reject(mut stream: TcpStream, status: &str) {
+    let _ = stream.set_write_timeout(Some(CLIENT_TIMEOUT));
+    let response = format!("HTTP/1.0 {}\r\nContent-Length: 0\r\n\r\n", status);
+    if let Err(e) = stream.write_all(response.as_bytes()) {
+        tracing::debug!("Failed to answer {}: {}", status, e);
+    }
+}
+
+/// Read a line of at most [`MAX_LINE_BYTES`] into `line`, failing with InvalidData on longer
+/// ones. Returns the number of bytes read, 0 at the end of the stream.
+fn read_line(reader: &mut impl BufRead, line: &mut String) -> io::Result<usize> {
+    let read = reader.take(MAX_LINE_BYTES).read_line(line)?;
+    if read as u64 == MAX_LINE_BYTES && !line.ends_with('\n') {
+        return Err(io::Error::new(ErrorKind::InvalidData, "line too long"));
+    }
+    Ok(read)
+}
+
+/// Read the request line and skip the headers, which are not used but which clients expect to
+/// be read before the response.
+fn read_request(reader: &mut impl BufRead) -> io::Result<String> {
+    let mut request = String::new();
+    read_line(reader, &mut request)?;
+    let mut header = String::new();
+    for _ in 0..=MAX_HEADERS {
+        header.clear();
+        if read_line(reader, &mut header)? == 0 || header.trim().is_empty() {
+            return Ok(request);
+        }
+    }
+    Err(io::Error::new(ErrorKind::InvalidData, "too many headers"))
+}
+
+fn handle_connection(
+    mut stream: TcpStream,
+    shared: &Shared,
+    options: &EncodingOptions,
+) -> io::Result<()> {
+    stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+    stream.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let request = match read_request(&mut reader) {
+        Ok(request) => request,
+        Err(e) if e.kind() == ErrorKind::InvalidData => {
+            reject(stream, "400 Bad Request");
+            return Err(e);
+        }
+        Err(e) => return Err(e),
+    };
+
+    let target = request.split_whitespace().nth(1).unwrap_or_default();
+    let path = target.split('?').next().unwrap_or_default();
+    tracing::debug!("{} requested {}", stream.peer_addr()?, path);
+    match path {
+        "/" | "/stream.mjpg" => {
+            write!(
+                stream,
+                "HTTP/1.0 200 OK\r\nContent-Type: multipart/x-mixed-replace; boundary={}\r\n\
+                 Cache-Control: no-cache\r\n\r\n",
+                BOUNDARY
+            )?;
+            let mut viewer = Viewer::join(shared);
+            loop {
+                let frame = match viewer.next_frame(FRAME_TIMEOUT) {
+                    Some(frame) => frame,
+                    None => return Err(io::Error::new(ErrorKind::TimedOut, "no frame captured")),
+                };
+                write!(
+                    stream,
+                    "--{}\r\nContent-Type: image/jpeg\r\nContent-Length: {}\r\n\r\n",
+                    BOUNDARY,
+                    frame.jpeg.len()
+                )?;
+                stream.write_all(&frame.jpeg)?;
+                stream.write_all(b"\r\n")?;
+            }
+        }
+        "/frame.png" => {
+            let frame = match Viewer::join(shared).next_frame(FRAME_TIMEOUT) {
+                Some(frame) => frame,
+                None => {
+                    reject(stream, "503 Service Unavailable");
+                    return Err(io::Error::new(ErrorKind::TimedOut, "no frame captured"));
+                }
+            };
+            match encode(&frame.image, EncodingFormat::Png, options) {
+                Ok(png) => {
+                    write!(
+                        stream,
+                        "HTTP/1.0 200 OK\r\nContent-Type: image/png\r\nContent-Length: {}\r\n\
+                         Cache-Control: no-cache\r\n\r\n",
+                        png.len()
+                    )?;
+                    stream.write_all(&png)
+                }
+                Err(e) => {
+                    let error = e.to_string().into();
+                    tracing::warn!(
+                        "{}",
+                        tr_args(Message::ServeCaptureFailed, &[("error", error)])
+                    );
+                    stream.write_all(
+                        b"HTTP/1.0 500 Internal Server Error\r\nContent-Length: 0\r\n\r\n",
+                    )
+                }
+            }
+        }
+        _ => stream.write_all(b"HTTP/1.0 404 Not Found\r\nContent-Length: 0\r\n\r\n"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn requests_are_read_up_to_the_blank_line() {
+        let mut reader = Cursor::new("GET /frame.png HTTP/1.1\r\nHost: a\r\n\r\nrest".as_bytes());
+        assert_eq!(
+            read_request(&mut reader).unwrap(),
+            "GET /frame.png HTTP/1.1\r\n"
+        );
+        let mut reader = Cursor::new("GET / HTTP/1.0\r\n".as_bytes());
+        assert_eq!(read_request(&mut reader).unwrap(), "GET / HTTP/1.0\r\n");
+    }
+
+    #[test]
+    fn long_lines_are_rejected() {
+        let long = "a".repeat(MAX_LINE_BYTES as usize);
+        let mut reader = Cursor::new(format!("GET /{} HTTP/1.0\r\n\r\n", long).into_bytes());
+        let error = read_request(&mut reader).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+
+        let request = format!("GET / HTTP/1.0\r\nX-Long: {}\r\n\r\n", long);
+        let error = read_request(&mut Cursor::new(request.into_bytes())).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn endless_headers_are_rejected() {
+        let mut request = "GET / HTTP/1.0\r\n".to_string();
+        request.push_str(&"X-Header: 1\r\n".repeat(MAX_HEADERS));
+        let mut allowed = request.clone();
+        allowed.push_str("\r\n");
+        assert!(read_request(&mut Cursor::new(allowed.into_bytes())).is_ok());
+
+        request.push_str("X-Header: 1\r\n\r\n");
+        let error = read_request(&mut Cursor::new(request.into_bytes())).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+    }
+
+    fn frame(sequence: u64) -> Frame {
+        Frame {
+            sequence,
+            image: Arc::new(RgbaImage::new(1, 1)),
+            jpeg: Arc::new(Vec::new()),
+        }
+    }
+
+    #[test]
+    fn viewers_give_up_without_new_frames() {
+        let shared = Shared::default();
+        shared.latest.lock().unwrap().frame = Some(frame(1));
+        let mut viewer = Viewer::join(&shared);
+        let started = Instant::now();
+        assert!(viewer.next_frame(Duration::from_millis(50)).is_none());
+        assert!(started.elapsed() >= Duration::from_millis(50));
+        assert_eq!(shared.latest.lock().unwrap().viewers, 1);
+        drop(viewer);
+        assert_eq!(shared.latest.lock().unwrap().viewers, 0);
+    }
+
+    #[test]
+    fn viewers_get_frames_captured_while_waiting() {
+        let shared = Shared::default();
+        let mut viewer = Viewer::join(&shared);
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                thread::sleep(Duration::from_millis(20));
+                shared.latest.lock().unwrap().frame = Some(frame(1));
+                shared.changed.notify_all();
+            });
+            let frame = viewer.next_frame(Duration::from_secs(10)).unwrap();
+            assert_eq!(frame.sequence, 1);
+        });
+        assert!(viewer.next_frame(Duration::ZERO).is_none());
+    }
+
+    #[test]
+    fn connections_are_limited() {
+        let shared = Arc::new(Shared::default());
+        let mut connections: Vec<Connection> = (0..MAX_CONNECTIONS)
+            .map(|_| Connection::open(&shared).unwrap())
+            .collect();
+        assert!(Connection::open(&shared).is_none());
+        connections.pop();
+        assert!(Connection::open(&shared).is_some());
+        drop(connections);
+        assert_eq!(shared.connections.load(Ordering::Acquire), 0);
+    }
+}
@@ -0,0 +1,85 @@
+/// Bytes processed together. The loops below work on fixed size arrays of this many lanes so
+/// the compiler turns them into SIMD instructions without any platform specific code.
+const LANES: usize = 64;
+
+/// Most frames --stack accepts, which keeps the sums of [`StackMode::Mean`] within a u16.
+pub const MAX_STACK_FRAMES: usize = 64;
+
+/// How the pixels of stacked frames are combined.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum StackMode {
+    /// Average every channel, smoothing out noise such as temporal dithering.
+    Mean,
+    /// Take the median of every channel, which also drops outliers, eg: a single frame of a
+    /// blinking caret.
+    Median,
+}
+
+/// Combine `frames` into `target` channel by channel, `target` being the first frame. All
+/// frames must have the same length and pixel layout.
+pub fn stack_frames(target: &mut [u8], frames: &[&[u8]], mode: StackMode) {
+    debug_assert!(frames.len() < MAX_STACK_FRAMES);
+    debug_assert!(frames.iter().all(|frame| frame.len() == target.len()));
+    if frames.is_empty() {
+        return;
+    }
+    match mode {
+        StackMode::Mean => stack_mean(target, frames),
+        StackMode::Median => stack_median(target, frames),
+    }
+}
+
+fn stack_mean(target: &mut [u8], frames: &[&[u8]]) {
+    let count = frames.len() as u16 + 1;
+    for start in (0..target.len()).step_by(LANES) {
+        let end = usize::min(start + LANES, target.len());
+        let mut sums = [0u16; LANES];
+        for (sum, &value) in sums.iter_mut().zip(&target[start..end]) {
+            *sum = value as u16;
+        }
+        for frame in frames {
+            for (sum, &value) in sums.iter_mut().zip(&frame[start..end]) {
+                *sum += value as u16;
+            }
+        }
+        for (value, sum) in target[start..end].iter_mut().zip(sums) {
+            *value = ((sum + count / 2) / count) as u8;
+        }
+    }
+}
+
+/// Sorts the lanes of every frame with an odd-even transposition sort, which only needs
+/// element wise minimums and maximums.
+fn stack_median(target: &mut [u8], frames: &[&[u8]]) {
+    let count = frames.len() + 1;
+    let mut lanes = vec![[0u8; LANES]; count];
+    for start in (0..target.len()).step_by(LANES) {
+        let end = usize::min(start + LANES, target.len());
+        lanes[0][..end - start].copy_from_slice(&target[start..end]);
+        for (lane, frame) in lanes[1..].iter_mut().zip(frames) {
+            lane[..end - start].copy_from_slice(&frame[start..end]);
+        }
+
+        for pass in 0..count {
+            for index in (pass % 2..count - 1).step_by(2) {
+                let (low, high) = lanes.split_at_mut(index + 1);
+                let (low, high) = (&mut low[index], &mut high[0]);
+                for (a, b) in low.iter_mut().zip(high.iter_mut()) {
+                    let (x, y) = (*a, *b);
+                    *a = x.min(y);
+                    *b = x.max(y);
+                }
+            }
+        }
+
+        let upper = &lanes[count / 2];
+        if count % 2 == 1 {
+            target[start..end].copy_from_slice(&upper[..end - start]);
+        } else {
+            let lower = &lanes[count / 2 - 1];
+            for ((value, &a), &b) in target[start..end].iter_mut().zip(lower).zip(upper) {
+                *value = (a as u16 + b as u16).div_ceil(2) as u8;
+            }
+        }
+    }
+}
@@ -0,0 +1,34 @@
+//! Fixtures shared by the unit tests.
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// Counts the directories created by this test binary, tests run concurrently.
+static DIRECTORIES: AtomicUsize = AtomicUsize::new(0);
+
+/// An empty directory of its own for a test, removed with everything in it when dropped.
+pub struct TempDirectory(PathBuf);
+
+impl TempDirectory {
+    pub fn new() -> Self {
+        let number = DIRECTORIES.fetch_add(1, Ordering::Relaxed);
+        let path = env::temp_dir().join(format!("wayshot-test-{}-{}", process::id(), number));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDirectory {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
@@ -0,0 +1,67 @@
+use std::time::Duration;
+
+use image::{Rgba, RgbaImage};
+
+/// Colors of the vertical bars of the calibration pattern, from left to right: those of SMPTE
+/// color bars, to catch swapped or dropped channels, then grays, to catch gamma or range
+/// conversions.
+pub const TEST_PATTERN_COLORS: [[u8; 3]; 12] = [
+    [255, 255, 255],
+    [255, 255, 0],
+    [0, 255, 255],
+    [0, 255, 0],
+    [255, 0, 255],
+    [255, 0, 0],
+    [0, 0, 255],
+    [0, 0, 0],
+    [64, 64, 64],
+    [128, 128, 128],
+    [192, 192, 192],
+    [16, 16, 16],
+];
+
+/// Time the compositor is given to present the pattern before it is captured.
+pub const TEST_PATTERN_SETTLE: Duration = Duration::from_millis(200);
+
+/// Largest per-channel difference between the pattern and its capture which is not reported as
+/// a mismatch, leaving room for dithering.
+pub const TEST_PATTERN_TOLERANCE: u8 = 2;
+
+/// The calibration pattern shown by `wayshot testpattern`: opaque vertical bars of
+/// [`TEST_PATTERN_COLORS`] spanning `width`.
+pub fn test_pattern(width: u32, height: u32) -> RgbaImage {
+    RgbaImage::from_fn(width, height, |x, _| {
+        let [red, green, blue] = TEST_PATTERN_COLORS[test_pattern_bar(x, width)];
+        Rgba([red, green, blue, 255])
+    })
+}
+
+/// Largest difference of each of the red, green and blue channels between the bars of
+/// [`test_pattern`] and `image`, a capture of it at the same size. Only the middle of each bar is
+/// compared, so that scaling and filtering at their edges do not count.
+pub fn measure_test_pattern(image: &RgbaImage) -> [u8; 3] {
+    let (width, height) = image.dimensions();
+    let bars = TEST_PATTERN_COLORS.len() as u32;
+    let mut deltas = [0u8; 3];
+    for (bar, expected) in TEST_PATTERN_COLORS.iter().enumerate() {
+        let start = (bar as u32 * width).div_ceil(bars);
+        let end = ((bar as u32 + 1) * width).div_ceil(bars);
+        let margin = (end - start) / 4;
+        for y in height / 4..height - height / 4 {
+            for x in start + margin..end - margin {
+                let pixel = image.get_pixel(x, y);
+                for channel in 0..3 {
+                    deltas[channel] =
+                        deltas[channel].max(pixel[channel].abs_diff(expected[channel]));
+                }
+            }
+        }
+    }
+    deltas
+}
+
+/// Index of the bar column `x` of a `width` wide pattern falls in.
+fn test_pattern_bar(x: u32, width: u32) -> usize {
+    let bars = TEST_PATTERN_COLORS.len() as u64;
+    (x as u64 * bars / width as u64) as usize
+}
@@ -0,0 +1,209 @@
+use wayland_client::protocol::wl_shm;
+
+use crate::backend::RawCapture;
+
+/// Luminance in nits of SDR white, which HDR frames are tone mapped relative to, from ITU-R
+/// BT.2408.
+pub const SDR_WHITE_NITS: f32 = 203.0;
+
+/// Entries of the table encoding linear values back to sRGB.
+const ENCODE_STEPS: usize = 4096;
+
+/// How --tone-map squeezes the highlights of HDR frames into the SDR range.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum ToneMapOperator {
+    /// Cut everything brighter than SDR white, keeping darker tones exactly.
+    Clip,
+    /// Extended Reinhard on luminance, bringing the brightest pixel of the frame down to SDR
+    /// white while keeping hues.
+    #[default]
+    Reinhard,
+    /// Filmic curve of Uncharted 2 by John Hable, applied per channel, with more contrast than
+    /// Reinhard and highlights going to white.
+    Hable,
+}
+
+/// Outputs whose frames hold HDR content, encoded with the PQ transfer function in BT.2020
+/// primaries. wlr-screencopy does not tell, so it is up to the user.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HdrOutputs {
+    /// Outputs whose frames use a 10 bit format, when other outputs of the capture use 8 bit
+    /// ones.
+    Auto,
+    /// Outputs with these names.
+    Named(Vec<String>),
+}
+
+/// Tone mapping of the HDR frames of a capture for --hdr-outputs, so that they match the
+/// brightness of SDR outputs once composited.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ToneMapping {
+    pub outputs: HdrOutputs,
+    pub operator: ToneMapOperator,
+}
+
+impl ToneMapping {
+    /// Tone map the frames of `captures` showing HDR content in place, leaving them in their
+    /// format, so that they are converted, streamed or composited like SDR frames.
+    pub fn apply(&self, captures: &mut [RawCapture]) {
+        let has_8_bit = captures.iter().any(|capture| {
+            channel_layout(capture.frame_format.format).is_some_and(|(bits, _)| bits == 8)
+        });
+        for capture in captures {
+            let hdr = match &self.outputs {
+                HdrOutputs::Auto => {
+                    has_8_bit
+                        && channel_layout(capture.frame_format.format)
+                            .is_some_and(|(bits, _)| bits == 10)
+                }
+                HdrOutputs::Named(names) => names.contains(&capture.name),
+            };
+            if hdr {
+                tone_map_frame(capture, self.operator);
+            }
+        }
+    }
+}
+
+/// Bits per color channel of `format` and the shifts of its red, green and blue channels
+/// within a little endian pixel, None if it can not be tone mapped.
+fn channel_layout(format: wl_shm::Format) -> Option<(u32, [u32; 3])> {
+    match format {
+        wl_shm::Format::Xbgr8888 | wl_shm::Format::Abgr8888 => Some((8, [0, 8, 16])),
+        wl_shm::Format::Xrgb8888 | wl_shm::Format::Argb8888 => Some((8, [16, 8, 0])),
+        wl_shm::Format::Xbgr2101010 | wl_shm::Format::Abgr2101010 => Some((10, [0, 10, 20])),
+        wl_shm::Format::Xrgb2101010 | wl_shm::Format::Argb2101010 => Some((10, [20, 10, 0])),
+        _ => None,
+    }
+}
+
+/// Replace the PQ encoded BT.2020 pixels of the frame of `capture` with sRGB ones, mapping
+/// SDR white to white and compressing brighter tones with `operator`. Alpha is kept.
+pub fn tone_map_frame(capture: &mut RawCapture, operator: ToneMapOperator) {
+    let format = capture.frame_format;
+    let (bits, shifts) = match channel_layout(format.format) {
+        Some(layout) => layout,
+        None => {
+            tracing::warn!("Can not tone map {:?} frames", format.format);
+            return;
+        }
+    };
+    let mask = (1u32 << bits) - 1;
+    // Linear light relative to SDR white of every code value, then the code value of every
+    // step of linear light.
+    let decode: Vec<f32> = (0..=mask)
+        .map(|code| pq_to_nits(code as f32 / mask as f32) / SDR_WHITE_NITS)
+        .collect();
+    let encode: Vec<u32> = (0..ENCODE_STEPS)
+        .map(|step| {
+            let linear = step as f32 / (ENCODE_STEPS - 1) as f32;
+            (srgb_encode(linear) * mask as f32).round() as u32
+        })
+        .collect();
+
+    let (width, height, stride) = (
+        format.width as usize,
+        format.height as usize,
+        format.stride as usize,
+    );
+    let rows = |frame: &[u8]| -> Vec<(usize, usize)> {
+        (0..height)
+            .map(|y| (y * stride, y * stride + width * 4))
+            .filter(|&(_, end)| end <= frame.len())
+            .collect()
+    };
+    let read = |pixel: &[u8]| {
+        let value = u32::from_le_bytes([pixel[0], pixel[1], pixel[2], pixel[3]]);
+        let linear = shifts.map(|shift| decode[((value >> shift) & mask) as usize]);
+        (value, bt2020_to_bt709(linear))
+    };
+
+    // Reinhard and Hable map the brightest pixel of the frame to white.
+    let mut peak = 1.0f32;
+    for (start, end) in rows(&capture.frame_mmap) {
+        for pixel in capture.frame_mmap[start..end].chunks_exact(4) {
+            peak = peak.max(luminance(read(pixel).1));
+        }
+    }
+    tracing::debug!(
+        "Tone mapping the HDR frame of {} with {:?}, peaking at {} nits",
+        capture.name,
+        operator,
+        peak * SDR_WHITE_NITS
+    );
+
+    let channel_bits = shifts.iter().fold(0, |bits, shift| bits | mask << shift);
+    for (start, end) in rows(&capture.frame_mmap) {
+        for pixel in capture.frame_mmap[start..end].chunks_exact_mut(4) {
+            let (value, linear) = read(pixel);
+            let mapped = tone_map(linear, peak, operator);
+            let mut value = value & !channel_bits;
+            for (channel, shift) in mapped.iter().zip(shifts) {
+                let step = (channel.clamp(0.0, 1.0) * (ENCODE_STEPS - 1) as f32).round();
+                value |= encode[step as usize] << shift;
+            }
+            pixel.copy_from_slice(&value.to_le_bytes());
+        }
+    }
+}
+
+/// Tone map linear BT.709 `rgb`, relative to SDR white, into the 0 to 1 range. `peak` is the
+/// highest luminance of the frame, at least 1.
+fn tone_map(rgb: [f32; 3], peak: f32, operator: ToneMapOperator) -> [f32; 3] {
+    match operator {
+        ToneMapOperator::Clip => rgb,
+        ToneMapOperator::Reinhard => {
+            let y = luminance(rgb);
+            if y <= 0.0 {
+                return [0.0; 3];
+            }
+            let mapped = y * (1.0 + y / (peak * peak)) / (1.0 + y);
+            rgb.map(|channel| channel * mapped / y)
+        }
+        ToneMapOperator::Hable => {
+            let curve = |x: f32| {
+                let (a, b, c, d, e, f) = (0.15, 0.50, 0.10, 0.20, 0.02, 0.30);
+                ((x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f)) - e / f
+            };
+            // Exposure bias of the original, so that mid tones keep their brightness.
+            let white = curve(2.0 * peak);
+            rgb.map(|channel| curve(2.0 * channel) / white)
+        }
+    }
+}
+
+/// Absolute luminance in nits of a PQ (SMPTE ST 2084) encoded value between 0 and 1.
+fn pq_to_nits(value: f32) -> f32 {
+    const M1: f32 = 2610.0 / 16384.0;
+    const M2: f32 = 2523.0 / 4096.0 * 128.0;
+    const C1: f32 = 3424.0 / 4096.0;
+    const C2: f32 = 2413.0 / 4096.0 * 32.0;
+    const C3: f32 = 2392.0 / 4096.0 * 32.0;
+    let power = value.powf(1.0 / M2);
+    let linear = ((power - C1).max(0.0) / (C2 - C3 * power)).powf(1.0 / M1);
+    linear * 10000.0
+}
+
+/// Linear light in BT.2020 primaries in BT.709 primaries, from ITU-R BT.2087. Colors outside
+/// of BT.709 come out with negative channels, which are cut.
+fn bt2020_to_bt709([r, g, b]: [f32; 3]) -> [f32; 3] {
+    [
+        1.6605 * r - 0.5876 * g - 0.0728 * b,
+        -0.1246 * r + 1.1329 * g - 0.0083 * b,
+        -0.0182 * r - 0.1006 * g + 1.1187 * b,
+    ]
+    .map(|channel| channel.max(0.0))
+}
+
+fn luminance([r, g, b]: [f32; 3]) -> f32 {
+    0.2126 * r + 0.7152 * g + 0.0722 * b
+}
+
+/// The sRGB transfer function, from linear light between 0 and 1.
+fn srgb_encode(linear: f32) -> f32 {
+    if linear <= 0.0031308 {
+        linear * 12.92
+    } else {
+        1.055 * linear.powf(1.0 / 2.4) - 0.055
+    }
+}
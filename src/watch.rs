@@ -0,0 +1,425 @@
+use std::{collections::HashMap, error::Error, fs, mem, path::Path, thread, time::Duration};
+
+use serde::Deserialize;
+
+use wayland_client::{
+    backend::ObjectId,
+    event_created_child,
+    protocol::{wl_output, wl_registry},
+    Connection, Dispatch, Proxy, QueueHandle,
+};
+use wayland_protocols_wlr::foreign_toplevel::v1::client::{
+    zwlr_foreign_toplevel_handle_v1::{self, ZwlrForeignToplevelHandleV1},
+    zwlr_foreign_toplevel_manager_v1::{self, ZwlrForeignToplevelManagerV1},
+};
+
+use crate::{
+    dispatch_error,
+    i18n::{tr, tr_args, Message},
+};
+
+/// Time given to a newly connected output to show its first frames, and to a window gone
+/// fullscreen to redraw at its new size, before they are captured.
+pub const WATCH_SETTLE_DURATION: Duration = Duration::from_millis(500);
+
+/// Event of the compositor that `wayshot watch` captures the screen on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Rule {
+    /// An output with this name connects, any output if None. Captures that output.
+    OutputConnected(Option<String>),
+    /// A window with this app_id becomes fullscreen, any window if None. Captures the output
+    /// the window is on.
+    Fullscreen(Option<String>),
+}
+
+impl Rule {
+    fn matches_output(&self, name: &str) -> bool {
+        match self {
+            Rule::OutputConnected(pattern) => pattern.as_deref().is_none_or(|p| p == name),
+            _ => false,
+        }
+    }
+
+    fn matches_fullscreen(&self, app_id: &str) -> bool {
+        match self {
+            Rule::Fullscreen(pattern) => pattern.as_deref().is_none_or(|p| p == app_id),
+            _ => false,
+        }
+    }
+}
+
+/// A rules file as written in TOML, see [`parse_rules`].
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RulesFile {
+    #[serde(default)]
+    rule: Vec<RuleEntry>,
+}
+
+/// One `[[rule]]` table, the event it waits for is named by its `on` key.
+#[derive(Deserialize)]
+#[serde(tag = "on", rename_all = "kebab-case", deny_unknown_fields)]
+enum RuleEntry {
+    OutputConnected { output: Option<String> },
+    Fullscreen { app_id: Option<String> },
+}
+
+impl From<RuleEntry> for Rule {
+    fn from(entry: RuleEntry) -> Self {
+        match entry {
+            RuleEntry::OutputConnected { output } => Rule::OutputConnected(output),
+            RuleEntry::Fullscreen { app_id } => Rule::Fullscreen(app_id),
+        }
+    }
+}
+
+/// Read a rules file, see [`parse_rules`].
+pub fn load_rules(path: &Path) -> Result<Vec<Rule>, Box<dyn Error>> {
+    let path_arg = || path.display().to_string().into();
+    let text = fs::read_to_string(path).map_err(|e| {
+        tr_args(
+            Message::RulesUnreadable,
+            &[("path", path_arg()), ("error", e.to_string().into())],
+        )
+    })?;
+    parse_rules(&text).map_err(|e| {
+        tr_args(
+            Message::RulesInvalid,
+            &[("path", path_arg()), ("error", e.into())],
+        )
+        .into()
+    })
+}
+
+/// Parse TOML rules made of `[[rule]]` tables, each with the event it waits for as `on`:
+/// "output-connected" with an optional `output` name, or "fullscreen" with an optional
+/// `app_id`. Leaving out the name or app_id matches any.
+pub fn parse_rules(text: &str) -> Result<Vec<Rule>, String> {
+    let file: RulesFile = toml::from_str(text).map_err(|e| e.to_string())?;
+    Ok(file.rule.into_iter().map(Rule::from).collect())
+}
+
+struct WatchedOutput {
+    wl_output: wl_output::WlOutput,
+    /// Name of the global, to tell which output a global_remove event is about.
+    global: u32,
+    name: String,
+    /// Whether its first done event arrived, after which it is known to be connected.
+    announced: bool,
+}
+
+#[derive(Default)]
+struct Toplevel {
+    app_id: String,
+    fullscreen: bool,
+    /// Fullscreen state sent since the last done event.
+    pending_fullscreen: bool,
+    outputs: Vec<wl_output::WlOutput>,
+}
+
+struct Watcher {
+    rules: Vec<Rule>,
+    outputs: Vec<WatchedOutput>,
+    toplevel_manager: Option<ZwlrForeignToplevelManagerV1>,
+    toplevels: HashMap<ObjectId, Toplevel>,
+    /// Whether the outputs and windows already there when watching started are known, only
+    /// events after that match rules.
+    started: bool,
+    /// Output to capture, None for all of them, and what triggered it.
+    captures: Vec<(Option<String>, String)>,
+}
+
+/// Watch the compositor of WAYLAND_DISPLAY, calling `capture` with the output to capture, None
+/// for every output, whenever one of `rules` matches. Outputs and windows already there when
+/// watching starts do not match. Failed captures are logged, watching only stops once the
+/// connection to the compositor is lost.
+pub fn watch(
+    rules: Vec<Rule>,
+    mut capture: impl FnMut(Option<&str>) -> Result<(), Box<dyn Error>>,
+) -> Result<(), Box<dyn Error>> {
+    let conn = Connection::connect_to_env()?;
+    let mut event_queue = conn.new_event_queue();
+    let _registry = conn.display().get_registry(&event_queue.handle(), ());
+    let mut watcher = Watcher {
+        rules,
+        outputs: Vec::new(),
+        toplevel_manager: None,
+        toplevels: HashMap::new(),
+        started: false,
+        captures: Vec::new(),
+    };
+    // The first roundtrip binds the globals, the second receives the outputs and windows.
+    event_queue
+        .roundtrip(&mut watcher)
+        .map_err(dispatch_error)?;
+    event_queue
+        .roundtrip(&mut watcher)
+        .map_err(dispatch_error)?;
+    if watcher.toplevel_manager.is_none()
+        && watcher
+            .rules
+            .iter()
+            .any(|rule| matches!(rule, Rule::Fullscreen(_)))
+    {
+        tracing::warn!("{}", tr(Message::ToplevelsUnavailable));
+    }
+    watcher.started = true;
+
+    loop {
+        event_queue
+            .blocking_dispatch(&mut watcher)
+            .map_err(dispatch_error)?;
+        for (output, reason) in mem::take(&mut watcher.captures) {
+            tracing::info!(
+                "{}",
+                tr_args(Message::WatchTriggered, &[("reason", reason.into())])
+            );
+            thread::sleep(WATCH_SETTLE_DURATION);
+            if let Err(e) = capture(output.as_deref()) {
+                let error = e.to_string().into();
+                tracing::warn!(
+                    "{}",
+                    tr_args(Message::WatchCaptureFailed, &[("error", error)])
+                );
+            }
+        }
+    }
+}
+
+impl Dispatch<wl_registry::WlRegistry, ()> for Watcher {
+    fn event(
+        state: &mut Self,
+        registry: &wl_registry::WlRegistry,
+        event: wl_registry::Event,
+        _: &(),
+        _: &Connection,
+        qh: &QueueHandle<Self>,
+    ) {
+        match event {
+            wl_registry::Event::Global {
+                name,
+                interface,
+                version,
+            } => match interface.as_str() {
+                // Names come with version 4, older outputs can not be told apart.
+                "wl_output" if version >= 4 => state.outputs.push(WatchedOutput {
+                    wl_output: registry.bind(name, 4, qh, ()),
+                    global: name,
+                    name: String::new(),
+                    announced: false,
+                }),
+                "zwlr_foreign_toplevel_manager_v1" => {
+                    state.toplevel_manager = Some(registry.bind(name, version.min(3), qh, ()));
+                }
+                _ => {}
+            },
+            wl_registry::Event::GlobalRemove { name } => {
+                if let Some(index) = state.outputs.iter().position(|o| o.global == name) {
+                    let output = state.outputs.remove(index);
+                    tracing::debug!("Output {} disconnected", output.name);
+                    output.wl_output.release();
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<wl_output::WlOutput, ()> for Watcher {
+    fn event(
+        state: &mut Self,
+        wl_output: &wl_output::WlOutput,
+        event: wl_output::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        let output = match state
+            .outputs
+            .iter_mut()
+            .find(|output| output.wl_output == *wl_output)
+        {
+            Some(output) => output,
+            None => return,
+        };
+        match event {
+            wl_output::Event::Name { name } => output.name = name,
+            wl_output::Event::Done if !output.announced => {
+                output.announced = true;
+                tracing::debug!("Output {} connected", output.name);
+                if state.started
+                    && state
+                        .rules
+                        .iter()
+                        .any(|rule| rule.matches_output(&output.name))
+                {
+                    let reason = format!("output-connected {}", output.name);
+                    state.captures.push((Some(output.name.clone()), reason));
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<ZwlrForeignToplevelManagerV1, ()> for Watcher {
+    fn event(
+        state: &mut Self,
+        _: &ZwlrForeignToplevelManagerV1,
+        event: zwlr_foreign_toplevel_manager_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let zwlr_foreign_toplevel_manager_v1::Event::Toplevel { toplevel } = event {
+            state.toplevels.insert(toplevel.id(), Toplevel::default());
+        }
+    }
+
+    event_created_child!(Watcher, ZwlrForeignToplevelManagerV1, [
+        zwlr_foreign_toplevel_manager_v1::EVT_TOPLEVEL_OPCODE => (ZwlrForeignToplevelHandleV1, ()),
+    ]);
+}
+
+impl Dispatch<ZwlrForeignToplevelHandleV1, ()> for Watcher {
+    fn event(
+        state: &mut Self,
+        handle: &ZwlrForeignToplevelHandleV1,
+        event: zwlr_foreign_toplevel_handle_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        let toplevel = match state.toplevels.get_mut(&handle.id()) {
+            Some(toplevel) => toplevel,
+            None => return,
+        };
+        match event {
+            zwlr_foreign_toplevel_handle_v1::Event::AppId { app_id } => toplevel.app_id = app_id,
+            zwlr_foreign_toplevel_handle_v1::Event::OutputEnter { output } => {
+                toplevel.outputs.push(output)
+            }
+            zwlr_foreign_toplevel_handle_v1::Event::OutputLeave { output } => {
+                toplevel.outputs.retain(|entered| *entered != output)
+            }
+            zwlr_foreign_toplevel_handle_v1::Event::State { state: states } => {
+                // An array of native endian u32.
+                toplevel.pending_fullscreen = states.chunks_exact(4).any(|value| {
+                    u32::from_ne_bytes([value[0], value[1], value[2], value[3]])
+                        == zwlr_foreign_toplevel_handle_v1::State::Fullscreen as u32
+                });
+            }
+            zwlr_foreign_toplevel_handle_v1::Event::Done => {
+                let entered = toplevel.pending_fullscreen && !toplevel.fullscreen;
+                toplevel.fullscreen = toplevel.pending_fullscreen;
+                if entered
+                    && state.started
+                    && state
+                        .rules
+                        .iter()
+                        .any(|rule| rule.matches_fullscreen(&toplevel.app_id))
+                {
+                    // The window may span several outputs, its first one is captured.
+                    let output = toplevel.outputs.first().and_then(|entered| {
+                        state
+                            .outputs
+                            .iter()
+                            .find(|output| output.wl_output == *entered)
+                            .map(|output| output.name.clone())
+                    });
+                    let reason = format!("fullscreen {}", toplevel.app_id);
+                    state.captures.push((output, reason));
+                }
+            }
+            zwlr_foreign_toplevel_handle_v1::Event::Closed => {
+                state.toplevels.remove(&handle.id());
+                handle.destroy();
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rules_are_read_from_rule_tables() {
+        let text = r#"
+# Capture a projector as soon as it is plugged in
+[[rule]]
+on = "output-connected"
+output = "HDMI-A-1"
+
+[[rule]]
+on = "fullscreen"
+app_id = "firefox"
+
+[[rule]]
+on = "output-connected"
+
+[[rule]]
+on = "fullscreen"
+"#;
+        assert_eq!(
+            parse_rules(text).unwrap(),
+            vec![
+                Rule::OutputConnected(Some("HDMI-A-1".to_string())),
+                Rule::Fullscreen(Some("firefox".to_string())),
+                Rule::OutputConnected(None),
+                Rule::Fullscreen(None),
+            ]
+        );
+        assert_eq!(parse_rules("").unwrap(), Vec::new());
+        assert_eq!(parse_rules("# nothing yet\n").unwrap(), Vec::new());
+    }
+
+    #[test]
+    fn rules_only_match_their_own_event() {
+        let projector = Rule::OutputConnected(Some("HDMI-A-1".to_string()));
+        assert!(projector.matches_output("HDMI-A-1"));
+        assert!(!projector.matches_output("DP-1"));
+        assert!(!projector.matches_fullscreen("HDMI-A-1"));
+        assert!(Rule::OutputConnected(None).matches_output("DP-1"));
+
+        let firefox = Rule::Fullscreen(Some("firefox".to_string()));
+        assert!(firefox.matches_fullscreen("firefox"));
+        assert!(!firefox.matches_fullscreen("org.mozilla.firefox"));
+        assert!(!firefox.matches_output("firefox"));
+        assert!(Rule::Fullscreen(None).matches_fullscreen("mpv"));
+    }
+
+    #[test]
+    fn malformed_rules_are_rejected() {
+        for text in [
+            // Not TOML.
+            "output-connected HDMI-A-1",
+            // No event.
+            "[[rule]]\noutput = \"HDMI-A-1\"",
+            // Unknown event.
+            "[[rule]]\non = \"output-disconnected\"",
+            // Key of the other event.
+            "[[rule]]\non = \"fullscreen\"\noutput = \"HDMI-A-1\"",
+            // Misspelt key.
+            "[[rule]]\non = \"fullscreen\"\napp-id = \"firefox\"",
+            // Wrong type.
+            "[[rule]]\non = \"output-connected\"\noutput = 1",
+            // Unknown table.
+            "[[rules]]\non = \"fullscreen\"",
+        ] {
+            assert!(parse_rules(text).is_err(), "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn errors_point_at_the_line() {
+        let text = "[[rule]]\non = \"fullscreen\"\n\n[[rule]]\non = \"bell\"\n";
+        let error = parse_rules(text).unwrap_err();
+        assert!(
+            error.contains("line 4") || error.contains("line 5"),
+            "{}",
+            error
+        );
+    }
+}
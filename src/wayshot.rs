@@ -1,87 +1,188 @@
 use std::{
-    cmp, env,
+    cmp,
+    collections::HashMap,
+    env,
     error::Error,
-    fs::File,
-    io::{stdout, BufWriter},
-    os::unix::prelude::FromRawFd,
+    ffi::{OsStr, OsString},
+    fmt,
+    io::{stdout, BufWriter, Write},
+    net::TcpListener,
+    num::NonZeroUsize,
+    os::unix::prelude::OsStrExt,
+    path::{Path, PathBuf},
     process::exit,
-    time::{SystemTime, UNIX_EPOCH},
+    rc::Rc,
+    thread,
+    time::{Duration, Instant},
 };
 
-use crate::backend::{create_shm_fd, FrameFormat, FrameState};
-use crate::convert::create_converter;
+use ::clap::{ArgMatches, ErrorKind};
+use chrono::{
+    format::{Item, StrftimeItems},
+    Local,
+};
+
+use crate::backend::FrameState;
+use crate::convert::{
+    create_converter, frame_transform, parse_shm_format, pixel_format, CONVERTIBLE_FORMATS,
+};
+use crate::i18n::{tr, tr_args, Message};
+use crate::options::{
+    invalid_encoding, parse_also_save, parse_analyses, parse_byte_size, parse_choice,
+    parse_encoding, parse_hdr_outputs, parse_output_formats, parse_size, parse_value,
+    valid_arguments, CHECKSUMS, COLLISIONS, COLOR_VISIONS, CURSOR_MODES, HOOK_FAILURES,
+    JPEG_SUBSAMPLINGS, PNG_FILTERS, REDACT_STYLES, STACK_MODES, TIFF_COMPRESSIONS,
+    TONE_MAP_OPERATORS, ZOOM_FILTERS,
+};
+use crate::worker::{capture_frame, FrameMemory, OutputFrame};
 
+pub use analyze::{
+    analysis_fields, analyze, average_color, channel_stats, choose_encoding, dominant_colors,
+    hex_color, uniform_color, Analysis, ChannelStats, ContentStats, DOMINANT_COLORS,
+};
+#[cfg(feature = "annotations")]
+pub use annotate::{draw_annotations, parse_annotations, Annotation, Shape};
+#[cfg(feature = "annotations")]
+pub use annotator::{blur, draw_rect, draw_text, Annotator, DrawCallback};
+#[cfg(feature = "archive")]
+pub use archive::{ArchiveFrame, ArchiveReader, ArchiveWriter};
+pub use backend::{
+    encode, estimate_size, write_to_disk_with_options, write_to_file, write_to_file_with_options,
+    EncodingFormat, EncodingOptions, FrameFormat, JpegSubsampling, PngFilter, RawCapture,
+    TiffCompression, ICO_SIZES, MAPPED_WRITE_THRESHOLD,
+};
+pub use bench::{bench_io, WriteMethod, WriteTiming, DEFAULT_BENCH_RUNS, DEFAULT_BENCH_SIZE};
+pub use bug_report::{
+    create_bug_report, write_provenance, FrameProvenance, Provenance, BUG_REPORT_LOG,
+    BUG_REPORT_PROVENANCE,
+};
+pub use cache::{
+    load_output_cache, output_cache_path, save_output_cache, CachedOutput, OutputCache,
+};
+pub use capabilities::{Capabilities, FEATURES};
+pub use capture_commands::{CaptureCommand, HookFailure, DEFAULT_HOOK_TIMEOUT};
+pub use checksum::{write_checksum, Checksum};
+pub use cursor::{load_cursor, CursorImage};
+pub use deadline::{Deadline, Stage};
+pub use dnd::{DoNotDisturb, NotificationDaemon, DND_SETTLE_DURATION};
+pub use dzi::{dzi_max_level, DziSink, DZI_OVERLAP, DZI_TILE_SIZE};
+pub use headless::{start_headless, Headless, DEFAULT_CI_COMPOSITOR, DEFAULT_CI_TIMEOUT};
+#[cfg(feature = "scripting")]
+pub use hooks::{CaptureInfo, Hooks};
+use image::ImageBuffer;
 pub use image::RgbaImage;
-pub use backend::{write_to_file, EncodingFormat};
-use image::{imageops::resize, GenericImage, ImageBuffer};
+pub use layout::{load_layout, parse_layout, Layout, LayoutEntry};
+pub use locker::{capture_for_locker, LockerArgs};
 use memmap2::MmapMut;
-use nix::unistd;
+pub use output::OutputMode;
+pub use palette::{quantize, IndexedImage, MAX_PALETTE_COLORS};
+#[cfg(feature = "annotations")]
+pub use pipeline::Annotations;
+pub use pipeline::{
+    available_space, degraded_encodings, AdjustTones, AnalyzeSink, AutoTrim, CaptureBackend,
+    Captured, Collision, FileSink, ForcedCursor, HighlightChanges, Pipeline, PipelineBuilder,
+    Placement, PostProcessor, Redact, Scale, SimulateColorVision, Sink, Split, Subject,
+    WaylandBackend, Zoom, MAX_BYTES_PER_PIXEL,
+};
+pub use postprocess::{
+    adjust_tones, auto_trim, changed_regions, draw_pixel_grid, highlight_changes, icon, redact,
+    simulate_color_vision, thumbnail, zoom, ColorVision, RedactStyle, ToneAdjustment, ZoomFilter,
+    CHANGE_CELL_SIZE, MAX_TONE_FACTOR, MAX_ZOOM, REDACT_BLUR_FACTOR,
+};
+pub use progress::{Progress, ProgressBar, ProgressWriter, PROGRESS_MIN_PIXELS};
+pub use quirks::{Compositor, Quirks, QUIRK_NAMES};
+pub use redact::{load_redactions, parse_redactions, Redaction};
+pub use region::{
+    parse_crop, parse_geometry, parse_output_geometry, visible_windows, ActiveWindowProvider,
+    Decorations, GeometryProvider, RegionProvider, SlurpProvider, StdinProvider, Window,
+};
+pub use stack::{stack_frames, StackMode, MAX_STACK_FRAMES};
+pub use testpattern::{
+    measure_test_pattern, test_pattern, TEST_PATTERN_COLORS, TEST_PATTERN_SETTLE,
+    TEST_PATTERN_TOLERANCE,
+};
+pub use tonemap::{tone_map_frame, HdrOutputs, ToneMapOperator, ToneMapping, SDR_WHITE_NITS};
+pub use watch::{load_rules, parse_rules, watch, Rule, WATCH_SETTLE_DURATION};
 use wayland_client::{
-    protocol::{
-        wl_buffer, wl_buffer::WlBuffer, wl_output, wl_registry, wl_shm, wl_shm_pool,
-        wl_shm_pool::WlShmPool,
-    },
-    Connection, Dispatch, QueueHandle, WEnum,
+    backend::WaylandError,
+    protocol::{wl_output, wl_registry, wl_shm},
+    Connection, Dispatch, DispatchError, QueueHandle, WEnum,
 };
 use wayland_protocols::xdg::xdg_output::zv1::client::{
     zxdg_output_manager_v1, zxdg_output_manager_v1::ZxdgOutputManagerV1, zxdg_output_v1,
     zxdg_output_v1::ZxdgOutputV1,
 };
 use wayland_protocols_wlr::screencopy::v1::client::{
-    zwlr_screencopy_frame_v1, zwlr_screencopy_frame_v1::ZwlrScreencopyFrameV1,
     zwlr_screencopy_manager_v1, zwlr_screencopy_manager_v1::ZwlrScreencopyManagerV1,
 };
+pub use wayshot_core::Region;
+use wayshot_core::{
+    bounding_region, composite_band, intersection, scaled_span, ConversionPlan, Tile, Transform,
+};
 
+mod adam7;
+mod analyze;
+#[cfg(feature = "annotations")]
+mod annotate;
+#[cfg(feature = "annotations")]
+mod annotator;
+#[cfg(feature = "archive")]
+mod archive;
 mod backend;
+mod bench;
+mod bug_report;
+mod cache;
+mod capabilities;
+mod capture_commands;
+mod checksum;
 mod clap;
+mod config;
 mod convert;
+mod cursor;
+mod deadline;
+mod dnd;
+mod dzi;
+mod feedback;
+mod headless;
+#[cfg(feature = "scripting")]
+mod hooks;
+mod i18n;
+mod layout;
+mod lock;
+mod locker;
+mod logging;
+mod options;
 mod output;
+mod palette;
+mod pipeline;
+mod postprocess;
+mod power;
+mod progress;
+mod quirks;
+mod redact;
+mod region;
+mod serve;
+mod stack;
+#[cfg(test)]
+mod testing;
+mod testpattern;
+mod tonemap;
+mod watch;
+mod worker;
 
 // TODO: Create a xdg-shell surface, check for the enter event, grab the output from it.
 //
 // TODO: Patch multiple output bug via multiple images composited into 1.
 
-fn parse_geometry(g: &str) -> Option<backend::CaptureRegion> {
-    let tail = g.trim();
-    let x_coordinate: i32;
-    let y_coordinate: i32;
-    let width: i32;
-    let height: i32;
-
-    if tail.contains(',') {
-        // this accepts: "%d,%d %dx%d"
-        let (head, tail) = tail.split_once(',')?;
-        x_coordinate = head.parse::<i32>().ok()?;
-        let (head, tail) = tail.split_once(' ')?;
-        y_coordinate = head.parse::<i32>().ok()?;
-        let (head, tail) = tail.split_once('x')?;
-        width = head.parse::<i32>().ok()?;
-        height = tail.parse::<i32>().ok()?;
-    } else {
-        // this accepts: "%d %d %d %d"
-        let (head, tail) = tail.split_once(' ')?;
-        x_coordinate = head.parse::<i32>().ok()?;
-        let (head, tail) = tail.split_once(' ')?;
-        y_coordinate = head.parse::<i32>().ok()?;
-        let (head, tail) = tail.split_once(' ')?;
-        width = head.parse::<i32>().ok()?;
-        height = tail.parse::<i32>().ok()?;
-    }
-
-    Some(backend::CaptureRegion {
-        x_coordinate,
-        y_coordinate,
-        width,
-        height,
-    })
-}
-
 pub struct WayshotState {
     formats: Vec<wl_shm::Format>,
     outputs: Vec<output::OutputInfo>,
     shm: Option<wl_shm::WlShm>,
     screencopy: Option<ZwlrScreencopyManagerV1>,
     xdg_output: Option<ZxdgOutputManagerV1>,
+    /// Name of the only output to capture, if known upfront. The others are released as soon
+    /// as their name is, so that nothing waits on their geometry.
+    output_filter: Option<String>,
 }
 
 impl Dispatch<wl_registry::WlRegistry, ()> for WayshotState {
@@ -105,8 +206,13 @@ impl Dispatch<wl_registry::WlRegistry, ()> for WayshotState {
                     state.shm = Some(shm);
                 }
                 "zwlr_screencopy_manager_v1" => {
-                    state.screencopy =
-                        Some(registry.bind::<ZwlrScreencopyManagerV1, _, _>(name, 1, qh, ()));
+                    // Version 2 adds copy_with_damage, used by --capture-on-present.
+                    state.screencopy = Some(registry.bind::<ZwlrScreencopyManagerV1, _, _>(
+                        name,
+                        version.min(2),
+                        qh,
+                        (),
+                    ));
                 }
                 "zxdg_output_manager_v1" => {
                     let manager = registry.bind::<ZxdgOutputManagerV1, _, _>(name, 1, qh, ());
@@ -115,32 +221,31 @@ impl Dispatch<wl_registry::WlRegistry, ()> for WayshotState {
                     }
                     state.xdg_output = Some(manager);
                 }
-                "wl_output" => {
-                    if version >= 4 {
-                        let output = registry.bind::<wl_output::WlOutput, _, _>(name, 4, qh, ());
-                        let xdg_output = match &state.xdg_output {
-                            Some(manager) => Some(manager.get_xdg_output(&output, qh, ())),
-                            None => None,
-                        };
-                        let info = output::OutputInfo {
-                            wl_output: output,
-                            name: "".to_string(),
-                            xdg_output,
-                            dimensions: output::OutputPositioning {
-                                x: 0,
-                                y: 0,
-                                width: 0,
-                                height: 0,
-                            },
-                            xdg_ready: false,
-                            wl_ready: false,
-                            frame: None,
-                            frame_state: None,
-                            frame_format: None,
-                            mem_fd: None,
-                        };
-                        state.outputs.push(info);
-                    }
+                "wl_output" if version >= 4 => {
+                    let output = registry.bind::<wl_output::WlOutput, _, _>(name, 4, qh, ());
+                    let xdg_output = state
+                        .xdg_output
+                        .as_ref()
+                        .map(|manager| manager.get_xdg_output(&output, qh, ()));
+                    let info = output::OutputInfo {
+                        wl_output: output,
+                        global: name,
+                        name: "".to_string(),
+                        xdg_output,
+                        dimensions: output::OutputPositioning {
+                            x: 0,
+                            y: 0,
+                            width: 0,
+                            height: 0,
+                        },
+                        xdg_ready: false,
+                        wl_ready: false,
+                        mode: None,
+                        wl_position: (0, 0),
+                        transform: wl_output::Transform::Normal,
+                        scale: 1,
+                    };
+                    state.outputs.push(info);
                 }
                 _ => {}
             }
@@ -157,6 +262,27 @@ impl Dispatch<wl_output::WlOutput, ()> for WayshotState {
         _: &Connection,
         _: &QueueHandle<Self>,
     ) {
+        if let wl_output::Event::Name { name } = &event {
+            if state
+                .output_filter
+                .as_ref()
+                .is_some_and(|chosen| chosen != name)
+            {
+                if let Some(index) = state
+                    .outputs
+                    .iter()
+                    .position(|output| output.wl_output == *wl_output)
+                {
+                    tracing::debug!("Releasing {}, which is not captured", name);
+                    let output = state.outputs.remove(index);
+                    if let Some(xdg_output) = output.xdg_output {
+                        xdg_output.destroy();
+                    }
+                    output.wl_output.release();
+                }
+                return;
+            }
+        }
         for output in state.outputs.iter_mut() {
             if output.wl_output != *wl_output {
                 continue;
@@ -165,7 +291,34 @@ impl Dispatch<wl_output::WlOutput, ()> for WayshotState {
             if let wl_output::Event::Name { name } = &event {
                 output.name = name.clone();
             }
-            if let wl_output::Event::Done {} = &event {
+            if let wl_output::Event::Geometry {
+                x, y, transform, ..
+            } = &event
+            {
+                output.wl_position = (*x, *y);
+                if let WEnum::Value(transform) = transform {
+                    output.transform = *transform;
+                }
+            }
+            if let wl_output::Event::Scale { factor } = &event {
+                output.scale = *factor;
+            }
+            if let wl_output::Event::Mode {
+                flags: WEnum::Value(flags),
+                width,
+                height,
+                refresh,
+            } = &event
+            {
+                if flags.contains(wl_output::Mode::Current) {
+                    output.mode = Some(OutputMode {
+                        width: *width,
+                        height: *height,
+                        refresh: *refresh,
+                    });
+                }
+            }
+            if let wl_output::Event::Done = &event {
                 output.wl_ready = true;
             }
         }
@@ -218,10 +371,11 @@ impl Dispatch<wl_shm::WlShm, ()> for WayshotState {
         _: &Connection,
         _: &QueueHandle<Self>,
     ) {
-        if let wl_shm::Event::Format { format } = event {
-            if let WEnum::Value(v) = format {
-                state.formats.push(v)
-            };
+        if let wl_shm::Event::Format {
+            format: WEnum::Value(v),
+        } = event
+        {
+            state.formats.push(v)
         }
     }
 }
@@ -250,115 +404,180 @@ impl Dispatch<ZwlrScreencopyManagerV1, ()> for WayshotState {
     }
 }
 
-impl Dispatch<ZwlrScreencopyFrameV1, ()> for WayshotState {
-    fn event(
-        state: &mut Self,
-        frame: &ZwlrScreencopyFrameV1,
-        event: zwlr_screencopy_frame_v1::Event,
-        _: &(),
-        _: &Connection,
-        _: &QueueHandle<Self>,
-    ) {
-        for output in state.outputs.iter_mut() {
-            let f = if let Some(f) = &output.frame {
-                f
-            } else {
-                continue;
-            };
-            if f != frame {
-                continue;
-            }
+#[derive(Debug, Default, Clone)]
+pub struct FrameArgs {
+    pub cursor: bool,
+    pub output: Option<String>,
+    pub listoutputs: bool,
+    /// Log the buffer format the compositor offers for each selected output and exit.
+    pub listformats: bool,
+    /// Read frames as this format whatever the compositor advertises, eg: when it advertises
+    /// the wrong one. The buffers keep the advertised format.
+    pub force_format: Option<wl_shm::Format>,
+    /// Region to capture, relative to `output` if one is chosen and global otherwise.
+    pub region: Option<Region>,
+    /// Wait for the next presented frame with damage instead of copying the last one.
+    pub capture_on_present: bool,
+    /// Positions outputs are moved to along with their captured area, instead of those of
+    /// the compositor, or offsets they are moved by. Outputs which are not listed keep their
+    /// position.
+    pub layout: Layout,
+    /// Capture every output of a set of clones, which share the same logical rectangle. By
+    /// default only the first one is captured.
+    pub all_clones: bool,
+    /// Time limit the capture reports its stages to, see [`Deadline`].
+    pub deadline: Option<Deadline>,
+    /// Workarounds for the compositor, see [`Quirks`].
+    pub quirks: Quirks,
+    /// Leave out outputs whose frame is not copied within this long, instead of waiting for
+    /// them, see [`capture_frame`].
+    pub output_timeout: Option<Duration>,
+    /// Take the geometry of outputs from the cache of the last run if they were not plugged in
+    /// or out since, skipping the roundtrip which describes them, see [`load_output_cache`].
+    pub fast: bool,
+    /// Tone mapping of HDR frames, see [`ToneMapping::apply`].
+    pub tone_mapping: Option<ToneMapping>,
+}
 
-            match event {
-                zwlr_screencopy_frame_v1::Event::Buffer {
-                    format,
-                    width,
-                    height,
-                    stride,
-                } => {
-                    log::debug!("Received Buffer event");
-                    output.frame_format = Some(FrameFormat {
-                        format: format.into_result().unwrap(),
-                        width,
-                        height,
-                        stride,
-                    });
-                }
-                zwlr_screencopy_frame_v1::Event::Flags { .. } => {
-                    log::debug!("Received Flags event");
-                }
-                zwlr_screencopy_frame_v1::Event::Ready { .. } => {
-                    // If the frame is successfully copied, a “flags” and a “ready” events are sent. Otherwise, a “failed” event is sent.
-                    // This is useful when we call .copy on the frame object.
-                    log::debug!("Received Ready event");
-                    output.frame_state = Some(FrameState::Finished)
-                }
-                zwlr_screencopy_frame_v1::Event::Failed => {
-                    log::debug!("Received Failed event");
-                    output.frame_state = Some(FrameState::Failed);
-                }
-                zwlr_screencopy_frame_v1::Event::Damage { .. } => {
-                    log::debug!("Received Damage event");
-                }
-                zwlr_screencopy_frame_v1::Event::LinuxDmabuf { .. } => {
-                    log::debug!("Received LinuxDmaBuf event");
-                }
-                zwlr_screencopy_frame_v1::Event::BufferDone => {
-                    log::debug!("Received bufferdone event");
-                    // todo: verify this arrived
-                }
-                _ => unreachable!(),
-            };
-        }
+impl FrameArgs {
+    fn enter_stage(&self, stage: Stage) -> Result<(), Box<dyn Error>> {
+        self.deadline
+            .as_ref()
+            .map_or(Ok(()), |deadline| deadline.enter(stage))
     }
-}
 
-impl Dispatch<WlBuffer, ()> for WayshotState {
-    fn event(
-        _: &mut Self,
-        _: &WlBuffer,
-        _: wl_buffer::Event,
-        _: &(),
-        _: &Connection,
-        _: &QueueHandle<Self>,
-    ) {
+    fn check_deadline(&self) -> Result<(), Box<dyn Error>> {
+        self.deadline.as_ref().map_or(Ok(()), Deadline::check)
     }
 }
 
-impl Dispatch<WlShmPool, ()> for WayshotState {
-    fn event(
-        _: &mut Self,
-        _: &WlShmPool,
-        _: wl_shm_pool::Event,
-        _: &(),
-        _: &Connection,
-        _: &QueueHandle<Self>,
-    ) {
+/// Image data captured from a single output.
+#[derive(Debug)]
+pub struct OutputCapture {
+    /// Name of the output the image was captured from.
+    pub name: String,
+    /// Position and size of the captured area in global logical coordinates.
+    pub region: Region,
+    /// Captured pixels, scaled to the logical size of `region`.
+    pub image: RgbaImage,
+    /// CLOCK_MONOTONIC time at which the captured frame was presented, if the compositor said.
+    pub presented_at: Option<Duration>,
+    /// Current mode of the output, if the compositor advertised one.
+    pub mode: Option<OutputMode>,
+}
+
+/// Intersection of an output with the capture region, in global logical coordinates. The
+/// width or height are not positive if they do not overlap.
+fn output_capture_region(
+    dimensions: &output::OutputPositioning,
+    region: &backend::CaptureRegion,
+) -> Region {
+    intersection(
+        &Region {
+            x: dimensions.x,
+            y: dimensions.y,
+            width: dimensions.width,
+            height: dimensions.height,
+        },
+        &Region {
+            x: region.x_coordinate,
+            y: region.y_coordinate,
+            width: region.width,
+            height: region.height,
+        },
+    )
+}
+
+pub fn get_frame(args: FrameArgs) -> Result<RgbaImage, Box<dyn Error>> {
+    logging::init(tracing::Level::INFO, None, None)?;
+    tracing::trace!("Logger initialized.");
+
+    let captures = capture_outputs(&args)?;
+    composite_captures(&captures)
+}
+
+/// Capture every output selected by `args`, returning one image per output.
+pub fn capture_outputs(args: &FrameArgs) -> Result<Vec<OutputCapture>, Box<dyn Error>> {
+    capture_raw_frames(args)?
+        .into_iter()
+        .map(convert_raw_capture)
+        .collect()
+}
+
+/// Turn a failed roundtrip into a readable error instead of panicking, eg: when the
+/// compositor exits or the connection drops halfway through a capture. Protocol errors name
+/// the offending object and error code, followed by an explanation of known errors.
+fn dispatch_error(error: DispatchError) -> Box<dyn Error> {
+    match error {
+        DispatchError::Backend(WaylandError::Io(error)) => tr_args(
+            Message::CompositorDisconnected,
+            &[("error", error.to_string().into())],
+        )
+        .into(),
+        DispatchError::Backend(WaylandError::Protocol(error)) => {
+            let details = format!(
+                "{}@{} error {}: {}",
+                error.object_interface, error.object_id, error.code, error.message
+            );
+            let mut description = tr_args(
+                Message::CompositorProtocolError,
+                &[("error", details.into())],
+            );
+            if let Some(explanation) = explain_protocol_error(&error.object_interface, error.code) {
+                description.push_str(&format!(". {}.", tr(explanation)));
+            }
+            description.into()
+        }
+        error => tr_args(
+            Message::CompositorProtocolError,
+            &[("error", error.to_string().into())],
+        )
+        .into(),
     }
 }
 
-#[derive(Debug, Default, PartialEq, Eq, Copy, Clone)]
-pub struct Region {
-    pub x: i32,
-    pub y: i32,
-    pub width: i32,
-    pub height: i32,
+/// Name of the sandbox wayshot runs in, if any. Compositors implementing security-context-v1
+/// filter privileged globals such as screencopy out of the registry of sandboxed clients.
+fn sandbox_name() -> Option<&'static str> {
+    if Path::new("/.flatpak-info").exists() {
+        Some("Flatpak")
+    } else if env::var_os("SNAP").is_some() {
+        Some("Snap")
+    } else {
+        None
+    }
 }
 
-#[derive(Debug, Default)]
-pub struct FrameArgs {
-    pub cursor: bool,
-    pub output: Option<String>,
-    pub listoutputs: bool,
-    pub region: Option<Region>,
+/// Likely cause of the error `code` of `interface`, for the objects wayshot creates. Codes are
+/// the `error` enums of the protocol definitions.
+fn explain_protocol_error(interface: &str, code: u32) -> Option<Message> {
+    match (interface, code) {
+        ("zwlr_screencopy_frame_v1", 0) => Some(Message::FrameAlreadyCopied),
+        ("zwlr_screencopy_frame_v1", 1) => Some(Message::InvalidFrameBuffer),
+        ("wl_shm" | "wl_shm_pool", 0) => Some(Message::InvalidShmFormat),
+        ("wl_shm" | "wl_shm_pool", 1) => Some(Message::InvalidShmStride),
+        ("wl_shm" | "wl_shm_pool", 2) => Some(Message::InvalidShmFd),
+        ("wl_display", 0 | 1) => Some(Message::InvalidRequest),
+        ("wl_display", 2) => Some(Message::CompositorOutOfMemory),
+        ("wl_display", 3) => Some(Message::CompositorInternalError),
+        _ => None,
+    }
 }
 
-pub fn get_frame(args: FrameArgs) -> Result<RgbaImage, Box<dyn Error>> {
-    env::set_var("RUST_LOG", "wayshot=info");
-    env_logger::init();
-    log::trace!("Logger initialized.");
+/// Capture every output selected by `args`, leaving the pixels in the shm buffers the
+/// compositor copied them to. With --listoutputs or --list-formats, the outputs or their
+/// formats are logged instead, and nothing is returned.
+fn capture_raw_frames(args: &FrameArgs) -> Result<Vec<RawCapture>, Box<dyn Error>> {
+    capture_raw_frames_in(args, &FrameMemory::Shm)
+}
 
-    let cursor_overlay: i32 = if args.cursor { 1 } else { 0 };
+/// [`capture_raw_frames`] allocating the frames in `memory`.
+#[tracing::instrument(name = "capture_raw_frames", skip_all)]
+fn capture_raw_frames_in(
+    args: &FrameArgs,
+    memory: &FrameMemory,
+) -> Result<Vec<RawCapture>, Box<dyn Error>> {
+    args.enter_stage(Stage::Discovery)?;
 
     let mut state = WayshotState {
         outputs: Vec::new(),
@@ -366,8 +585,18 @@ pub fn get_frame(args: FrameArgs) -> Result<RgbaImage, Box<dyn Error>> {
         screencopy: None,
         xdg_output: None,
         formats: Vec::new(),
+        // Every output is listed with --listoutputs, and cached with --fast.
+        output_filter: args
+            .output
+            .clone()
+            .filter(|_| !args.listoutputs && !args.fast),
     };
-    let conn = wayland_client::Connection::connect_to_env().unwrap();
+    let conn = wayland_client::Connection::connect_to_env().map_err(|e| {
+        tr_args(
+            Message::CompositorConnectionFailed,
+            &[("error", e.to_string().into())],
+        )
+    })?;
     let display = conn.display();
 
     let mut event_queue = conn.new_event_queue();
@@ -376,42 +605,129 @@ pub fn get_frame(args: FrameArgs) -> Result<RgbaImage, Box<dyn Error>> {
     let _registry = display.get_registry(&qh, ());
 
     // First roundtrip: bind all globals and outputs
-    event_queue.roundtrip(&mut state).unwrap();
+    tracing::info_span!("roundtrip", stage = "globals")
+        .in_scope(|| event_queue.roundtrip(&mut state))
+        .map_err(dispatch_error)?;
+    args.check_deadline()?;
     if state.shm.is_none() {
-        log::error!("Compositor is missing wl_shm interface");
-        exit(1);
+        return Err(tr(Message::MissingShm).into());
     }
-    if state.shm.is_none() {
-        log::error!("Compositor is missing wl_shm interface");
-        exit(1);
+    if state.screencopy.is_none() {
+        let mut message = tr(Message::MissingScreencopy);
+        if let Some(sandbox) = sandbox_name() {
+            message.push('\n');
+            message.push_str(&tr_args(
+                Message::ScreencopySandboxed,
+                &[("sandbox", sandbox.into())],
+            ));
+        }
+        return Err(message.into());
     }
 
-    // Second roundtrip: learn output names and geometry
-    event_queue.roundtrip(&mut state).unwrap();
+    // With --fast, outputs plugged in the same as in the last run are described by the cache.
+    let globals: Vec<u32> = state.outputs.iter().map(|output| output.global).collect();
+    let layout_hash = cache::layout_hash(&globals);
+    let cached = if args.fast {
+        load_output_cache().filter(|cache| cache.layout_hash == layout_hash)
+    } else {
+        None
+    };
+    if let Some(cache) = cached {
+        tracing::debug!("Outputs unchanged, using their cached geometry");
+        for output in state.outputs.iter_mut() {
+            if let Some(cached) = cache.outputs.iter().find(|c| c.global == output.global) {
+                cached.apply(output);
+            }
+        }
+    } else {
+        // Second roundtrip: learn output names and geometry
+        tracing::info_span!("roundtrip", stage = "outputs")
+            .in_scope(|| event_queue.roundtrip(&mut state))
+            .map_err(dispatch_error)?;
+        args.check_deadline()?;
+
+        // Some compositors send xdg_output geometry late, older ones never do.
+        let deadline = Instant::now() + XDG_OUTPUT_TIMEOUT;
+        while state.xdg_output.is_some()
+            && state.outputs.iter().any(|output| !output.xdg_ready)
+            && Instant::now() < deadline
+        {
+            thread::sleep(Duration::from_millis(10));
+            event_queue.roundtrip(&mut state).map_err(dispatch_error)?;
+            args.check_deadline()?;
+        }
+        for output in state.outputs.iter_mut().filter(|output| !output.xdg_ready) {
+            match output.wl_dimensions() {
+                Some(dimensions) => {
+                    tracing::warn!(
+                        "{}",
+                        tr_args(
+                            Message::LogicalGeometryEstimated,
+                            &[("output", output.name.as_str().into())],
+                        )
+                    );
+                    output.dimensions = dimensions;
+                }
+                None => tracing::warn!(
+                    "{}",
+                    tr_args(
+                        Message::LogicalGeometryMissing,
+                        &[("output", output.name.as_str().into())],
+                    )
+                ),
+            }
+        }
+
+        if args.fast {
+            let cache = OutputCache {
+                layout_hash,
+                outputs: state
+                    .outputs
+                    .iter()
+                    .filter(|output| output.wl_ready)
+                    .map(CachedOutput::from_info)
+                    .collect(),
+            };
+            if let Err(e) = save_output_cache(&cache) {
+                let error = e.to_string().into();
+                tracing::warn!(
+                    "{}",
+                    tr_args(Message::OutputCacheFailed, &[("error", error)])
+                );
+            }
+        }
+    }
 
     if args.listoutputs {
         for output in state.outputs {
-            if output.wl_ready {
-                log::info!("{:#?}", output.name);
+            if !output.wl_ready {
+                tracing::error!("{}", tr(Message::OutputWithoutName));
+            } else if let Some(mode) = output.mode {
+                tracing::info!("{:#?} {}", output.name, mode);
             } else {
-                log::error!("An output did not report its name");
+                tracing::info!("{:#?}", output.name);
             }
         }
-        exit(1);
+        return Ok(Vec::new());
     }
 
     // If an output is chosen, select only it
-    if let Some(chosen_output) = args.output {
+    let mut args_region = args.region;
+    if let Some(chosen_output) = &args.output {
         // Remove all outputs which do not match
-        state.outputs = state
+        state
             .outputs
-            .into_iter()
-            .filter(|output| output.wl_ready && output.name == chosen_output)
-            .collect();
+            .retain(|output| output.wl_ready && output.name == *chosen_output);
         // todo: impl drop?
+
+        // Regions given along with an output are relative to it
+        if let (Some(region), Some(output)) = (args_region.as_mut(), state.outputs.first()) {
+            region.x += output.dimensions.x;
+            region.y += output.dimensions.y;
+        }
     }
 
-    let region = if let Some(r) = args.region {
+    let region = if let Some(r) = args_region {
         backend::CaptureRegion {
             x_coordinate: r.x,
             y_coordinate: r.y,
@@ -429,489 +745,1716 @@ pub fn get_frame(args: FrameArgs) -> Result<RgbaImage, Box<dyn Error>> {
     };
 
     // Remove all outputs which do not overlap the target region
-    state.outputs = state
-        .outputs
-        .into_iter()
-        .filter(|output| {
-            let x1: i32 = cmp::max(output.dimensions.x, region.x_coordinate);
-            let y1: i32 = cmp::max(output.dimensions.y, region.y_coordinate);
-            let x2: i32 = cmp::min(
-                output.dimensions.x + output.dimensions.width,
-                region.x_coordinate + region.width,
-            );
-            let y2: i32 = cmp::min(
-                output.dimensions.y + output.dimensions.height,
-                region.y_coordinate + region.height,
-            );
-
-            let width = x2 - x1;
-            let height = y2 - y1;
-            width > 0 && height > 0
-        })
-        .collect();
+    state.outputs.retain(|output| {
+        let overlap = output_capture_region(&output.dimensions, &region);
+        overlap.width > 0 && overlap.height > 0
+    });
 
     if state.outputs.is_empty() {
-        log::error!("Provided capture region doesn't intersect with any outputs!");
-        exit(1);
-    }
-
-    let mut net_x1: i32 = i32::MAX;
-    let mut net_x2: i32 = i32::MIN;
-    let mut net_y1: i32 = i32::MAX;
-    let mut net_y2: i32 = i32::MIN;
-    for output in state.outputs.iter_mut() {
-        let manager = state.screencopy.as_mut().unwrap();
-
-        let x1: i32 = cmp::max(output.dimensions.x, region.x_coordinate);
-        let y1: i32 = cmp::max(output.dimensions.y, region.y_coordinate);
-        let x2: i32 = cmp::min(
-            output.dimensions.x + output.dimensions.width,
-            region.x_coordinate + region.width,
-        );
-        let y2: i32 = cmp::min(
-            output.dimensions.y + output.dimensions.height,
-            region.y_coordinate + region.height,
-        );
-
-        net_x1 = cmp::min(net_x1, x1);
-        net_x2 = cmp::max(net_x2, x2);
-        net_y1 = cmp::min(net_y1, y1);
-        net_y2 = cmp::max(net_y2, y2);
-
-        // Quoting spec: "The region is given in output logical coordinates"
-        // So subtract output position from global logical coordinates
-        let frame = manager.capture_output_region(
-            cursor_overlay,
-            &output.wl_output,
-            x1 - output.dimensions.x,
-            y1 - output.dimensions.y,
-            x2 - x1,
-            y2 - y1,
-            &qh,
-            (),
-        );
-        output.frame = Some(frame);
+        return Err(tr(Message::RegionOutsideOutputs).into());
     }
 
-    // Third roundtrip: learn frame parameters for requests
-    event_queue.roundtrip(&mut state).unwrap();
-
-    for output in state.outputs.iter_mut() {
-        let shm = state.shm.as_mut().unwrap();
-
-        let frame_format = if let Some(frame_format) = output.frame_format {
-            frame_format
-        } else {
-            log::error!("Output did not specify a frame format");
-            exit(1);
-        };
-
-        let frame_bytes = frame_format.stride * frame_format.height;
-
-        // Create an in memory file and return it's file descriptor.
-        let mem_fd = create_shm_fd()?;
-        unistd::ftruncate(mem_fd, frame_bytes as i64).unwrap();
-        output.mem_fd = Some(mem_fd);
-
-        let shm_pool = shm.create_pool(mem_fd, frame_bytes as i32, &qh, ());
-        let buffer = shm_pool.create_buffer(
-            0,
-            frame_format.width as i32,
-            frame_format.height as i32,
-            frame_format.stride as i32,
-            frame_format.format,
-            &qh,
-            (),
-        );
-
-        // Copy the pixel data advertised by the compositor into the buffer we just created.
-        output.frame.as_mut().unwrap().copy(&buffer);
+    // Mirrored outputs would only be captured twice and drawn over each other.
+    if !args.all_clones {
+        let mut rectangles = Vec::with_capacity(state.outputs.len());
+        state.outputs.retain(|output| {
+            if rectangles.contains(&output.dimensions) {
+                tracing::debug!("Skipping {}, a clone of another output", output.name);
+                return false;
+            }
+            rectangles.push(output.dimensions.clone());
+            true
+        });
     }
 
-    // Fourth roundtrip: learn whether captures succeeded or failed.
-    loop {
-        // todo: how to dispatch?
-        event_queue.roundtrip(&mut state).unwrap();
-        if !state
+    args.enter_stage(Stage::Capture)?;
+    let screencopy = state.screencopy.as_ref().unwrap();
+    let shm = state.shm.as_ref().unwrap();
+    let span = tracing::Span::current();
+    // Every output is captured on a worker thread and an event queue of its own, so that a
+    // stalled one does not hold up the others.
+    let frames: Vec<OutputFrame> = thread::scope(|scope| {
+        let workers: Vec<_> = state
             .outputs
             .iter()
-            .any(|output| output.frame_state.is_none())
-        {
-            break;
+            .map(|output| {
+                let overlap = output_capture_region(&output.dimensions, &region);
+                // Quoting spec: "The region is given in output logical coordinates"
+                // So subtract output position from global logical coordinates
+                let area = Region {
+                    x: overlap.x - output.dimensions.x,
+                    y: overlap.y - output.dimensions.y,
+                    ..overlap
+                };
+                let (conn, span) = (&conn, &span);
+                scope.spawn(move || {
+                    span.in_scope(|| {
+                        capture_frame(conn, screencopy, shm, output, area, args, memory)
+                    })
+                })
+            })
+            .collect();
+        // Frames of the other outputs are dropped along with their buffers when one fails.
+        workers
+            .into_iter()
+            .map(|worker| {
+                worker
+                    .join()
+                    .unwrap_or_else(|_| Err(tr(Message::CaptureWorkerPanicked)))
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+            .collect::<Result<_, _>>()
+    })?;
+
+    if args.listformats {
+        for (output, frame) in state.outputs.iter().zip(&frames) {
+            match frame.frame_format {
+                Some(frame_format) if create_converter(frame_format.format).is_some() => {
+                    tracing::info!(
+                        "{:#?} {:?} {}x{}, stride {}",
+                        output.name,
+                        frame_format.format,
+                        frame_format.width,
+                        frame_format.height,
+                        frame_format.stride
+                    );
+                }
+                Some(frame_format) => tracing::info!(
+                    "{:#?} {}x{}, stride {}: {}",
+                    output.name,
+                    frame_format.width,
+                    frame_format.height,
+                    frame_format.stride,
+                    tr_args(
+                        Message::UnsupportedBufferFormat,
+                        &[("format", format!("{:?}", frame_format.format).into())],
+                    )
+                ),
+                None => tracing::info!(
+                    "{}",
+                    tr_args(
+                        Message::MissingFrameFormat,
+                        &[("output", output.name.as_str().into())],
+                    )
+                ),
+            }
         }
+        tracing::info!("wl_shm: {:?}", state.formats);
+        return Ok(Vec::new());
     }
 
-    // TODO: render at 2x or higher scale later? Default should probably be >2x
-    // max fractional scale, or something close to a rational multiple of all outputs
-    let dest_width = (net_x2 - net_x1) as u32;
-    let dest_height = (net_y2 - net_y1) as u32;
-    let mut dest: RgbaImage = ImageBuffer::new(dest_width, dest_height);
+    if frames
+        .iter()
+        .any(|frame| matches!(frame.state, Some(FrameState::Failed)))
+    {
+        return Err(tr(Message::FrameCopyFailed).into());
+    }
 
-    for output in state.outputs.iter_mut() {
-        match output.frame_state {
-            None => unreachable!(),
-            Some(FrameState::Failed) => {
-                log::error!("Frame copy failed");
-                exit(1);
+    let mut captures = Vec::with_capacity(state.outputs.len());
+    for (output, frame) in state.outputs.iter().zip(frames) {
+        match frame.state {
+            None | Some(FrameState::Failed) => unreachable!(),
+            Some(FrameState::TimedOut) => {
+                tracing::warn!(
+                    "{}",
+                    tr_args(
+                        Message::FrameTimedOut,
+                        &[("output", output.name.as_str().into())],
+                    )
+                );
             }
             Some(FrameState::Finished) => {
-                let mem_fd = output.mem_fd.unwrap();
-
-                let frame_format = output.frame_format.unwrap();
-
-                let mem_file = unsafe { File::from_raw_fd(mem_fd) };
-                let mut frame_mmap = unsafe { MmapMut::map_mut(&mem_file)? };
-                let data = &mut *frame_mmap;
-                if let Some(converter) = create_converter(frame_format.format) {
-                    converter.convert_inplace(data)
+                let mem_file = frame.mem_file.unwrap();
+                let frame_mmap = unsafe { MmapMut::map_mut(&mem_file)? };
+                let frame_format = frame.frame_format.unwrap();
+                let mut transform = if args.quirks.rotate {
+                    frame_transform(output.transform)
                 } else {
-                    log::error!("Unsupported buffer format: {:?}", frame_format.format);
-                    log::error!("You can send a feature request for the above format to the mailing list for wayshot over at https://sr.ht/~shinyzenith/wayshot.");
-                    exit(1);
+                    Transform::Normal
                 };
-                let frame_image = RgbaImage::from_raw(
-                    frame_format.width,
-                    frame_format.height,
-                    (&*frame_mmap).to_vec(),
-                )
-                .unwrap();
-
-                let x1: i32 = cmp::max(output.dimensions.x, region.x_coordinate);
-                let y1: i32 = cmp::max(output.dimensions.y, region.y_coordinate);
-                let x2: i32 = cmp::min(
-                    output.dimensions.x + output.dimensions.width,
-                    region.x_coordinate + region.width,
-                );
-                let y2: i32 = cmp::min(
-                    output.dimensions.y + output.dimensions.height,
-                    region.y_coordinate + region.height,
-                );
+                if frame.y_invert && args.quirks.y_invert {
+                    transform = transform.flipped_vertically();
+                }
 
-                let resized: RgbaImage = resize(
-                    &frame_image,
-                    (x2 - x1) as u32,
-                    (y2 - y1) as u32,
-                    image::imageops::FilterType::Triangle,
+                tracing::debug!(
+                    "Frame of {} ({:?}) presented at {:?} (CLOCK_MONOTONIC)",
+                    output.name,
+                    output.mode,
+                    frame.presented_at
                 );
-                if let Err(e) = dest.copy_from(&resized, (x1 - net_x1) as u32, (y1 - net_y1) as u32)
-                {
-                    log::error!("Failed to copy output image onto dest image: {:?}", e);
-                    exit(1);
+                let mut capture_region = output_capture_region(&output.dimensions, &region);
+                if let Some(entry) = args.layout.get(&output.name) {
+                    let (x, y) = entry.origin((output.dimensions.x, output.dimensions.y));
+                    capture_region.x += x - output.dimensions.x;
+                    capture_region.y += y - output.dimensions.y;
                 }
+                captures.push(RawCapture {
+                    name: output.name.clone(),
+                    region: capture_region,
+                    frame_format,
+                    frame_mmap,
+                    transform,
+                    presented_at: frame.presented_at,
+                    mode: output.mode,
+                });
 
                 // todo: cleanup?
             }
         }
     }
+    if captures.is_empty() {
+        return Err(tr(Message::NoFrameCopied).into());
+    }
 
-    Ok(dest)
+    Ok(captures)
 }
 
-#[allow(dead_code)]
-fn main() -> Result<(), Box<dyn Error>> {
-    let args = clap::set_flags().get_matches();
-    env::set_var("RUST_LOG", "wayshot=info");
+/// Convert a raw capture to Rgba8 and scale it to the logical size of its region.
+fn convert_raw_capture(raw: RawCapture) -> Result<OutputCapture, Box<dyn Error>> {
+    convert_raw_capture_at_scale(raw, 1.0)
+}
+
+/// Convert a raw capture to Rgba8 and scale it to the size its region covers on a grid with
+/// `scale` pixels per logical pixel, see [`scaled_span`].
+#[tracing::instrument(skip_all, fields(output = %raw.name, scale = scale))]
+fn convert_raw_capture_at_scale(
+    raw: RawCapture,
+    scale: f64,
+) -> Result<OutputCapture, Box<dyn Error>> {
+    let frame_format = raw.frame_format;
+    let pixel_format = match pixel_format(frame_format.format) {
+        Some(pixel_format) => pixel_format,
+        None => {
+            let format = format!("{:?}", frame_format.format).into();
+            let message = tr_args(Message::UnsupportedBufferFormat, &[("format", format)]);
+            return Err(format!("{}\n{}", message, tr(Message::FeatureRequest)).into());
+        }
+    };
+
+    let (_, width) = scaled_span(raw.region.x, raw.region.width, scale);
+    let (_, height) = scaled_span(raw.region.y, raw.region.height, scale);
+    let plan = ConversionPlan::new(
+        pixel_format,
+        frame_format.width,
+        frame_format.height,
+        frame_format.stride,
+    )
+    .transform(raw.transform)
+    .scale_to(width, height);
+    tracing::debug!(
+        "Converting {:?} {}x{} frame to {}x{}: {:?}, cost {}",
+        raw.transform,
+        frame_format.width,
+        frame_format.height,
+        width,
+        height,
+        plan.strategy(),
+        plan.cost()
+    );
+
+    let row_bytes = width as usize * 4;
+    let mut pixels = vec![0u8; row_bytes * height as usize];
+    let bands = thread::available_parallelism()
+        .map(NonZeroUsize::get)
+        .unwrap_or(1)
+        .min((plan.cost() / CONVERT_BAND_MIN_COST) as usize)
+        .max(1);
+    let band_rows = (height as usize).div_ceil(bands).max(1);
+    thread::scope(|scope| {
+        for (band, rows) in pixels
+            .chunks_mut((band_rows * row_bytes).max(1))
+            .enumerate()
+        {
+            let (plan, frame) = (&plan, &raw.frame_mmap[..]);
+            scope.spawn(move || plan.execute_rows(frame, (band * band_rows) as u32, rows));
+        }
+    });
+    let resized = RgbaImage::from_raw(width, height, pixels).unwrap();
+    Ok(OutputCapture {
+        name: raw.name,
+        region: raw.region,
+        image: resized,
+        presented_at: raw.presented_at,
+        mode: raw.mode,
+    })
+}
 
-    if args.is_present("debug") {
-        env::set_var("RUST_LOG", "wayshot=trace");
+/// Combine every set of `frames`, captured after `captures`, into `captures`. The frames are
+/// converted to Rgba8 beforehand, so 10 bit formats are stacked at the precision they end up
+/// saved with.
+#[tracing::instrument(skip_all, fields(frames = frames.len() + 1, mode = ?mode))]
+fn stack_raw_captures(
+    captures: &mut [RawCapture],
+    mut frames: Vec<Vec<RawCapture>>,
+    mode: StackMode,
+) -> Result<(), Box<dyn Error>> {
+    let unchanged = frames.iter().all(|frame| {
+        frame.len() == captures.len()
+            && frame.iter().zip(captures.iter()).all(|(a, b)| {
+                a.name == b.name
+                    && a.region == b.region
+                    && a.frame_format == b.frame_format
+                    && a.transform == b.transform
+            })
+    });
+    if !unchanged {
+        return Err(tr(Message::StackOutputsChanged).into());
     }
 
-    env_logger::init();
-    log::trace!("Logger initialized.");
+    for (index, capture) in captures.iter_mut().enumerate() {
+        let format = capture.frame_format.format;
+        let converter = create_converter(format).ok_or_else(|| {
+            tr_args(
+                Message::UnsupportedBufferFormat,
+                &[("format", format!("{:?}", format).into())],
+            )
+        })?;
+        converter.convert_inplace(&mut capture.frame_mmap);
+        for frame in frames.iter_mut() {
+            converter.convert_inplace(&mut frame[index].frame_mmap);
+        }
+        let others: Vec<&[u8]> = frames
+            .iter()
+            .map(|frame| &frame[index].frame_mmap[..])
+            .collect();
+        stack_frames(&mut capture.frame_mmap, &others, mode);
 
-    let cursor_overlay: i32 = if args.is_present("cursor") { 1 } else { 0 };
+        // The buffer now holds Rgba8 pixels, which is Abgr8888 in wl_shm terms.
+        capture.frame_format.format = match format {
+            wl_shm::Format::Xrgb8888 | wl_shm::Format::Xbgr8888 => wl_shm::Format::Xbgr8888,
+            _ => wl_shm::Format::Abgr8888,
+        };
+    }
+    Ok(())
+}
 
-    let mut state = WayshotState {
-        outputs: Vec::new(),
-        shm: None,
-        screencopy: None,
-        xdg_output: None,
-        formats: Vec::new(),
-    };
-    let conn = wayland_client::Connection::connect_to_env().unwrap();
-    let display = conn.display();
+/// Largest scale of the captured outputs, ie: the scale at which no capture loses detail.
+fn max_capture_scale(captures: &[RawCapture]) -> f64 {
+    captures
+        .iter()
+        .map(|capture| capture.shown_size().0 as f64 / capture.region.width as f64)
+        .fold(1.0, f64::max)
+}
 
-    let mut event_queue = conn.new_event_queue();
-    let qh: QueueHandle<WayshotState> = event_queue.handle();
-    // todo: use the registry abstraction from wayland-client
-    let _registry = display.get_registry(&qh, ());
+/// Stitch per-output captures together into a single image covering all of them.
+pub fn composite_captures(captures: &[OutputCapture]) -> Result<RgbaImage, Box<dyn Error>> {
+    composite_captures_at_scale(captures, 1.0)
+}
 
-    // First roundtrip: bind all globals and outputs
-    event_queue.roundtrip(&mut state).unwrap();
-    if state.shm.is_none() {
-        log::error!("Compositor is missing wl_shm interface");
-        exit(1);
+/// Stitch together captures whose images were scaled to `scale` pixels per logical pixel.
+#[tracing::instrument(skip_all, fields(scale = scale))]
+pub fn composite_captures_at_scale(
+    captures: &[OutputCapture],
+    scale: f64,
+) -> Result<RgbaImage, Box<dyn Error>> {
+    let mut net_x1: i32 = i32::MAX;
+    let mut net_x2: i32 = i32::MIN;
+    let mut net_y1: i32 = i32::MAX;
+    let mut net_y2: i32 = i32::MIN;
+    for capture in captures {
+        net_x1 = cmp::min(net_x1, capture.region.x);
+        net_x2 = cmp::max(net_x2, capture.region.x + capture.region.width);
+        net_y1 = cmp::min(net_y1, capture.region.y);
+        net_y2 = cmp::max(net_y2, capture.region.y + capture.region.height);
     }
-    if state.shm.is_none() {
-        log::error!("Compositor is missing wl_shm interface");
-        exit(1);
+
+    let (origin_x, dest_width) = scaled_span(net_x1, net_x2 - net_x1, scale);
+    let (origin_y, dest_height) = scaled_span(net_y1, net_y2 - net_y1, scale);
+    let mut dest: RgbaImage = ImageBuffer::new(dest_width, dest_height);
+
+    let mut tiles = Vec::with_capacity(captures.len());
+    for capture in captures {
+        let (x, _) = scaled_span(capture.region.x, capture.region.width, scale);
+        let (y, _) = scaled_span(capture.region.y, capture.region.height, scale);
+        let (x, y) = ((x - origin_x) as u32, (y - origin_y) as u32);
+        if x + capture.image.width() > dest_width || y + capture.image.height() > dest_height {
+            let output = capture.name.as_str().into();
+            return Err(tr_args(Message::CompositeFailed, &[("output", output)]).into());
+        }
+        tiles.push(Tile {
+            pixels: capture.image.as_raw(),
+            width: capture.image.width(),
+            height: capture.image.height(),
+            x,
+            y,
+        });
     }
 
-    // Second roundtrip: learn output names and geometry
-    event_queue.roundtrip(&mut state).unwrap();
+    // Every thread fills its own band of rows, so outputs are copied in parallel without locks.
+    let row_bytes = dest_width as usize * 4;
+    let bands = thread::available_parallelism()
+        .map_or(1, NonZeroUsize::get)
+        .min(dest_height as usize / COMPOSITE_BAND_MIN_ROWS)
+        .max(1);
+    let band_rows = (dest_height as usize).div_ceil(bands).max(1);
+    thread::scope(|scope| {
+        for (band, pixels) in dest.chunks_mut(band_rows * row_bytes).enumerate() {
+            let tiles = &tiles;
+            scope
+                .spawn(move || composite_band(tiles, (band * band_rows) as u32, row_bytes, pixels));
+        }
+    });
 
-    if args.is_present("listoutputs") {
-        for output in state.outputs {
-            if output.wl_ready {
-                log::info!("{:#?}", output.name);
-            } else {
-                log::error!("An output did not report its name");
-            }
+    Ok(dest)
+}
+
+/// How long to wait for xdg_output geometry before falling back to wl_output.
+const XDG_OUTPUT_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Fewest rows of the canvas composited by one thread, below which spawning is not worth it.
+const COMPOSITE_BAND_MIN_ROWS: usize = 64;
+
+/// Least work, see [`ConversionPlan::cost`], given to one thread converting a capture. Plain
+/// conversions of a 1080p frame stay on one thread, scaling or 4K frames are split.
+const CONVERT_BAND_MIN_COST: u64 = 16 * 1024 * 1024;
+
+/// Stem of generated file names, eg: 20220728-213913-wayshot.
+const DEFAULT_FILENAME_FORMAT: &str = "%Y%m%d-%H%M%S-wayshot";
+
+/// Exit status of --verify when a captured frame is blank, told apart from other errors.
+const BLANK_FRAME_EXIT_CODE: i32 = 3;
+
+/// Format the current time in the local timezone with the strftime `format`, or None if the
+/// format is empty or contains invalid specifiers.
+fn format_local_time(format: &str) -> Option<String> {
+    let items = StrftimeItems::new(format).collect::<Vec<_>>();
+    if format.is_empty() || items.contains(&Item::Error) {
+        return None;
+    }
+    Some(
+        Local::now()
+            .format_with_items(items.into_iter())
+            .to_string(),
+    )
+}
+
+/// `text` without leading and trailing ASCII whitespace, keeping bytes which are not valid
+/// UTF-8 as is.
+fn trim_os(text: &OsStr) -> &OsStr {
+    OsStr::from_bytes(text.as_bytes().trim_ascii())
+}
+
+/// Turn on do-not-disturb for --dnd, warning when it can not be, since notifications showing
+/// up are no reason to give up on the screenshot.
+fn enable_dnd() -> Option<DoNotDisturb> {
+    match DoNotDisturb::enable() {
+        Ok(Some(dnd)) => Some(dnd),
+        Ok(None) => {
+            tracing::warn!("{}", tr(Message::DndUnavailable));
+            None
+        }
+        Err(e) => {
+            let error = e.to_string().into();
+            tracing::warn!("{}", tr_args(Message::DndFailed, &[("error", error)]));
+            None
         }
-        exit(1);
     }
+}
 
-    // If an output is chosen, select only it
-    if let Some(chosen_output) = args.value_of("output") {
-        // Remove all outputs which do not match
-        state.outputs = state
-            .outputs
-            .into_iter()
-            .filter(|output| output.wl_ready && output.name == chosen_output)
-            .collect();
-        // todo: impl drop?
+/// Write the provenance of --bug-report, warning when it can not be as the screenshot and the
+/// logs are still worth saving.
+fn write_bug_report_provenance(directory: &Path, provenance: &Provenance, private: bool) {
+    if let Err(e) = write_provenance(directory, provenance, private) {
+        let error = e.to_string().into();
+        tracing::warn!("{}", tr_args(Message::BugReportFailed, &[("error", error)]));
+    }
+}
+
+/// Exit status of a run whose failure was reported already, eg: [`BLANK_FRAME_EXIT_CODE`].
+#[derive(Debug)]
+struct ExitStatus(i32);
+
+impl fmt::Display for ExitStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "exit status {}", self.0)
     }
+}
+
+impl Error for ExitStatus {}
 
-    let region = if let Some(slurpval) = args.value_of("slurp") {
-        if slurpval == "" {
-            log::error!("Failed to recieve geometry.");
-            exit(1);
+// Unused by the library, which this file is as well.
+#[allow(dead_code)]
+fn main() {
+    let e = match run() {
+        Ok(()) => return,
+        Err(e) => e,
+    };
+    // Usage errors, --help and --version are printed by clap, which picks the exit status.
+    if let Some(e) = e.downcast_ref::<::clap::Error>() {
+        e.exit();
+    }
+    let status = match e.downcast_ref::<ExitStatus>() {
+        Some(ExitStatus(status)) => *status,
+        None if tracing::dispatcher::has_been_set() => {
+            tracing::error!("{}", e);
+            1
+        }
+        // The logger failed to start.
+        None => {
+            eprintln!("{}", e);
+            1
         }
-        let region: backend::CaptureRegion =
-            parse_geometry(slurpval).expect("Invalid geometry specification");
-        region
+    };
+    exit(status);
+}
+
+/// Do what the command line asks, failing with the error [`main`] reports and exits with.
+fn run() -> Result<(), Box<dyn Error>> {
+    let args = parse_args()?;
+    let level = if args.is_present("debug") {
+        tracing::Level::TRACE
+    } else if args.is_present("quiet") {
+        tracing::Level::WARN
     } else {
-        backend::CaptureRegion {
-            // with signed integers, x_1,x_2,y_1,y_2 is better structure
-            x_coordinate: i32::MIN / 2,
-            y_coordinate: i32::MIN / 2,
-            width: i32::MAX,
-            height: i32::MAX,
+        tracing::Level::INFO
+    };
+    // Created before the logger, which writes the logs of the run to it.
+    let bug_report = args.value_of_os("bug-report").map(Path::new);
+    let (log_file, bug_report_error) = match bug_report
+        .map(|directory| create_bug_report(directory, args.is_present("private")))
+    {
+        Some(Ok(file)) => (Some(file), None),
+        Some(Err(e)) => (None, Some(e)),
+        None => (None, None),
+    };
+    // Keep the guard alive until the end of run, the chrome trace is written when it drops.
+    let _trace_guard = logging::init(
+        level,
+        args.value_of_os("trace-output").map(Path::new),
+        log_file,
+    )?;
+    tracing::trace!("Logger initialized.");
+    if let Some(e) = bug_report_error {
+        let error = e.to_string().into();
+        return Err(tr_args(Message::BugReportFailed, &[("error", error)]).into());
+    }
+
+    #[cfg(feature = "scripting")]
+    let hooks = if args.is_present("no-hooks") || args.is_present("private") {
+        None
+    } else {
+        hooks::Hooks::load()?
+    };
+
+    match args.subcommand() {
+        Some(("export", export_args)) => return export(export_args),
+        Some(("bench", bench_args)) => return bench(bench_args),
+        Some(("capabilities", capabilities_args)) => return report_capabilities(capabilities_args),
+        _ => {}
+    }
+
+    let compositor = Compositor::detect();
+    let mut quirks = Quirks::for_compositor(compositor);
+    match args.value_of("quirks") {
+        Some("list") => {
+            list_quirks(compositor, quirks);
+            return Ok(());
         }
+        Some(overrides) => quirks.apply(overrides).map_err(|name| {
+            let invalid = tr_args(Message::InvalidQuirk, &[("quirk", name.into())]);
+            format!("{}\n{}", invalid, valid_arguments(QUIRK_NAMES))
+        })?,
+        None => {}
+    }
+    tracing::debug!("Quirks for {}: {:?}", compositor, quirks);
+
+    if let Some(("testpattern", _)) = args.subcommand() {
+        return testpattern(&args, quirks);
+    }
+    // Kept until the end of run, the compositor and application are stopped once wayshot exits.
+    let _headless = match args.subcommand() {
+        Some(("ci-shot", ci_args)) => Some(start_ci_shot(ci_args, quirks)?),
+        _ => None,
     };
 
-    // Remove all outputs which do not overlap the target region
-    state.outputs = state
-        .outputs
-        .into_iter()
-        .filter(|output| {
-            let x1: i32 = cmp::max(output.dimensions.x, region.x_coordinate);
-            let y1: i32 = cmp::max(output.dimensions.y, region.y_coordinate);
-            let x2: i32 = cmp::min(
-                output.dimensions.x + output.dimensions.width,
-                region.x_coordinate + region.width,
-            );
-            let y2: i32 = cmp::min(
-                output.dimensions.y + output.dimensions.height,
-                region.y_coordinate + region.height,
-            );
+    let decorations = if args.is_present("with-decorations") {
+        Decorations::Include
+    } else if args.is_present("without-decorations") {
+        Decorations::Exclude
+    } else {
+        Decorations::Compositor
+    };
+    let (geometry_output, region) = selected_region(&args, decorations)?;
+    // Windows of --app-id with their number, and regions of --crop with their name, are
+    // captured together through their bounding box.
+    let app_windows = app_windows(&args, decorations)?;
+    let crops = crops(&args)?;
+    let region = match (&app_windows, &crops) {
+        (Some(windows), _) => Some(bounding_region(windows.iter().map(|(window, _)| window))),
+        (None, Some(crops)) => Some(bounding_region(crops.iter().map(|(_, crop)| crop))),
+        (None, None) => region,
+    };
+    let split = match (app_windows, crops) {
+        (Some(windows), _) => Split::Windows {
+            app_id: args.value_of("app-id").unwrap().to_string(),
+            windows,
+        },
+        (None, Some(crops)) => Split::Crops(crops),
+        (None, None) if args.is_present("separate-outputs") || args.is_present("all-clones") => {
+            Split::Outputs
+        }
+        (None, None) => Split::Composite,
+    };
 
-            let width = x2 - x1;
-            let height = y2 - y1;
-            width > 0 && height > 0
-        })
-        .collect();
+    let analyses = args
+        .value_of("analyze")
+        .map(parse_analyses)
+        .transpose()?
+        .unwrap_or_default();
+    let max_duration = parse_value(
+        &args,
+        "max-duration",
+        |&milliseconds: &u64| milliseconds > 0,
+        || tr(Message::InvalidMaxDuration),
+    )?
+    .map(Duration::from_millis);
+    let (pre_capture, post_capture) = capture_commands(&args)?;
+    let progress: Option<Rc<dyn Progress>> = if args.is_present("quiet") {
+        None
+    } else {
+        ProgressBar::stderr().map(|bar| Rc::new(bar) as Rc<dyn Progress>)
+    };
+    let mut sink = file_sink(&args, bug_report, progress.clone())?;
+
+    let layout = args
+        .value_of_os("layout")
+        .map(|path| load_layout(Path::new(path)))
+        .transpose()?
+        .unwrap_or_default();
+    let (overlay_cursor, force_cursor) = cursor_mode(&args, quirks)?;
+    // The pointer position is only known in compositor coordinates.
+    let force_cursor = if force_cursor && !layout.is_empty() {
+        tracing::warn!("{}", tr(Message::CursorUnavailableWithLayout));
+        false
+    } else {
+        force_cursor
+    };
+    let forced_cursor = if force_cursor {
+        match cursor::load_cursor().and_then(|image| Ok((image, cursor::pointer_position()?))) {
+            Ok((cursor, position)) => Some(ForcedCursor { cursor, position }),
+            Err(e) => {
+                let error = e.to_string().into();
+                tracing::warn!(
+                    "{}",
+                    tr_args(Message::CursorUnavailable, &[("error", error)])
+                );
+                None
+            }
+        }
+    } else {
+        None
+    };
 
-    if state.outputs.is_empty() {
-        log::error!("Provided capture region doesn't intersect with any outputs!");
-        exit(1);
+    #[allow(unused_mut)]
+    let mut pipeline = pipeline_builder(&args, split, forced_cursor, progress)?;
+    #[cfg(feature = "scripting")]
+    if let Some(hooks) = hooks.as_ref().filter(|h| h.has_hook("post_process")) {
+        pipeline = pipeline.post_process(hooks);
+    }
+    let pipeline = pipeline.build();
+
+    let mut frame_args = frame_args(
+        &args,
+        quirks,
+        geometry_output,
+        region,
+        layout,
+        overlay_cursor,
+    )?;
+    // The capture logs listings instead of capturing. --listoutputs exits with 1 as it always
+    // has.
+    if frame_args.listoutputs || frame_args.listformats {
+        capture_raw_frames(&frame_args)?;
+        if frame_args.listoutputs {
+            return Err(ExitStatus(1).into());
+        }
+        return Ok(());
     }
 
-    let mut net_x1: i32 = i32::MAX;
-    let mut net_x2: i32 = i32::MIN;
-    let mut net_y1: i32 = i32::MAX;
-    let mut net_y2: i32 = i32::MIN;
-    for output in state.outputs.iter_mut() {
-        let manager = state.screencopy.as_mut().unwrap();
-
-        let x1: i32 = cmp::max(output.dimensions.x, region.x_coordinate);
-        let y1: i32 = cmp::max(output.dimensions.y, region.y_coordinate);
-        let x2: i32 = cmp::min(
-            output.dimensions.x + output.dimensions.width,
-            region.x_coordinate + region.width,
-        );
-        let y2: i32 = cmp::min(
-            output.dimensions.y + output.dimensions.height,
-            region.y_coordinate + region.height,
-        );
+    match args.subcommand() {
+        Some(("serve", serve_args)) => {
+            return serve_captures(&args, serve_args, &pipeline, &frame_args, sink.options)
+        }
+        Some(("watch", watch_args)) => {
+            return watch_captures(&args, watch_args, &pipeline, &frame_args, &mut sink)
+        }
+        _ => {}
+    }
 
-        net_x1 = cmp::min(net_x1, x1);
-        net_x2 = cmp::max(net_x2, x2);
-        net_y1 = cmp::min(net_y1, y1);
-        net_y2 = cmp::max(net_y2, y2);
-
-        // Quoting spec: "The region is given in output logical coordinates"
-        // So subtract output position from global logical coordinates
-        let frame = manager.capture_output_region(
-            cursor_overlay,
-            &output.wl_output,
-            x1 - output.dimensions.x,
-            y1 - output.dimensions.y,
-            x2 - x1,
-            y2 - y1,
-            &qh,
-            (),
-        );
-        output.frame = Some(frame);
+    // Starts once the region is selected, which may wait on the user.
+    frame_args.deadline = max_duration.map(Deadline::new);
+    if let Some(deadline) = &frame_args.deadline {
+        deadline.spawn_watchdog();
     }
 
-    // Third roundtrip: learn frame parameters for requests
-    event_queue.roundtrip(&mut state).unwrap();
+    // Held until the screenshot is written.
+    let _capture_lock = if args.is_present("no-lock") {
+        None
+    } else {
+        Some(lock::CaptureLock::acquire()?)
+    };
 
-    for output in state.outputs.iter_mut() {
-        let shm = state.shm.as_mut().unwrap();
+    let captured = capture(
+        &args,
+        &pipeline,
+        &frame_args,
+        bug_report,
+        pre_capture.as_ref(),
+        post_capture.as_ref(),
+    )?;
+    // Saving a blank frame would only hide why the capture failed.
+    if args.is_present("verify") {
+        verify_frames(&captured)?;
+    }
+    frame_args.enter_stage(Stage::Encode)?;
+
+    // Runs while the image is encoded, the frames are already copied.
+    let flash = args.is_present("flash").then(|| {
+        let outputs: Vec<String> = captured.captures.iter().map(|c| c.name.clone()).collect();
+        let frame_args = frame_args.clone();
+        thread::spawn(move || {
+            if let Err(e) = feedback::flash(&frame_args, &outputs, feedback::FLASH_DURATION) {
+                let error = e.to_string().into();
+                tracing::warn!("{}", tr_args(Message::FlashFailed, &[("error", error)]));
+            }
+        })
+    });
+    if args.is_present("shutter-sound") {
+        if let Err(e) = feedback::play_shutter_sound() {
+            let error = e.to_string().into();
+            tracing::warn!(
+                "{}",
+                tr_args(Message::ShutterSoundFailed, &[("error", error)])
+            );
+        }
+    }
 
-        let frame_format = if let Some(frame_format) = output.frame_format {
-            frame_format
-        } else {
-            log::error!("Output did not specify a frame format");
-            exit(1);
+    #[cfg(feature = "scripting")]
+    if let Some(hooks) = &hooks {
+        let captures = &captured.captures;
+        let region = bounding_region(captures.iter().map(|capture| &capture.region));
+        let (_, width) = scaled_span(region.x, region.width, captured.scale);
+        let (_, height) = scaled_span(region.y, region.height, captured.scale);
+        let info = hooks::CaptureInfo {
+            outputs: captures.iter().map(|c| c.name.clone()).collect(),
+            modes: captures
+                .iter()
+                .map(|c| c.mode.map(|mode| mode.to_string()).unwrap_or_default())
+                .collect(),
+            region,
+            width,
+            height,
+            format: if sink.auto_format {
+                "auto"
+            } else {
+                sink.format.extension()
+            },
         };
+        hooks.on_capture(&info)?;
+        if let Some(stem) = hooks.filename(&info)? {
+            sink.stem = stem;
+        }
+    }
+
+    let result = write_screenshot(&args, &pipeline, captured, &mut sink, analyses);
+    if let Some(flash) = flash {
+        let _ = flash.join();
+    }
+    result
+}
 
-        let frame_bytes = frame_format.stride * frame_format.height;
-
-        // Create an in memory file and return it's file descriptor.
-        let mem_fd = create_shm_fd()?;
-        unistd::ftruncate(mem_fd, frame_bytes as i64).unwrap();
-        output.mem_fd = Some(mem_fd);
-
-        let shm_pool = shm.create_pool(mem_fd, frame_bytes as i32, &qh, ());
-        let buffer = shm_pool.create_buffer(
-            0,
-            frame_format.width as i32,
-            frame_format.height as i32,
-            frame_format.stride as i32,
-            frame_format.format,
-            &qh,
-            (),
+/// Parse the command line, as that of grim when wayshot is invoked as grim, see
+/// [`clap::grim_flags`], or with the config file providing defaults otherwise.
+fn parse_args() -> Result<ArgMatches, Box<dyn Error>> {
+    let argv: Vec<OsString> = env::args_os().collect();
+    if clap::is_grim_invocation(&argv) {
+        let argv = clap::grim_to_wayshot_args(argv);
+        return Ok(clap::set_flags().try_get_matches_from(argv)?);
+    }
+    let mut command = clap::set_flags();
+    let configured = config::load_config(&config::config_path())
+        .map_err(|e| e.to_string())
+        .and_then(|config| config::apply_config(command.clone(), &config));
+    match configured {
+        Ok(configured) => Ok(configured.try_get_matches_from(argv)?),
+        Err(e) => Err(command.error(ErrorKind::InvalidValue, e).into()),
+    }
+}
+
+/// `wayshot export`, see [`ArchiveReader`].
+#[cfg(feature = "archive")]
+fn export(export_args: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let archive_path = Path::new(export_args.value_of_os("ARCHIVE").unwrap());
+    let directory = Path::new(
+        export_args
+            .value_of_os("directory")
+            .unwrap_or(OsStr::new(".")),
+    );
+    let exported = archive::export_to_png(archive_path, directory)?;
+    tracing::info!(
+        "{}",
+        tr_args(
+            Message::ExportedFrames,
+            &[
+                ("count", exported.into()),
+                ("directory", directory.display().to_string().into()),
+            ],
+        )
+    );
+    Ok(())
+}
+
+#[cfg(not(feature = "archive"))]
+fn export(_: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let args = [("feature", "export".into())];
+    Err(tr_args(Message::FeatureUnavailable, &args).into())
+}
+
+/// `wayshot bench`, see [`bench_io`].
+fn bench(bench_args: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let megabytes = parse_value(
+        bench_args,
+        "size",
+        |&megabytes: &u64| megabytes > 0,
+        || tr(Message::InvalidBenchSize),
+    )?
+    .unwrap_or(DEFAULT_BENCH_SIZE);
+    let runs = parse_value(
+        bench_args,
+        "runs",
+        |&runs: &u32| runs > 0,
+        || tr(Message::InvalidBenchRuns),
+    )?
+    .unwrap_or(DEFAULT_BENCH_RUNS);
+    let directory = Path::new(
+        bench_args
+            .value_of_os("directory")
+            .unwrap_or(OsStr::new(".")),
+    );
+    let timings = bench_io(directory, megabytes, runs).map_err(|e| {
+        let error = e.to_string().into();
+        tr_args(Message::BenchFailed, &[("error", error)])
+    })?;
+    for timing in timings {
+        tracing::info!(
+            "{}",
+            tr_args(
+                Message::BenchWriteTiming,
+                &[
+                    ("method", timing.method.name().into()),
+                    ("bytes", timing.bytes.into()),
+                    (
+                        "milliseconds",
+                        timing.fastest.as_millis().to_string().into()
+                    ),
+                    (
+                        "throughput",
+                        format!("{:.0}", timing.megabytes_per_second()).into()
+                    ),
+                ],
+            )
         );
+    }
+    Ok(())
+}
+
+/// `wayshot capabilities`, see [`Capabilities`].
+fn report_capabilities(capabilities_args: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let capabilities = Capabilities::detect();
+    if capabilities_args.is_present("json") {
+        writeln!(stdout(), "{}", capabilities.to_json())?;
+        return Ok(());
+    }
+    tracing::info!("wayshot {}", capabilities.version);
+    for (name, enabled) in &capabilities.features {
+        tracing::info!("{} {}", name, if *enabled { "on" } else { "off" });
+    }
+    tracing::info!(
+        "{}",
+        tr_args(
+            Message::Encoders,
+            &[("encoders", capabilities.encoders.join(", ").into())],
+        )
+    );
+    tracing::info!(
+        "{}",
+        tr_args(
+            Message::DetectedCompositor,
+            &[("compositor", capabilities.compositor.to_string().into())],
+        )
+    );
+    match &capabilities.globals {
+        Some(globals) => {
+            for (interface, version) in globals {
+                tracing::info!("{} {}", interface, version);
+            }
+        }
+        None => tracing::warn!("{}", tr(Message::CompositorUnavailable)),
+    }
+    Ok(())
+}
 
-        // Copy the pixel data advertised by the compositor into the buffer we just created.
-        output.frame.as_mut().unwrap().copy(&buffer);
+/// `--quirks list`, the quirks of `compositor` along with whether they are on.
+fn list_quirks(compositor: Compositor, quirks: Quirks) {
+    tracing::info!(
+        "{}",
+        tr_args(
+            Message::DetectedCompositor,
+            &[("compositor", compositor.to_string().into())],
+        )
+    );
+    for name in QUIRK_NAMES {
+        let enabled = quirks.get(name).unwrap_or_default();
+        tracing::info!("{} {}", name, if enabled { "on" } else { "off" });
     }
+}
 
-    // Fourth roundtrip: learn whether captures succeeded or failed.
-    loop {
-        // todo: how to dispatch?
-        event_queue.roundtrip(&mut state).unwrap();
-        if !state
-            .outputs
-            .iter()
-            .any(|output| output.frame_state.is_none())
-        {
-            break;
+/// `wayshot testpattern`, see [`measure_test_pattern`].
+fn testpattern(args: &ArgMatches, quirks: Quirks) -> Result<(), Box<dyn Error>> {
+    let capture_args = FrameArgs {
+        output: args.value_of("output").map(str::to_string),
+        quirks,
+        ..Default::default()
+    };
+    let shown = feedback::with_test_pattern(capture_args.output.as_deref(), || {
+        thread::sleep(TEST_PATTERN_SETTLE);
+        capture_outputs(&capture_args)
+    });
+    let captures = match shown {
+        Ok(Ok(captures)) => captures,
+        Ok(Err(e)) | Err(e) => {
+            let error = e.to_string().into();
+            return Err(tr_args(Message::TestPatternFailed, &[("error", error)]).into());
+        }
+    };
+    let mut matches = true;
+    for capture in captures {
+        let [red, green, blue] = measure_test_pattern(&capture.image);
+        tracing::info!(
+            "{}",
+            tr_args(
+                Message::TestPatternDeltas,
+                &[
+                    ("output", capture.name.as_str().into()),
+                    ("red", red.into()),
+                    ("green", green.into()),
+                    ("blue", blue.into()),
+                ],
+            )
+        );
+        if red.max(green).max(blue) > TEST_PATTERN_TOLERANCE {
+            tracing::warn!(
+                "{}",
+                tr_args(
+                    Message::TestPatternMismatch,
+                    &[("output", capture.name.as_str().into())],
+                )
+            );
+            matches = false;
         }
     }
+    if !matches {
+        return Err(ExitStatus(1).into());
+    }
+    Ok(())
+}
+
+/// Start the headless compositor and application of `wayshot ci-shot`.
+fn start_ci_shot(ci_args: &ArgMatches, quirks: Quirks) -> Result<Headless, Box<dyn Error>> {
+    let timeout = parse_value(
+        ci_args,
+        "timeout",
+        |&milliseconds: &u64| milliseconds > 0,
+        || tr(Message::InvalidCiTimeout),
+    )?
+    .map_or(DEFAULT_CI_TIMEOUT, Duration::from_millis);
+    let compositor = ci_args
+        .value_of("compositor")
+        .unwrap_or(DEFAULT_CI_COMPOSITOR);
+    let command = ci_args.value_of("command").unwrap();
+    let capture_args = FrameArgs {
+        quirks,
+        ..Default::default()
+    };
+    start_headless(compositor, command, &capture_args, timeout).map_err(|e| {
+        let error = e.to_string().into();
+        tr_args(Message::CiShotFailed, &[("error", error)]).into()
+    })
+}
 
-    // Process and save outputs. (At the moment, just dump captures to files, using distinct names
-    // if there are many.)
-    let extension = if args.is_present("extension") {
-        let ext: &str = &args.value_of("extension").unwrap().trim().to_lowercase();
-        match ext {
-            "jpeg" | "jpg" => backend::EncodingFormat::Jpg,
-            "png" => backend::EncodingFormat::Png,
-            "ppm" => backend::EncodingFormat::Ppm,
-            _ => {
-                log::error!("Invalid extension provided.\nValid extensions:\n1) jpeg\n2) jpg\n3) png\n4) ppm");
-                exit(1);
+/// Output and region, in global logical coordinates, selected by --slurp, --region-provider,
+/// --geometry or a geometry piped by slurp.
+fn selected_region(
+    args: &ArgMatches,
+    decorations: Decorations,
+) -> Result<(Option<String>, Option<Region>), Box<dyn Error>> {
+    let region_provider: Option<Box<dyn RegionProvider>> = if let Some(geometry) =
+        args.value_of("slurp")
+    {
+        Some(Box::new(region::GeometryProvider {
+            geometry: geometry.to_string(),
+        }))
+    } else {
+        match args.value_of("region-provider") {
+            Some("active-window") => Some(Box::new(region::ActiveWindowProvider { decorations })),
+            name => name.and_then(region::provider_from_name),
+        }
+    };
+    let geometry = match args.value_of("geometry") {
+        Some("-") => match region::read_stdin_line() {
+            Ok(line) if !line.trim().is_empty() => Some(line),
+            Ok(_) => return Err(tr(Message::GeometryNotReceived).into()),
+            Err(e) => {
+                let error = e.to_string().into();
+                return Err(tr_args(Message::GeometryUnreadable, &[("error", error)]).into());
             }
+        },
+        spec => spec.map(str::to_string),
+    };
+    // Without anything telling what to capture, a geometry piped by slurp is used like -g -.
+    let targeted = [
+        "slurp",
+        "region-provider",
+        "app-id",
+        "crop",
+        "output",
+        "listoutputs",
+        "list-formats",
+    ]
+    .iter()
+    .any(|name| args.is_present(name));
+    let (geometry_output, geometry_region) = match geometry {
+        Some(spec) => {
+            region::parse_output_geometry(&spec).ok_or_else(|| tr(Message::InvalidGeometry))?
         }
-    } else {
-        backend::EncodingFormat::Png
+        None if !targeted && args.subcommand().is_none() => match region::piped_geometry() {
+            Some((output, region)) => {
+                tracing::debug!("Geometry from standard input: {:?} {:?}", output, region);
+                (output, Some(region))
+            }
+            None => (None, None),
+        },
+        None => (None, None),
+    };
+    let region = match region_provider {
+        Some(provider) => {
+            let region = provider.region()?;
+            tracing::debug!("Region from {} provider: {:?}", provider.name(), region);
+            Some(region)
+        }
+        None => geometry_region,
     };
+    Ok((geometry_output, region))
+}
 
-    if extension != backend::EncodingFormat::Png {
-        log::debug!("Using custom extension: {:#?}", extension);
-    }
+/// Windows along with their number, see [`Split::Windows`].
+type NumberedWindows = Vec<(Region, usize)>;
 
-    // TODO: render at 2x or higher scale later? Default should probably be >2x
-    // max fractional scale, or something close to a rational multiple of all outputs
-    let dest_width = (net_x2 - net_x1) as u32;
-    let dest_height = (net_y2 - net_y1) as u32;
-    let mut dest: RgbaImage = ImageBuffer::new(dest_width, dest_height);
+/// Regions along with their name, see [`Split::Crops`].
+type NamedCrops = Vec<(String, Region)>;
+
+/// Windows of --app-id, or only its --nth one, along with their number.
+fn app_windows(
+    args: &ArgMatches,
+    decorations: Decorations,
+) -> Result<Option<NumberedWindows>, Box<dyn Error>> {
+    let app_id = match args.value_of("app-id") {
+        Some(app_id) => app_id,
+        None => return Ok(None),
+    };
+    let windows = region::app_windows(app_id, decorations)?;
+    if windows.is_empty() {
+        let args = [("app_id", app_id.into())];
+        return Err(tr_args(Message::NoAppWindows, &args).into());
+    }
+    tracing::debug!("Windows of {}: {:?}", app_id, windows);
+    let count = windows.len();
+    let nth = parse_value(
+        args,
+        "nth",
+        |nth: &usize| (1..=count).contains(nth),
+        || tr_args(Message::InvalidWindowIndex, &[("count", count.into())]),
+    )?;
+    Ok(Some(match nth {
+        Some(nth) => vec![(windows[nth - 1], nth)],
+        None => windows.into_iter().zip(1..).collect(),
+    }))
+}
 
-    for output in state.outputs.iter_mut() {
-        match output.frame_state {
-            None => unreachable!(),
-            Some(FrameState::Failed) => {
-                log::error!("Frame copy failed");
-                exit(1);
+/// Regions of --crop along with their name, which must be unique.
+fn crops(args: &ArgMatches) -> Result<Option<NamedCrops>, Box<dyn Error>> {
+    let specs = match args.values_of("crop") {
+        Some(specs) => specs,
+        None => return Ok(None),
+    };
+    let mut crops: NamedCrops = Vec::new();
+    for spec in specs {
+        match region::parse_crop(spec) {
+            Some((name, _)) if crops.iter().any(|(taken, _)| *taken == name) => {
+                let args = [("name", name.into())];
+                return Err(tr_args(Message::DuplicateCropName, &args).into());
             }
-            Some(FrameState::Finished) => {
-                let mem_fd = output.mem_fd.unwrap();
+            Some(crop) => crops.push(crop),
+            None => {
+                let args = [("crop", spec.into())];
+                return Err(tr_args(Message::InvalidCrop, &args).into());
+            }
+        }
+    }
+    Ok(Some(crops))
+}
 
-                let frame_format = output.frame_format.unwrap();
+/// Commands of --pre-capture and --post-capture.
+fn capture_commands(
+    args: &ArgMatches,
+) -> Result<(Option<CaptureCommand>, Option<CaptureCommand>), Box<dyn Error>> {
+    let timeout = parse_value(
+        args,
+        "hook-timeout",
+        |&milliseconds: &u64| milliseconds > 0,
+        || tr(Message::InvalidHookTimeout),
+    )?
+    .map_or(DEFAULT_HOOK_TIMEOUT, Duration::from_millis);
+    let failure = parse_choice(args, "hook-failure", &HOOK_FAILURES)?.unwrap_or_default();
+    let capture_command = |name: &str| {
+        args.value_of(name).map(|command| CaptureCommand {
+            command: command.to_string(),
+            timeout,
+            failure,
+        })
+    };
+    Ok((
+        capture_command("pre-capture"),
+        capture_command("post-capture"),
+    ))
+}
 
-                let mem_file = unsafe { File::from_raw_fd(mem_fd) };
-                let mut frame_mmap = unsafe { MmapMut::map_mut(&mem_file)? };
-                let data = &mut *frame_mmap;
-                if let Some(converter) = create_converter(frame_format.format) {
-                    converter.convert_inplace(data)
-                } else {
-                    log::error!("Unsupported buffer format: {:?}", frame_format.format);
-                    log::error!("You can send a feature request for the above format to the mailing list for wayshot over at https://sr.ht/~shinyzenith/wayshot.");
-                    exit(1);
-                };
-                let frame_image = RgbaImage::from_raw(
-                    frame_format.width,
-                    frame_format.height,
-                    (&*frame_mmap).to_vec(),
-                )
-                .unwrap();
+/// Options of the encoders, those of --extension and the like start from.
+fn encoding_options(args: &ArgMatches) -> Result<EncodingOptions, Box<dyn Error>> {
+    let mut options = EncodingOptions {
+        jpeg_progressive: args.is_present("jpeg-progressive"),
+        png_interlaced: args.is_present("png-interlace"),
+        png_srgb: args.is_present("png-srgb"),
+        tiff_16bit: args.is_present("tiff-16bit"),
+        deterministic: args.is_present("deterministic"),
+        ..Default::default()
+    };
+    options.png_palette = parse_value(
+        args,
+        "palette",
+        |colors: &u16| (2..=MAX_PALETTE_COLORS).contains(colors),
+        || {
+            tr_args(
+                Message::InvalidPaletteSize,
+                &[("max", MAX_PALETTE_COLORS.into())],
+            )
+        },
+    )?;
+    if let Some(subsampling) = parse_choice(args, "jpeg-subsampling", &JPEG_SUBSAMPLINGS)? {
+        options.jpeg_subsampling = subsampling;
+    }
+    if let Some(filter) = parse_choice(args, "png-filter", &PNG_FILTERS)? {
+        options.png_filter = filter;
+    }
+    if let Some(compression) = parse_choice(args, "tiff-compression", &TIFF_COMPRESSIONS)? {
+        options.tiff_compression = compression;
+    }
+    Ok(options)
+}
 
-                let x1: i32 = cmp::max(output.dimensions.x, region.x_coordinate);
-                let y1: i32 = cmp::max(output.dimensions.y, region.y_coordinate);
-                let x2: i32 = cmp::min(
-                    output.dimensions.x + output.dimensions.width,
-                    region.x_coordinate + region.width,
-                );
-                let y2: i32 = cmp::min(
-                    output.dimensions.y + output.dimensions.height,
-                    region.y_coordinate + region.height,
-                );
+/// Stem of generated file names, see [`format_local_time`].
+fn filename_format(args: &ArgMatches) -> &str {
+    args.value_of("filename-format")
+        .unwrap_or(DEFAULT_FILENAME_FORMAT)
+}
 
-                let resized: RgbaImage = resize(
-                    &frame_image,
-                    (x2 - x1) as u32,
-                    (y2 - y1) as u32,
-                    image::imageops::FilterType::Triangle,
-                );
-                if let Err(e) = dest.copy_from(&resized, (x1 - net_x1) as u32, (y1 - net_y1) as u32)
-                {
-                    log::error!("Failed to copy output image onto dest image: {:?}", e);
-                    exit(1);
-                }
+/// Where and how images are saved, see [`FileSink`].
+fn file_sink(
+    args: &ArgMatches,
+    bug_report: Option<&Path>,
+    progress: Option<Rc<dyn Progress>>,
+) -> Result<FileSink, Box<dyn Error>> {
+    let base_options = encoding_options(args)?;
+    // Auto picks the encoder of every image once it is rendered, png standing in until then.
+    let auto_format = args
+        .value_of("extension")
+        .is_some_and(|ext| ext.trim().eq_ignore_ascii_case("auto"));
+    if auto_format && args.is_present("stream") {
+        return Err(tr(Message::AutoFormatStream).into());
+    }
+    let (format, options) = match args.value_of("extension") {
+        Some(ext) if !auto_format => {
+            parse_encoding(ext, base_options).ok_or_else(|| invalid_encoding(true))?
+        }
+        _ => (EncodingFormat::Png, base_options),
+    };
+    if format != EncodingFormat::Png {
+        tracing::debug!("Using custom extension: {:#?}", format);
+    }
+    if args.is_present("dzi")
+        && !auto_format
+        && !matches!(format, EncodingFormat::Png | EncodingFormat::Jpg)
+    {
+        return Err(tr(Message::InvalidDziFormat).into());
+    }
 
-                // todo: cleanup?
-            }
+    let output_formats = match args.value_of("output-format") {
+        Some(spec) => parse_output_formats(spec, base_options)
+            .ok_or_else(|| tr(Message::InvalidOutputFormats))?,
+        None => HashMap::new(),
+    };
+    // Extensions after the first one are also saved next to the composite.
+    let mut also_save = Vec::new();
+    for ext in args.values_of("extension").into_iter().flatten().skip(1) {
+        let (format, options) =
+            parse_encoding(ext, base_options).ok_or_else(|| invalid_encoding(false))?;
+        also_save.push((format, options, None));
+    }
+    for spec in args.values_of("also-save").into_iter().flatten() {
+        let (format, options, path) =
+            parse_also_save(spec, base_options).ok_or_else(|| tr(Message::InvalidAlsoSave))?;
+        also_save.push((format, options, Some(path)));
+    }
+    for format in output_formats
+        .values()
+        .map(|(format, _)| format)
+        .chain(also_save.iter().map(|(format, _, _)| format))
+        .chain([&format])
+    {
+        if !format.is_available() {
+            let args = [("feature", format.extension().into())];
+            return Err(tr_args(Message::FeatureUnavailable, &args).into());
         }
     }
 
-    if args.is_present("stdout") {
-        let stdout = stdout();
-        let writer = BufWriter::new(stdout.lock());
-        backend::write_to_file(writer, extension, dest)?;
-    } else {
-        let path = if args.is_present("file") {
-            args.value_of("file").unwrap().trim().to_string()
-        } else {
-            let time = match SystemTime::now().duration_since(UNIX_EPOCH) {
-                Ok(n) => n.as_secs().to_string(),
-                Err(_) => {
-                    log::error!("SystemTime before UNIX EPOCH!");
-                    exit(1);
-                }
-            };
+    let thumbnail = args
+        .value_of("thumbnail")
+        .map(|size| parse_size(size).ok_or_else(|| tr(Message::InvalidThumbnailSize)))
+        .transpose()?;
+    let stem = format_local_time(filename_format(args)).ok_or_else(|| {
+        let args = [("example", DEFAULT_FILENAME_FORMAT.into())];
+        tr_args(Message::InvalidFilenameFormat, &args)
+    })?;
+    let directory = match bug_report {
+        Some(directory) => directory.as_os_str(),
+        None => args.value_of_os("dir").unwrap_or(OsStr::new(".")),
+    };
+    let max_size = args
+        .value_of("max-size")
+        .map(|size| {
+            parse_byte_size(size)
+                .filter(|&size| size > 0)
+                .ok_or_else(|| tr(Message::InvalidMaxSize))
+        })
+        .transpose()?;
+    Ok(FileSink {
+        directory: PathBuf::from(directory),
+        stem,
+        file: args
+            .value_of_os("file")
+            .map(|file| PathBuf::from(trim_os(file))),
+        stdout: args.is_present("stdout"),
+        format,
+        options,
+        output_formats,
+        thumbnail,
+        private: args.is_present("private"),
+        auto_format,
+        encrypt: args.value_of("encrypt").map(str::to_string),
+        collision: parse_choice(args, "collision", &COLLISIONS)?,
+        progress,
+        auto_degrade: args.is_present("auto-degrade"),
+        also_save,
+        checksum: parse_choice(args, "checksum", &CHECKSUMS)?,
+        last_checksum: None,
+        max_size,
+    })
+}
+
+/// Whether the compositor should overlay the cursor and whether wayshot should draw it itself,
+/// see [`CURSOR_MODES`]. Forced cursors are drawn from the cursor theme instead of letting the
+/// compositor overlay them, which some compositors never do for hardware cursor planes.
+fn cursor_mode(args: &ArgMatches, quirks: Quirks) -> Result<(bool, bool), Box<dyn Error>> {
+    if !args.is_present("cursor") {
+        return Ok((false, false));
+    }
+    match parse_choice(args, "cursor", &CURSOR_MODES)? {
+        None | Some((true, false)) if quirks.software_cursor => Ok((false, true)),
+        None => Ok((true, false)),
+        Some(mode) => Ok(mode),
+    }
+}
+
+/// Adjustments of --gamma, --brightness, --contrast and --invert.
+fn tone_adjustment(args: &ArgMatches) -> Result<ToneAdjustment, Box<dyn Error>> {
+    let defaults = ToneAdjustment::default();
+    let max = || [("max", MAX_TONE_FACTOR.into())];
+    Ok(ToneAdjustment {
+        gamma: parse_value(
+            args,
+            "gamma",
+            |&gamma: &f64| gamma > 0.0 && gamma <= MAX_TONE_FACTOR,
+            || tr_args(Message::InvalidGamma, &max()),
+        )?
+        .unwrap_or(defaults.gamma),
+        brightness: parse_value(
+            args,
+            "brightness",
+            |brightness: &f64| (-1.0..=1.0).contains(brightness),
+            || tr(Message::InvalidBrightness),
+        )?
+        .unwrap_or(defaults.brightness),
+        contrast: parse_value(
+            args,
+            "contrast",
+            |contrast: &f64| (0.0..=MAX_TONE_FACTOR).contains(contrast),
+            || tr_args(Message::InvalidContrast, &max()),
+        )?
+        .unwrap_or(defaults.contrast),
+        invert: args.is_present("invert"),
+    })
+}
+
+/// The stages of the screenshot, up to the post processors of hooks, see [`Pipeline`].
+fn pipeline_builder<'a>(
+    args: &ArgMatches,
+    split: Split,
+    forced_cursor: Option<ForcedCursor>,
+    progress: Option<Rc<dyn Progress>>,
+) -> Result<PipelineBuilder<'a>, Box<dyn Error>> {
+    let stack_count = parse_value(
+        args,
+        "stack",
+        |count: &usize| (1..=MAX_STACK_FRAMES).contains(count),
+        || {
+            tr_args(
+                Message::InvalidStackCount,
+                &[("max", MAX_STACK_FRAMES.into())],
+            )
+        },
+    )?
+    .unwrap_or(1);
+    let stack_mode = parse_choice(args, "stack-mode", &STACK_MODES)?.unwrap_or(StackMode::Mean);
+    let scale = match args.value_of("scale") {
+        Some("max") => Scale::Max,
+        _ => Scale::Fixed(
+            parse_value(
+                args,
+                "scale",
+                |&scale: &f64| scale > 0.0,
+                || tr(Message::InvalidScale),
+            )?
+            .unwrap_or(1.0),
+        ),
+    };
+    let mut pipeline = Pipeline::builder(WaylandBackend)
+        .stack(stack_count, stack_mode)
+        .scale(scale)
+        .split(split);
+    if let Some(progress) = progress {
+        pipeline = pipeline.progress(progress);
+    }
+    // Before anything else, so that nothing can reveal what the windows show.
+    if let Some(path) = args.value_of_os("redact") {
+        let redactions = load_redactions(Path::new(path))?;
+        let style = parse_choice(args, "redact-style", &REDACT_STYLES)?.unwrap_or_default();
+        if !redactions.is_empty() {
+            pipeline = pipeline.post_process(Redact { redactions, style });
+        }
+    }
+    // Before anything is drawn onto the image, which would only show up as changes.
+    if let Some(("diff", diff_args)) = args.subcommand() {
+        let path = diff_args.value_of_os("highlight").unwrap();
+        let reference = image::open(path).map_err(|e| {
+            tr_args(
+                Message::ReferenceUnreadable,
+                &[
+                    ("path", path.to_string_lossy().into_owned().into()),
+                    ("error", e.to_string().into()),
+                ],
+            )
+        })?;
+        let tolerance = parse_value(
+            diff_args,
+            "tolerance",
+            |_: &u8| true,
+            || tr(Message::InvalidDiffTolerance),
+        )?
+        .unwrap_or(0);
+        pipeline = pipeline.post_process(HighlightChanges {
+            reference: reference.to_rgba8(),
+            tolerance,
+        });
+    }
+    // Before zoom, which leaves more pixels to adjust, and whatever is drawn in its own colors.
+    let tones = tone_adjustment(args)?;
+    if !tones.is_identity() {
+        pipeline = pipeline.post_process(AdjustTones(tones));
+    }
+    // How the adjusted colors look, before the cursor and annotations, which are no part of the
+    // screen being checked.
+    if let Some(vision) = parse_choice(args, "simulate", &COLOR_VISIONS)? {
+        pipeline = pipeline.post_process(SimulateColorVision(vision));
+    }
+    if let Some(forced_cursor) = forced_cursor {
+        pipeline = pipeline.post_process(forced_cursor);
+    }
+    // Before annotations, whose coordinates are in pixels of the final image.
+    let zoom_factor = parse_value(
+        args,
+        "zoom",
+        |&factor: &f64| factor > 0.0 && factor <= MAX_ZOOM,
+        || tr_args(Message::InvalidZoom, &[("max", MAX_ZOOM.into())]),
+    )?;
+    let zoom_filter = parse_choice(args, "zoom-filter", &ZOOM_FILTERS)?.unwrap_or_default();
+    if let Some(factor) = zoom_factor {
+        pipeline = pipeline.post_process(Zoom {
+            factor,
+            filter: zoom_filter,
+            grid: args.is_present("zoom-grid"),
+        });
+    }
+    #[cfg(not(feature = "annotations"))]
+    if args.is_present("draw") {
+        let args = [("feature", "--draw".into())];
+        return Err(tr_args(Message::FeatureUnavailable, &args).into());
+    }
+    #[cfg(feature = "annotations")]
+    if let Some(spec) = args.value_of("draw") {
+        let annotations = annotate::parse_annotations(spec).map_err(|e| {
+            let error = e.to_string().into();
+            tr_args(Message::InvalidAnnotations, &[("error", error)])
+        })?;
+        if !annotations.is_empty() {
+            pipeline = pipeline.post_process(Annotations(annotations));
+        }
+    }
+    if args.is_present("auto-trim") {
+        let tolerance = args
+            .value_of("trim-tolerance")
+            .unwrap_or("0")
+            .parse::<u8>()
+            .map_err(|_| tr(Message::InvalidTrimTolerance))?;
+        pipeline = pipeline.post_process(AutoTrim { tolerance });
+    }
+    Ok(pipeline)
+}
+
+/// What to capture and how, see [`FrameArgs`]. `output` and `region` are those selected, see
+/// [`selected_region`], the output of --output taking precedence.
+fn frame_args(
+    args: &ArgMatches,
+    quirks: Quirks,
+    output: Option<String>,
+    region: Option<Region>,
+    layout: Layout,
+    cursor: bool,
+) -> Result<FrameArgs, Box<dyn Error>> {
+    let force_format = args
+        .value_of("force-format")
+        .map(|name| {
+            parse_shm_format(name).ok_or_else(|| {
+                let names = CONVERTIBLE_FORMATS.map(|(name, _)| name);
+                format!(
+                    "{}\n{}",
+                    tr(Message::InvalidForceFormat),
+                    valid_arguments(names)
+                )
+            })
+        })
+        .transpose()?;
+    let operator = parse_choice(args, "tone-map", &TONE_MAP_OPERATORS)?.unwrap_or_default();
+    let tone_mapping = args
+        .value_of("hdr-outputs")
+        .map(|outputs| {
+            parse_hdr_outputs(outputs)
+                .map(|outputs| ToneMapping { outputs, operator })
+                .ok_or_else(|| tr(Message::InvalidHdrOutputs))
+        })
+        .transpose()?;
+    let output_timeout = parse_value(
+        args,
+        "output-timeout",
+        |&milliseconds: &u64| milliseconds > 0,
+        || tr(Message::InvalidOutputTimeout),
+    )?
+    .map(Duration::from_millis);
+    Ok(FrameArgs {
+        cursor,
+        output: args.value_of("output").map(str::to_string).or(output),
+        listoutputs: args.is_present("listoutputs"),
+        listformats: args.is_present("list-formats"),
+        force_format,
+        region,
+        capture_on_present: args.is_present("capture-on-present"),
+        layout,
+        all_clones: args.is_present("all-clones"),
+        deadline: None,
+        quirks,
+        output_timeout,
+        fast: args.is_present("fast"),
+        tone_mapping,
+    })
+}
 
-            time + match extension {
-                backend::EncodingFormat::Png => "-wayshot.png",
-                backend::EncodingFormat::Jpg => "-wayshot.jpg",
-                backend::EncodingFormat::Ppm => "-wayshot.ppm",
+/// `wayshot serve`, see [`serve::serve`].
+fn serve_captures(
+    args: &ArgMatches,
+    serve_args: &ArgMatches,
+    pipeline: &Pipeline,
+    frame_args: &FrameArgs,
+    options: EncodingOptions,
+) -> Result<(), Box<dyn Error>> {
+    if !EncodingFormat::Jpg.is_available() {
+        let args = [("feature", "serve".into())];
+        return Err(tr_args(Message::FeatureUnavailable, &args).into());
+    }
+    let port = parse_value(
+        serve_args,
+        "port",
+        |&port: &u16| port > 0,
+        || tr(Message::InvalidPort),
+    )?
+    .unwrap_or(serve::DEFAULT_PORT);
+    let max = || [("max", serve::MAX_FPS.into())];
+    let fps = parse_value(
+        serve_args,
+        "fps",
+        |fps: &u32| (1..=serve::MAX_FPS).contains(fps),
+        || tr_args(Message::InvalidFps, &max()),
+    )?
+    .unwrap_or(serve::DEFAULT_FPS);
+    let battery_fps = parse_value(
+        serve_args,
+        "battery-fps",
+        |&battery_fps: &u32| battery_fps <= serve::MAX_FPS,
+        || tr_args(Message::InvalidBatteryFps, &max()),
+    )?;
+    let address = serve_args
+        .value_of("bind")
+        .unwrap_or(serve::DEFAULT_BIND_ADDRESS);
+    let listener = TcpListener::bind((address, port)).map_err(|e| {
+        tr_args(
+            Message::ServeBindFailed,
+            &[
+                ("address", format!("{}:{}", address, port).into()),
+                ("error", e.to_string().into()),
+            ],
+        )
+    })?;
+    // Serve threads inherit the blocked exit signals, which leaves them to the thread
+    // restoring do-not-disturb.
+    if args.is_present("dnd") {
+        if let Some(dnd) = enable_dnd() {
+            if let Err(e) = dnd.restore_on_exit_signal() {
+                let error = e.to_string().into();
+                tracing::warn!("{}", tr_args(Message::DndFailed, &[("error", error)]));
             }
+        }
+    }
+    let url = format!("http://{}", listener.local_addr()?);
+    tracing::info!("{}", tr_args(Message::Serving, &[("url", url.into())]));
+    serve::serve(listener, pipeline, frame_args, fps, battery_fps, options)
+}
+
+/// `wayshot watch`, see [`watch`].
+fn watch_captures(
+    args: &ArgMatches,
+    watch_args: &ArgMatches,
+    pipeline: &Pipeline,
+    frame_args: &FrameArgs,
+    sink: &mut FileSink,
+) -> Result<(), Box<dyn Error>> {
+    let rules = load_rules(Path::new(watch_args.value_of_os("rules").unwrap()))?;
+    watch(rules, |output| {
+        let mut frame_args = frame_args.clone();
+        if let Some(output) = output {
+            frame_args.output = Some(output.to_string());
+        }
+        // Validated along with the stem of the first screenshot.
+        if let Some(stem) = format_local_time(filename_format(args)) {
+            sink.stem = stem;
+        }
+        let _capture_lock = if args.is_present("no-lock") {
+            None
+        } else {
+            Some(lock::CaptureLock::acquire()?)
         };
+        pipeline.run(&frame_args, sink)
+    })
+}
 
-        backend::write_to_file(File::create(path)?, extension, dest)?;
+/// Capture `frame_args` through `pipeline` between the capture commands, recording how it went
+/// in the provenance of --bug-report.
+fn capture(
+    args: &ArgMatches,
+    pipeline: &Pipeline,
+    frame_args: &FrameArgs,
+    bug_report: Option<&Path>,
+    pre_capture: Option<&CaptureCommand>,
+    post_capture: Option<&CaptureCommand>,
+) -> Result<Captured, Box<dyn Error>> {
+    // Capture commands learn what is about to be captured, and the post capture one whether it
+    // was.
+    let mut hook_env = Vec::new();
+    if let Some(output) = &frame_args.output {
+        hook_env.push(("WAYSHOT_OUTPUT", output.clone()));
+    }
+    if let Some(r) = frame_args.region {
+        let region = format!("{},{} {}x{}", r.x, r.y, r.width, r.height);
+        hook_env.push(("WAYSHOT_REGION", region));
+    }
+    // Written before capturing as well, so that it is there when wayshot exits early.
+    let mut provenance = bug_report.map(|directory| {
+        let provenance = Provenance::collect(frame_args.quirks);
+        write_bug_report_provenance(directory, &provenance, args.is_present("private"));
+        provenance
+    });
+
+    // Held until the frames are captured.
+    let dnd = if args.is_present("dnd") {
+        enable_dnd()
+    } else {
+        None
+    };
+    let captured = match pre_capture {
+        Some(command) => command.run("pre-capture", &hook_env),
+        None => Ok(()),
+    }
+    .and_then(|()| pipeline.capture(frame_args));
+    if let Some(command) = post_capture {
+        match &captured {
+            Ok(captured) => {
+                let outputs: Vec<&str> =
+                    captured.captures.iter().map(|c| c.name.as_str()).collect();
+                hook_env.push(("WAYSHOT_OUTPUTS", outputs.join(",")));
+                hook_env.push(("WAYSHOT_CAPTURE_STATUS", "ok".to_string()));
+            }
+            Err(_) => hook_env.push(("WAYSHOT_CAPTURE_STATUS", "failed".to_string())),
+        }
+        let ran = command.run("post-capture", &hook_env);
+        if captured.is_ok() {
+            ran?;
+        }
     }
+    drop(dnd);
+    if let (Some(directory), Some(provenance)) = (bug_report, &mut provenance) {
+        match &captured {
+            Ok(captured) => {
+                provenance.frames = captured
+                    .captures
+                    .iter()
+                    .map(FrameProvenance::from_capture)
+                    .collect();
+            }
+            Err(e) => provenance.error = Some(e.to_string()),
+        }
+        write_bug_report_provenance(directory, provenance, args.is_present("private"));
+    }
+    captured
+}
 
+/// Fail with [`BLANK_FRAME_EXIT_CODE`] for --verify when a frame is of a single color.
+fn verify_frames(captured: &Captured) -> Result<(), Box<dyn Error>> {
+    let mut blank = false;
+    for capture in &captured.captures {
+        if let Some([r, g, b, a]) = uniform_color(capture) {
+            let color = format!("#{:02x}{:02x}{:02x}{:02x}", r, g, b, a);
+            tracing::warn!(
+                "{}",
+                tr_args(
+                    Message::BlankFrame,
+                    &[
+                        ("output", capture.name.as_str().into()),
+                        ("color", color.into()),
+                    ],
+                )
+            );
+            blank = true;
+        }
+    }
+    if blank {
+        return Err(ExitStatus(BLANK_FRAME_EXIT_CODE).into());
+    }
     Ok(())
 }
+
+/// Write `captured` to `sink`, or the tiles of --dzi, printing the measurements of `analyses`.
+/// With --stream, or for 16 bit tiff images, the image is written straight from the captures
+/// when it can be.
+fn write_screenshot(
+    args: &ArgMatches,
+    pipeline: &Pipeline,
+    mut captured: Captured,
+    sink: &mut FileSink,
+    analyses: Vec<Analysis>,
+) -> Result<(), Box<dyn Error>> {
+    let (extension, encoding_options) = (sink.format, sink.options);
+    let mut dzi = args.value_of_os("dzi").map(|directory| DziSink {
+        directory: PathBuf::from(directory),
+        stem: sink.stem.clone(),
+        format: extension,
+        options: encoding_options,
+        auto_format: sink.auto_format,
+        private: args.is_present("private"),
+        collision: sink.collision.unwrap_or_default(),
+    });
+
+    let streamed = args.is_present("stream") && {
+        let can_stream = sink.also_save.is_empty()
+            && sink.max_size.is_none()
+            && pipeline.can_stream(&captured, extension, &encoding_options);
+        if !can_stream {
+            tracing::warn!("{}", tr(Message::StreamFallback));
+        }
+        can_stream
+    };
+    // 16 bit tiff images skip the 8 bit pipeline when they can, keeping the precision of 10 bit
+    // outputs.
+    let deep = !streamed
+        && extension == EncodingFormat::Tiff
+        && encoding_options.tiff_16bit
+        && sink.output_formats.is_empty()
+        && sink.thumbnail.is_none()
+        && sink.encrypt.is_none()
+        && sink.also_save.is_empty()
+        && sink.max_size.is_none()
+        && dzi.is_none()
+        && analyses.is_empty()
+        && pipeline.can_composite_raw(&captured);
+    if streamed || deep {
+        let path = if args.is_present("stdout") {
+            None
+        } else {
+            Some(sink.path(Subject::Composite)?)
+        };
+        let writer: Box<dyn Write> = match &path {
+            Some(path) => Box::new(BufWriter::new(sink.create(path)?)),
+            None => Box::new(BufWriter::new(stdout())),
+        };
+        let written = if streamed {
+            backend::write_streamed(writer, extension, &encoding_options, &mut captured.captures)
+        } else {
+            backend::write_deep_tiff(writer, &encoding_options, &captured.captures)
+        };
+        return match (written, path, sink.checksum) {
+            (Ok(()), Some(path), Some(checksum)) => write_checksum(checksum, &path, sink.private)
+                .map(|_| ())
+                .map_err(Into::into),
+            (written, ..) => written,
+        };
+    }
+
+    let inner: Option<&mut dyn Sink> = if args.is_present("analyze-only") {
+        None
+    } else if let Some(dzi) = &mut dzi {
+        Some(dzi)
+    } else {
+        Some(sink)
+    };
+    match inner {
+        Some(inner) if analyses.is_empty() => pipeline.render(captured, inner),
+        inner => pipeline.render(captured, &mut AnalyzeSink { analyses, inner }),
+    }
+}
@@ -0,0 +1,348 @@
+use std::{
+    fs::File,
+    io::{self, ErrorKind},
+    os::unix::prelude::{AsRawFd, FromRawFd},
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+use nix::{
+    errno::Errno,
+    poll::{poll, PollFd, PollFlags},
+};
+use wayland_client::{
+    backend::WaylandError,
+    protocol::{
+        wl_buffer::{self, WlBuffer},
+        wl_shm::WlShm,
+        wl_shm_pool::{self, WlShmPool},
+    },
+    Connection, Dispatch, DispatchError, EventQueue, Proxy, QueueHandle,
+};
+use wayland_protocols_wlr::screencopy::v1::client::{
+    zwlr_screencopy_frame_v1::{self, ZwlrScreencopyFrameV1},
+    zwlr_screencopy_manager_v1::ZwlrScreencopyManagerV1,
+};
+
+use crate::{
+    backend::{create_memory_fd, create_shm_fd, FrameFormat, FrameState},
+    dispatch_error,
+    i18n::{tr, tr_args, Message},
+    output::OutputInfo,
+    FrameArgs, Region,
+};
+
+/// Where the frames of a capture are allocated, see [`capture_frame`].
+#[derive(Debug)]
+pub enum FrameMemory {
+    /// Shared memory, or a file in XDG_RUNTIME_DIR when it is unavailable.
+    Shm,
+    /// Shared memory only, for captures confined by [`capture_for_locker`]. Holds the bytes left
+    /// for frames, claimed before each one is allocated, so that a capture going over them fails
+    /// without allocating the frame.
+    ///
+    /// [`capture_for_locker`]: crate::capture_for_locker
+    Confined(AtomicU64),
+}
+
+/// Frame of a single output, dispatched on an event queue of its own.
+#[derive(Debug, Default)]
+pub struct OutputFrame {
+    pub frame_format: Option<FrameFormat>,
+    /// None until the compositor copied the frame, failed to, or the output timed out.
+    pub state: Option<FrameState>,
+    /// Shm buffer the frame is copied to, closed when the frame is dropped whatever its state.
+    pub mem_file: Option<File>,
+    /// CLOCK_MONOTONIC time of the ready event.
+    pub presented_at: Option<Duration>,
+    /// From zwlr_screencopy_frame_v1::Event::Flags.
+    pub y_invert: bool,
+}
+
+impl Dispatch<ZwlrScreencopyFrameV1, ()> for OutputFrame {
+    fn event(
+        frame: &mut Self,
+        _: &ZwlrScreencopyFrameV1,
+        event: zwlr_screencopy_frame_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        match event {
+            zwlr_screencopy_frame_v1::Event::Buffer {
+                format,
+                width,
+                height,
+                stride,
+            } => {
+                tracing::debug!("Received Buffer event");
+                frame.frame_format = Some(FrameFormat {
+                    format: format.into_result().unwrap(),
+                    width,
+                    height,
+                    stride,
+                });
+            }
+            zwlr_screencopy_frame_v1::Event::Flags { flags } => {
+                tracing::debug!("Received Flags event");
+                frame.y_invert = matches!(
+                    flags.into_result(),
+                    Ok(flags) if flags.contains(zwlr_screencopy_frame_v1::Flags::YInvert)
+                );
+            }
+            zwlr_screencopy_frame_v1::Event::Ready {
+                tv_sec_hi,
+                tv_sec_lo,
+                tv_nsec,
+            } => {
+                // If the frame is successfully copied, a “flags” and a “ready” events are sent. Otherwise, a “failed” event is sent.
+                // This is useful when we call .copy on the frame object.
+                tracing::debug!("Received Ready event");
+                let tv_sec = ((tv_sec_hi as u64) << 32) | tv_sec_lo as u64;
+                frame.presented_at = Some(Duration::new(tv_sec, tv_nsec));
+                frame.state = Some(FrameState::Finished)
+            }
+            zwlr_screencopy_frame_v1::Event::Failed => {
+                tracing::debug!("Received Failed event");
+                frame.state = Some(FrameState::Failed);
+            }
+            zwlr_screencopy_frame_v1::Event::Damage { .. } => {
+                tracing::debug!("Received Damage event");
+            }
+            zwlr_screencopy_frame_v1::Event::LinuxDmabuf { .. } => {
+                tracing::debug!("Received LinuxDmaBuf event");
+            }
+            zwlr_screencopy_frame_v1::Event::BufferDone => {
+                tracing::debug!("Received bufferdone event");
+                // todo: verify this arrived
+            }
+            _ => unreachable!(),
+        };
+    }
+}
+
+impl Dispatch<WlBuffer, ()> for OutputFrame {
+    fn event(
+        _: &mut Self,
+        _: &WlBuffer,
+        _: wl_buffer::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<WlShmPool, ()> for OutputFrame {
+    fn event(
+        _: &mut Self,
+        _: &WlShmPool,
+        _: wl_shm_pool::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+/// Capture `area` of `output`, in output logical coordinates, on an event queue of its own.
+/// Meant to run on a worker thread per output, so that an output whose frame never comes, eg:
+/// one turned off by DPMS, only holds up its own worker. Such outputs end up in
+/// [`FrameState::TimedOut`] once --output-timeout passes. With --list-formats, returns as soon
+/// as the frame format is known. The frame is allocated in `memory`.
+#[tracing::instrument(skip_all, fields(output = %output.name))]
+pub fn capture_frame(
+    conn: &Connection,
+    screencopy: &ZwlrScreencopyManagerV1,
+    shm: &WlShm,
+    output: &OutputInfo,
+    area: Region,
+    args: &FrameArgs,
+    memory: &FrameMemory,
+) -> Result<OutputFrame, String> {
+    let mut queue = conn.new_event_queue();
+    let qh = queue.handle();
+    let mut frame = OutputFrame::default();
+    let timeout = args.output_timeout.map(|timeout| Instant::now() + timeout);
+
+    let cursor_overlay: i32 = if args.cursor { 1 } else { 0 };
+    let screencopy_frame = screencopy.capture_output_region(
+        cursor_overlay,
+        &output.wl_output,
+        area.x,
+        area.y,
+        area.width,
+        area.height,
+        &qh,
+        (),
+    );
+
+    // The compositor describes the buffer it wants right away.
+    if !dispatch_until(&mut queue, &mut frame, timeout, |frame| {
+        frame.frame_format.is_some() || frame.state.is_some()
+    })? {
+        screencopy_frame.destroy();
+        frame.state = Some(FrameState::TimedOut);
+        return Ok(frame);
+    }
+    let frame_format = match frame.frame_format {
+        Some(frame_format) if !args.listformats && frame.state.is_none() => frame_format,
+        _ => return Ok(frame),
+    };
+
+    let frame_bytes = frame_format.buffer_size().ok_or_else(|| {
+        tr_args(
+            Message::InvalidFrameSize,
+            &[
+                ("output", output.name.as_str().into()),
+                ("width", frame_format.width.into()),
+                ("height", frame_format.height.into()),
+                ("stride", frame_format.stride.into()),
+            ],
+        )
+    })?;
+
+    if let Some(format) = args
+        .force_format
+        .filter(|&format| format != frame_format.format)
+    {
+        // Every format wayshot can convert has 4 bytes per pixel.
+        if frame_format.stride < frame_format.width * 4 {
+            return Err(tr_args(
+                Message::ForcedFormatMismatch,
+                &[
+                    ("output", output.name.as_str().into()),
+                    ("format", format!("{:?}", frame_format.format).into()),
+                ],
+            ));
+        }
+        tracing::debug!(
+            "Reading the frame of {} as {:?} instead of {:?}",
+            output.name,
+            format,
+            frame_format.format
+        );
+        frame.frame_format = Some(FrameFormat {
+            format,
+            ..frame_format
+        });
+    }
+
+    if let FrameMemory::Confined(budget) = memory {
+        budget
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |left| {
+                left.checked_sub(frame_bytes as u64)
+            })
+            .map_err(|left| {
+                tr_args(
+                    Message::FrameBudgetExceeded,
+                    &[
+                        ("output", output.name.as_str().into()),
+                        ("needed", frame_bytes.into()),
+                        ("left", left.into()),
+                    ],
+                )
+            })?;
+    }
+
+    // Create an in memory file and return it's file descriptor. Confined captures never fall
+    // back to a file on disk.
+    let mem_fd = match memory {
+        FrameMemory::Shm => create_shm_fd(),
+        FrameMemory::Confined(_) => create_memory_fd().and_then(|mem_fd| {
+            mem_fd.ok_or_else(|| io::Error::new(ErrorKind::Unsupported, "No shared memory"))
+        }),
+    }
+    .map_err(|e| e.to_string())?;
+    let mem_file = unsafe { File::from_raw_fd(mem_fd) };
+    mem_file
+        .set_len(frame_bytes as u64)
+        .map_err(|e| e.to_string())?;
+    frame.mem_file = Some(mem_file);
+
+    let shm_pool = shm.create_pool(mem_fd, frame_bytes, &qh, ());
+    let buffer = shm_pool.create_buffer(
+        0,
+        frame_format.width as i32,
+        frame_format.height as i32,
+        frame_format.stride as i32,
+        frame_format.format,
+        &qh,
+        (),
+    );
+
+    // Copy the pixel data advertised by the compositor into the buffer we just created.
+    if args.capture_on_present
+        && screencopy_frame.version() >= 2
+        && !args.quirks.no_copy_with_damage
+    {
+        screencopy_frame.copy_with_damage(&buffer);
+    } else {
+        if args.capture_on_present {
+            tracing::warn!("{}", tr(Message::CopyWithDamageUnsupported));
+        }
+        screencopy_frame.copy(&buffer);
+    }
+
+    if !dispatch_until(&mut queue, &mut frame, timeout, |frame| {
+        frame.state.is_some()
+    })? {
+        screencopy_frame.destroy();
+        frame.state = Some(FrameState::TimedOut);
+    }
+    Ok(frame)
+}
+
+/// Dispatch the events of `queue` until `done` holds, returning false if `timeout` passes
+/// first. Workers share the connection, so the socket is read through read guards like
+/// [`EventQueue::blocking_dispatch`] does, polling it with a timeout instead of blocking.
+fn dispatch_until(
+    queue: &mut EventQueue<OutputFrame>,
+    frame: &mut OutputFrame,
+    timeout: Option<Instant>,
+    done: impl Fn(&OutputFrame) -> bool,
+) -> Result<bool, String> {
+    loop {
+        queue.dispatch_pending(frame).map_err(worker_error)?;
+        if done(frame) {
+            return Ok(true);
+        }
+        let poll_timeout = match timeout {
+            Some(timeout) => {
+                let left = timeout.saturating_duration_since(Instant::now());
+                if left.is_zero() {
+                    return Ok(false);
+                }
+                // Rounded up, so that the deadline has passed when the poll times out.
+                (left.as_nanos().div_ceil(1_000_000)).min(i32::MAX as u128) as i32
+            }
+            None => -1,
+        };
+
+        queue.flush().map_err(worker_error)?;
+        let guard = queue.prepare_read().map_err(worker_error)?;
+        // Another worker may have read events of this queue since they were dispatched.
+        if queue.dispatch_pending(frame).map_err(worker_error)? > 0 {
+            continue;
+        }
+        let mut fds = [PollFd::new(
+            guard.connection_fd().as_raw_fd(),
+            PollFlags::POLLIN | PollFlags::POLLERR,
+        )];
+        match poll(&mut fds, poll_timeout) {
+            Ok(0) | Err(Errno::EINTR) => continue,
+            Ok(_) => {}
+            Err(e) => return Err(e.to_string()),
+        }
+        match guard.read() {
+            Ok(_) => {}
+            Err(WaylandError::Io(e)) if e.kind() == ErrorKind::WouldBlock => {}
+            Err(e) => return Err(worker_error(e)),
+        }
+    }
+}
+
+/// Errors of workers are strings, as boxed errors can not be sent back to the main thread.
+fn worker_error(error: impl Into<DispatchError>) -> String {
+    dispatch_error(error.into()).to_string()
+}
@@ -0,0 +1,106 @@
+//! Converting a 4K frame with each [`ConversionPlan`] strategy, against the passes it replaced:
+//! converting the pixel format in place, copying the frame into an image, turning it with the
+//! image crate and resizing it with its triangle filter.
+//!
+//! Run with `cargo bench -p wayshot-core`.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use image::{
+    imageops::{self, FilterType},
+    RgbaImage,
+};
+use wayshot_core::{create_converter, ConversionPlan, PixelFormat, Strategy, Transform};
+
+const WIDTH: u32 = 3840;
+const HEIGHT: u32 = 2160;
+
+/// A frame of noise, so that nothing is faster for being uniform.
+fn frame() -> Vec<u8> {
+    let mut state = 0x2545_f491_u32;
+    (0..WIDTH * HEIGHT * 4)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as u8
+        })
+        .collect()
+}
+
+/// The conversion as it was done before being planned, one pass over the frame per step.
+fn multi_pass(format: PixelFormat, frame: &[u8], transform: Transform, size: (u32, u32)) {
+    let mut pixels = frame.to_vec();
+    create_converter(format)
+        .unwrap()
+        .convert_inplace(&mut pixels);
+    let image = RgbaImage::from_raw(WIDTH, HEIGHT, pixels).unwrap();
+    let image = match transform {
+        Transform::Rotate90 => imageops::rotate90(&image),
+        _ => image,
+    };
+    // Unscaled captures skipped resizing, which the image crate does even to the same size.
+    if image.dimensions() != size {
+        black_box(imageops::resize(
+            &image,
+            size.0,
+            size.1,
+            FilterType::Triangle,
+        ));
+    } else {
+        black_box(image);
+    }
+}
+
+fn conversion(c: &mut Criterion) {
+    let frame = frame();
+    let mut group = c.benchmark_group("conversion");
+    group
+        .sample_size(10)
+        .throughput(Throughput::Bytes(frame.len() as u64));
+
+    let cases = [
+        (
+            "copy",
+            PixelFormat::Abgr8888,
+            Transform::Normal,
+            (WIDTH, HEIGHT),
+        ),
+        (
+            "convert",
+            PixelFormat::Xrgb8888,
+            Transform::Normal,
+            (WIDTH, HEIGHT),
+        ),
+        (
+            "remap",
+            PixelFormat::Xrgb8888,
+            Transform::Rotate90,
+            (HEIGHT, WIDTH),
+        ),
+        (
+            "resample",
+            PixelFormat::Xrgb8888,
+            Transform::Normal,
+            (2560, 1440),
+        ),
+    ];
+    for (name, format, transform, (width, height)) in cases {
+        let plan = ConversionPlan::new(format, WIDTH, HEIGHT, WIDTH * 4)
+            .transform(transform)
+            .scale_to(width, height);
+        assert_eq!(format!("{:?}", plan.strategy()).to_lowercase(), name);
+        let mut image = vec![0; (width * height * 4) as usize];
+        group.bench_function(format!("plan/{}", name), |b| {
+            b.iter(|| plan.execute(black_box(&frame), &mut image))
+        });
+        if plan.strategy() != Strategy::Copy {
+            group.bench_function(format!("multi-pass/{}", name), |b| {
+                b.iter(|| multi_pass(format, black_box(&frame), transform, (width, height)))
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, conversion);
+criterion_main!(benches);
@@ -0,0 +1,164 @@
+/// Pixel formats captures can be converted from, named after their wl_shm counterparts, ie:
+/// from the most significant byte of a little endian 32 bit pixel down.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PixelFormat {
+    Xbgr8888,
+    Abgr8888,
+    Xrgb8888,
+    Argb8888,
+    Xbgr2101010,
+    Abgr2101010,
+    Xrgb2101010,
+    Argb2101010,
+}
+
+pub trait Convert {
+    /// Convert raw image data into Rgba8 in place.
+    fn convert_inplace(&self, data: &mut [u8]);
+}
+
+struct ConvertBGR10;
+
+struct ConvertRGB10;
+
+struct ConvertNone;
+
+struct ConvertRGB8;
+
+const SHIFT10BITS_1: u32 = 20;
+const SHIFT10BITS_2: u32 = 10;
+
+/// Creates format converter based of input format, return None if conversion
+/// isn't possible. Conversion is happening inplace.
+pub fn create_converter(format: PixelFormat) -> Option<&'static dyn Convert> {
+    match format {
+        PixelFormat::Xbgr8888 | PixelFormat::Abgr8888 => Some(&ConvertNone),
+        PixelFormat::Xrgb8888 | PixelFormat::Argb8888 => Some(&ConvertRGB8),
+        PixelFormat::Xbgr2101010 | PixelFormat::Abgr2101010 => Some(&ConvertBGR10),
+        PixelFormat::Xrgb2101010 | PixelFormat::Argb2101010 => Some(&ConvertRGB10),
+    }
+}
+
+impl Convert for ConvertNone {
+    fn convert_inplace(&self, _data: &mut [u8]) {}
+}
+
+impl Convert for ConvertRGB8 {
+    fn convert_inplace(&self, data: &mut [u8]) {
+        for chunk in data.chunks_exact_mut(4) {
+            chunk.swap(0, 2);
+        }
+    }
+}
+
+/// Convert one Xrgb8888 or Argb8888 pixel into Rgba8.
+pub(crate) fn convert_rgb8([b, g, r, a]: [u8; 4]) -> [u8; 4] {
+    [r, g, b, a]
+}
+
+/// Simple conversion from 10 to 8 bits for one channel
+fn convert10_to_8(color: u32) -> u8 {
+    ((color >> 2) & 255) as u8
+}
+
+impl Convert for ConvertBGR10 {
+    fn convert_inplace(&self, data: &mut [u8]) {
+        for chunk in data.chunks_exact_mut(4) {
+            let pixel = convert_bgr10([chunk[0], chunk[1], chunk[2], chunk[3]]);
+            chunk.copy_from_slice(&pixel);
+        }
+    }
+}
+
+/// Convert one Xbgr2101010 or Abgr2101010 pixel into opaque Rgba8.
+pub(crate) fn convert_bgr10(chunk: [u8; 4]) -> [u8; 4] {
+    let pixel = u32::from_le_bytes(chunk);
+    let r = convert10_to_8(pixel >> SHIFT10BITS_1);
+    let g = convert10_to_8(pixel >> SHIFT10BITS_2);
+    let b = convert10_to_8(pixel);
+    [b, g, r, 255]
+}
+
+impl Convert for ConvertRGB10 {
+    fn convert_inplace(&self, data: &mut [u8]) {
+        for chunk in data.chunks_exact_mut(4) {
+            let pixel = convert_rgb10([chunk[0], chunk[1], chunk[2], chunk[3]]);
+            chunk.copy_from_slice(&pixel);
+        }
+    }
+}
+
+/// Convert one Xrgb2101010 or Argb2101010 pixel into opaque Rgba8.
+pub(crate) fn convert_rgb10(chunk: [u8; 4]) -> [u8; 4] {
+    let pixel = u32::from_le_bytes(chunk);
+    let r = convert10_to_8(pixel >> SHIFT10BITS_1);
+    let g = convert10_to_8(pixel >> SHIFT10BITS_2);
+    let b = convert10_to_8(pixel);
+    [r, g, b, 255]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Little endian bytes of a pixel given from its most significant bit down.
+    fn bytes(pixel: u32) -> [u8; 4] {
+        pixel.to_le_bytes()
+    }
+
+    fn converted(format: PixelFormat, pixel: u32) -> [u8; 4] {
+        let mut data = bytes(pixel);
+        create_converter(format).unwrap().convert_inplace(&mut data);
+        data
+    }
+
+    #[test]
+    fn bgr8_is_already_rgba8() {
+        for format in [PixelFormat::Xbgr8888, PixelFormat::Abgr8888] {
+            assert_eq!(converted(format, 0x80_30_20_10), [0x10, 0x20, 0x30, 0x80]);
+        }
+    }
+
+    #[test]
+    fn rgb8_swaps_red_and_blue() {
+        for format in [PixelFormat::Xrgb8888, PixelFormat::Argb8888] {
+            assert_eq!(converted(format, 0x80_30_20_10), [0x30, 0x20, 0x10, 0x80]);
+        }
+        assert_eq!(convert_rgb8(bytes(0xff_00_00_ff)), [0x00, 0x00, 0xff, 0xff]);
+    }
+
+    /// 2 bit alpha or padding, then three 10 bit channels from the most significant bits.
+    fn pixel10(high: u32, middle: u32, low: u32) -> u32 {
+        (0b11 << 30) | (high << 20) | (middle << 10) | low
+    }
+
+    #[test]
+    fn bgr10_keeps_the_high_8_bits_and_is_opaque() {
+        for format in [PixelFormat::Xbgr2101010, PixelFormat::Abgr2101010] {
+            // Blue, green and red, the low 2 bits of each are dropped.
+            let pixel = pixel10(0x3ff, 0x203, 0x004);
+            assert_eq!(converted(format, pixel), [0x01, 0x80, 0xff, 0xff]);
+            let pixel = pixel10(0, 0, 0) & 0x3fff_ffff;
+            assert_eq!(converted(format, pixel), [0, 0, 0, 0xff]);
+        }
+    }
+
+    #[test]
+    fn rgb10_keeps_the_high_8_bits_and_is_opaque() {
+        for format in [PixelFormat::Xrgb2101010, PixelFormat::Argb2101010] {
+            // Red, green and blue, the low 2 bits of each are dropped.
+            let pixel = pixel10(0x3ff, 0x203, 0x004);
+            assert_eq!(converted(format, pixel), [0xff, 0x80, 0x01, 0xff]);
+        }
+    }
+
+    #[test]
+    fn every_pixel_is_converted() {
+        let mut data = [0x10, 0x20, 0x30, 0x40, 0x50, 0x60, 0x70, 0x80, 0xaa];
+        create_converter(PixelFormat::Xrgb8888)
+            .unwrap()
+            .convert_inplace(&mut data);
+        // The trailing byte is not a whole pixel and left alone.
+        assert_eq!(data, [0x30, 0x20, 0x10, 0x40, 0x70, 0x60, 0x50, 0x80, 0xaa]);
+    }
+}
@@ -0,0 +1,200 @@
+use core::cmp;
+
+/// A rectangle in logical coordinates.
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone)]
+pub struct Region {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+/// An image placed on a canvas, see [`composite_band`].
+#[derive(Debug, Copy, Clone)]
+pub struct Tile<'a> {
+    /// Rgba8 pixels, row by row without padding.
+    pub pixels: &'a [u8],
+    pub width: u32,
+    pub height: u32,
+    /// Position of the top left corner on the canvas.
+    pub x: u32,
+    pub y: u32,
+}
+
+/// Overlap of `a` and `b`. The width or height are not positive if they do not overlap.
+pub fn intersection(a: &Region, b: &Region) -> Region {
+    let x1 = cmp::max(a.x, b.x);
+    let y1 = cmp::max(a.y, b.y);
+    let x2 = cmp::min(a.x + a.width, b.x + b.width);
+    let y2 = cmp::min(a.y + a.height, b.y + b.height);
+    Region {
+        x: x1,
+        y: y1,
+        width: x2 - x1,
+        height: y2 - y1,
+    }
+}
+
+/// Smallest region containing all of `regions`.
+pub fn bounding_region<'a>(regions: impl Iterator<Item = &'a Region>) -> Region {
+    let (mut x1, mut y1, mut x2, mut y2) = (i32::MAX, i32::MAX, i32::MIN, i32::MIN);
+    for region in regions {
+        x1 = cmp::min(x1, region.x);
+        y1 = cmp::min(y1, region.y);
+        x2 = cmp::max(x2, region.x + region.width);
+        y2 = cmp::max(y2, region.y + region.height);
+    }
+    Region {
+        x: x1,
+        y: y1,
+        width: x2 - x1,
+        height: y2 - y1,
+    }
+}
+
+/// Start and length in pixels of the logical span `start..start + length` on a grid with
+/// `scale` pixels per logical pixel. Both ends are rounded in global coordinates, so spans of
+/// neighbouring outputs always line up without gaps or overlaps, even at fractional scales.
+pub fn scaled_span(start: i32, length: i32, scale: f64) -> (i64, u32) {
+    let begin = round(start as f64 * scale);
+    let end = round((start + length) as f64 * scale);
+    (begin, (end - begin) as u32)
+}
+
+/// Round half away from zero like `f64::round`, which needs std. Taking the integer part off
+/// is exact, unlike adding 0.5, which rounds values just below a half up.
+fn round(value: f64) -> i64 {
+    let integer = value as i64;
+    let fraction = value - integer as f64;
+    if fraction >= 0.5 {
+        integer + 1
+    } else if fraction <= -0.5 {
+        integer - 1
+    } else {
+        integer
+    }
+}
+
+/// Copy the rows of `tiles` which fall into `band`, the rows of an Rgba8 canvas `row_bytes`
+/// wide starting at `band_y`. Splitting the canvas into bands lets several threads fill it
+/// without locks.
+pub fn composite_band(tiles: &[Tile], band_y: u32, row_bytes: usize, band: &mut [u8]) {
+    let band_end = band_y + (band.len() / row_bytes) as u32;
+    for tile in tiles {
+        let source_row_bytes = tile.width as usize * 4;
+        for dest_y in tile.y.max(band_y)..(tile.y + tile.height).min(band_end) {
+            let source_start = (dest_y - tile.y) as usize * source_row_bytes;
+            let dest_start = (dest_y - band_y) as usize * row_bytes + tile.x as usize * 4;
+            band[dest_start..dest_start + source_row_bytes]
+                .copy_from_slice(&tile.pixels[source_start..source_start + source_row_bytes]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const fn region(x: i32, y: i32, width: i32, height: i32) -> Region {
+        Region {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    #[test]
+    fn intersection_of_overlapping_regions() {
+        let a = region(0, 0, 100, 50);
+        let b = region(60, -10, 100, 30);
+        assert_eq!(intersection(&a, &b), region(60, 0, 40, 20));
+        assert_eq!(intersection(&b, &a), region(60, 0, 40, 20));
+        assert_eq!(
+            intersection(&a, &region(10, 10, 5, 5)),
+            region(10, 10, 5, 5)
+        );
+    }
+
+    #[test]
+    fn intersection_of_separate_regions_is_empty() {
+        let a = region(0, 0, 100, 50);
+        let touching = intersection(&a, &region(100, 0, 20, 20));
+        assert_eq!(touching.width, 0);
+        let apart = intersection(&a, &region(-50, 80, 20, 20));
+        assert!(apart.width <= 0 && apart.height <= 0);
+    }
+
+    #[test]
+    fn bounding_region_contains_every_region() {
+        let regions = [
+            region(0, 0, 1920, 1080),
+            region(1920, -200, 1080, 1920),
+            region(-1280, 500, 1280, 1024),
+        ];
+        assert_eq!(
+            bounding_region(regions.iter()),
+            region(-1280, -200, 4280, 1920)
+        );
+        assert_eq!(bounding_region(regions[..1].iter()), regions[0]);
+    }
+
+    #[test]
+    fn scaled_spans_of_neighbours_line_up() {
+        assert_eq!(scaled_span(10, 20, 1.0), (10, 20));
+        assert_eq!(scaled_span(10, 20, 2.0), (20, 40));
+        // 1.5 * 1 rounds up to 2 and 1.5 * 2 is 3, leaving the first span 2 pixels long.
+        assert_eq!(scaled_span(0, 1, 1.5), (0, 2));
+        assert_eq!(scaled_span(1, 1, 1.5), (2, 1));
+        assert_eq!(scaled_span(-1, 1, 1.5), (-2, 2));
+        for scale in [1.25, 1.5, 1.75, 2.0 / 3.0] {
+            let mut end = scaled_span(-7, 0, scale).0;
+            for start in -7..7 {
+                let (begin, length) = scaled_span(start, 1, scale);
+                assert_eq!(begin, end, "gap before {} at scale {}", start, scale);
+                end = begin + length as i64;
+            }
+        }
+    }
+
+    #[test]
+    fn rounding_is_half_away_from_zero() {
+        assert_eq!(round(0.5), 1);
+        assert_eq!(round(-0.5), -1);
+        assert_eq!(round(0.49999999999999994), 0);
+        assert_eq!(round(-2.4), -2);
+    }
+
+    #[test]
+    fn bands_receive_the_rows_of_the_tiles_they_cover() {
+        // A 2x3 tile of pixels numbered by row at 1,1 and a 1x1 tile at 0,3 on a 3x4 canvas.
+        let first = [[1; 4], [1; 4], [2; 4], [2; 4], [3; 4], [3; 4]].concat();
+        let second = [9; 4];
+        let tiles = [
+            Tile {
+                pixels: &first,
+                width: 2,
+                height: 3,
+                x: 1,
+                y: 1,
+            },
+            Tile {
+                pixels: &second,
+                width: 1,
+                height: 1,
+                x: 0,
+                y: 3,
+            },
+        ];
+        let row_bytes = 3 * 4;
+        let mut canvas = [0u8; 3 * 4 * 4];
+        // Bands of 3 and 1 rows, split in the middle of the first tile.
+        let (top, bottom) = canvas.split_at_mut(3 * row_bytes);
+        composite_band(&tiles, 0, row_bytes, top);
+        composite_band(&tiles, 3, row_bytes, bottom);
+
+        let pixel = |x: usize, y: usize| canvas[y * row_bytes + x * 4];
+        let rows: [[u8; 3]; 4] = core::array::from_fn(|y| core::array::from_fn(|x| pixel(x, y)));
+        assert_eq!(rows, [[0, 0, 0], [0, 1, 1], [0, 2, 2], [9, 3, 3]]);
+    }
+}
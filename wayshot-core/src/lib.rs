@@ -0,0 +1,13 @@
+//! The pure parts of wayshot: converting captured pixels to Rgba8 and the math placing the
+//! captures of several outputs on one canvas, along with planning the conversion, rotation and
+//! scaling of a capture as a single pass. Nothing here depends on Wayland, the filesystem
+//! or an allocator, so it can be reused by other screenshot tools and recorders.
+#![cfg_attr(not(test), no_std)]
+
+mod convert;
+mod geometry;
+mod plan;
+
+pub use convert::{create_converter, Convert, PixelFormat};
+pub use geometry::{bounding_region, composite_band, intersection, scaled_span, Region, Tile};
+pub use plan::{ConversionPlan, Strategy, Transform};
@@ -0,0 +1,639 @@
+use crate::convert::{convert_bgr10, convert_rgb10, convert_rgb8, PixelFormat};
+
+/// Orientation of the pixels of a frame, named after the wl_output transforms: the frame is
+/// the image as shown, mirrored horizontally for the flipped transforms, then turned
+/// counter-clockwise.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum Transform {
+    #[default]
+    Normal,
+    Rotate90,
+    Rotate180,
+    Rotate270,
+    Flipped,
+    Flipped90,
+    Flipped180,
+    Flipped270,
+}
+
+impl Transform {
+    /// Whether the frame is as wide as the image it shows is tall.
+    pub fn swaps_axes(self) -> bool {
+        self.quarter_turns() % 2 == 1
+    }
+
+    /// This transform followed by turning the frame upside down, eg: for compositors which
+    /// copy frames y-inverted.
+    pub fn flipped_vertically(self) -> Self {
+        // Upside down is mirrored then turned by half, and mirroring reverses the direction of
+        // the turns made before it.
+        Self::from_parts((6 - self.quarter_turns()) % 4, !self.is_flipped())
+    }
+
+    fn quarter_turns(self) -> u32 {
+        match self {
+            Transform::Normal | Transform::Flipped => 0,
+            Transform::Rotate90 | Transform::Flipped90 => 1,
+            Transform::Rotate180 | Transform::Flipped180 => 2,
+            Transform::Rotate270 | Transform::Flipped270 => 3,
+        }
+    }
+
+    fn is_flipped(self) -> bool {
+        matches!(
+            self,
+            Transform::Flipped
+                | Transform::Flipped90
+                | Transform::Flipped180
+                | Transform::Flipped270
+        )
+    }
+
+    fn from_parts(quarter_turns: u32, flipped: bool) -> Self {
+        match (quarter_turns, flipped) {
+            (1, false) => Transform::Rotate90,
+            (2, false) => Transform::Rotate180,
+            (3, false) => Transform::Rotate270,
+            (0, true) => Transform::Flipped,
+            (1, true) => Transform::Flipped90,
+            (2, true) => Transform::Flipped180,
+            (3, true) => Transform::Flipped270,
+            _ => Transform::Normal,
+        }
+    }
+
+    /// Pixel of the frame showing pixel (`x`, `y`) of a `width` by `height` image.
+    fn source(self, x: u32, y: u32, width: u32, height: u32) -> (u32, u32) {
+        let x = if self.is_flipped() { width - 1 - x } else { x };
+        match self.quarter_turns() {
+            0 => (x, y),
+            1 => (y, width - 1 - x),
+            2 => (width - 1 - x, height - 1 - y),
+            _ => (height - 1 - y, x),
+        }
+    }
+}
+
+/// How a [`ConversionPlan`] goes over the frame, from the cheapest.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Strategy {
+    /// Rows are copied as they are, bottom up for frames upside down.
+    Copy,
+    /// Pixels are converted row by row, bottom up for frames upside down.
+    Convert,
+    /// Every pixel of the image is converted from the pixel of the frame showing it.
+    Remap,
+    /// Every pixel of the image is a weighted average of the frame pixels around the spot
+    /// showing it, like a resize with the triangle filter of the image crate.
+    Resample,
+}
+
+/// Conversion of a captured frame into an Rgba8 image, fusing the pixel format conversion,
+/// undoing the transform of the frame and scaling into a single pass. Done one after the other,
+/// they go over the whole image several times, which on 4K frames costs more in memory
+/// bandwidth than in computation.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ConversionPlan {
+    format: PixelFormat,
+    frame_width: u32,
+    frame_height: u32,
+    stride: u32,
+    transform: Transform,
+    image_width: u32,
+    image_height: u32,
+}
+
+impl ConversionPlan {
+    /// Plan converting a `width` by `height` frame of `format` pixels with rows `stride` bytes
+    /// apart, as it is.
+    pub fn new(format: PixelFormat, width: u32, height: u32, stride: u32) -> Self {
+        Self {
+            format,
+            frame_width: width,
+            frame_height: height,
+            stride,
+            transform: Transform::Normal,
+            image_width: width,
+            image_height: height,
+        }
+    }
+
+    /// Undo `transform`, the orientation of the frame. The image takes the size of the frame
+    /// as shown, so scale afterwards.
+    pub fn transform(mut self, transform: Transform) -> Self {
+        self.transform = transform;
+        (self.image_width, self.image_height) = self.shown_size();
+        self
+    }
+
+    /// Scale the image to `width` by `height` pixels.
+    pub fn scale_to(mut self, width: u32, height: u32) -> Self {
+        self.image_width = width;
+        self.image_height = height;
+        self
+    }
+
+    /// Width and height of the image.
+    pub fn image_size(&self) -> (u32, u32) {
+        (self.image_width, self.image_height)
+    }
+
+    pub fn strategy(&self) -> Strategy {
+        if self.shown_size() != self.image_size() {
+            Strategy::Resample
+        } else if !self.keeps_rows() {
+            Strategy::Remap
+        } else if matches!(self.format, PixelFormat::Xbgr8888 | PixelFormat::Abgr8888) {
+            Strategy::Copy
+        } else {
+            Strategy::Convert
+        }
+    }
+
+    /// Estimated work of the plan in bytes read and written. Resampling reads every pixel of
+    /// the frame columns once for each image row they are averaged into, then every column sum
+    /// once for each image pixel it is averaged into. Callers can split large conversions
+    /// between threads with [`ConversionPlan::execute_rows`].
+    pub fn cost(&self) -> u64 {
+        let (image_width, image_height) = (self.image_width as u64, self.image_height as u64);
+        let image_bytes = image_width * image_height * 4;
+        if self.strategy() != Strategy::Resample {
+            return 2 * image_bytes;
+        }
+        let (shown_width, shown_height) = self.shown_size();
+        let taps = |length: u32, new_length: u32| {
+            Taps::new(new_length / 2, length, new_length).len() as u64
+        };
+        let column_sums = shown_width as u64 * image_height * taps(shown_height, self.image_height);
+        image_bytes * (1 + taps(shown_width, self.image_width)) + column_sums * 4
+    }
+
+    /// Convert `frame` into `image`, Rgba8 pixels row by row without padding.
+    pub fn execute(&self, frame: &[u8], image: &mut [u8]) {
+        self.execute_rows(frame, 0, image);
+    }
+
+    /// Convert the rows of the image starting at `first_row` into `rows`, as many as it holds.
+    /// Bands of rows can be converted by several threads at once.
+    pub fn execute_rows(&self, frame: &[u8], first_row: u32, rows: &mut [u8]) {
+        match self.format {
+            PixelFormat::Xbgr8888 | PixelFormat::Abgr8888 => {
+                self.execute_with(frame, first_row, rows, |pixel| pixel)
+            }
+            PixelFormat::Xrgb8888 | PixelFormat::Argb8888 => {
+                self.execute_with(frame, first_row, rows, convert_rgb8)
+            }
+            PixelFormat::Xbgr2101010 | PixelFormat::Abgr2101010 => {
+                self.execute_with(frame, first_row, rows, convert_bgr10)
+            }
+            PixelFormat::Xrgb2101010 | PixelFormat::Argb2101010 => {
+                self.execute_with(frame, first_row, rows, convert_rgb10)
+            }
+        }
+    }
+
+    /// Whether rows of the frame show rows of the image, in order or bottom up.
+    fn keeps_rows(&self) -> bool {
+        matches!(self.transform, Transform::Normal | Transform::Flipped180)
+    }
+
+    /// Width and height of the frame as shown, before scaling.
+    fn shown_size(&self) -> (u32, u32) {
+        if self.transform.swaps_axes() {
+            (self.frame_height, self.frame_width)
+        } else {
+            (self.frame_width, self.frame_height)
+        }
+    }
+
+    /// Body of [`ConversionPlan::execute_rows`], generic over the pixel conversion so that it
+    /// is inlined into the loops.
+    fn execute_with(
+        &self,
+        frame: &[u8],
+        first_row: u32,
+        rows: &mut [u8],
+        convert: impl Fn([u8; 4]) -> [u8; 4],
+    ) {
+        let row_bytes = self.image_width as usize * 4;
+        if row_bytes == 0 {
+            return;
+        }
+        let (shown_width, shown_height) = self.shown_size();
+        let pixel = |x: u32, y: u32| {
+            let offset = y as usize * self.stride as usize + x as usize * 4;
+            convert([
+                frame[offset],
+                frame[offset + 1],
+                frame[offset + 2],
+                frame[offset + 3],
+            ])
+        };
+        let strategy = self.strategy();
+        let frame_row = |y: u32| {
+            if self.transform == Transform::Flipped180 {
+                self.frame_height - 1 - y
+            } else {
+                y
+            }
+        };
+
+        for (row, y) in rows.chunks_exact_mut(row_bytes).zip(first_row..) {
+            match strategy {
+                Strategy::Copy => {
+                    let start = frame_row(y) as usize * self.stride as usize;
+                    row.copy_from_slice(&frame[start..start + row_bytes]);
+                }
+                Strategy::Convert => {
+                    let y = frame_row(y);
+                    for (dest, x) in row.chunks_exact_mut(4).zip(0..) {
+                        dest.copy_from_slice(&pixel(x, y));
+                    }
+                }
+                Strategy::Remap => {
+                    for (dest, x) in row.chunks_exact_mut(4).zip(0..) {
+                        let (x, y) = self.transform.source(x, y, shown_width, shown_height);
+                        dest.copy_from_slice(&pixel(x, y));
+                    }
+                }
+                Strategy::Resample => {
+                    let rows_taps = Taps::new(y, shown_height, self.image_height);
+                    // Weighted sum of the rows of `rows_taps` in a column of the shown frame.
+                    let column_sum = |column: u32| {
+                        let mut sum = [0f32; 4];
+                        for (source_y, weight) in rows_taps.weights() {
+                            let (x, y) =
+                                self.transform
+                                    .source(column, source_y, shown_width, shown_height);
+                            for (sum, channel) in sum.iter_mut().zip(pixel(x, y)) {
+                                *sum += channel as f32 * weight;
+                            }
+                        }
+                        sum
+                    };
+                    // Column sums shared by neighbouring image pixels, by column modulo the
+                    // size of the cache. Columns are only ever needed in increasing order.
+                    let mut cache = [[0f32; 4]; COLUMN_CACHE];
+                    let mut cached_end = 0;
+
+                    for (dest, x) in row.chunks_exact_mut(4).zip(0..) {
+                        let columns_taps = Taps::new(x, shown_width, self.image_width);
+                        let cached = columns_taps.len() <= COLUMN_CACHE;
+                        if cached {
+                            for column in cached_end.max(columns_taps.start)..columns_taps.end {
+                                cache[column as usize % COLUMN_CACHE] = column_sum(column);
+                            }
+                            cached_end = columns_taps.end;
+                        }
+                        let mut sum = [0f32; 4];
+                        for (column, weight) in columns_taps.weights() {
+                            let column_sum = if cached {
+                                cache[column as usize % COLUMN_CACHE]
+                            } else {
+                                column_sum(column)
+                            };
+                            for (sum, channel) in sum.iter_mut().zip(column_sum) {
+                                *sum += channel * weight;
+                            }
+                        }
+                        for (dest, sum) in dest.iter_mut().zip(sum) {
+                            *dest = round_channel(sum);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Columns of the frame whose sums are kept while resampling a row, enough for scaling down
+/// by up to 30 times. Columns of pixels averaging more are summed again for every pixel.
+const COLUMN_CACHE: usize = 64;
+
+/// Pixels along one axis of the shown frame averaged into an image pixel when resampling,
+/// weighted like the triangle filter of the image crate: linear interpolation when scaling up,
+/// a tent spanning the pixels which shrink into one when scaling down.
+#[derive(Copy, Clone)]
+struct Taps {
+    start: u32,
+    end: u32,
+    /// Position of the image pixel on the frame, in frame pixels.
+    center: f32,
+    /// Inverse of the half width of the tent, in frame pixels.
+    inverse_support: f32,
+    /// Inverse of the sum of the weights, which are normalized by it.
+    inverse_sum: f32,
+}
+
+impl Taps {
+    fn new(index: u32, length: u32, new_length: u32) -> Self {
+        let ratio = length as f32 / new_length as f32;
+        let support = if ratio < 1.0 { 1.0 } else { ratio };
+        let center = (index as f32 + 0.5) * ratio;
+        let start = floor(center - support).clamp(0, length as i64 - 1) as u32;
+        let end = ceil(center + support).clamp(start as i64 + 1, length as i64) as u32;
+        let mut taps = Self {
+            start,
+            end,
+            center: center - 0.5,
+            inverse_support: 1.0 / support,
+            inverse_sum: 1.0,
+        };
+        taps.inverse_sum = 1.0 / taps.weights().map(|(_, weight)| weight).sum::<f32>();
+        taps
+    }
+
+    fn len(&self) -> usize {
+        (self.end - self.start) as usize
+    }
+
+    fn weights(self) -> impl Iterator<Item = (u32, f32)> {
+        (self.start..self.end).map(move |i| {
+            let distance = (i as f32 - self.center) * self.inverse_support;
+            let distance = if distance < 0.0 { -distance } else { distance };
+            let weight = if distance < 1.0 { 1.0 - distance } else { 0.0 };
+            (i, weight * self.inverse_sum)
+        })
+    }
+}
+
+/// `f32::floor`, which needs std.
+fn floor(value: f32) -> i64 {
+    let integer = value as i64;
+    if (integer as f32) > value {
+        integer - 1
+    } else {
+        integer
+    }
+}
+
+/// `f32::ceil`, which needs std.
+fn ceil(value: f32) -> i64 {
+    let integer = value as i64;
+    if (integer as f32) < value {
+        integer + 1
+    } else {
+        integer
+    }
+}
+
+/// Clamp a resampled channel to 0-255 and round it half away from zero, like the image crate.
+fn round_channel(value: f32) -> u8 {
+    let value = value.clamp(0.0, 255.0);
+    let integer = value as u8;
+    if value - integer as f32 >= 0.5 {
+        integer + 1
+    } else {
+        integer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{
+        imageops::{self, FilterType},
+        RgbaImage,
+    };
+    use proptest::{
+        prelude::{any, prop, prop_assert, prop_assert_eq, prop_oneof, proptest, Just},
+        strategy::Strategy as _,
+    };
+
+    use super::*;
+
+    const FORMATS: [PixelFormat; 8] = [
+        PixelFormat::Xbgr8888,
+        PixelFormat::Abgr8888,
+        PixelFormat::Xrgb8888,
+        PixelFormat::Argb8888,
+        PixelFormat::Xbgr2101010,
+        PixelFormat::Abgr2101010,
+        PixelFormat::Xrgb2101010,
+        PixelFormat::Argb2101010,
+    ];
+
+    const TRANSFORMS: [Transform; 8] = [
+        Transform::Normal,
+        Transform::Rotate90,
+        Transform::Rotate180,
+        Transform::Rotate270,
+        Transform::Flipped,
+        Transform::Flipped90,
+        Transform::Flipped180,
+        Transform::Flipped270,
+    ];
+
+    /// Rgba8 of a pixel, decoded bit by bit from its little endian value.
+    fn reference_pixel(format: PixelFormat, bytes: &[u8]) -> [u8; 4] {
+        let pixel = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        let channel8 = |shift: u32| (pixel >> shift & 0xff) as u8;
+        let channel10 = |shift: u32| ((pixel >> shift & 0x3ff) >> 2) as u8;
+        match format {
+            PixelFormat::Xbgr8888 | PixelFormat::Abgr8888 => {
+                [channel8(0), channel8(8), channel8(16), channel8(24)]
+            }
+            PixelFormat::Xrgb8888 | PixelFormat::Argb8888 => {
+                [channel8(16), channel8(8), channel8(0), channel8(24)]
+            }
+            PixelFormat::Xbgr2101010 | PixelFormat::Abgr2101010 => {
+                [channel10(0), channel10(10), channel10(20), 255]
+            }
+            PixelFormat::Xrgb2101010 | PixelFormat::Argb2101010 => {
+                [channel10(20), channel10(10), channel10(0), 255]
+            }
+        }
+    }
+
+    /// Pixels of `grid`, `width` wide, turned clockwise by a quarter.
+    fn turn_clockwise(grid: &[[u8; 4]], width: usize) -> (Vec<[u8; 4]>, usize) {
+        let height = grid.len() / width;
+        let turned = (0..width * height)
+            .map(|i| {
+                let (x, y) = (i % height, i / height);
+                grid[(height - 1 - x) * width + y]
+            })
+            .collect();
+        (turned, height)
+    }
+
+    /// The conversion done step by step: every pixel converted, the counter-clockwise turns
+    /// of the transform undone one quarter at a time, then the mirroring undone.
+    fn reference_conversion(
+        format: PixelFormat,
+        frame: &[u8],
+        (width, height, stride): (u32, u32, u32),
+        transform: Transform,
+    ) -> Vec<u8> {
+        let mut grid: Vec<[u8; 4]> = (0..height as usize)
+            .flat_map(|y| {
+                let row = &frame[y * stride as usize..];
+                (0..width as usize).map(move |x| reference_pixel(format, &row[x * 4..]))
+            })
+            .collect();
+        let mut width = width as usize;
+        for _ in 0..transform.quarter_turns() {
+            (grid, width) = turn_clockwise(&grid, width);
+        }
+        if transform.is_flipped() {
+            for row in grid.chunks_exact_mut(width) {
+                row.reverse();
+            }
+        }
+        grid.concat()
+    }
+
+    /// A frame of any format and orientation with random pixels and row padding.
+    fn frames(
+    ) -> impl proptest::strategy::Strategy<Value = (PixelFormat, (u32, u32, u32), Transform, Vec<u8>)>
+    {
+        (
+            0..FORMATS.len(),
+            1..9u32,
+            1..9u32,
+            0..3u32,
+            0..TRANSFORMS.len(),
+        )
+            .prop_flat_map(|(format, width, height, padding, transform)| {
+                let stride = (width + padding) * 4;
+                (
+                    Just(FORMATS[format]),
+                    Just((width, height, stride)),
+                    Just(TRANSFORMS[transform]),
+                    proptest::collection::vec(any::<u8>(), (stride * height) as usize),
+                )
+            })
+    }
+
+    /// The conversion as it was done before being planned, one pass over the frame per step:
+    /// the pixel format converted in place, the frame turned and mirrored by the image crate,
+    /// then resized with its triangle filter.
+    fn multi_pass_conversion(
+        format: PixelFormat,
+        frame: &[u8],
+        (width, height, stride): (u32, u32, u32),
+        transform: Transform,
+        (image_width, image_height): (u32, u32),
+    ) -> Vec<u8> {
+        let mut pixels: Vec<u8> = frame
+            .chunks(stride as usize)
+            .flat_map(|row| &row[..width as usize * 4])
+            .copied()
+            .collect();
+        crate::create_converter(format)
+            .unwrap()
+            .convert_inplace(&mut pixels);
+        let mut image = RgbaImage::from_raw(width, height, pixels).unwrap();
+        for _ in 0..transform.quarter_turns() {
+            image = imageops::rotate90(&image);
+        }
+        if transform.is_flipped() {
+            imageops::flip_horizontal_in_place(&mut image);
+        }
+        imageops::resize(&image, image_width, image_height, FilterType::Triangle).into_raw()
+    }
+
+    fn plan(format: PixelFormat, (width, height, stride): (u32, u32, u32)) -> ConversionPlan {
+        ConversionPlan::new(format, width, height, stride)
+    }
+
+    proptest! {
+        #[test]
+        fn conversion_matches_the_step_by_step_reference(
+            (format, size, transform, frame) in frames()
+        ) {
+            let plan = plan(format, size).transform(transform);
+            let (width, height) = plan.image_size();
+            let mut image = vec![0; (width * height * 4) as usize];
+            plan.execute(&frame, &mut image);
+            prop_assert_eq!(image, reference_conversion(format, &frame, size, transform));
+        }
+
+        #[test]
+        fn resampling_matches_the_multi_pass_conversion_within_1(
+            (format, size, transform, frame) in frames(),
+            (image_width, image_height) in (1..20u32, 1..20u32),
+        ) {
+            let plan = plan(format, size)
+                .transform(transform)
+                .scale_to(image_width, image_height);
+            let mut image = vec![0; (image_width * image_height * 4) as usize];
+            plan.execute(&frame, &mut image);
+            let expected =
+                multi_pass_conversion(format, &frame, size, transform, plan.image_size());
+            for (index, (channel, expected)) in image.iter().zip(&expected).enumerate() {
+                prop_assert!(
+                    channel.abs_diff(*expected) <= 1,
+                    "channel {} is {}, expected {}",
+                    index,
+                    channel,
+                    expected
+                );
+            }
+        }
+
+        #[test]
+        fn bands_of_rows_match_the_whole_image(
+            (format, size, transform, frame) in frames(),
+            scale in prop_oneof![Just(None), (1..12u32, 1..12u32).prop_map(Some)],
+            split in any::<prop::sample::Index>(),
+        ) {
+            let mut plan = plan(format, size).transform(transform);
+            if let Some((width, height)) = scale {
+                plan = plan.scale_to(width, height);
+            }
+            let (width, height) = plan.image_size();
+            let row_bytes = (width * 4) as usize;
+            let mut image = vec![0; row_bytes * height as usize];
+            plan.execute(&frame, &mut image);
+
+            let first_band_rows = split.index(height as usize + 1);
+            let mut bands = vec![0; image.len()];
+            let (top, bottom) = bands.split_at_mut(first_band_rows * row_bytes);
+            plan.execute_rows(&frame, 0, top);
+            plan.execute_rows(&frame, first_band_rows as u32, bottom);
+            prop_assert_eq!(bands, image);
+        }
+
+        #[test]
+        fn resampling_a_uniform_frame_keeps_its_color(
+            pixel in any::<[u8; 4]>(),
+            (width, height) in (1..20u32, 1..20u32),
+            (image_width, image_height) in (1..40u32, 1..40u32),
+        ) {
+            let frame = pixel.repeat((width * height) as usize);
+            let plan = ConversionPlan::new(PixelFormat::Abgr8888, width, height, width * 4)
+                .scale_to(image_width, image_height);
+            let mut image = vec![0; (image_width * image_height * 4) as usize];
+            plan.execute(&frame, &mut image);
+            prop_assert!(image.chunks_exact(4).all(|converted| converted == pixel));
+        }
+    }
+
+    #[test]
+    fn strategy_is_the_cheapest_that_does_the_job() {
+        let plan = |format| ConversionPlan::new(format, 4, 2, 16);
+        assert_eq!(plan(PixelFormat::Abgr8888).strategy(), Strategy::Copy);
+        assert_eq!(plan(PixelFormat::Xrgb8888).strategy(), Strategy::Convert);
+        let upside_down = plan(PixelFormat::Abgr8888).transform(Transform::Flipped180);
+        assert_eq!(upside_down.strategy(), Strategy::Copy);
+        let turned = plan(PixelFormat::Abgr8888).transform(Transform::Rotate90);
+        assert_eq!(turned.image_size(), (2, 4));
+        assert_eq!(turned.strategy(), Strategy::Remap);
+        assert_eq!(turned.scale_to(2, 4).strategy(), Strategy::Remap);
+        assert_eq!(turned.scale_to(4, 8).strategy(), Strategy::Resample);
+    }
+
+    #[test]
+    fn flipping_vertically_twice_is_the_identity() {
+        for transform in TRANSFORMS {
+            let flipped = transform.flipped_vertically();
+            assert_ne!(flipped, transform);
+            assert_eq!(flipped.flipped_vertically(), transform);
+            assert_eq!(flipped.swaps_axes(), transform.swaps_axes());
+        }
+        assert_eq!(
+            Transform::Normal.flipped_vertically(),
+            Transform::Flipped180
+        );
+    }
+}